@@ -1,5 +1,10 @@
+pub mod admin_console;
 pub mod auth;
+pub mod client_ip;
 pub mod filters;
 pub mod handlers;
+pub mod schema;
 pub mod serve;
 pub mod socketio;
+pub mod streams;
+pub mod timing;
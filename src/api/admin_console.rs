@@ -0,0 +1,225 @@
+use crate::api::client_ip::ClientIp;
+use crate::api::filters::AdminAuth;
+use crate::api::serve::AppState;
+use crate::config::CONFIG;
+use crate::database::types::Id;
+use crate::minecraft::server::{McStdout, ServerMutex, next_output};
+use axum::extract::State;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+/// how often the console looks for servers that were created since it last did
+const SERVER_SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// which worlds the output is sent of
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Focus {
+    /// every world other than these
+    Except(HashSet<Id>),
+    /// only these worlds
+    Only(HashSet<Id>),
+}
+
+impl Focus {
+    fn contains(&self, id: Id) -> bool {
+        match self {
+            Focus::Except(worlds) => !worlds.contains(&id),
+            Focus::Only(worlds) => worlds.contains(&id),
+        }
+    }
+
+    /// focuses on the `worlds`, on top of the ones already focused on. [`None`] goes back to all of them
+    fn subscribe(&mut self, worlds: Option<Vec<Id>>) {
+        match (worlds, &mut *self) {
+            (None, _) => *self = Focus::Except(HashSet::new()),
+            (Some(worlds), Focus::Except(_)) => *self = Focus::Only(worlds.into_iter().collect()),
+            (Some(worlds), Focus::Only(focused)) => focused.extend(worlds),
+        }
+    }
+
+    /// stops sending the output of the `worlds`. [`None`] stops all of them
+    fn unsubscribe(&mut self, worlds: Option<Vec<Id>>) {
+        match (worlds, &mut *self) {
+            (None, _) => *self = Focus::Only(HashSet::new()),
+            (Some(worlds), Focus::Except(excluded)) => excluded.extend(worlds),
+            (Some(worlds), Focus::Only(focused)) => {
+                for world in worlds {
+                    focused.remove(&world);
+                }
+            }
+        }
+    }
+}
+
+/// messages sent by the client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe { worlds: Option<Vec<Id>> },
+    Unsubscribe { worlds: Option<Vec<Id>> },
+    Command { world_id: Id, command: String },
+}
+
+/// messages sent to the client
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ServerMessage {
+    Line { world_id: Id, line: McStdout },
+    Error { error: String },
+}
+
+/// `GET /api/admin/console`, a WebSocket streaming the console output of every server as `{world_id, line}`.
+/// clients can send `{"type": "subscribe", "worlds": [...]}` and `{"type": "unsubscribe", "worlds": [...]}` to pick
+/// the worlds (without `worlds` they apply to all of them), and `{"type": "command", "world_id": ..., "command": ...}`
+/// to run a command. it counts toward the [`crate::config::StreamLimits`] until it's closed
+pub async fn admin_console(
+    AdminAuth(admin): AdminAuth,
+    ClientIp(ip): ClientIp,
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let slot = match state.streams.open(ip, Some(admin.id), &CONFIG.http.streams) {
+        Ok(slot) => slot,
+        Err(err) => {
+            debug!("rejected the admin console of {} from {ip}: {err}", admin.username);
+            return (StatusCode::TOO_MANY_REQUESTS, err.to_string()).into_response();
+        }
+    };
+    info!("{} opened the admin console", admin.username);
+    ws.on_upgrade(move |socket| async move {
+        run(socket, state).await;
+        drop(slot);
+    })
+}
+
+async fn run(mut socket: WebSocket, state: AppState) {
+    let (output_tx, mut output_rx) = mpsc::channel::<(Id, McStdout)>(256);
+    let (error_tx, mut error_rx) = mpsc::channel::<String>(16);
+    let mut relays: HashMap<Id, JoinHandle<()>> = HashMap::new();
+    let mut focus = Focus::Except(HashSet::new());
+    let mut scan = tokio::time::interval(SERVER_SCAN_INTERVAL);
+
+    // nothing in the loop waits for a server's lock, a server that's busy stopping would hold up the whole console.
+    // the relays and the commands take the locks in their own tasks
+    loop {
+        let message = tokio::select! {
+            _ = scan.tick() => {
+                // servers are created when worlds are first used and keep their output channel when restarted, so
+                // only new ones need to be picked up. relays of removed servers end on their own
+                relays.retain(|_, relay| !relay.is_finished());
+                for (id, server) in state.servers.get_all_servers_with_ids().await {
+                    relays.entry(id).or_insert_with(|| tokio::task::spawn(relay(id, server, output_tx.clone())));
+                }
+                continue;
+            }
+            Some(error) = error_rx.recv() => ServerMessage::Error { error },
+            Some((world_id, line)) = output_rx.recv() => {
+                if !focus.contains(world_id) {
+                    continue;
+                }
+                ServerMessage::Line { world_id, line }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe { worlds }) => {
+                        focus.subscribe(worlds);
+                        continue;
+                    }
+                    Ok(ClientMessage::Unsubscribe { worlds }) => {
+                        focus.unsubscribe(worlds);
+                        continue;
+                    }
+                    Ok(ClientMessage::Command { world_id, command }) => {
+                        let (state, errors) = (state.clone(), error_tx.clone());
+                        tokio::task::spawn(async move {
+                            if let Err(error) = run_command(&state, world_id, &command).await {
+                                let _ = errors.send(error).await;
+                            }
+                        });
+                        continue;
+                    }
+                    Err(err) => ServerMessage::Error { error: format!("invalid message: {err}") },
+                },
+                Some(Ok(Message::Close(_))) | None => break,
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => {
+                    debug!("admin console connection failed: {err}");
+                    break;
+                }
+            },
+        };
+
+        let message = serde_json::to_string(&message).expect("failed to serialize a console message");
+        if socket.send(Message::Text(message.into())).await.is_err() {
+            break;
+        }
+    }
+
+    for relay in relays.into_values() {
+        relay.abort();
+    }
+}
+
+/// forwards the output of a server to the console until the server is dropped or the console is closed. a slow
+/// console holds the relay back, and once it falls too far behind it gets a [`McStdout::Lagged`] for the lines it missed
+async fn relay(id: Id, server: ServerMutex, output: mpsc::Sender<(Id, McStdout)>) {
+    let mut stdout: broadcast::Receiver<McStdout> = server.lock().await.stdout();
+    // holding on to the server would keep it from being dropped, which is what ends the relay
+    drop(server);
+    while let Some(line) = next_output(&mut stdout).await {
+        if let McStdout::Lagged { skipped } = line {
+            debug!("the admin console skipped {skipped} lines of {id}");
+        }
+        if output.send((id, line)).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn run_command(state: &AppState, id: Id, command: &str) -> Result<(), String> {
+    let Some(server) = state.servers.get_server(id).await else {
+        return Err(format!("the server of {id} is not running"));
+    };
+    info!("running \"{command}\" in {id} from the admin console");
+    server
+        .lock()
+        .await
+        .write_console(format!("{command}\n"))
+        .await
+        .map_err(|err| {
+            error!("could not run a command in {id}: {err}");
+            format!("could not run the command in {id}")
+        })
+}
+
+#[test]
+fn focus_test() {
+    let (a, b) = (Id::new_random(), Id::new_random());
+    let mut focus = Focus::Except(HashSet::new());
+    assert!(focus.contains(a));
+    focus.unsubscribe(Some(vec![a]));
+    assert!(!focus.contains(a) && focus.contains(b));
+
+    focus.subscribe(Some(vec![a]));
+    assert!(focus.contains(a) && !focus.contains(b));
+    focus.subscribe(Some(vec![b]));
+    assert!(focus.contains(a) && focus.contains(b));
+    focus.unsubscribe(Some(vec![a]));
+    assert!(!focus.contains(a) && focus.contains(b));
+    focus.unsubscribe(None);
+    assert!(!focus.contains(b));
+    focus.subscribe(None);
+    assert!(focus.contains(a) && focus.contains(b));
+
+    let message: ClientMessage =
+        serde_json::from_value(serde_json::json!({"type": "command", "world_id": a, "command": "list"})).unwrap();
+    assert!(matches!(message, ClientMessage::Command { world_id, .. } if world_id == a));
+    assert!(serde_json::from_value::<ClientMessage>(serde_json::json!({"type": "subscribe"})).is_ok());
+}
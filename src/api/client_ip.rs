@@ -0,0 +1,147 @@
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, Request, StatusCode};
+use std::net::{IpAddr, SocketAddr};
+use tower_governor::GovernorError;
+use tower_governor::key_extractor::KeyExtractor;
+
+/// the address of the client that made the request. behind trusted proxies it's read from the `Forwarded` or
+/// `X-Forwarded-For` header, see [`client_ip`]. it's added to every request when the connection is accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<ClientIp>()
+            .copied()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// rate limits requests by their [`ClientIp`]
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIpKeyExtractor;
+
+impl KeyExtractor for ClientIpKeyExtractor {
+    type Key = IpAddr;
+
+    fn name(&self) -> &'static str {
+        "client IP"
+    }
+
+    fn extract<T>(&self, request: &Request<T>) -> Result<Self::Key, GovernorError> {
+        request
+            .extensions()
+            .get::<ClientIp>()
+            .map(|ip| ip.0)
+            .ok_or(GovernorError::UnableToExtractKey)
+    }
+
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.to_string())
+    }
+}
+
+/// the address of the client, given the address the request came from (`peer`). the forwarding headers are only
+/// read if the peer is one of the `trusted` proxies, anyone else could put anything in them. the addresses in them
+/// are walked from the last one, as every proxy appends the address it got the request from, until one that isn't
+/// trusted is found. `Forwarded` is used if it's present, otherwise `X-Forwarded-For`
+pub fn client_ip(headers: &HeaderMap, peer: IpAddr, trusted: &[IpAddr]) -> IpAddr {
+    if !trusted.contains(&peer) {
+        return peer;
+    }
+
+    let chain = if headers.contains_key("forwarded") {
+        header_list(headers, "forwarded")
+            .iter()
+            .filter_map(|element| {
+                element.split(';').find_map(|pair| {
+                    let (key, value) = pair.split_once('=')?;
+                    key.trim().eq_ignore_ascii_case("for").then(|| parse_node(value))
+                })
+            })
+            .collect::<Vec<_>>()
+    } else {
+        header_list(headers, "x-forwarded-for")
+            .iter()
+            .map(|address| parse_node(address))
+            .collect()
+    };
+
+    let mut client = peer;
+    for address in chain.into_iter().rev() {
+        if !trusted.contains(&client) {
+            break;
+        }
+        // obfuscated or broken addresses can't be followed any further
+        let Some(address) = address else {
+            break;
+        };
+        client = address;
+    }
+    client
+}
+
+/// the comma separated values of every instance of the header
+fn header_list(headers: &HeaderMap, name: &str) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// parses an address like `192.0.2.1`, `192.0.2.1:4711`, `"[2001:db8::1]:4711"` or `2001:db8::1`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(address) = node.parse::<SocketAddr>() {
+        return Some(address.ip());
+    }
+    node.strip_prefix('[')?.split(']').next()?.parse().ok()
+}
+
+#[test]
+fn client_ip_test() {
+    let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+    let headers = |pairs: &[(&'static str, &str)]| {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, value.parse().unwrap());
+        }
+        headers
+    };
+    let proxy = ip("10.0.0.1");
+    let trusted = [proxy, ip("10.0.0.2")];
+
+    // untrusted peers can't pick their address
+    let spoofed = headers(&[("x-forwarded-for", "1.1.1.1")]);
+    assert_eq!(client_ip(&spoofed, ip("203.0.113.5"), &trusted), ip("203.0.113.5"));
+    assert_eq!(client_ip(&spoofed, proxy, &[]), proxy);
+
+    assert_eq!(client_ip(&spoofed, proxy, &trusted), ip("1.1.1.1"));
+    assert_eq!(client_ip(&HeaderMap::new(), proxy, &trusted), proxy);
+
+    // the client prepended a fake address, only the one the trusted proxies added counts
+    let chained = headers(&[("x-forwarded-for", "1.1.1.1, 203.0.113.5:1234, 10.0.0.2")]);
+    assert_eq!(client_ip(&chained, proxy, &trusted), ip("203.0.113.5"));
+    let split = headers(&[("x-forwarded-for", "1.1.1.1"), ("x-forwarded-for", "203.0.113.5")]);
+    assert_eq!(client_ip(&split, proxy, &trusted), ip("203.0.113.5"));
+
+    let forwarded = headers(&[
+        ("forwarded", "for=1.1.1.1, for=\"[2001:db8::1]:4711\";proto=https"),
+        ("x-forwarded-for", "198.51.100.1"),
+    ]);
+    assert_eq!(client_ip(&forwarded, proxy, &trusted), ip("2001:db8::1"));
+    let hidden = headers(&[("forwarded", "for=1.1.1.1, for=_hidden, For=10.0.0.2")]);
+    assert_eq!(client_ip(&hidden, proxy, &trusted), ip("10.0.0.2"));
+}
@@ -100,6 +100,29 @@ impl FromRequestParts<AppState> for UserAuth {
     }
 }
 
+/// like [`UserAuth`], but only lets through users in a privileged [`Group`](crate::database::objects::Group). the
+/// others get `403 Forbidden`
+pub struct AdminAuth(pub User);
+
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = UserAuth::from_request_parts(parts, state).await?.0;
+
+        if user.group(state.database.clone(), None).await.is_privileged {
+            Ok(Self(user))
+        } else {
+            // authenticated, just not allowed
+            debug!("user {} is not privileged", user.id);
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
 pub struct FileUpload {
     pub bytes: Bytes,
     pub content_type: Mime,
@@ -137,3 +160,40 @@ impl<S: std::marker::Send + std::marker::Sync> FromRequest<S> for FileUpload {
         Err(StatusCode::BAD_REQUEST)
     }
 }
+
+#[tokio::test]
+async fn admin_auth_test() {
+    use crate::database::objects::Group;
+    use crate::database::testing::{test_group, test_state};
+    use crate::database::types::Id;
+
+    let database = crate::database::Database::memory().await;
+    let state = test_state(database.clone());
+
+    let admins = Group { id: Id::new_random(), name: String::from("admins"), is_privileged: true, ..test_group() };
+    let players = Group { id: Id::new_random(), name: String::from("players"), ..test_group() };
+    database.insert(&admins, None).await.unwrap();
+    database.insert(&players, None).await.unwrap();
+
+    let mut tokens = vec![];
+    for (username, group) in [("admin", &admins), ("player", &players)] {
+        let user = User { username: String::from(username), group_id: group.id, ..Default::default() };
+        let user = database.create_user_from(user, "hunter2").await.unwrap();
+        let session = Session { id: Id::new_random(), user_id: user.id, token: Uuid::new_v4(), created: chrono::Utc::now(), expires: true };
+        database.insert(&session, None).await.unwrap();
+        tokens.push(Some(session.token));
+    }
+    tokens.push(None);
+
+    let mut statuses = vec![];
+    for token in tokens {
+        let mut request = axum::http::Request::builder();
+        if let Some(token) = token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        statuses.push(AdminAuth::from_request_parts(&mut parts, &state).await.err());
+    }
+    // not logged in is unauthorized, logged in without the privileges is forbidden
+    assert_eq!(statuses, vec![None, Some(StatusCode::FORBIDDEN), Some(StatusCode::UNAUTHORIZED)]);
+}
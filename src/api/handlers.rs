@@ -1,29 +1,29 @@
-use crate::api::filters::{FileUpload, UserAuth, WithSession};
+use crate::api::filters::{AdminAuth, FileUpload, UserAuth, WithSession};
+use crate::api::timing::TimedQuery;
 use crate::api::serve::AppState;
 use crate::api::auth;
-use crate::config::CONFIG;
-use crate::database::objects::{DbObject, FromJson, InviteLink, UpdateJson, User, World};
+use crate::config::{CONFIG, LoginMessageType, SameSite, SessionCookieConfig};
+use crate::database::objects::{DbObject, FromJson, Group, InviteLink, Mod, UpdateJson, User, World};
 use crate::database::types::Id;
 use crate::database::{Cachable, DatabasePool, QueryBuilder, ValueType, WhereOperand};
 pub(crate) use crate::database::DatabaseError;
 use crate::{execute_on_enum, util};
 use crate::util::base64::base64_decode;
-use crate::util::dirs::icons_dir;
+use crate::util::dirs::{icon_path, icon_variant_path};
 use async_trait::async_trait;
 use axum::body::Bytes;
 use axum::extract::{Path, State};
 use axum::http::{HeaderMap, StatusCode, header};
-use axum::response::IntoResponse;
+use axum::response::{IntoResponse, Response};
 use axum::{Json, Router};
 use chrono::DateTime;
 use image::imageops::FilterType;
 use image::{DynamicImage, ImageFormat, ImageReader, Limits};
-use log::{debug, error};
+use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::{FromRow, IntoArguments};
 use std::io::{BufWriter, Cursor, Write};
-use std::path::PathBuf;
 use std::str::FromStr;
 use tokio_util::io::ReaderStream;
 use uuid::Uuid;
@@ -49,21 +49,80 @@ pub struct PaginationSettings {
     pub limit: u32,
 }
 
+/// the furthest into a list a page can start. nothing has this many objects, so later pages are rejected instead of
+/// making the database skip through nothing
+pub const MAX_PAGINATION_OFFSET: u64 = 10_000_000;
+
+impl PaginationSettings {
+    /// how many objects come before the page. computed in a [`u64`], so it can't overflow
+    pub fn offset(&self) -> u64 {
+        u64::from(self.page).saturating_mul(u64::from(self.limit))
+    }
+}
+
 impl PaginationQuery {
-    pub fn unwrap(self) -> PaginationSettings {
-        PaginationSettings {
+    /// the page to return, with the limits from [`crate::config::PaginationConfig`]. fails with
+    /// `400 Bad Request` on `limit=0`, or a page starting after [`MAX_PAGINATION_OFFSET`]
+    pub fn validate(self) -> Result<PaginationSettings, StatusCode> {
+        self.validate_with(&CONFIG.pagination)
+    }
+
+    fn validate_with(self, config: &crate::config::PaginationConfig) -> Result<PaginationSettings, StatusCode> {
+        let limit = match self.limit {
+            Some(0) => return Err(StatusCode::BAD_REQUEST),
+            Some(limit) => limit.min(config.max_limit),
+            None => config.default_limit.min(config.max_limit),
+        };
+        let settings = PaginationSettings {
             page: self.page.unwrap_or(0),
-            limit: self.limit.unwrap_or(50).min(100),
+            limit,
+        };
+        if settings.offset() > MAX_PAGINATION_OFFSET {
+            return Err(StatusCode::BAD_REQUEST);
         }
+        Ok(settings)
     }
 }
 
 impl Default for PaginationSettings {
     fn default() -> Self {
-        Self { page: 0, limit: 50 }
+        Self {
+            page: 0,
+            limit: CONFIG.pagination.default_limit.min(CONFIG.pagination.max_limit),
+        }
     }
 }
 
+#[test]
+fn pagination_test() {
+    let config = crate::config::PaginationConfig {
+        default_limit: 50,
+        max_limit: 100,
+    };
+    let validate = |page: Option<u32>, limit: Option<u32>| {
+        PaginationQuery { page, limit }
+            .validate_with(&config)
+            .map(|settings| (settings.page, settings.limit))
+    };
+
+    assert_eq!(validate(None, None), Ok((0, 50)));
+    assert_eq!(validate(Some(2), Some(100)), Ok((2, 100)));
+    assert_eq!(validate(Some(1), Some(101)), Ok((1, 100)));
+    assert_eq!(validate(None, Some(1)), Ok((0, 1)));
+    assert_eq!(validate(None, Some(0)), Err(StatusCode::BAD_REQUEST));
+    // the offset would overflow a u32
+    assert_eq!(validate(Some(u32::MAX), Some(100)), Err(StatusCode::BAD_REQUEST));
+    let last_page = u32::try_from(MAX_PAGINATION_OFFSET / 100).unwrap();
+    assert_eq!(validate(Some(last_page), Some(100)), Ok((last_page, 100)));
+    assert_eq!(validate(Some(last_page + 1), Some(100)), Err(StatusCode::BAD_REQUEST));
+
+    let settings = PaginationSettings {
+        page: u32::MAX,
+        limit: u32::MAX,
+    };
+    assert_eq!(settings.offset(), u64::from(u32::MAX) * u64::from(u32::MAX));
+}
+
 #[async_trait]
 pub trait ApiList: ApiObject
 where
@@ -74,21 +133,41 @@ where
     Self: Unpin,
     Self: Cachable,
 {
+    /// takes the filters only this object understands out of `filters`, turning them into the ids a column has to be
+    /// one of. see [`crate::database::objects::Mod`]'s `compatible_with`
+    async fn list_constraints(
+        _state: &AppState,
+        _user: &User,
+        _filters: &mut Vec<(String, String)>,
+    ) -> Result<Vec<(&'static str, Vec<Id>)>, StatusCode> {
+        Ok(Vec::new())
+    }
+
     //in theory the user filter should be done within the sql query, but for the sake of simplicity we do that when collecting the results
     async fn api_list(
         State(state): State<AppState>,
         UserAuth(user): UserAuth,
         recursive: axum::extract::Query<RecursiveQuery>,
         pagination: axum::extract::Query<PaginationQuery>,
-        axum::extract::Query(filters): axum::extract::Query<Vec<(String, String)>>,
+        axum::extract::Query(mut filters): axum::extract::Query<Vec<(String, String)>>,
     ) -> Result<impl IntoResponse, StatusCode> {
-        let pagination = pagination.0.unwrap();
+        let pagination = pagination.0.validate()?;
+        let constraints = Self::list_constraints(&state, &user, &mut filters).await?;
 
         let group = user.group(state.database.clone(), None).await;
         let objects: Vec<Self> = {
             execute_on_enum!(&state.database.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
                 let mut query = QueryBuilder::select::<Self>();
+                for (column, ids) in constraints {
+                    query.where_in(column, ids);
+                }
                 for (column, value) in filters {
+                    if column == "q" {
+                        if !value.is_empty() {
+                            query.where_search(Self::searchable_columns(), &value);
+                        }
+                        continue;
+                    }
                     let (value, filter_type) = {
                         if let Some(value) = value.strip_prefix("!") {
                             (value.to_string(), WhereOperand::NotEqual)
@@ -158,7 +237,7 @@ where
                                             );
                                         }
                                     }
-                                    ValueType::Integer => {
+                                    ValueType::Integer | ValueType::BigInteger => {
                                         if let Ok(value) = i64::from_str(&value) {
                                             query.where_operand(
                                                 column.name(),
@@ -189,6 +268,11 @@ where
                                     ValueType::Text => {
                                         query.where_operand(column.name(), value, filter_type)
                                     }
+                                    ValueType::Enum(_) => {
+                                        if column.data_type.accepts(&value) {
+                                            query.where_operand(column.name(), value, filter_type)
+                                        }
+                                    }
                                 }
                             }
                         }
@@ -203,6 +287,7 @@ where
                     .query_builder
                     .build_query_as()
                     .fetch_all(pool)
+                    .timed()
                     .await
                     .map_err(DatabaseError::from)
                     .map_err(handle_database_error)?
@@ -238,6 +323,95 @@ where
                 .collect::<Vec<_>>(),
         ))
     }
+
+    /// `GET /api/{table}/export.csv`, every object the user can see as CSV, one column for each that isn't hidden.
+    /// the rows are sent as they are read from the database (see [`crate::database::Database::stream_all`]), so the
+    /// table is never held in memory
+    async fn api_export_csv(
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<impl IntoResponse, StatusCode>
+    where
+        Self: Send,
+    {
+        use futures::StreamExt;
+
+        let group = user.group(state.database.clone(), None).await;
+        let columns: Vec<&'static str> = Self::columns()
+            .iter()
+            .filter(|column| !column.hidden)
+            .map(|column| column.name)
+            .collect();
+
+        let header = csv_line(columns.iter().map(|column| column.to_string()));
+        let rows = state.database.stream_all::<Self>(Some((user, group))).map(move |object| {
+            let object = serde_json::to_value(object?).map_err(|err| DatabaseError::InternalServerError(err.to_string()))?;
+            Ok::<_, DatabaseError>(Bytes::from(csv_line(columns.iter().map(|column| csv_value(&object[*column])))))
+        });
+        let body = futures::stream::once(async { Ok(Bytes::from(header)) })
+            .chain(rows)
+            .inspect(|row| {
+                if let Err(err) = row {
+                    error!("failed to export /{}: {err}", Self::table_name());
+                }
+            });
+
+        Ok((
+            [
+                (header::CONTENT_TYPE, String::from("text/csv; charset=utf-8")),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"{}.csv\"", Self::table_name()),
+                ),
+            ],
+            axum::body::Body::from_stream(body),
+        ))
+    }
+}
+
+/// a value of an exported object as it's written into a CSV field. null is left empty. strings a spreadsheet would
+/// run as a formula, like a world named `=HYPERLINK(...)`, get a `'` in front so they're shown as text
+fn csv_value(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(value) if value.starts_with(['=', '+', '-', '@', '\t', '\r']) => format!("'{value}"),
+        serde_json::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// a line of a CSV file (RFC 4180), with the fields that need it quoted
+fn csv_line(fields: impl Iterator<Item = String>) -> String {
+    let mut line = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push_str("\r\n");
+    line
+}
+
+#[test]
+fn csv_test() {
+    assert_eq!(csv_line(["a", "b c", ""].into_iter().map(String::from)), "a,b c,\r\n");
+    assert_eq!(
+        csv_line(["say \"hi\"", "1,2", "two\nlines"].into_iter().map(String::from)),
+        "\"say \"\"hi\"\"\",\"1,2\",\"two\nlines\"\r\n"
+    );
+    assert_eq!(csv_value(&json!(null)), "");
+    assert_eq!(csv_value(&json!("text")), "text");
+    assert_eq!(csv_value(&json!(42)), "42");
+    assert_eq!(csv_value(&json!(["a"])), "[\"a\"]");
+    // formulas are written as text, numbers stay numbers
+    assert_eq!(csv_value(&json!("=1+1")), "'=1+1");
+    assert_eq!(csv_value(&json!("@SUM(A1)")), "'@SUM(A1)");
+    assert_eq!(csv_value(&json!("-world")), "'-world");
+    assert_eq!(csv_value(&json!(-5)), "-5");
 }
 
 #[async_trait]
@@ -257,22 +431,23 @@ where
         UserAuth(user): UserAuth,
     ) -> Result<impl IntoResponse, StatusCode> {
         let group = user.group(state.database.clone(), None).await;
-        Ok(Json(if recursive.recursive.unwrap_or(false) {
+        let object = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(handle_database_error)?;
+        // the tag is of the object itself, also when the related objects are included
+        let etag = etag(&object);
+        let value = if recursive.recursive.unwrap_or(false) {
             state
                 .database
                 .get_recursive::<Self>(id, Some((&user, &group)))
                 .await
                 .map_err(handle_database_error)?
         } else {
-            serde_json::to_value(
-                state
-                    .database
-                    .get_one::<Self>(id, Some((&user, &group)))
-                    .await
-                    .map_err(handle_database_error)?,
-            )
-            .unwrap()
-        }))
+            serde_json::to_value(object).unwrap()
+        };
+        Ok(([(header::ETAG, etag)], Json(value)))
     }
 }
 
@@ -293,8 +468,56 @@ where
         recursive: axum::extract::Query<RecursiveQuery>,
         State(state): State<AppState>,
         UserAuth(user): UserAuth,
-        Json(data): Json<Self::JsonFrom>,
+        headers: HeaderMap,
+        Json(body): Json<serde_json::Value>,
     ) -> Result<impl IntoResponse, StatusCode> {
+        // read as a value first, so a repeated request can be compared with the first one
+        let body_hash = etag(&body);
+        let data = serde_json::from_value::<Self::JsonFrom>(body).map_err(|err| {
+            debug!("invalid body for /{}: {err}", Self::table_name());
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?;
+        let idempotency_key = match headers.get("Idempotency-Key") {
+            Some(key) => {
+                let key = key.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
+                if key.is_empty() || key.len() > 255 {
+                    return Err(StatusCode::BAD_REQUEST);
+                }
+                Some(key.to_string())
+            }
+            None => None,
+        };
+
+        let create = Self::create_from_api(recursive.0, state.clone(), user.clone(), data);
+
+        // repeated requests with the same key get the response of the first one, instead of creating the object again.
+        // reusing the key for a different body is a client error, it would silently get the response of the other one
+        let value = match idempotency_key {
+            Some(key) => {
+                let (first_hash, value) = state
+                    .idempotency_keys
+                    .try_get_with((user.id, Self::table_name(), key), async {
+                        create.await.map(|value| (body_hash.clone(), value))
+                    })
+                    .await
+                    .map_err(|err| *err)?;
+                if first_hash != body_hash {
+                    return Err(StatusCode::UNPROCESSABLE_ENTITY);
+                }
+                value
+            }
+            None => create.await?,
+        };
+
+        Ok(axum::Json(value))
+    }
+
+    async fn create_from_api(
+        recursive: RecursiveQuery,
+        state: AppState,
+        user: User,
+        data: Self::JsonFrom,
+    ) -> Result<serde_json::Value, StatusCode> {
         let mut data = data;
         let group = user.group(state.database.clone(), None).await;
 
@@ -327,16 +550,14 @@ where
         };
 
         if recursive.recursive.unwrap_or(false) {
-            return Ok(axum::Json(
-                state
-                    .database
-                    .get_recursive::<Self>(object.id(), Some((&user, &group)))
-                    .await
-                    .map_err(handle_database_error)?,
-            ));
+            return state
+                .database
+                .get_recursive::<Self>(object.id(), Some((&user, &group)))
+                .await
+                .map_err(handle_database_error);
         }
 
-        Ok(axum::Json(serde_json::to_value(object).unwrap()))
+        Ok(serde_json::to_value(object).unwrap())
     }
 
     #[allow(unused)]
@@ -396,40 +617,63 @@ where
             Self::table_name(),
             object.id()
         );
-        object
+        // like after the update, so the reason a change isn't possible gets to the user
+        if let Err(err) = object
             .before_api_update(state.clone(), &mut data, &user)
             .await
-            .map_err(handle_database_error)?;
+        {
+            return Ok(database_error_response(err));
+        }
 
+        let tag = etag(&object);
         let object = object.update_with_json(&data);
 
-        let _ = state
+        // only written if nothing else changed it since it was read, the changes would be lost otherwise
+        match state
             .database
-            .update(&object, Some((&user, &group)))
+            .update_unchanged(&object, Some((&user, &group)), |stored| etag(stored) == tag)
             .await
-            .map_err(handle_database_error)?;
+        {
+            Ok(()) => {}
+            Err(DatabaseError::PreconditionFailed) => {
+                debug!("not updating {} in /{}, it changed in the meantime", object.id(), Self::table_name());
+                return Err(StatusCode::CONFLICT);
+            }
+            Err(err) => return Err(handle_database_error(err)),
+        }
 
         debug!(
             "running after update for /{}/{}",
             Self::table_name(),
             object.id()
         );
-        object
+        if let Err(err) = object
             .after_api_update(state.clone(), &mut data, &user)
             .await
-            .map_err(handle_database_error)?;
+        {
+            return Ok(database_error_response(err));
+        }
 
-        if recursive.recursive.unwrap_or(false) {
-            return Ok(axum::Json(
-                state
-                    .database
-                    .get_recursive::<Self>(object.id(), Some((&user, &group)))
-                    .await
-                    .map_err(handle_database_error)?,
-            ));
+        let mut response = if recursive.recursive.unwrap_or(false) {
+            state
+                .database
+                .get_recursive::<Self>(object.id(), Some((&user, &group)))
+                .await
+                .map_err(handle_database_error)?
+        } else {
+            serde_json::to_value(object).unwrap()
+        };
+        if let Some(response) = response.as_object_mut() {
+            response.extend(Self::update_details(&data));
         }
 
-        Ok(axum::Json(serde_json::to_value(object).unwrap()))
+        Ok(axum::Json(response).into_response())
+    }
+
+    /// fields added to the response of [`ApiUpdate::api_update`], about what the update did besides changing the
+    /// object. the hooks can keep what they need for it in the json
+    fn update_details(_json: &Self::JsonUpdate) -> serde_json::Map<String, serde_json::Value> {
+        serde_json::Map::new()
     }
     #[allow(unused)]
     /// runs before the database entry update
@@ -455,6 +699,9 @@ where
     }
 }
 
+/// most objects `POST /api/{table}/delete` removes at once
+pub const MAX_BULK_DELETE: usize = 100;
+
 #[async_trait]
 pub trait ApiRemove: ApiObject
 where
@@ -465,18 +712,71 @@ where
     Self: Unpin,
     Self: Cachable,
 {
+    /// with an `If-Match` header, the object is only removed if it still has one of the [`etag`]s, otherwise it
+    /// answers `412 Precondition Failed`
     async fn api_remove(
         Path(id): Path<Id>,
         State(state): State<AppState>,
         UserAuth(user): UserAuth,
+        headers: HeaderMap,
     ) -> Result<StatusCode, StatusCode> {
+        let if_match = match headers.get(header::IF_MATCH) {
+            Some(value) => Some(value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?),
+            None => None,
+        };
         let group = user.group(state.database.clone(), None).await;
+        Self::remove_by_id(&state, &user, &group, id, if_match).await?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// removes up to [`MAX_BULK_DELETE`] objects, each the same way as [`ApiRemove::api_remove`], returning the status
+    /// of every removal. they are removed one by one instead of in a transaction, as the hooks also remove files and
+    /// servers, which can't be rolled back
+    async fn api_remove_many(
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        Json(ids): Json<Vec<Id>>,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        if ids.len() > MAX_BULK_DELETE {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        let group = user.group(state.database.clone(), None).await;
+
+        let mut results = Vec::with_capacity(ids.len());
+        for id in ids {
+            let status = match Self::remove_by_id(&state, &user, &group, id, None).await {
+                Ok(()) => StatusCode::NO_CONTENT,
+                Err(status) => status,
+            };
+            results.push(json!({
+                "id": id,
+                "removed": status.is_success(),
+                "status": status.as_u16(),
+            }));
+        }
+        Ok(Json(results))
+    }
 
+    /// checks the access to the object and removes it, running [`ApiRemove::before_api_delete`] and
+    /// [`ApiRemove::after_api_delete`]. `if_match` is the `If-Match` header the object has to match
+    async fn remove_by_id(
+        state: &AppState,
+        user: &User,
+        group: &Group,
+        id: Id,
+        if_match: Option<&str>,
+    ) -> Result<(), StatusCode> {
         let object = state
             .database
-            .get_one::<Self>(id, Some((&user, &group)))
+            .get_one::<Self>(id, Some((user, group)))
             .await
             .map_err(handle_database_error)?;
+        if let Some(if_match) = if_match
+            && !matches_etag(if_match, &etag(&object))
+        {
+            debug!("not removing {} from /{}, it changed", object.id(), Self::table_name());
+            return Err(StatusCode::PRECONDITION_FAILED);
+        }
 
         debug!(
             "running before delete for /{}/{}",
@@ -484,15 +784,23 @@ where
             object.id()
         );
         object
-            .before_api_delete(state.clone(), &user)
+            .before_api_delete(state.clone(), user)
             .await
             .map_err(handle_database_error)?;
 
-        let _ = state
-            .database
-            .remove(&object, Some((&user, &group)))
-            .await
-            .map_err(handle_database_error)?;
+        // the tag is checked again in the same transaction as the delete, the object could have changed since
+        match if_match {
+            Some(if_match) => state
+                .database
+                .remove_unchanged(&object, Some((user, group)), |stored| matches_etag(if_match, &etag(stored)))
+                .await
+                .map_err(handle_database_error)?,
+            None => state
+                .database
+                .remove(&object, Some((user, group)))
+                .await
+                .map_err(handle_database_error)?,
+        }
 
         debug!(
             "running after delete for /{}/{}",
@@ -500,11 +808,11 @@ where
             object.id()
         );
         object
-            .after_api_delete(state.clone(), &user)
+            .after_api_delete(state.clone(), user)
             .await
             .map_err(handle_database_error)?;
 
-        Ok(StatusCode::NO_CONTENT)
+        Ok(())
     }
 
     #[allow(unused)]
@@ -551,65 +859,14 @@ where
             .await
             .map_err(|_| StatusCode::NOT_FOUND)?;
 
-        let (bytes, image_format) = (
-            file.bytes,
-            ImageFormat::from_mime_type(file.content_type.essence_str()),
-        );
-        if image_format.is_none() {
-            return Err(StatusCode::BAD_REQUEST);
-        }
-        let image_format = image_format.unwrap();
-
-        let is_gif = match image_format {
-            ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Bmp => false,
-            ImageFormat::Gif => true,
-            _ => return Err(StatusCode::BAD_REQUEST),
-        };
-
-        let gif_path = crate::util::dirs::icons_dir()
-            .join(Self::table_name())
-            .join(format!("{id}.gif"));
-        let webp_path = crate::util::dirs::icons_dir()
-            .join(Self::table_name())
-            .join(format!("{id}.webp"));
-
-        if is_gif {
-            // i think this is to verify if the image is valid
-            let _ = bytes_to_image(&bytes, image_format)?;
-
-            let image_file =
-                std::fs::File::create(&gif_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            let mut writer = BufWriter::new(image_file);
-
-            if webp_path.exists() {
-                std::fs::remove_file(&webp_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            }
-
-            // after making sure the file is a valid image write the original, as we don't want to alter a gif
-            writer
-                .write_all(bytes.as_ref())
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        } else {
-            let image = bytes_to_image(&bytes, image_format)?;
-            let image = crop_image_to_square(&image);
-
-            let after_icon_update_future = self_.after_icon_update(state.clone(), &user, &image);
-
-            let image = image.resize(256, 256, FilterType::CatmullRom);
+        let image_format =
+            ImageFormat::from_mime_type(file.content_type.essence_str()).ok_or(StatusCode::BAD_REQUEST)?;
 
-            let image_file =
-                std::fs::File::create(&webp_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            let mut writer = BufWriter::new(image_file);
-
-            if gif_path.exists() {
-                std::fs::remove_file(&gif_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-            }
-
-            image
-                .write_to(&mut writer, ImageFormat::WebP)
+        if let Some(image) = save_icon(Self::table_name(), id, &file.bytes, image_format)? {
+            self_
+                .after_icon_update(state.clone(), &user, &image)
+                .await
                 .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-            after_icon_update_future.await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
         }
 
         Ok(StatusCode::OK)
@@ -620,15 +877,23 @@ where
         Ok(())
     }
 
+    /// the stored icon, or with `?format=` or `?size=` a square variant of it in that format and size, see
+    /// [`IconQuery`]. variants are rendered once and cached next to the icon
     async fn get_icon(
         id: Path<Id>,
+        axum::extract::Query(query): axum::extract::Query<IconQuery>,
         _user: UserAuth, /*check if the user is authenticated, but do not check if they have access to the object, since it doesn't justify the extra DB lookups*/
+        headers: HeaderMap,
     ) -> Result<impl IntoResponse, StatusCode> {
-        let path = icons_dir().join(Self::table_name());
-        let (path, is_gif) = if path.join(format!("{}.webp", *id)).exists() {
-            (path.join(format!("{}.webp", *id)), false)
-        } else if path.join(format!("{}.gif", *id)).exists() {
-            (path.join(format!("{}.gif", *id)), true)
+        if query.size.is_some_and(|size| !ICON_VARIANT_SIZES.contains(&size)) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let webp_path = icon_path(Self::table_name(), *id, "webp");
+        let gif_path = icon_path(Self::table_name(), *id, "gif");
+        let (path, is_gif) = if webp_path.exists() {
+            (webp_path, false)
+        } else if gif_path.exists() {
+            (gif_path, true)
         } else {
             return Ok((
                 StatusCode::SEE_OTHER,
@@ -636,18 +901,26 @@ where
             ).into_response());
         };
 
-        let file = tokio::fs::File::open(path)
-            .await
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        let stream = ReaderStream::new(file);
-        let body = axum::body::Body::from_stream(stream);
-
-        let mime_type = if is_gif { "image/gif" } else { "image/webp" };
-
-        let header = [(header::CONTENT_TYPE, mime_type)];
+        let (path, mime_type) = if query.format.is_none() && query.size.is_none() {
+            (path, if is_gif { "image/gif" } else { "image/webp" })
+        } else {
+            let format = query.format.unwrap_or(IconFormat::Webp);
+            let size = query.size.unwrap_or(ICON_SIZE);
+            let variant = icon_variant_path(Self::table_name(), *id, size, format.extension());
+            if !variant.exists() {
+                let target = variant.clone();
+                tokio::task::spawn_blocking(move || render_icon_variant(&path, &target, size, format))
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                    .map_err(|err| {
+                        error!("could not render a variant of the icon {id}: {err}", id = *id);
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    })?;
+            }
+            (variant, format.mime_type())
+        };
 
-        Ok((header, body).into_response())
+        stream_file_with_range(&path, mime_type, &headers).await
     }
 
     const DEFAULT_ICON_BYTES: &'static [u8];
@@ -673,6 +946,141 @@ where
     }
 }
 
+/// what a `Range` header asks for, see [`parse_range`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeRequest {
+    /// the whole file
+    Full,
+    /// the bytes from the first to the last offset, both included
+    Partial(u64, u64),
+    /// a range outside of the file
+    Unsatisfiable,
+}
+
+/// the range of a file of `length` bytes the `Range` header asks for. anything other than a single range of bytes,
+/// which is all the downloads need for resuming, gets the whole file, as does an unparsable header
+fn parse_range(range: Option<&str>, length: u64) -> RangeRequest {
+    let Some(range) = range.and_then(|range| range.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if range.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = range.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        // the last bytes
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(suffix) => (length.saturating_sub(suffix), length.saturating_sub(1)),
+            Err(_) => return RangeRequest::Full,
+        },
+        (start, "") => match start.parse() {
+            Ok(start) => (start, length.saturating_sub(1)),
+            Err(_) => return RangeRequest::Full,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(length.saturating_sub(1))),
+            _ => return RangeRequest::Full,
+        },
+    };
+    if start >= length {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(start, end)
+}
+
+/// streams the file at `path`, or with a `Range` header just the part it asks for as `206 Partial Content`, so
+/// downloads can be resumed. a range outside of the file is answered with `416 Range Not Satisfiable`. with an
+/// `If-Range` the whole file is sent, callers that can check it remove it when it matches
+pub(crate) async fn stream_file_with_range(
+    path: &std::path::Path,
+    mime_type: &str,
+    headers: &HeaderMap,
+) -> Result<Response, StatusCode> {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = tokio::fs::File::open(path).await.map_err(|err| match err.kind() {
+        std::io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    })?;
+    let length = file
+        .metadata()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .len();
+
+    let range = if headers.contains_key(header::IF_RANGE) {
+        RangeRequest::Full
+    } else {
+        parse_range(headers.get(header::RANGE).and_then(|range| range.to_str().ok()), length)
+    };
+    let (start, end) = match range {
+        RangeRequest::Full => {
+            let body = axum::body::Body::from_stream(ReaderStream::new(file));
+            return Ok((
+                [
+                    (header::CONTENT_TYPE, mime_type.to_string()),
+                    (header::CONTENT_LENGTH, length.to_string()),
+                    (header::ACCEPT_RANGES, String::from("bytes")),
+                ],
+                body,
+            )
+                .into_response());
+        }
+        RangeRequest::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(header::CONTENT_RANGE, format!("bytes */{length}"))],
+            )
+                .into_response());
+        }
+        RangeRequest::Partial(start, end) => (start, end),
+    };
+
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let body = axum::body::Body::from_stream(ReaderStream::new(file.take(end - start + 1)));
+    Ok((
+        StatusCode::PARTIAL_CONTENT,
+        [
+            (header::CONTENT_TYPE, mime_type.to_string()),
+            (header::CONTENT_LENGTH, (end - start + 1).to_string()),
+            (header::ACCEPT_RANGES, String::from("bytes")),
+            (header::CONTENT_RANGE, format!("bytes {start}-{end}/{length}")),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// the `ETag` of an object, a hash of it serialized. it goes through a [`serde_json::Value`], which sorts the keys of
+/// maps, so the same object always gets the same tag
+pub(crate) fn etag<T: Serialize>(object: &T) -> String {
+    use sha1::{Digest, Sha1};
+
+    let value = serde_json::to_value(object).expect("serialization failed");
+    let json = serde_json::to_vec(&value).expect("serialization failed");
+    format!("\"{:x}\"", Sha1::digest(json))
+}
+
+/// whether the `If-Match` header matches the tag. it compares the tags strongly, so weak ones never match
+pub(crate) fn matches_etag(if_match: &str, etag: &str) -> bool {
+    if_match.split(',').map(str::trim).any(|tag| tag == "*" || tag == etag)
+}
+
+/// like [`handle_database_error`], but keeps the reason of a [`DatabaseError::Unprocessable`] in the response body
+pub(crate) fn database_error_response(err: DatabaseError) -> Response {
+    match err {
+        DatabaseError::Unprocessable(reason) => {
+            (StatusCode::UNPROCESSABLE_ENTITY, reason).into_response()
+        }
+        err => handle_database_error(err).into_response(),
+    }
+}
+
 pub(crate) fn handle_database_error(err: DatabaseError) -> StatusCode {
     match err {
         DatabaseError::Unauthorized => StatusCode::UNAUTHORIZED,
@@ -682,6 +1090,16 @@ pub(crate) fn handle_database_error(err: DatabaseError) -> StatusCode {
             StatusCode::INTERNAL_SERVER_ERROR
         }
         DatabaseError::Conflict => StatusCode::CONFLICT,
+        DatabaseError::BadRequest(err) => {
+            debug!("bad request: {err}");
+            StatusCode::BAD_REQUEST
+        }
+        DatabaseError::Unprocessable(err) => {
+            debug!("unprocessable: {err}");
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        DatabaseError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        DatabaseError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
         DatabaseError::SqlxError(err) => match err {
             sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
             _ => {
@@ -784,8 +1202,32 @@ pub async fn user_register(
 
 //this in theory could be transformed into ApiCreate implementation, but it would require a fair amount of changes, and for now it's not causing any problems
 #[allow(clippy::unused_async)]
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct LoginQuery {
+    /// whether to set the `session-token` cookie. clients sending the token in the `Authorization` header themselves
+    /// can turn it off. default is true
+    pub cookie: Option<bool>,
+}
+
+/// the `Set-Cookie` value storing the session token, with the attributes from `config`
+fn session_cookie(config: &SessionCookieConfig, token: &str, max_age: u64) -> String {
+    let mut cookie = format!(
+        "session-token={token}; Path=/api; HttpOnly; SameSite={}; Max-Age={max_age}",
+        config.same_site
+    );
+    // browsers reject SameSite=None cookies without Secure
+    if config.secure || config.same_site == SameSite::None {
+        cookie.push_str("; Secure");
+    }
+    if !config.domain.is_empty() {
+        cookie.push_str(&format!("; Domain={}", config.domain));
+    }
+    cookie
+}
+
 pub async fn user_auth(
     database: State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<LoginQuery>,
     credentials: axum::extract::Json<Login>,
 ) -> Result<impl IntoResponse, StatusCode> {
     let database = database.0;
@@ -798,25 +1240,39 @@ pub async fn user_auth(
             .await
             .map_err(handle_database_error)?
     };
+    let token = session.token.as_simple().to_string();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+        header::HeaderValue::from_static("true"),
+    );
+    if query.cookie.unwrap_or(true) {
+        headers.insert(
+            header::SET_COOKIE,
+            session_cookie(&CONFIG.session_cookie, &token, CONFIG.sessions.lifetime)
+                .parse()
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+    }
     Ok((
         StatusCode::CREATED,
-        [(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true")],
-        [(
-            header::SET_COOKIE,
-            format!(
-                "session-token={}; Path=/api; HttpOnly; Max-Age=1209600; charset=UTF-8",
-                session.token.as_simple()
-            ),
-        )],
-        axum::Json(json!({"token": session.token.as_simple().to_string()})),
+        headers,
+        axum::Json(json!({"token": token})),
     ))
 }
 
+/// the `Set-Cookie` value making the browser drop the session cookie. the attributes have to match the ones it was
+/// set with, see [`session_cookie`]
+fn clear_session_cookie(config: &SessionCookieConfig) -> String {
+    session_cookie(config, "", 0)
+}
+
 #[allow(clippy::unused_async)]
 pub async fn logout(
     state: State<AppState>,
     session: WithSession,
-) -> Result<StatusCode, StatusCode> {
+) -> Result<impl IntoResponse, StatusCode> {
     let state = state.0;
     let session = session.0;
 
@@ -826,7 +1282,10 @@ pub async fn logout(
         .await
         .map_err(handle_database_error)?;
 
-    Ok(StatusCode::OK)
+    Ok((
+        StatusCode::OK,
+        [(header::SET_COOKIE, clear_session_cookie(&CONFIG.session_cookie))],
+    ))
 }
 
 #[allow(clippy::unused_async)]
@@ -847,14 +1306,28 @@ pub async fn user_info(
     }))
 }
 
-#[allow(clippy::unused_async)]
-pub async fn server_info() -> Result<impl IntoResponse, StatusCode> {
+/// cheap check of whether the session is valid, without the full user profile of [`user_info`]
+pub async fn whoami(
+    UserAuth(user): UserAuth,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let group = user.group(state.database, None).await;
+    Ok(Json(json!({
+        "id": user.id,
+        "username": user.username,
+        "is_privileged": group.is_privileged,
+    })))
+}
+
+/// the body of [`server_info`]. it only depends on the config, which doesn't change while running, so it's only
+/// serialized once
+static SERVER_INFO: std::sync::LazyLock<Bytes> = std::sync::LazyLock::new(|| {
     #[derive(Serialize)]
     struct ServerInfo {
         name: String,
         login_message: String,
         login_message_title: String,
-        login_message_type: String,
+        login_message_type: LoginMessageType,
         requires_invite: bool,
         world: WorldInfo,
     }
@@ -866,19 +1339,99 @@ pub async fn server_info() -> Result<impl IntoResponse, StatusCode> {
         port: u16,
     }
 
-    Ok(axum::Json(ServerInfo {
+    let info = ServerInfo {
         name: CONFIG.info.name.clone(),
         login_message: CONFIG.info.login_message.clone(),
         login_message_title: CONFIG.info.login_message_title.clone(),
-        login_message_type: CONFIG.info.login_message_type.clone(),
+        login_message_type: CONFIG.info.login_message_type,
         requires_invite: CONFIG.require_invite_to_register,
         world: WorldInfo {
             min_memory: CONFIG.world.minimum_memory,
-            default_memory: CONFIG.world_defaults.allocated_memory,
+            default_memory: CONFIG.world_defaults.allocated_memory.mib(),
             hostname: CONFIG.proxy.hostname.clone(),
             port: CONFIG.proxy.port,
         },
-    }))
+    };
+    Bytes::from(serde_json::to_vec(&info).expect("serialization failed"))
+});
+
+#[allow(clippy::unused_async)]
+pub async fn server_info() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, String::from("application/json")),
+            (
+                header::CACHE_CONTROL,
+                format!("public, max-age={}", CONFIG.info.cache_max_age),
+            ),
+        ],
+        SERVER_INFO.clone(),
+    )
+}
+
+/// `GET /api/proxy/config`, the config files of the Minecraft proxy next to the hosts it routes right now, see
+/// [`crate::minecraft::proxy::proxy_config`]
+pub async fn proxy_config(_: AdminAuth, State(state): State<AppState>) -> impl IntoResponse {
+    let hosts = state
+        .proxy_hosts
+        .read()
+        .expect("the proxy hosts lock is poisoned")
+        .clone();
+    let secrets = &crate::config::secrets::SECRETS;
+    Json(crate::minecraft::proxy::proxy_config(
+        &util::dirs::infrarust_dir(),
+        &hosts,
+        &[&secrets.forwarding_secret, &secrets.api_secret],
+    ))
+}
+
+/// `GET /api/health`, pings the database. answers `503 Service Unavailable` if it can't be reached, so it can be
+/// used by load balancers and container health checks. also reports the background tasks, see
+/// [`crate::tasks::TaskHealth::report`]. doesn't require authentication
+pub async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    let tasks = state.tasks.report();
+    match state.database.ping().await {
+        Ok(()) => (StatusCode::OK, Json(json!({"database": "ok", "tasks": tasks}))),
+        Err(err) => {
+            debug!("health check failed: {err}");
+            (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"database": "unavailable", "tasks": tasks})))
+        }
+    }
+}
+
+/// defaults and limits for creating a world, computed for the authenticated user
+pub async fn world_defaults(
+    UserAuth(user): UserAuth,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let group = user.group(state.database.clone(), None).await;
+    let worlds = state
+        .database
+        .get_all_where::<World, _>("owner_id", user.id, None)
+        .await
+        .map_err(handle_database_error)?;
+
+    Ok(Json(json!({
+        "memory": {
+            "minimum": CONFIG.world.minimum_memory,
+            "default": CONFIG.world_defaults.allocated_memory,
+            "maximum": group.per_world_memory_limit,
+            // how much more the user can enable, a world over it can be created, but not enabled
+            "available": group
+                .total_memory_limit
+                .map(|limit| (i64::from(limit) - user.total_memory_usage).max(0)),
+        },
+        "version_id": CONFIG.world_defaults.version_id,
+        "worlds": {
+            "count": worlds.len(),
+            "limit": group.world_limit,
+            "active_limit": group.active_world_limit,
+        },
+        "hostname": {
+            "allowed_characters": crate::database::objects::world::HOSTNAME_CHARS,
+            "domain": CONFIG.proxy.hostname,
+        },
+    })))
 }
 
 #[allow(clippy::unused_async)]
@@ -898,6 +1451,270 @@ pub async fn generate_console_ticket(
     Ok(Json(Ticket {ticket}))
 }
 
+#[allow(clippy::unused_async)]
+pub async fn cache_stats(
+    _: AdminAuth,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    Ok(Json(state.database.cache.stats()))
+}
+
+/// recalculates the memory usage of every user
+pub async fn recalculate_memory(
+    AdminAuth(admin): AdminAuth,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    info!("{} is recalculating the memory usage of all users", admin.username);
+    let mut recalculations = Vec::new();
+    for user in state
+        .database
+        .get_all::<User>(None)
+        .await
+        .map_err(handle_database_error)?
+    {
+        recalculations.push(
+            user.recalculate_memory_usage(&state.database)
+                .await
+                .map_err(handle_database_error)?,
+        );
+    }
+    Ok(Json(recalculations))
+}
+
+/// information about the host mcmanager runs on, and the resource limits the running servers have
+pub async fn system_info(_: AdminAuth, State(state): State<AppState>) -> impl IntoResponse {
+    let mut resource_limits = serde_json::Map::new();
+    let mut uptime = serde_json::Map::new();
+    for server in state.servers.get_all_servers().await {
+        let server = server.lock().await;
+        if let Some(server_uptime) = server.uptime() {
+            uptime.insert(server.id().to_string(), json!(server_uptime.as_secs()));
+        }
+        if let Some(limits) = server.resource_limits() {
+            resource_limits.insert(
+                server.id().to_string(),
+                serde_json::to_value(limits).expect("serialization failed"),
+            );
+        }
+    }
+
+    Json(json!({
+        "java_version": *crate::minecraft::util::JAVA_VERSION,
+        "cgroup": CONFIG.world.cgroup,
+        "resource_limits": resource_limits,
+        // in seconds, of the servers that are running
+        "uptime": uptime,
+    }))
+}
+
+/// `GET /api/admin/overview`, everything an admin dashboard shows at once. the counts come from `COUNT` queries and
+/// the running worlds from the servers in memory, so it doesn't load any table
+pub async fn admin_overview(_: AdminAuth, State(state): State<AppState>) -> Result<impl IntoResponse, StatusCode> {
+    let database = &state.database;
+    let users = database.count::<User>().await.map_err(handle_database_error)?;
+    let enabled_users = database.count_where::<User, _>("enabled", true).await.map_err(handle_database_error)?;
+    let worlds = database.count::<World>().await.map_err(handle_database_error)?;
+    let enabled_worlds = database.count_where::<World, _>("enabled", true).await.map_err(handle_database_error)?;
+    let mods = database.count::<Mod>().await.map_err(handle_database_error)?;
+    let allocated_memory = database
+        .sum_where::<World, _>("allocated_memory", "enabled", true)
+        .await
+        .map_err(handle_database_error)?;
+
+    let mut running_worlds = 0;
+    let mut running_memory = 0;
+    for server in state.servers.get_all_servers().await {
+        let server = server.lock().await;
+        if matches!(server.status().await, Ok(crate::minecraft::server::MinecraftServerStatus::Running)) {
+            running_worlds += 1;
+            running_memory += i64::from(server.world().allocated_memory);
+        }
+    }
+
+    let ports = state.servers.port_allocations().await;
+    let proxy_status = *state.proxy_status.read().expect("the proxy status lock is poisoned");
+    let proxy_hosts = state.proxy_hosts.read().expect("the proxy hosts lock is poisoned").len();
+
+    Ok(Json(json!({
+        "users": {"total": users, "enabled": enabled_users},
+        "worlds": {"total": worlds, "enabled": enabled_worlds, "running": running_worlds},
+        "mods": mods,
+        // in MiB. allocated is what the enabled worlds are given, running what the running ones are
+        "memory": {"allocated": allocated_memory, "running": running_memory},
+        "ports": {"range": ports.range, "used": ports.taken.len(), "free": ports.free},
+        "proxy": {
+            "status": proxy_status.map(crate::database::objects::world::MinecraftServerStatusJson::from),
+            "hosts": proxy_hosts,
+        },
+        "database": {
+            "available": database.is_available(),
+            "pool": database.pool_stats(),
+        },
+    })))
+}
+
+/// `GET /api/admin/capacity`, the instance limits of the config and how much of them is used, see
+/// [`crate::minecraft::capacity`]. it's always counted again instead of using the cached usage
+pub async fn instance_capacity(_: AdminAuth, State(state): State<AppState>) -> Result<impl IntoResponse, StatusCode> {
+    let usage = crate::minecraft::capacity::current_usage(&state)
+        .await
+        .map_err(handle_database_error)?;
+    Ok(Json(json!({
+        "limits": CONFIG.instance,
+        "usage": usage,
+    })))
+}
+
+/// kills a world's server process, for servers that don't stop. unlike disabling the world it doesn't send `stop` or
+/// wait for the server to save and exit, so anything not saved is lost
+pub async fn kill_server(
+    AdminAuth(admin): AdminAuth,
+    State(state): State<AppState>,
+    Path(id): Path<Id>,
+) -> Result<impl IntoResponse, StatusCode> {
+    state
+        .database
+        .get_one::<World>(id, None)
+        .await
+        .map_err(handle_database_error)?;
+    info!("{} is killing the server of {id}", admin.username);
+
+    let status = match state.servers.get_server(id).await {
+        Some(server) => server.lock().await.kill().await.map_err(|err| {
+            error!("could not kill the server of {id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?,
+        None => crate::minecraft::server::MinecraftServerStatus::Exited(0),
+    };
+    Ok(Json(crate::database::objects::world::MinecraftServerStatusJson::from(status)))
+}
+
+/// the configured port range, which ports are reserved and by which worlds, and how many are free
+pub async fn port_allocations(_: AdminAuth, State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.servers.port_allocations().await)
+}
+
+/// rebuilds the port reservations from the running servers, freeing the ones no running server is using
+pub async fn reconcile_ports(AdminAuth(admin): AdminAuth, State(state): State<AppState>) -> impl IntoResponse {
+    let freed = state.servers.reconcile_ports().await;
+    info!("{} reconciled the reserved ports, freeing {freed:?}", admin.username);
+    Json(json!({"freed": freed}))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawQuery {
+    query: String,
+}
+
+/// runs a read-only SQL query for reports, if `database.admin_queries` is enabled in the config
+pub async fn raw_query(
+    AdminAuth(admin): AdminAuth,
+    State(state): State<AppState>,
+    Json(data): Json<RawQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    if !CONFIG.database.admin_queries {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    info!("admin {} ({}) is running a query: {}", admin.username, admin.id, data.query);
+
+    match state.database.raw_query(&data.query).await {
+        Ok((rows, truncated)) => Ok(Json(json!({
+            "rows": rows,
+            "truncated": truncated,
+        }))
+        .into_response()),
+        Err(DatabaseError::BadRequest(reason)) => {
+            Ok((StatusCode::BAD_REQUEST, reason).into_response())
+        }
+        Err(err) => Err(handle_database_error(err)),
+    }
+}
+
+/// lists the files in the data directory that no database row refers to
+pub async fn list_orphans(
+    _: AdminAuth,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let orphans = crate::database::orphans::find_orphans(&state.database, &state.servers)
+        .await
+        .map_err(handle_database_error)?;
+    Ok(Json(orphans))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OrphanCleanupQuery {
+    /// only list what would be deleted. defaults to true, so nothing is deleted by accident
+    dry_run: Option<bool>,
+}
+
+/// deletes the files in the data directory that no database row refers to
+pub async fn cleanup_orphans(
+    AdminAuth(admin): AdminAuth,
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<OrphanCleanupQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let orphans = crate::database::orphans::find_orphans(&state.database, &state.servers)
+        .await
+        .map_err(handle_database_error)?;
+    let dry_run = query.dry_run.unwrap_or(true);
+    if dry_run {
+        return Ok(Json(json!({
+            "dry_run": true,
+            "orphans": orphans,
+        })));
+    }
+
+    for orphan in &orphans {
+        info!(
+            "{} is deleting the orphaned {} {}",
+            admin.username,
+            orphan.kind,
+            orphan.path.display()
+        );
+    }
+    let failed = crate::database::orphans::remove_orphans(orphans.clone()).await;
+    for (orphan, err) in &failed {
+        error!("could not delete {}: {err}", orphan.path.display());
+    }
+    let freed: u64 = orphans.iter().map(|orphan| orphan.size).sum::<u64>()
+        - failed.iter().map(|(orphan, _)| orphan.size).sum::<u64>();
+
+    Ok(Json(json!({
+        "dry_run": false,
+        "orphans": orphans,
+        "failed": failed
+            .iter()
+            .map(|(orphan, err)| json!({"path": orphan.path, "error": err}))
+            .collect::<Vec<_>>(),
+        "freed": freed,
+    })))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FlushCacheQuery {
+    table: Option<String>,
+}
+
+pub async fn flush_cache(
+    AdminAuth(user): AdminAuth,
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<FlushCacheQuery>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let evicted = state
+        .database
+        .flush_cache(query.table.as_deref())
+        .await
+        .map_err(handle_database_error)?;
+
+    info!(
+        "{} flushed the cache ({}), evicting {evicted} entries",
+        user.username,
+        query.table.as_deref().unwrap_or("all tables")
+    );
+
+    Ok(Json(json!({"evicted": evicted})))
+}
+
 pub async fn get_username_valid(
     username: Path<String>,
     database: State<AppState>,
@@ -917,15 +1734,13 @@ pub async fn get_invite_valid(
     Path(invite_link): Path<Uuid>,
     State(database): State<AppState>,
 ) -> impl IntoResponse {
-    if database
+    match database
         .database
         .get_where::<InviteLink, _>("invite_token", invite_link, None)
         .await
-        .is_ok()
     {
-        Json(json!({"valid": true}))
-    } else {
-        Json(json!({"valid": false}))
+        Ok(invite_link) => Json(json!({"valid": true, "url": invite_link.url()})),
+        Err(_) => Json(json!({"valid": false})),
     }
 }
 
@@ -935,7 +1750,11 @@ pub async fn get_hostname_valid(
     State(state): State<AppState>,
     _: UserAuth,
 ) -> impl IntoResponse {
-    if state
+    if crate::database::objects::world::is_reserved_hostname(
+        &hostname,
+        &CONFIG.proxy.reserved_hostnames,
+        &CONFIG.proxy.hostname,
+    ) || state
         .database
         .get_where::<World, _>("hostname", hostname, None)
         .await
@@ -956,10 +1775,6 @@ fn bytes_to_image(bytes: &Bytes, format: ImageFormat) -> Result<DynamicImage, St
 
     reader.set_format(format);
 
-    let mut file =
-        std::fs::File::create(PathBuf::from_str("/home/mhanak/uploaded.png").unwrap()).unwrap();
-    file.write_all(bytes).unwrap();
-
     reader.limits(limits);
     let image = reader.decode().map_err(|err| {
         error!("{err}");
@@ -969,6 +1784,147 @@ fn bytes_to_image(bytes: &Bytes, format: ImageFormat) -> Result<DynamicImage, St
     Ok(image)
 }
 
+/// the size icons are stored in
+const ICON_SIZE: u32 = 256;
+/// the sizes icons can be requested in, see [`IconQuery`]
+const ICON_VARIANT_SIZES: [u32; 3] = [64, 128, ICON_SIZE];
+
+/// formats icons can be converted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconFormat {
+    Png,
+    Webp,
+}
+
+impl IconFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            IconFormat::Png => "png",
+            IconFormat::Webp => "webp",
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            IconFormat::Png => "image/png",
+            IconFormat::Webp => "image/webp",
+        }
+    }
+
+    fn image_format(self) -> ImageFormat {
+        match self {
+            IconFormat::Png => ImageFormat::Png,
+            IconFormat::Webp => ImageFormat::WebP,
+        }
+    }
+}
+
+/// the variant of an icon [`ApiIcon::get_icon`] returns. with only one of them set, the format defaults to webp
+/// and the size to the stored one. animated icons lose their animation
+#[derive(Debug, Clone, Deserialize)]
+pub struct IconQuery {
+    pub format: Option<IconFormat>,
+    /// one of [`ICON_VARIANT_SIZES`]
+    pub size: Option<u32>,
+}
+
+/// renders the icon at `source` as a `size` by `size` square in the format, written to `target`. it's written under
+/// another name first, so requests reading the variant at the same time never see half of it
+fn render_icon_variant(source: &std::path::Path, target: &std::path::Path, size: u32, format: IconFormat) -> color_eyre::Result<()> {
+    let image = crop_image_to_square(&image::open(source)?).resize_exact(size, size, FilterType::CatmullRom);
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let partial = target.with_extension(format!("{}.{}", format.extension(), Uuid::new_v4()));
+    let mut writer = BufWriter::new(std::fs::File::create(&partial)?);
+    let written = image
+        .write_to(&mut writer, format.image_format())
+        .map_err(color_eyre::Report::from)
+        .and_then(|()| Ok(writer.flush()?))
+        .and_then(|()| Ok(std::fs::rename(&partial, target)?));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    written
+}
+
+/// removes the cached variants of an icon, after it was replaced
+/// whether an icon was uploaded for the object
+pub(crate) fn has_icon(table: &str, id: Id) -> bool {
+    icon_path(table, id, "webp").exists() || icon_path(table, id, "gif").exists()
+}
+
+/// validates the image and stores it as the icon of the object, replacing the previous one. gifs are kept as they are,
+/// other images are cropped to a square and stored as webp. returns the cropped image, or [`None`] for a gif
+pub(crate) fn save_icon(
+    table: &str,
+    id: Id,
+    bytes: &Bytes,
+    image_format: ImageFormat,
+) -> Result<Option<DynamicImage>, StatusCode> {
+    let is_gif = match image_format {
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Bmp => false,
+        ImageFormat::Gif => true,
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let gif_path = icon_path(table, id, "gif");
+    let webp_path = icon_path(table, id, "webp");
+    if let Some(shard) = webp_path.parent() {
+        std::fs::create_dir_all(shard).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    if is_gif {
+        // i think this is to verify if the image is valid
+        let _ = bytes_to_image(bytes, image_format)?;
+
+        let image_file = std::fs::File::create(&gif_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let mut writer = BufWriter::new(image_file);
+
+        if webp_path.exists() {
+            std::fs::remove_file(&webp_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+
+        // after making sure the file is a valid image write the original, as we don't want to alter a gif
+        writer
+            .write_all(bytes.as_ref())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        remove_icon_variants(table, id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(None)
+    } else {
+        let image = bytes_to_image(bytes, image_format)?;
+        let image = crop_image_to_square(&image);
+
+        let resized = image.resize(ICON_SIZE, ICON_SIZE, FilterType::CatmullRom);
+
+        let image_file = std::fs::File::create(&webp_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        let mut writer = BufWriter::new(image_file);
+
+        if gif_path.exists() {
+            std::fs::remove_file(&gif_path).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+
+        resized
+            .write_to(&mut writer, ImageFormat::WebP)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        remove_icon_variants(table, id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(Some(image))
+    }
+}
+
+fn remove_icon_variants(table: &str, id: Id) -> std::io::Result<()> {
+    for size in ICON_VARIANT_SIZES {
+        for format in [IconFormat::Png, IconFormat::Webp] {
+            let path = icon_variant_path(table, id, size, format.extension());
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 fn crop_image_to_square(image: &DynamicImage) -> DynamicImage {
     let delta = image.width().abs_diff(image.height());
     if image.width() > image.height() {
@@ -979,3 +1935,233 @@ fn crop_image_to_square(image: &DynamicImage) -> DynamicImage {
 
 
 }
+
+#[test]
+fn session_cookie_test() {
+    let config = SessionCookieConfig {
+        secure: false,
+        same_site: SameSite::Lax,
+        domain: String::new(),
+    };
+    assert_eq!(
+        session_cookie(&config, "abc", 60),
+        "session-token=abc; Path=/api; HttpOnly; SameSite=Lax; Max-Age=60"
+    );
+
+    // SameSite=None is only accepted together with Secure
+    let config = SessionCookieConfig {
+        secure: false,
+        same_site: SameSite::None,
+        domain: String::from("example.net"),
+    };
+    assert_eq!(
+        session_cookie(&config, "abc", 60),
+        "session-token=abc; Path=/api; HttpOnly; SameSite=None; Max-Age=60; Secure; Domain=example.net"
+    );
+}
+
+#[test]
+fn clear_session_cookie_test() {
+    let config = SessionCookieConfig {
+        secure: true,
+        same_site: SameSite::Strict,
+        domain: String::from("example.net"),
+    };
+    assert_eq!(
+        clear_session_cookie(&config),
+        "session-token=; Path=/api; HttpOnly; SameSite=Strict; Max-Age=0; Secure; Domain=example.net"
+    );
+}
+
+#[test]
+fn icon_variant_test() {
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let source = dir.join("icon.webp");
+    DynamicImage::new_rgb8(300, 200).save_with_format(&source, ImageFormat::WebP).unwrap();
+
+    let target = dir.join("64").join("icon.png");
+    render_icon_variant(&source, &target, 64, IconFormat::Png).unwrap();
+    let variant = ImageReader::open(&target).unwrap().with_guessed_format().unwrap();
+    assert_eq!(variant.format(), Some(ImageFormat::Png));
+    let variant = variant.decode().unwrap();
+    assert_eq!((variant.width(), variant.height()), (64, 64));
+    // nothing is left behind besides the variant
+    assert_eq!(std::fs::read_dir(dir.join("64")).unwrap().count(), 1);
+
+    let query: IconQuery = serde_json::from_value(json!({"format": "png", "size": 64})).unwrap();
+    assert_eq!((query.format, query.size), (Some(IconFormat::Png), Some(64)));
+    assert!(serde_json::from_value::<IconQuery>(json!({"format": "bmp"})).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn etag_test() {
+    use crate::database::testing::test_group;
+
+    let group = Group {
+        id: Id::new_random(),
+        name: String::from("players"),
+        config_limits: (0..20)
+            .map(|limit| (format!("key-{limit}"), crate::minecraft::server::ServerConfigLimit::LessThan(limit)))
+            .collect(),
+        ..test_group()
+    };
+    // a map with the same entries in another order gets the same tag
+    let reordered = Group { config_limits: group.config_limits.clone().into_iter().collect(), ..group.clone() };
+    let tag = etag(&group);
+    assert_eq!(tag, etag(&reordered));
+    assert_ne!(tag, etag(&Group { name: String::from("admins"), ..group }));
+
+    assert!(matches_etag(&tag, &tag));
+    assert!(matches_etag(&format!("\"other\", {tag}"), &tag));
+    assert!(matches_etag("*", &tag));
+    assert!(!matches_etag(&format!("W/{tag}"), &tag));
+    assert!(!matches_etag("\"other\"", &tag));
+}
+
+#[test]
+fn parse_range_test() {
+    assert_eq!(parse_range(None, 100), RangeRequest::Full);
+    assert_eq!(parse_range(Some("bytes=10-19"), 100), RangeRequest::Partial(10, 19));
+    assert_eq!(parse_range(Some("bytes=90-"), 100), RangeRequest::Partial(90, 99));
+    assert_eq!(parse_range(Some("bytes=-10"), 100), RangeRequest::Partial(90, 99));
+    assert_eq!(parse_range(Some("bytes=-500"), 100), RangeRequest::Partial(0, 99));
+    // the end is cut to the file
+    assert_eq!(parse_range(Some("bytes=50-500"), 100), RangeRequest::Partial(50, 99));
+    assert_eq!(parse_range(Some("bytes=100-"), 100), RangeRequest::Unsatisfiable);
+    assert_eq!(parse_range(Some("bytes=-0"), 100), RangeRequest::Unsatisfiable);
+    assert_eq!(parse_range(Some("bytes=0-"), 0), RangeRequest::Unsatisfiable);
+    // not supported or not valid, so the whole file is sent
+    assert_eq!(parse_range(Some("bytes=0-1,5-6"), 100), RangeRequest::Full);
+    assert_eq!(parse_range(Some("items=0-1"), 100), RangeRequest::Full);
+    assert_eq!(parse_range(Some("bytes=20-10"), 100), RangeRequest::Full);
+    assert_eq!(parse_range(Some("bytes=a-b"), 100), RangeRequest::Full);
+}
+
+#[tokio::test]
+async fn stream_file_with_range_test() {
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("backup.tar.gz");
+    let contents: Vec<u8> = (0..1000u32).map(|byte| byte as u8).collect();
+    std::fs::write(&path, &contents).unwrap();
+
+    let headers = |range: Option<&str>| {
+        let mut headers = HeaderMap::new();
+        if let Some(range) = range {
+            headers.insert(header::RANGE, range.parse().unwrap());
+        }
+        headers
+    };
+    let body = |response: Response| async { axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap() };
+
+    let response = stream_file_with_range(&path, "application/gzip", &headers(None)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::ACCEPT_RANGES], "bytes");
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], "1000");
+    assert_eq!(body(response).await, contents);
+
+    // the middle of the file
+    let response = stream_file_with_range(&path, "application/gzip", &headers(Some("bytes=300-499"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 300-499/1000");
+    assert_eq!(response.headers()[header::CONTENT_LENGTH], "200");
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/gzip");
+    assert_eq!(body(response).await, contents[300..500]);
+
+    let response = stream_file_with_range(&path, "application/gzip", &headers(Some("bytes=1000-"))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes */1000");
+
+    // a validator this doesn't know about could be of an older version of the file
+    let mut if_range = headers(Some("bytes=300-499"));
+    if_range.insert(header::IF_RANGE, "\"old\"".parse().unwrap());
+    let response = stream_file_with_range(&path, "application/gzip", &if_range).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    assert_eq!(
+        stream_file_with_range(&dir.join("missing"), "application/gzip", &headers(None)).await.unwrap_err(),
+        StatusCode::NOT_FOUND
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn remove_if_match_test() {
+    use crate::database::DatabaseError;
+    use crate::database::testing::{test_group, test_state};
+
+    let database = crate::database::Database::memory().await;
+    let state = test_state(database.clone());
+
+    let admins = Group { id: Id::new_random(), name: String::from("admins"), is_privileged: true, ..test_group() };
+    database.insert(&admins, None).await.unwrap();
+    let admin = database
+        .create_user_from(User { username: String::from("admin"), group_id: admins.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+    let group = Group { id: Id::new_random(), name: String::from("players"), ..test_group() };
+    database.insert(&group, None).await.unwrap();
+    let tag = etag(&group);
+
+    let headers = |tag: &str| HeaderMap::from_iter([(header::IF_MATCH, tag.parse().unwrap())]);
+    // changed after the tag was taken
+    let renamed = Group { name: String::from("renamed"), ..group.clone() };
+    database.update(&renamed, None).await.unwrap();
+    let status = Group::api_remove(Path(group.id), State(state.clone()), UserAuth(admin.clone()), headers(&tag)).await;
+    assert_eq!(status, Err(StatusCode::PRECONDITION_FAILED));
+    assert!(database.get_one::<Group>(group.id, None).await.is_ok());
+
+    // the stored object is compared again when it's removed, in case it changed after the first check
+    let result = database.remove_unchanged(&renamed, None, |_| false).await;
+    assert!(matches!(result, Err(DatabaseError::PreconditionFailed)));
+    assert!(database.get_one::<Group>(group.id, None).await.is_ok());
+
+    let status = Group::api_remove(Path(group.id), State(state.clone()), UserAuth(admin), headers(&etag(&renamed))).await;
+    assert_eq!(status, Ok(StatusCode::NO_CONTENT));
+    assert!(matches!(database.get_one::<Group>(group.id, None).await, Err(DatabaseError::NotFound)));
+}
+
+#[tokio::test]
+async fn idempotency_key_test() {
+    use crate::database::testing::{test_group, test_state};
+
+    let database = crate::database::Database::memory().await;
+    let mut state = test_state(database.clone());
+    state.idempotency_keys = moka::future::Cache::new(16);
+
+    let admins = Group { id: Id::new_random(), name: String::from("admins"), is_privileged: true, ..test_group() };
+    database.insert(&admins, None).await.unwrap();
+    let admin = database
+        .create_user_from(User { username: String::from("admin"), group_id: admins.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+
+    let create = |key: &str, body: serde_json::Value| {
+        let headers = HeaderMap::from_iter([(header::HeaderName::from_static("idempotency-key"), key.parse().unwrap())]);
+        InviteLink::api_create(
+            axum::extract::Query(RecursiveQuery { recursive: None }),
+            State(state.clone()),
+            UserAuth(admin.clone()),
+            headers,
+            Json(body),
+        )
+    };
+    let body = |response: Response| async { axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap() };
+
+    let first = body(create("first", json!({})).await.unwrap().into_response()).await;
+    // repeated with the same body, it gets the same response without creating another link
+    let repeated = body(create("first", json!({})).await.unwrap().into_response()).await;
+    assert_eq!(first, repeated);
+    assert_eq!(database.count::<InviteLink>().await.unwrap(), 1);
+
+    // the same key with another body is rejected instead of answered with the first response
+    let result = create("first", json!({"note": "another"})).await;
+    assert_eq!(result.err(), Some(StatusCode::UNPROCESSABLE_ENTITY));
+    assert_eq!(database.count::<InviteLink>().await.unwrap(), 1);
+
+    let other = body(create("second", json!({"note": "another"})).await.unwrap().into_response()).await;
+    assert_ne!(first, other);
+    assert_eq!(database.count::<InviteLink>().await.unwrap(), 2);
+}
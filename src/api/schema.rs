@@ -0,0 +1,240 @@
+use crate::config::CONFIG;
+use crate::database::ValueType;
+use crate::database::objects::{
+    DbObject, Group, InviteLink, Mod, ModLoader, Session, User, Version, World, WorldShare,
+};
+use axum::body::Bytes;
+use axum::http::header;
+use axum::response::IntoResponse;
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value, json};
+
+/// the JSON Schema of a value stored in a column of the type
+pub fn value_schema(value_type: &ValueType) -> Value {
+    match value_type {
+        ValueType::Integer | ValueType::BigInteger => json!({"type": "integer"}),
+        ValueType::Float => json!({"type": "number"}),
+        ValueType::Text => json!({"type": "string"}),
+        ValueType::Boolean => json!({"type": "boolean"}),
+        ValueType::Blob => json!({"type": "string", "contentEncoding": "base64"}),
+        ValueType::Id => json!({"type": "string", "pattern": "^[A-Za-z0-9_-]{8}$"}),
+        ValueType::Token => json!({"type": "string", "format": "uuid"}),
+        ValueType::Datetime => json!({"type": "string", "format": "date-time"}),
+        ValueType::Enum(values) => json!({"type": "string", "enum": values}),
+    }
+}
+
+/// the JSON Schema of the object, made from its [`DbObject::columns`]. hidden columns are left out, as they're never
+/// sent to clients, and the id is read-only
+pub fn object_schema<T: DbObject>() -> Value {
+    let columns = T::columns();
+    let id_column = columns[T::id_column_index()].name;
+
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for column in columns.iter().filter(|column| !column.hidden) {
+        let mut schema = value_schema(&column.data_type);
+        if column.nullable {
+            schema["type"] = json!([schema["type"], "null"]);
+        }
+        if column.name == id_column {
+            schema["readOnly"] = json!(true);
+        }
+        if let Some(comment) = column.comment {
+            schema["description"] = json!(comment);
+        }
+        properties.insert(column.name.to_string(), schema);
+        required.push(column.name);
+    }
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+    })
+}
+
+/// the name the schema of the object is listed under in `components/schemas`
+fn schema_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// the routes every [`crate::api::handlers::ApiObject`] is mounted with, see [`crate::api::handlers`]. objects that
+/// can't be changed after they're created don't have `PATCH /{id}`
+fn object_paths<T: DbObject>(paths: &mut Map<String, Value>, updatable: bool) {
+    let name = schema_name::<T>();
+    let table = T::table_name();
+    let object = json!({"$ref": format!("#/components/schemas/{name}")});
+    let response = |description: &str, schema: &Value| {
+        json!({"description": description, "content": {"application/json": {"schema": schema}}})
+    };
+    let id = json!({"$ref": "#/components/parameters/id"});
+    let recursive = json!({"$ref": "#/components/parameters/recursive"});
+
+    let mut list_parameters = vec![
+        json!({"$ref": "#/components/parameters/page"}),
+        json!({"$ref": "#/components/parameters/limit"}),
+        recursive.clone(),
+    ];
+    if !T::searchable_columns().is_empty() {
+        list_parameters.push(json!({
+            "name": "q",
+            "in": "query",
+            "description": format!("searches {}", T::searchable_columns().join(", ")),
+            "schema": {"type": "string"},
+        }));
+    }
+    for column in T::columns().iter().filter(|column| !column.hidden) {
+        list_parameters.push(json!({
+            "name": column.name,
+            "in": "query",
+            "description": "only returns the objects with this value. prefix it with !, <, <=, > or >= to compare \
+                instead, `null` matches missing values",
+            "schema": {"type": "string"},
+        }));
+    }
+
+    paths.insert(
+        format!("/{table}"),
+        json!({
+            "get": {
+                "summary": format!("list {table}"),
+                "parameters": list_parameters,
+                "responses": {"200": response("a page of the objects", &json!({"type": "array", "items": object}))},
+            },
+            "post": {
+                "summary": format!("create one of the {table}"),
+                "description": "fields the server sets itself are ignored. repeating a request with the same \
+                    Idempotency-Key returns the object created by the first one",
+                "parameters": [
+                    recursive,
+                    {"name": "Idempotency-Key", "in": "header", "schema": {"type": "string", "maxLength": 255}},
+                ],
+                "requestBody": {"content": {"application/json": {"schema": object}}},
+                "responses": {"200": response("the created object", &object)},
+            },
+        }),
+    );
+
+    let mut single = json!({
+        "parameters": [id],
+        "get": {
+            "summary": format!("get one of the {table}"),
+            "parameters": [recursive],
+            "responses": {"200": response("the object", &object), "404": {"description": "not found"}},
+        },
+        "delete": {
+            "summary": format!("remove one of the {table}"),
+            "responses": {"204": {"description": "removed"}, "404": {"description": "not found"}},
+        },
+    });
+    if updatable {
+        single["patch"] = json!({
+            "summary": format!("update one of the {table}"),
+            "description": "applied like a JSON merge patch: missing fields are left alone and `null` clears a field",
+            "parameters": [recursive],
+            "requestBody": {"content": {"application/json": {"schema": object}}},
+            "responses": {"200": response("the updated object", &object), "404": {"description": "not found"}},
+        });
+    }
+    paths.insert(format!("/{table}/{{id}}"), single);
+
+    paths.insert(
+        format!("/{table}/delete"),
+        json!({
+            "post": {
+                "summary": format!("remove many of the {table}"),
+                "requestBody": {"content": {"application/json": {"schema": {
+                    "type": "array",
+                    "items": value_schema(&ValueType::Id),
+                }}}},
+                "responses": {"200": {"description": "the status of every removal, in the order of the ids"}},
+            },
+        }),
+    );
+}
+
+/// an OpenAPI document describing the routes of the objects, made from their columns
+pub fn api_schema() -> Value {
+    let mut schemas = Map::new();
+    let mut paths = Map::new();
+    macro_rules! object {
+        ($object:ty, $updatable:expr) => {
+            schemas.insert(schema_name::<$object>().to_string(), object_schema::<$object>());
+            object_paths::<$object>(&mut paths, $updatable);
+        };
+    }
+    object!(Mod, true);
+    paths["/mods"]["get"]["parameters"].as_array_mut().expect("the list has parameters").push(json!({
+        "name": "compatible_with",
+        "in": "query",
+        "description": "only returns the mods for the Minecraft version and mod loader of this world",
+        "schema": value_schema(&ValueType::Id),
+    }));
+    object!(Version, true);
+    object!(ModLoader, true);
+    object!(World, true);
+    object!(WorldShare, false);
+    object!(Group, true);
+    object!(User, true);
+    object!(Session, false);
+    object!(InviteLink, false);
+
+    json!({
+        "openapi": "3.1.0",
+        "info": {"title": CONFIG.info.name, "version": env!("CARGO_PKG_VERSION")},
+        "servers": [{"url": "/api"}],
+        "paths": paths,
+        "components": {
+            "schemas": schemas,
+            "parameters": {
+                "id": {"name": "id", "in": "path", "required": true, "schema": value_schema(&ValueType::Id)},
+                "page": {"name": "page", "in": "query", "schema": {"type": "integer", "minimum": 0}},
+                "limit": {
+                    "name": "limit",
+                    "in": "query",
+                    "schema": {"type": "integer", "minimum": 1, "maximum": CONFIG.pagination.max_limit},
+                },
+                "recursive": {
+                    "name": "recursive",
+                    "in": "query",
+                    "description": "replaces the ids of related objects with the objects",
+                    "schema": {"type": "boolean"},
+                },
+            },
+        },
+    })
+}
+
+static API_SCHEMA: Lazy<Bytes> =
+    Lazy::new(|| Bytes::from(serde_json::to_vec(&api_schema()).expect("serialization failed")));
+
+/// `GET /api/schema`, see [`api_schema`]
+#[allow(clippy::unused_async)]
+pub async fn get_api_schema() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], API_SCHEMA.clone())
+}
+
+#[test]
+fn api_schema_test() {
+    let schema = object_schema::<Session>();
+    // the token is hidden
+    assert!(schema["properties"].get("token").is_none());
+    assert_eq!(schema["properties"]["id"]["readOnly"], json!(true));
+    assert_eq!(schema["properties"]["created"]["format"], "date-time");
+    assert_eq!(schema["properties"]["user_id"]["type"], json!(["string", "null"]));
+    assert_eq!(schema["properties"]["expires"]["type"], "boolean");
+
+    let version = object_schema::<Version>();
+    assert!(version["properties"]["kind"]["enum"].as_array().unwrap().contains(&json!("release")));
+
+    let document = api_schema();
+    assert_eq!(document["components"]["schemas"]["World"]["type"], "object");
+    assert!(document["paths"]["/worlds/{id}"].get("patch").is_some());
+    assert!(document["paths"]["/sessions/{id}"].get("patch").is_none());
+    let list = document["paths"]["/worlds"]["get"]["parameters"].as_array().unwrap();
+    assert!(list.iter().any(|parameter| parameter["name"] == "hostname"));
+    let list = document["paths"]["/mods"]["get"]["parameters"].as_array().unwrap();
+    assert!(list.iter().any(|parameter| parameter["name"] == "compatible_with"));
+}
@@ -2,15 +2,113 @@ use std::sync::{Arc};
 use log::{debug, error};
 use serde::{Deserialize, Serialize};
 use socketioxide::extract::{Data, SocketRef, State};
-use tokio::sync::Mutex;
+use socketioxide::{SendError, SocketError};
+use tokio::sync::{Mutex, broadcast};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
+use crate::api::client_ip::ClientIp;
 use crate::api::serve::AppState;
-use crate::database::objects::{Session, User};
+use crate::config::CONFIG;
+use crate::database::objects::world_share::SharePermission;
+use crate::database::objects::{Session, User, World, WorldShare};
 use crate::database::types::Id;
+use crate::minecraft::server::{McStdout, next_output};
 
 #[derive(Debug, Deserialize)]
 pub struct ConnectValues{
-    ticket: Uuid,
+    ticket: Option<Uuid>,
+    /// a [`WorldShare`] token, used instead of the ticket
+    share: Option<Uuid>,
+}
+
+/// who is connected to the console
+#[derive(Debug, Clone)]
+enum ConsoleAuth {
+    User(User),
+    Share(WorldShare),
+}
+
+impl ConsoleAuth {
+    /// whether the console `command` can be run in the world. shares are limited by the world owner's group
+    async fn can_run_command(&self, state: &AppState, world: &World, command: &str) -> bool {
+        let group = match self {
+            ConsoleAuth::User(user) => user.group(state.database.clone(), None).await,
+            ConsoleAuth::Share(_) => {
+                match state.database.get_one::<User>(world.owner_id, None).await {
+                    Ok(owner) => {
+                        // sharing a world doesn't share the owner's privileges
+                        let mut group = owner.group(state.database.clone(), None).await;
+                        group.is_privileged = false;
+                        group
+                    }
+                    Err(err) => {
+                        error!("could not get the owner of world {}: {err}", world.id);
+                        return false;
+                    }
+                }
+            }
+        };
+        group.can_run_command(command)
+    }
+
+
+    async fn world(&self, state: &AppState, id: Id, permission: SharePermission) -> Option<World> {
+        match self {
+            ConsoleAuth::User(user) => state
+                .database
+                .get_one(id, Some((user, &user.group(state.database.clone(), None).await)))
+                .await
+                .ok(),
+            ConsoleAuth::Share(share) => {
+                if share.grants(id, permission) {
+                    state.database.get_one(id, None).await.ok()
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// the task forwarding a server's console output to a socket, which ends when this is dropped
+struct Subscription(JoinHandle<()>);
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// emits the console output to the socket until the server is dropped or the socket is closed. the messages that don't
+/// fit in the socket's buffer, because the client doesn't read them fast enough, are dropped and the client is sent a
+/// [`McStdout::Lagged`] with how many once there is room again
+async fn forward(socket: SocketRef, mut stdout: broadcast::Receiver<McStdout>) {
+    let mut skipped = 0;
+    while let Some(message) = next_output(&mut stdout).await {
+        if skipped > 0 {
+            match socket.emit("console", &McStdout::Lagged { skipped }) {
+                Ok(()) => skipped = 0,
+                Err(SendError::Socket(SocketError::InternalChannelFull)) => {}
+                Err(_) => return,
+            }
+        }
+        let message = match message {
+            McStdout::Lagged { skipped: lagged } => {
+                skipped += lagged;
+                continue;
+            }
+            message => message,
+        };
+        if skipped > 0 {
+            skipped += 1;
+            continue;
+        }
+        match socket.emit("console", &message) {
+            Ok(()) => {}
+            Err(SendError::Socket(SocketError::InternalChannelFull)) => skipped += 1,
+            Err(_) => return,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -25,6 +123,13 @@ pub enum ConnectionStatus {
 pub enum ConnectionError {
     NotFound,
     InvalidTicket,
+    Forbidden,
+    /// the command can't be run by the user, because of their group's console command limits
+    CommandNotAllowed(String),
+    /// the client or the user has too many connections open, see [`crate::config::StreamLimits`]
+    TooManyConnections,
+    /// the world's server couldn't be loaded
+    ServerUnavailable,
 }
 
 pub async fn console_socketio(
@@ -34,23 +139,64 @@ pub async fn console_socketio(
 ) {
     socket.emit("hello", "hiiii").expect("TODO: panic message");
 
-    let session_id = if let Some(session_id) = state.console_tickets.get(&data.ticket).await {
-        session_id
+    let auth = if let Some(share) = data.share {
+        if let Ok(share) = WorldShare::from_token(&state.database, share).await {
+            ConsoleAuth::Share(share)
+        } else {
+            _ = socket.emit("error", &ConnectionError::InvalidTicket);
+            socket.disconnect().expect("could not close socket");
+            return;
+        }
     } else {
-        _ = socket.emit("error", &ConnectionError::InvalidTicket);
-        socket.disconnect().expect("could not close socket");
-        return;
+        let session_id = if let Some(ticket) = data.ticket
+            && let Some(session_id) = state.console_tickets.get(&ticket).await
+        {
+            session_id
+        } else {
+            _ = socket.emit("error", &ConnectionError::InvalidTicket);
+            socket.disconnect().expect("could not close socket");
+            return;
+        };
+
+        let session = if let Ok(session) = state.database.get_one::<Session>(session_id, None).await {
+            session
+        } else {
+            _ = socket.emit("error", &ConnectionError::InvalidTicket);
+            socket.disconnect().expect("could not close socket");
+            return;
+        };
+
+        let user = match state.database.get_one::<User>(session.user_id, None).await {
+            Ok(user) => user,
+            Err(err) => {
+                error!("could not get the user of session {}: {err}", session.id);
+                _ = socket.emit("error", &ConnectionError::InvalidTicket);
+                socket.disconnect().expect("could not close socket");
+                return;
+            }
+        };
+        ConsoleAuth::User(user)
     };
 
-    let session = if let Ok(session) = state.database.get_one::<Session>(session_id, None).await {
-        session
-    } else {
-        _ = socket.emit("error", &ConnectionError::InvalidTicket);
+    // the connection is added to every request, socket.io's handshake included
+    let Some(ClientIp(ip)) = socket.req_parts().extensions.get::<ClientIp>().copied() else {
+        error!("the console connection has no client address");
         socket.disconnect().expect("could not close socket");
         return;
     };
-
-    let user: User = state.database.get_one(session.user_id, None).await.expect("user not found");
+    let user_id = match &auth {
+        ConsoleAuth::User(user) => Some(user.id),
+        ConsoleAuth::Share(_) => None,
+    };
+    let slot = match state.streams.open(ip, user_id, &CONFIG.http.streams) {
+        Ok(slot) => Arc::new(Mutex::new(Some(slot))),
+        Err(err) => {
+            debug!("rejected a console connection from {ip}: {err}");
+            _ = socket.emit("error", &ConnectionError::TooManyConnections);
+            socket.disconnect().expect("could not close socket");
+            return;
+        }
+    };
 
     let connected = Arc::new(Mutex::new(None));
     #[derive(Deserialize)]
@@ -61,23 +207,22 @@ pub async fn console_socketio(
     socket.on("subscribe", {
         let send_task = connected.clone();
         let state = state.clone();
-        let user = user.clone();
+        let auth = auth.clone();
         async move |s: SocketRef, Data::<SubscribeData>(data)| {
             let id = data.id;
             debug!("SocketIO subscribe: {id}");
-            if let Ok(world) = state.database.get_one(id, Some((&user, &user.group(state.database.clone(), None).await))).await {
-                let server = state.servers.get_or_create_server(&world).await.expect("could not get server");
-                let mut stdout = server.lock().await.stdout();
-                send_task.lock().await.replace((tokio::task::spawn_blocking({
-                    let s = s.clone();
-                    move || {
-                        while let Ok(log) = stdout.blocking_recv() {
-                            if s.emit("console", &log).is_err() {
-                                return;
-                            }
-                        }
+            if let Some(world) = auth.world(&state, id, SharePermission::Console).await {
+                let server = match state.servers.get_or_create_server(&world).await {
+                    Ok(server) => server,
+                    Err(err) => {
+                        error!("could not get the server of world {id}: {err}");
+                        _ = s.emit("error", &ConnectionError::ServerUnavailable);
+                        return;
                     }
-                }), id));
+                };
+                let stdout = server.lock().await.stdout();
+                // replacing the subscription ends the previous one
+                send_task.lock().await.replace((Subscription(tokio::task::spawn(forward(s.clone(), stdout))), id));
                 let _ = s.emit("status", &ConnectionStatus::Connected(id));
             } else {
                 _ = s.emit("error", &ConnectionError::NotFound);
@@ -85,6 +230,15 @@ pub async fn console_socketio(
         }
     });
 
+    // the forwarding task holds the socket, so it has to be ended for the socket to be dropped
+    socket.on_disconnect({
+        let connected = connected.clone();
+        async move || {
+            connected.lock().await.take();
+            slot.lock().await.take();
+        }
+    });
+
     socket.on("unsubscribe", {
         let connected = connected.clone();
         async move |s: SocketRef| {
@@ -103,11 +257,25 @@ pub async fn console_socketio(
             let command = data.command;
             debug!("SocketIO command: {command}");
             if let Some((_, id)) = *connected.lock().await {
-                if let Ok(world) = state.database.get_one(id, Some((&user, &user.group(state.database.clone(), None).await))).await {
-                    let server = state.servers.get_or_create_server(&world).await.expect("could not get server");
+                if let Some(world) = auth.world(&state, id, SharePermission::Command).await {
+                    if !auth.can_run_command(&state, &world, &command).await {
+                        let name = crate::minecraft::util::command_name(&command).to_string();
+                        _ = s.emit("error", &ConnectionError::CommandNotAllowed(name));
+                        return;
+                    }
+                    let server = match state.servers.get_or_create_server(&world).await {
+                        Ok(server) => server,
+                        Err(err) => {
+                            error!("could not get the server of world {id}: {err}");
+                            _ = s.emit("error", &ConnectionError::ServerUnavailable);
+                            return;
+                        }
+                    };
                     if let Err(err) = server.lock().await.write_console(format!("{command}\n")).await {
                         error!("{err}")
                     }
+                } else {
+                    _ = s.emit("error", &ConnectionError::Forbidden);
                 }
             } else {
                 let _ = s.emit("status", &ConnectionStatus::Disconnected);
@@ -0,0 +1,125 @@
+//! limits how many streaming connections, like the console sockets, a client and a user can hold open at once, see
+//! [`crate::config::StreamLimits`]
+
+use crate::config::StreamLimits;
+use crate::database::types::Id;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+/// who a stream is counted against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum StreamOwner {
+    Ip(IpAddr),
+    User(Id),
+}
+
+/// the streaming connections open right now, by client address and by user
+#[derive(Debug, Clone, Default)]
+pub struct ActiveStreams(Arc<Mutex<HashMap<StreamOwner, usize>>>);
+
+/// why a stream couldn't be opened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamLimitError {
+    /// the client address has [`StreamLimits::max_per_ip`] streams open
+    Ip,
+    /// the user has [`StreamLimits::max_per_user`] streams open
+    User,
+}
+
+impl Display for StreamLimitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamLimitError::Ip => write!(f, "too many connections are open from this address"),
+            StreamLimitError::User => write!(f, "too many connections are open for this user"),
+        }
+    }
+}
+
+impl ActiveStreams {
+    /// takes a slot for a stream of the client at `ip`, and of the `user` if it's known, unless either of them has
+    /// all theirs in use. the slot is given back when the [`StreamSlot`] is dropped, so it has to live as long as the
+    /// connection
+    pub fn open(&self, ip: IpAddr, user: Option<Id>, limits: &StreamLimits) -> Result<StreamSlot, StreamLimitError> {
+        let mut counts = self.0.lock().expect("stream counter lock poisoned");
+        if counts.get(&StreamOwner::Ip(ip)).copied().unwrap_or(0) >= limits.max_per_ip {
+            return Err(StreamLimitError::Ip);
+        }
+        if let Some(user) = user
+            && counts.get(&StreamOwner::User(user)).copied().unwrap_or(0) >= limits.max_per_user
+        {
+            return Err(StreamLimitError::User);
+        }
+
+        let owners: Vec<StreamOwner> = std::iter::once(StreamOwner::Ip(ip))
+            .chain(user.map(StreamOwner::User))
+            .collect();
+        for owner in &owners {
+            *counts.entry(*owner).or_default() += 1;
+        }
+        Ok(StreamSlot {
+            streams: self.clone(),
+            owners,
+        })
+    }
+
+    /// how many streams the client at `ip` has open
+    pub fn count_ip(&self, ip: IpAddr) -> usize {
+        let counts = self.0.lock().expect("stream counter lock poisoned");
+        counts.get(&StreamOwner::Ip(ip)).copied().unwrap_or(0)
+    }
+}
+
+/// an open stream, counted in [`ActiveStreams`] until it's dropped
+#[derive(Debug)]
+pub struct StreamSlot {
+    streams: ActiveStreams,
+    owners: Vec<StreamOwner>,
+}
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        let mut counts = self.streams.0.lock().expect("stream counter lock poisoned");
+        for owner in &self.owners {
+            if let Some(count) = counts.get_mut(owner) {
+                *count -= 1;
+                if *count == 0 {
+                    counts.remove(owner);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn stream_limit_test() {
+    let limits = StreamLimits {
+        max_per_ip: 3,
+        max_per_user: 2,
+    };
+    let streams = ActiveStreams::default();
+    let (a, b) = (IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]));
+    let user = Id::new_random();
+
+    // anonymous streams, like the ones of world shares, are only limited by the address
+    let mut slots: Vec<StreamSlot> = (0..3).map(|_| streams.open(a, None, &limits).unwrap()).collect();
+    assert_eq!(streams.open(a, None, &limits).unwrap_err(), StreamLimitError::Ip);
+    assert_eq!(streams.count_ip(a), 3);
+    // closing one frees its slot
+    slots.pop();
+    let _slot = streams.open(a, None, &limits).unwrap();
+    assert!(streams.open(b, None, &limits).is_ok());
+
+    // a user is limited across addresses
+    slots.clear();
+    let _first = streams.open(a, Some(user), &limits).unwrap();
+    let second = streams.open(b, Some(user), &limits).unwrap();
+    assert_eq!(streams.open(b, Some(user), &limits).unwrap_err(), StreamLimitError::User);
+    // a rejected stream doesn't take a slot
+    assert_eq!(streams.count_ip(b), 1);
+    drop(second);
+    assert_eq!(streams.count_ip(b), 0);
+    assert!(streams.open(b, Some(user), &limits).is_ok());
+    assert!(streams.0.lock().unwrap().get(&StreamOwner::User(user)).is_some_and(|count| *count == 1));
+}
@@ -0,0 +1,159 @@
+//! per-request timings, reported to the client in the `Server-Timing` header
+
+use axum::extract::Request;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use log::debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+tokio::task_local! {
+    static TIMINGS: Arc<Timings>;
+}
+
+/// time spent on a request, accumulated while it is handled
+#[derive(Debug, Default)]
+pub struct Timings {
+    /// time spent waiting for database queries, in nanoseconds
+    database: AtomicU64,
+    /// number of database queries
+    queries: AtomicU64,
+}
+
+impl Timings {
+    pub fn database(&self) -> Duration {
+        Duration::from_nanos(self.database.load(Ordering::Relaxed))
+    }
+
+    pub fn queries(&self) -> u64 {
+        self.queries.load(Ordering::Relaxed)
+    }
+
+    /// the `Server-Timing` header value, like `db;dur=12.3;desc="4 queries", total;dur=30.1`
+    fn header(&self, total: Duration) -> String {
+        format!(
+            "db;dur={:.1};desc=\"{} queries\", total;dur={:.1}",
+            self.database().as_secs_f64() * 1000.0,
+            self.queries(),
+            total.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// adds the time of a database query to the timings of the request being handled. does nothing outside of a request
+pub fn record_query(duration: Duration) {
+    let _ = TIMINGS.try_with(|timings| {
+        timings.database.fetch_add(
+            u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        timings.queries.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+/// a future whose duration is recorded with [`record_query`]
+pub trait TimedQuery: Future + Sized {
+    fn timed(self) -> impl Future<Output = Self::Output> {
+        async move {
+            let start = Instant::now();
+            let output = self.await;
+            record_query(start.elapsed());
+            output
+        }
+    }
+}
+
+impl<F: Future> TimedQuery for F {}
+
+/// middleware collecting the [`Timings`] of a request. they are available to handlers in the request extensions.
+/// the number of queries is logged, so requests fanning out into a query per object stand out. queries made by tasks
+/// the handler spawns aren't counted, as they don't see the request's timings
+pub async fn server_timing(mut request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let timings = Arc::new(Timings::default());
+    request.extensions_mut().insert(timings.clone());
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    let mut response = TIMINGS.scope(timings.clone(), next.run(request)).await;
+
+    debug!(
+        "{method} {path} made {} database queries taking {:.1}ms",
+        timings.queries(),
+        timings.database().as_secs_f64() * 1000.0
+    );
+    if let Ok(value) = HeaderValue::from_str(&timings.header(start.elapsed())) {
+        response.headers_mut().insert("server-timing", value);
+    }
+    response
+}
+
+#[test]
+fn server_timing_test() {
+    let timings = Timings::default();
+    TIMINGS.sync_scope(Arc::new(Timings::default()), || {
+        record_query(Duration::from_micros(1500));
+        record_query(Duration::from_micros(500));
+        TIMINGS.with(|scoped| {
+            assert_eq!(scoped.database(), Duration::from_millis(2));
+            assert_eq!(scoped.queries(), 2);
+        });
+    });
+    // outside of a request nothing is recorded
+    record_query(Duration::from_secs(1));
+    assert_eq!(
+        timings.header(Duration::from_micros(30_100)),
+        "db;dur=0.0;desc=\"0 queries\", total;dur=30.1"
+    );
+}
+
+#[tokio::test]
+async fn server_timing_middleware_test() {
+    use crate::database::objects::Group;
+    use axum::Router;
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use tower::ServiceExt;
+
+    let database = crate::database::Database::memory().await;
+    let app = Router::new()
+        .route(
+            "/query",
+            get(move || async move {
+                database.get_all::<Group>(None).await.unwrap();
+                // a task the handler spawns doesn't see the request's timings
+                let spawned = database.clone();
+                tokio::spawn(async move { spawned.get_all::<Group>(None).await })
+                    .await
+                    .unwrap()
+                    .unwrap();
+                "ok"
+            }),
+        )
+        .route("/fail", get(|| async { StatusCode::INTERNAL_SERVER_ERROR }))
+        .layer(axum::middleware::from_fn(server_timing));
+    let request = |uri: &str| {
+        let app = app.clone();
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        async move { app.oneshot(request).await.unwrap() }
+    };
+    let header = |response: &Response| {
+        response.headers()["server-timing"].to_str().unwrap().to_string()
+    };
+
+    let response = request("/query").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let timing = header(&response);
+    assert!(timing.starts_with("db;dur=") && timing.contains(";desc=\"1 queries\", total;dur="), "{timing}");
+
+    // responses that aren't successful get it too
+    let response = request("/fail").await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(header(&response).contains("desc=\"0 queries\""));
+    let response = request("/missing").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(header(&response).contains("desc=\"0 queries\""));
+}
@@ -1,9 +1,15 @@
-use crate::database::types::Id;
+use crate::database::types::{Id, Memory, TimeWindow};
 use crate::util;
 use log::debug;
 use once_cell::sync::Lazy;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+use std::num::NonZeroU64;
 use std::ops::Range;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
@@ -11,14 +17,91 @@ pub struct Config {
     pub listen_port: u16,
     pub api_rate_limit: f32,
     pub require_invite_to_register: bool,
+    /// where the frontend is served, for building the links that are shared, like the ones of invites. [`None`] leaves
+    /// it to the frontend
+    #[serde(default)]
+    pub public_url: Option<PublicUrl>,
+    pub http: HttpConfig,
+    /// serve the api over HTTPS. [`None`] serves it over plain HTTP
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    pub pagination: PaginationConfig,
     pub info: FrontendInfo,
+    pub session_cookie: SessionCookieConfig,
+    pub sessions: SessionConfig,
     pub database: DatabaseConfig,
     pub minecraft_server_type: ServerType,
     pub remote: RemoteConfig,
     pub world: WorldConfig,
+    #[serde(default)]
+    pub instance: InstanceLimits,
+    pub backups: BackupConfig,
+    pub uploads: UploadConfig,
     pub user_defaults: UserDefaults,
     pub world_defaults: WorldDefaults,
     pub proxy: ProxyConfig,
+    #[serde(default)]
+    pub accounts: AccountConfig,
+    #[serde(default)]
+    pub modrinth: ModrinthConfig,
+}
+
+/// timeouts of the connections to the api server, see [`crate::api::serve::run`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpConfig {
+    /// how long a client has to send the headers of a request, in seconds. also how long an idle connection is kept
+    pub header_read_timeout: u64,
+    /// whether connections are reused for multiple requests
+    pub keep_alive: bool,
+    /// how long the body of a request can go without any data arriving, in seconds
+    pub body_read_timeout: u64,
+    /// how long a request can take to be answered, in seconds. [`None`] doesn't limit it
+    #[serde(default)]
+    pub request_timeout: Option<u64>,
+    /// the reverse proxies the api is behind. the client address is read from the forwarding headers of requests
+    /// coming from them, see [`crate::api::client_ip::client_ip`]
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    #[serde(default)]
+    pub streams: StreamLimits,
+}
+
+/// how many streaming connections, like the console sockets, can be open at once, see
+/// [`crate::api::streams::ActiveStreams`]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StreamLimits {
+    /// per client address
+    pub max_per_ip: usize,
+    /// per user, across addresses. connections through world shares only count toward [`StreamLimits::max_per_ip`]
+    pub max_per_user: usize,
+}
+
+impl Default for StreamLimits {
+    fn default() -> Self {
+        Self {
+            max_per_ip: 20,
+            max_per_user: 10,
+        }
+    }
+}
+
+/// the certificate the api is served over HTTPS with, see [`crate::api::serve::load_tls_config`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain, starting with the server's certificate
+    pub cert: PathBuf,
+    /// PEM file with the private key of the certificate
+    pub key: PathBuf,
+}
+
+/// how many objects the list endpoints return per page, see [`crate::api::handlers::PaginationQuery`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct PaginationConfig {
+    /// used when the request doesn't have a `limit`
+    pub default_limit: u32,
+    /// larger `limit`s are lowered to it
+    pub max_limit: u32,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -26,7 +109,137 @@ pub struct FrontendInfo {
     pub name: String,
     pub login_message: String,
     pub login_message_title: String,
-    pub login_message_type: String,
+    pub login_message_type: LoginMessageType,
+    /// how long clients can cache `/api/server`, in seconds
+    pub cache_max_age: u64,
+}
+
+/// How the login message is displayed by the frontend
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginMessageType {
+    Info,
+    Warning,
+    Error,
+    /// same as [`LoginMessageType::Info`], but without the icon
+    None,
+}
+
+impl Display for LoginMessageType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoginMessageType::Info => f.write_str("info"),
+            LoginMessageType::Warning => f.write_str("warning"),
+            LoginMessageType::Error => f.write_str("error"),
+            LoginMessageType::None => f.write_str("none"),
+        }
+    }
+}
+
+impl FromStr for LoginMessageType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "info" => Ok(LoginMessageType::Info),
+            "warning" | "warn" => Ok(LoginMessageType::Warning),
+            "error" => Ok(LoginMessageType::Error),
+            "none" | "" => Ok(LoginMessageType::None),
+            _ => Err(format!(
+                "invalid login_message_type \"{s}\", expected one of: info, warning, error, none"
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LoginMessageType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// the address of the frontend, an HTTP(S) URL that pages can be appended to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicUrl(url::Url);
+
+impl PublicUrl {
+    /// the URL of the frontend's `page`, like `register`
+    pub fn page(&self, page: &str) -> url::Url {
+        let mut url = self.0.clone();
+        url.path_segments_mut()
+            .expect("the public url can be a base")
+            .pop_if_empty()
+            .push(page);
+        url
+    }
+}
+
+impl FromStr for PublicUrl {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = url::Url::parse(s).map_err(|err| format!("invalid public_url \"{s}\": {err}"))?;
+        if !matches!(url.scheme(), "http" | "https") || url.cannot_be_a_base() {
+            return Err(format!("invalid public_url \"{s}\", expected an http or https URL"));
+        }
+        if url.query().is_some() || url.fragment().is_some() {
+            return Err(format!("invalid public_url \"{s}\", it can't have a query or fragment"));
+        }
+        Ok(Self(url))
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicUrl {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// attributes of the `session-token` cookie set on login
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionCookieConfig {
+    /// whether the cookie is only sent over HTTPS. always set with [`SameSite::None`], as browsers require it
+    pub secure: bool,
+    pub same_site: SameSite,
+    /// domain the cookie is sent to, including its subdomains. empty means only the host that set it
+    pub domain: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionConfig {
+    /// how long a session lasts, in seconds. sessions created with `expires: false` last until they're removed
+    pub lifetime: u64,
+    /// the most sessions a user can have, creating another one removes the oldest
+    pub max_per_user: usize,
+    /// how often the expired sessions are removed, in seconds
+    pub cleanup_interval: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// sent with cross-site requests too, for frontends on a different site than the api
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SameSite::Strict => f.write_str("Strict"),
+            SameSite::Lax => f.write_str("Lax"),
+            SameSite::None => f.write_str("None"),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -34,7 +247,20 @@ pub struct DatabaseConfig {
     pub database_type: DatabaseType,
     pub cache_time_to_live: u64,
     pub max_connections: u32,
+    /// how long a query waits for a free connection before failing with `503 Service Unavailable`, in seconds
+    pub acquire_timeout: u64,
+    /// how often the database is pinged, in seconds, see [`crate::database::Database::monitor_health`]. 0 is rejected
+    /// when the config is loaded
+    pub health_check_interval: NonZeroU64,
+    /// how many pings have to fail in a row before the api stops sending requests to the database
+    pub unavailable_after: u32,
     pub pg_host: String,
+    /// whether instances sharing a Postgres database tell each other to drop changed objects from their caches
+    pub cache_invalidation: bool,
+    /// whether admins can run read-only SQL queries through `/api/admin/query`
+    pub admin_queries: bool,
+    /// whether the groups, mod loaders and versions are loaded into the cache on startup
+    pub warm_cache: bool,
 }
 
 #[derive(Debug, Clone, Copy, Deserialize)]
@@ -60,6 +286,10 @@ pub enum ProxyType {
 #[derive(Debug, Clone, Deserialize)]
 pub struct RemoteConfig {
     pub host: url::Url,
+    /// how many times a failed request is retried
+    pub retries: u32,
+    /// how long to wait for a response, in seconds
+    pub timeout: u64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -68,6 +298,144 @@ pub struct WorldConfig {
     pub port_range: Range<u16>,
     pub java_launch_command: String,
     pub minimum_memory: u32,
+    /// how many seconds the players are warned before a running server is restarted to apply a change to its world,
+    /// like the allocated memory. 0 restarts it right away
+    pub restart_warning: u64,
+    /// how many seconds the stopped server of a disabled world is kept before it's removed, to be created again when
+    /// it's needed. 0 keeps them
+    #[serde(default)]
+    pub prune_stopped_after: u64,
+    /// paths to Java binaries by their major version, for versions with [`crate::database::objects::Version::required_java`]
+    #[serde(default)]
+    pub java_runtimes: HashMap<String, PathBuf>,
+    /// niceness the servers run with, see [`crate::minecraft::limits`]. groups can override it
+    #[serde(default)]
+    pub nice: Option<i32>,
+    /// how much CPU time a server can use, in percent of one core. needs `cgroup`. groups can override it
+    #[serde(default)]
+    pub cpu_limit: Option<u32>,
+    /// memory a server can use on top of its allocated memory, in MiB. needs `cgroup`. [`None`] doesn't limit memory
+    #[serde(default)]
+    pub memory_overhead: Option<u32>,
+    /// cgroup (v2) directory the cgroups of the servers are created in
+    #[serde(default)]
+    pub cgroup: Option<PathBuf>,
+    /// MOTD the servers get, see [`crate::minecraft::util::render_motd`]. users who can edit `motd` can replace it
+    #[serde(default)]
+    pub motd_template: Option<String>,
+    /// when users (other than privileged ones) can enable their worlds. [`None`] allows it at any time
+    #[serde(default)]
+    pub start_window: Option<TimeWindow>,
+    /// forces `online-mode` of every world to the value, so nobody can change it. [`None`] leaves it to the worlds
+    #[serde(default)]
+    pub online_mode: Option<bool>,
+    /// the largest radius a world can be pregenerated in, in blocks. [`None`] doesn't limit it
+    #[serde(default)]
+    pub max_pregeneration_radius: Option<u32>,
+    /// only privileged users can download the server jars of the versions
+    #[serde(default)]
+    pub privileged_jar_downloads: bool,
+    #[serde(default)]
+    pub hooks: HookConfig,
+}
+
+/// commands run on the host when servers start and stop, see [`crate::minecraft::hooks`]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HookConfig {
+    /// run before a server is launched, once it has a port
+    pub before_start: Option<String>,
+    /// run after a server was launched
+    pub after_start: Option<String>,
+    /// run after a server exited
+    pub after_stop: Option<String>,
+    /// how long a hook can run before it's killed, in seconds
+    pub timeout: u64,
+    /// a failing `before_start` hook keeps the server from starting. otherwise failing hooks are only logged
+    pub fatal: bool,
+}
+
+impl Default for HookConfig {
+    fn default() -> Self {
+        Self {
+            before_start: None,
+            after_start: None,
+            after_stop: None,
+            timeout: 30,
+            fatal: false,
+        }
+    }
+}
+
+/// limits of the whole instance, on top of the ones of the groups, see [`crate::minecraft::capacity`]. [`None`] is no
+/// limit
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct InstanceLimits {
+    #[serde(default)]
+    pub max_worlds: Option<u32>,
+    #[serde(default)]
+    pub max_running_worlds: Option<u32>,
+    /// how much memory all the enabled worlds can have allocated, in MiB
+    #[serde(default)]
+    pub max_memory: Option<u32>,
+}
+
+/// how the Minecraft accounts users link are checked, see [`crate::minecraft::account`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct AccountConfig {
+    /// look the accounts up with the profile api, which also gives their UUID. without it the usernames are taken as
+    /// they are
+    pub verify: bool,
+    /// the profile lookup endpoint of the Mojang api, the username is appended to it
+    pub profile_api: String,
+}
+
+impl Default for AccountConfig {
+    fn default() -> Self {
+        Self {
+            verify: true,
+            profile_api: String::from("https://api.mojang.com/users/profiles/minecraft/"),
+        }
+    }
+}
+
+/// where the icons of mods with a Modrinth project are fetched from, see [`crate::minecraft::modrinth`]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ModrinthConfig {
+    /// fetch the icon of a mod when it's given a Modrinth project and doesn't have one yet
+    pub fetch_icons: bool,
+    /// the Modrinth api, the project lookup is appended to it
+    pub api: String,
+    /// largest icon that gets downloaded, in bytes
+    pub max_icon_size: u64,
+    /// the least time between two requests to Modrinth, in milliseconds
+    pub request_interval: u64,
+}
+
+impl Default for ModrinthConfig {
+    fn default() -> Self {
+        Self {
+            fetch_icons: true,
+            api: String::from("https://api.modrinth.com/v2/"),
+            max_icon_size: 1024 * 1024,
+            request_interval: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackupConfig {
+    /// how many backups of a world are kept
+    pub retention: usize,
+    /// maximum total size of a world's backups in MiB
+    pub max_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct UploadConfig {
+    /// command the uploaded mod jars are scanned with, see [`crate::minecraft::jar::CommandScanner`]
+    pub scan_command: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -78,14 +446,58 @@ pub struct UserDefaults {
 #[derive(Debug, Clone, Deserialize)]
 pub struct WorldDefaults {
     /// Default amount of memory allocated to a server (in MiB)
-    pub allocated_memory: u32,
+    pub allocated_memory: Memory,
+    /// [`crate::database::objects::Version`] preselected when creating a world
+    #[serde(default)]
+    pub version_id: Option<Id>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct ProxyConfig {
     pub port: u16,
     pub hostname: String,
+    /// hostnames worlds can't use, on top of [`ProxyConfig::hostname`]. compared ignoring case
+    #[serde(default)]
+    pub reserved_hostnames: Vec<String>,
+    /// send the address of players to the servers with the PROXY protocol
+    #[serde(default)]
+    pub forward_player_ip: bool,
     pub infrarust_executable_name: String,
+    #[serde(default)]
+    pub offline_status: OfflineStatusConfig,
+}
+
+impl ProxyConfig {
+    /// whether the port is used by the proxy or its [`OfflineStatusConfig`] responder, so servers can't use it
+    pub fn uses_port(&self, port: u16) -> bool {
+        port == self.port || (self.offline_status.enabled && port == self.offline_status.port)
+    }
+}
+
+/// what players see when the world they connect to through the proxy exists, but isn't running. see
+/// [`crate::minecraft::offline`]
+#[derive(Debug, Clone, Deserialize)]
+pub struct OfflineStatusConfig {
+    /// route the hostnames of stopped worlds to the responder. without it the proxy doesn't know them at all. off by
+    /// default, since the responder binds [`OfflineStatusConfig::port`]
+    pub enabled: bool,
+    /// the local port the responder listens on
+    pub port: u16,
+    /// shown in the server list. `{name}` is replaced with the name of the world and `{hostname}` with its hostname
+    pub motd: String,
+    /// shown to players that try to join, with the same replacements as [`OfflineStatusConfig::motd`]
+    pub kick_message: String,
+}
+
+impl Default for OfflineStatusConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 25566,
+            motd: String::from("{name} is currently offline"),
+            kick_message: String::from("This server is currently offline"),
+        }
+    }
 }
 
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {
@@ -114,6 +526,53 @@ pub static CONFIG: Lazy<Config> = Lazy::new(|| {
         .expect("failed to parse config")
 });
 
+#[test]
+fn public_url_test() {
+    use pretty_assertions::assert_eq;
+
+    let url: PublicUrl = "https://mc.example.net".parse().unwrap();
+    assert_eq!(url.page("register").as_str(), "https://mc.example.net/register");
+    let url: PublicUrl = "https://example.net/mcmanager/".parse().unwrap();
+    assert_eq!(url.page("register").as_str(), "https://example.net/mcmanager/register");
+
+    assert!("example.net".parse::<PublicUrl>().is_err());
+    assert!("ftp://example.net".parse::<PublicUrl>().is_err());
+    assert!("mailto:admin@example.net".parse::<PublicUrl>().is_err());
+    assert!("https://example.net/?page=1".parse::<PublicUrl>().is_err());
+}
+
+#[test]
+fn health_check_interval_test() {
+    let load = |interval: &str| {
+        config::Config::builder()
+            .add_source(config::File::from_str(
+                &include_str!("resources/configs/default_config.toml").replace("$default_group_id", "AAAAAAAA"),
+                config::FileFormat::Toml,
+            ))
+            .add_source(config::File::from_str(
+                &format!("[database]\nhealth_check_interval = {interval}"),
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize::<Config>()
+    };
+    assert_eq!(load("5").unwrap().database.health_check_interval.get(), 5);
+    assert!(load("0").is_err());
+}
+
+#[test]
+fn login_message_type() {
+    use pretty_assertions::assert_eq;
+
+    assert_eq!("info".parse(), Ok(LoginMessageType::Info));
+    assert_eq!(" Warning ".parse(), Ok(LoginMessageType::Warning));
+    assert_eq!("ERROR".parse(), Ok(LoginMessageType::Error));
+    assert_eq!("".parse(), Ok(LoginMessageType::None));
+    assert!("informational".parse::<LoginMessageType>().is_err());
+    assert_eq!(LoginMessageType::Warning.to_string(), "warning");
+}
+
 pub mod secrets {
     use crate::util;
     use config::Config;
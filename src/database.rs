@@ -1,14 +1,15 @@
 use crate::api::handlers::PaginationSettings;
+use crate::api::timing::TimedQuery;
 use crate::database::objects::{DbObject, Group};
 use crate::database::objects::{
-    InviteLink, Mod, ModLoader, Password, Session, User, Version, World,
+    InviteLink, Mod, ModLoader, Password, Session, User, Version, World, WorldShare,
 };
 use crate::database::types::{Id, Modifier};
 use crate::execute_on_enum;
 use async_recursion::async_recursion;
 use dyn_clone::DynClone;
 use futures::TryFutureExt;
-use log::{debug, error};
+use log::{debug, error, info, warn};
 use moka::future::Cache;
 use serde::Serialize;
 use serde_json::Value;
@@ -18,10 +19,22 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
 use std::time::Duration;
 use uuid::Uuid;
 
+/// how many rows [`Database::stream_all`] reads ahead of the consumer
+const STREAM_BUFFER: usize = 64;
+/// how many rows [`Database::stream_all`] reads with each query
+const STREAM_PAGE_SIZE: usize = 256;
+/// how many ids [`Database::get_many`] looks up with each query, so a long list doesn't go over the databases' limits
+/// on bound parameters
+const GET_MANY_BATCH_SIZE: usize = 500;
+
 pub mod objects;
+pub mod orphans;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod types;
 
 pub trait Cachable: DynClone + Sync + Send + Any {
@@ -34,6 +47,21 @@ type StdCache = Cache<Id, Box<dyn Cachable>>;
 #[derive(Clone)]
 pub struct DatabaseCache {
     pub caches: Arc<HashMap<&'static str, StdCache>>,
+    counters: Arc<HashMap<&'static str, CacheCounters>>,
+}
+
+#[derive(Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// hit and miss counts of a single table's cache
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: u64,
 }
 
 impl Default for DatabaseCache {
@@ -46,8 +74,6 @@ impl DatabaseCache {
     pub fn new() -> Self {
         let mut caches = HashMap::new();
 
-        const CACHES_SIZE: u64 = 1000;
-
         caches.insert(Group::table_name(), Cache::new(CACHES_SIZE));
         caches.insert(InviteLink::table_name(), Cache::new(CACHES_SIZE));
         caches.insert(ModLoader::table_name(), Cache::new(CACHES_SIZE));
@@ -57,9 +83,60 @@ impl DatabaseCache {
         caches.insert(Password::table_name(), Cache::new(CACHES_SIZE));
         caches.insert(Version::table_name(), Cache::new(CACHES_SIZE));
         caches.insert(World::table_name(), Cache::new(CACHES_SIZE));
+        caches.insert(WorldShare::table_name(), Cache::new(CACHES_SIZE));
+
+        let counters = caches
+            .keys()
+            .map(|table| (*table, CacheCounters::default()))
+            .collect();
 
         Self {
             caches: Arc::new(caches),
+            counters: Arc::new(counters),
+        }
+    }
+
+    /// hit and miss counts of every table's cache since startup
+    pub fn stats(&self) -> HashMap<&'static str, CacheStats> {
+        self.counters
+            .iter()
+            .map(|(table, counters)| {
+                (
+                    *table,
+                    CacheStats {
+                        hits: counters.hits.load(Ordering::Relaxed),
+                        misses: counters.misses.load(Ordering::Relaxed),
+                        entries: self.get_cache(table).entry_count(),
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// removes all the entries from a table's cache. returns the number of evicted entries, or [`None`] if the table has no cache
+    pub async fn clear(&self, table: &str) -> Option<u64> {
+        let cache = self.caches.get(table)?;
+        let evicted = cache.iter().count() as u64;
+        cache.invalidate_all();
+        cache.run_pending_tasks().await;
+        Some(evicted)
+    }
+
+    /// removes all the entries from every cache. returns the number of evicted entries
+    pub async fn clear_all(&self) -> u64 {
+        let mut evicted = 0;
+        for table in self.caches.keys() {
+            evicted += self.clear(table).await.unwrap_or(0);
+        }
+        evicted
+    }
+
+    pub fn log_stats(&self) {
+        for (table, stats) in self.stats() {
+            debug!(
+                "cache {table}: {} hits, {} misses, {} entries",
+                stats.hits, stats.misses, stats.entries
+            );
         }
     }
 
@@ -69,7 +146,16 @@ impl DatabaseCache {
 
     pub async fn get<T: DbObject + 'static>(&self, id: Id) -> Option<T> {
         let cache = self.get_cache(T::table_name());
-        let value = cache.get(&id).await?;
+        let value = cache.get(&id).await;
+
+        if let Some(counters) = self.counters.get(T::table_name()) {
+            if value.is_some() {
+                counters.hits.fetch_add(1, Ordering::Relaxed);
+            } else {
+                counters.misses.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        let value = value?;
 
         let value = value.into_any().downcast::<T>();
 
@@ -103,6 +189,56 @@ impl DatabaseCache {
             debug!("value from {} with id {} not in cache, cannot remove", T::table_name(), id);
         }
     }
+
+    /// removes a value from the cache of a table given by name. does nothing if the table has no cache
+    pub async fn invalidate(&self, table: &str, id: Id) {
+        if let Some(cache) = self.caches.get(table) {
+            cache.invalidate(&id).await;
+        }
+    }
+}
+
+/// how many objects the cache of each table holds
+const CACHES_SIZE: u64 = 1000;
+/// Postgres channel the cache invalidations are published on, see [`Database::listen_for_invalidations`]
+const INVALIDATION_CHANNEL: &str = "mcmanager_cache_invalidation";
+/// how long to wait before listening again after the listener fails
+const INVALIDATION_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// most rows returned by [`Database::raw_query`], the rest are cut off
+const RAW_QUERY_ROW_LIMIT: usize = 1000;
+
+/// whether the database can be reached, kept up to date by [`Database::monitor_health`]
+#[derive(Debug, Default)]
+struct DatabaseHealth {
+    /// health checks that failed in a row
+    failures: AtomicU32,
+    unavailable: AtomicBool,
+}
+
+impl DatabaseHealth {
+    /// records the result of a health check. the database becomes unavailable after `unavailable_after` failures in a
+    /// row, and available again with the first success. returns whether that changed
+    fn record(&self, healthy: bool, unavailable_after: u32) -> bool {
+        if healthy {
+            self.failures.store(0, Ordering::Relaxed);
+            return self.unavailable.swap(false, Ordering::Relaxed);
+        }
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        failures >= unavailable_after && !self.unavailable.swap(true, Ordering::Relaxed)
+    }
+}
+
+/// a changed object, sent to other instances sharing the database so they drop it from their caches
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, serde::Deserialize)]
+struct Invalidation {
+    table: String,
+    id: Id,
+}
+
+impl Debug for Database {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Database").finish_non_exhaustive()
+    }
 }
 
 #[derive(Clone)]
@@ -111,6 +247,7 @@ pub struct Database {
     pub pool: DatabasePool,
     pub cache: DatabaseCache,
     pub session_cache: Cache<Uuid, Session>,
+    health: Arc<DatabaseHealth>,
 }
 
 pub enum DatabaseType {
@@ -132,6 +269,7 @@ impl Database {
             pool,
             cache: DatabaseCache::new(),
             session_cache,
+            health: Arc::default(),
         }
     }
 
@@ -148,11 +286,309 @@ impl Database {
             sqlx::query(&format!("CREATE TABLE IF NOT EXISTS {} ({});", Version::table_name(),    Version::database_descriptor(&self.db_type()))).execute(pool).await?;
             sqlx::query(&format!("CREATE TABLE IF NOT EXISTS {} ({});", Mod::table_name(),        Mod::database_descriptor(&self.db_type()))).execute(pool).await?;
             sqlx::query(&format!("CREATE TABLE IF NOT EXISTS {} ({});", World::table_name(),      World::database_descriptor(&self.db_type()))).execute(pool).await?;
+            sqlx::query(&format!("CREATE TABLE IF NOT EXISTS {} ({});", WorldShare::table_name(), WorldShare::database_descriptor(&self.db_type()))).execute(pool).await?;
+        });
+
+        // tables created by older versions are missing the columns added since
+        self.add_missing_columns::<Group>().await?;
+        self.add_missing_columns::<User>().await?;
+        self.add_missing_columns::<Password>().await?;
+        self.add_missing_columns::<Session>().await?;
+        self.add_missing_columns::<InviteLink>().await?;
+        self.add_missing_columns::<ModLoader>().await?;
+        self.add_missing_columns::<Version>().await?;
+        self.add_missing_columns::<Mod>().await?;
+        self.add_missing_columns::<World>().await?;
+        self.add_missing_columns::<WorldShare>().await?;
+
+        self.add_conflict_index::<Group>().await;
+        self.add_conflict_index::<User>().await;
+        self.add_conflict_index::<Password>().await;
+        self.add_conflict_index::<Session>().await;
+        self.add_conflict_index::<InviteLink>().await;
+        self.add_conflict_index::<ModLoader>().await;
+        self.add_conflict_index::<Version>().await;
+        self.add_conflict_index::<Mod>().await;
+        self.add_conflict_index::<World>().await;
+        self.add_conflict_index::<WorldShare>().await;
+
+        // Sqlite has no comments, they only show up in the schema
+        if let DatabasePool::Postgres(pool) = &self.pool {
+            let statements = [
+                Group::comment_statements(),
+                User::comment_statements(),
+                World::comment_statements(),
+            ];
+            for statement in statements.iter().flatten() {
+                sqlx::query(statement).execute(pool).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// adds the columns of `T` its table doesn't have. existing rows get the column's default, so columns added this
+    /// way need one if they're not null
+    async fn add_missing_columns<T: DbObject>(&self) -> sqlx::Result<()> {
+        let existing: Vec<String> = match &self.pool {
+            DatabasePool::Sqlite(pool) => {
+                sqlx::query_scalar(&format!("SELECT name FROM pragma_table_info('{}');", T::table_name()))
+                    .fetch_all(pool)
+                    .await?
+            }
+            DatabasePool::Postgres(pool) => {
+                sqlx::query_scalar(&format!(
+                    "SELECT column_name::TEXT FROM information_schema.columns WHERE table_name = '{}';",
+                    T::table_name()
+                ))
+                .fetch_all(pool)
+                .await?
+            }
+        };
+
+        for column in T::columns().iter() {
+            if existing.iter().any(|name| name == column.name()) {
+                continue;
+            }
+            info!("adding the column {} to {}", column.name(), T::table_name());
+            let statement = format!(
+                "ALTER TABLE {} ADD COLUMN {} {};",
+                T::table_name(),
+                column.name(),
+                column.descriptor(&self.db_type())
+            );
+            execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+                sqlx::query(&statement).execute(pool).await?;
+            });
+        }
+        Ok(())
+    }
+
+    /// makes the [`DbObject::conflict_column`] of `T` unique, so upserts can match by it. it's an index instead of a
+    /// `UNIQUE` on the column, so tables created by older versions get it too. if the table already has duplicates it
+    /// can't be created, which is only logged, as everything but upserts still works
+    async fn add_conflict_index<T: DbObject>(&self) {
+        let column = T::conflict_column();
+        if column == T::columns()[T::id_column_index()].name {
+            return;
+        }
+        let statement = format!(
+            "CREATE UNIQUE INDEX IF NOT EXISTS {table}_{column}_key ON {table} ({column});",
+            table = T::table_name()
+        );
+        let result = execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            sqlx::query(&statement).execute(pool).await.map(|_| ())
         });
+        if let Err(err) = result {
+            warn!("could not make {column} of {} unique, upserting them will fail: {err}", T::table_name());
+        }
+    }
+
+    /// clears the cache of the provided table, or all caches if it's [`None`]. the session cache is always cleared.
+    /// returns the number of evicted entries
+    pub async fn flush_cache(&self, table: Option<&str>) -> Result<u64, DatabaseError> {
+        let mut evicted = match table {
+            Some(table) => self.cache.clear(table).await.ok_or(DatabaseError::NotFound)?,
+            None => self.cache.clear_all().await,
+        };
+
+        evicted += self.session_cache.iter().count() as u64;
+        self.session_cache.invalidate_all();
+        self.session_cache.run_pending_tasks().await;
+
+        Ok(evicted)
+    }
+
+    /// runs a single read-only `SELECT`, for admin reports that don't fit the objects. returns the rows as JSON
+    /// objects, at most [`RAW_QUERY_ROW_LIMIT`] of them, and whether any were cut off. on Postgres the query runs in
+    /// a read-only transaction, so even a `SELECT` calling a writing function can't change anything
+    pub async fn raw_query(&self, query: &str) -> Result<(Vec<Value>, bool), DatabaseError> {
+        use futures::TryStreamExt;
+
+        let query = validate_select(query)?;
+        let map_err = |err: sqlx::Error| match err {
+            sqlx::Error::Database(err) => DatabaseError::BadRequest(err.message().to_string()),
+            err => DatabaseError::SqlxError(err),
+        };
+
+        let mut rows = Vec::new();
+        let mut truncated = false;
+        // the rows are streamed, the query is recorded once they're all read instead of once per row
+        let start = std::time::Instant::now();
+        match &self.pool {
+            DatabasePool::Postgres(pool) => {
+                let mut transaction = pool.begin().await?;
+                sqlx::query("SET TRANSACTION READ ONLY")
+                    .execute(&mut *transaction)
+                    .await?;
+                let mut stream = sqlx::query(query).fetch(&mut *transaction);
+                while let Some(row) = stream.try_next().await.map_err(map_err)? {
+                    if rows.len() == RAW_QUERY_ROW_LIMIT {
+                        truncated = true;
+                        break;
+                    }
+                    rows.push(pg_row_to_json(&row));
+                }
+                drop(stream);
+                transaction.rollback().await?;
+            }
+            DatabasePool::Sqlite(pool) => {
+                let mut stream = sqlx::query(query).fetch(pool);
+                while let Some(row) = stream.try_next().await.map_err(map_err)? {
+                    if rows.len() == RAW_QUERY_ROW_LIMIT {
+                        truncated = true;
+                        break;
+                    }
+                    rows.push(sqlite_row_to_json(&row));
+                }
+            }
+        }
+        crate::api::timing::record_query(start.elapsed());
+        Ok((rows, truncated))
+    }
 
+    /// tells other instances sharing the Postgres database that an object changed. does nothing with sqlite, or if
+    /// `database.cache_invalidation` is disabled. failing to publish is only logged, as the write already succeeded
+    async fn publish_invalidation(&self, table: &str, id: Id) {
+        let DatabasePool::Postgres(pool) = &self.pool else {
+            return;
+        };
+        if !crate::config::CONFIG.database.cache_invalidation {
+            return;
+        }
+        let payload = serde_json::to_string(&Invalidation {
+            table: table.to_string(),
+            id,
+        })
+        .expect("serialization failed");
+        if let Err(err) = sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(INVALIDATION_CHANNEL)
+            .bind(payload)
+            .execute(pool)
+            .timed()
+            .await
+        {
+            error!("could not publish the invalidation of {table} {id}: {err}");
+        }
+    }
+
+    /// drops objects changed by other instances from the caches. runs forever, with Postgres and
+    /// `database.cache_invalidation` enabled, otherwise it returns right away.
+    ///
+    /// delivery is at least once: every instance also receives its own invalidations, and since Postgres drops
+    /// notifications sent while the listener is disconnected, all the caches are cleared after reconnecting.
+    /// invalidating twice only costs a cache miss
+    pub async fn listen_for_invalidations(&self) {
+        let DatabasePool::Postgres(pool) = &self.pool else {
+            return;
+        };
+        if !crate::config::CONFIG.database.cache_invalidation {
+            return;
+        }
+
+        loop {
+            let mut listener = match sqlx::postgres::PgListener::connect_with(pool).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    error!("could not connect the cache invalidation listener: {err}");
+                    tokio::time::sleep(INVALIDATION_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            if let Err(err) = listener.listen(INVALIDATION_CHANNEL).await {
+                error!("could not listen for cache invalidations: {err}");
+                tokio::time::sleep(INVALIDATION_RETRY_DELAY).await;
+                continue;
+            }
+            // anything could have changed since the last time we listened
+            let _ = self.flush_cache(None).await;
+            debug!("listening for cache invalidations");
+
+            loop {
+                match listener.try_recv().await {
+                    Ok(Some(notification)) => {
+                        match serde_json::from_str::<Invalidation>(notification.payload()) {
+                            Ok(invalidation) => self.apply_invalidation(&invalidation).await,
+                            Err(err) => error!(
+                                "invalid cache invalidation \"{}\": {err}",
+                                notification.payload()
+                            ),
+                        }
+                    }
+                    // the connection was lost and the listener reconnects on the next call, notifications sent in
+                    // the meantime are gone
+                    Ok(None) => {
+                        debug!("cache invalidation listener reconnecting");
+                        let _ = self.flush_cache(None).await;
+                    }
+                    Err(err) => {
+                        error!("cache invalidation listener failed: {err}");
+                        break;
+                    }
+                }
+            }
+            tokio::time::sleep(INVALIDATION_RETRY_DELAY).await;
+        }
+    }
+
+    /// runs a trivial query, failing if the database can't be reached
+    pub async fn ping(&self) -> Result<(), DatabaseError> {
+        execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            sqlx::query("SELECT 1;").execute(pool).await?;
+        });
         Ok(())
     }
 
+    /// whether the last health checks succeeded, see [`Database::monitor_health`]
+    pub fn is_available(&self) -> bool {
+        !self.health.unavailable.load(Ordering::Relaxed)
+    }
+
+    /// pings the database every `database.health_check_interval` seconds, runs forever. after
+    /// `database.unavailable_after` failed pings in a row the database is marked unavailable, and the api answers with
+    /// `503 Service Unavailable` instead of queueing requests for it, until a ping succeeds again.
+    ///
+    /// sqlx already recovers from dropped connections on its own: the pool checks connections before handing them
+    /// out and opens new ones in place of broken ones, so once the database is back the next ping reconnects. what it
+    /// doesn't do is retry a query whose connection broke in the middle of it, or give up on waiting for a
+    /// connection before `database.acquire_timeout`, which is what the health checks are for
+    pub async fn monitor_health(&self) {
+        let config = &crate::config::CONFIG.database;
+        let mut interval = tokio::time::interval(Duration::from_secs(config.health_check_interval.get()));
+        loop {
+            interval.tick().await;
+            self.check_health(config.unavailable_after).await;
+        }
+    }
+
+    async fn check_health(&self, unavailable_after: u32) {
+        let result = self.ping().await;
+        let changed = self.health.record(result.is_ok(), unavailable_after);
+        match result {
+            Ok(()) if changed => info!("the database is available again"),
+            Ok(()) => {}
+            Err(err) if changed => error!(
+                "the database is unavailable, requests are answered with 503 Service Unavailable until it's back: {err}"
+            ),
+            Err(err) => warn!("database health check failed: {err}"),
+        }
+    }
+
+    async fn apply_invalidation(&self, invalidation: &Invalidation) {
+        self.cache.invalidate(&invalidation.table, invalidation.id).await;
+        if invalidation.table == Session::table_name() {
+            let tokens: Vec<Uuid> = self
+                .session_cache
+                .iter()
+                .filter(|(_, session)| session.id == invalidation.id)
+                .map(|(token, _)| *token)
+                .collect();
+            for token in tokens {
+                self.session_cache.invalidate(&token).await;
+            }
+        }
+    }
+
     fn db_type(&self) -> DatabaseType {
         match self.pool {
             DatabasePool::Postgres(_) => DatabaseType::Postgres,
@@ -177,6 +613,7 @@ impl Database {
                 return Err(DatabaseError::Unauthorized);
             }
         }
+        value.validate()?;
         value.before_create(self).await?;
 
         execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
@@ -185,12 +622,14 @@ impl Database {
                 .query_builder
                 .build()
                 .execute(pool)
+                .timed()
                 .await
                 .map_err(DatabaseError::from)?;
         });
         value.after_create(self).await?;
 
         self.cache.insert(value.clone()).await;
+        self.publish_invalidation(T::table_name(), value.id()).await;
 
         if let Some(session) = (value as &dyn Any).downcast_ref::<Session>() {
             self.session_cache
@@ -201,6 +640,44 @@ impl Database {
         Ok(())
     }
 
+    /// inserts the object, or if one with the same [`DbObject::conflict_column`] is already stored, leaves it alone or
+    /// updates it to this one depending on `on_conflict`. an updated object keeps its [`Id`]. returns the stored
+    /// object, or [`None`] if it was left alone. it's meant for seeding, so access isn't checked and out of the hooks
+    /// only [`DbObject::validate`] is called
+    pub async fn upsert<
+        T: DbObject
+            + for<'a> IntoArguments<'a, sqlx::Sqlite>
+            + for<'a> IntoArguments<'a, sqlx::Postgres>
+            + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
+            + for<'r> FromRow<'r, sqlx::postgres::PgRow>
+            + Unpin
+            + Cachable,
+    >(
+        &self,
+        value: &T,
+        on_conflict: OnConflict,
+    ) -> Result<Option<T>, DatabaseError> {
+        value.validate()?;
+
+        let stored: Option<T> = execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            let mut query = QueryBuilder::insert(value.clone());
+            query.on_conflict::<T>(on_conflict);
+            query
+                .query_builder
+                .build_query_as()
+                .fetch_optional(pool)
+                .timed()
+                .await
+                .map_err(DatabaseError::from)
+        })?;
+
+        if let Some(stored) = &stored {
+            self.cache.insert(stored.clone()).await;
+            self.publish_invalidation(T::table_name(), stored.id()).await;
+        }
+        Ok(stored)
+    }
+
     pub async fn update<
         T: DbObject
             + for<'a> IntoArguments<'a, sqlx::Sqlite>
@@ -218,6 +695,7 @@ impl Database {
                 return Err(DatabaseError::Unauthorized);
             }
         }
+        value.validate()?;
         value.before_update(self).await?;
 
         execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
@@ -232,11 +710,13 @@ impl Database {
                 .query_builder
                 .build()
                 .execute(pool)
+                .timed()
                 .await
                 .map_err(DatabaseError::from)?;
         });
 
         self.cache.insert(value.clone()).await;
+        self.publish_invalidation(T::table_name(), value.id()).await;
 
         if let Some(session) = (value as &dyn Any).downcast_ref::<Session>() {
             self.session_cache
@@ -284,14 +764,139 @@ impl Database {
                 .query_builder
                 .build()
                 .execute(pool)
+                .timed()
                 .await
                 .map_err(DatabaseError::from)?;
         });
+        self.publish_invalidation(T::table_name(), value.id()).await;
 
         value.after_delete(self).await?;
         Ok(())
     }
 
+    /// [`Database::remove`], but only if the stored object still passes `unchanged`. the stored object is read and
+    /// removed in one transaction that locks the row (with `FOR UPDATE` on Postgres, and by starting the transaction with
+    /// `BEGIN IMMEDIATE` on Sqlite), so a write in between can't be lost. fails with [`DatabaseError::PreconditionFailed`]
+    /// if the stored object doesn't pass
+    pub async fn remove_unchanged<
+        T: DbObject
+            + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
+            + for<'r> FromRow<'r, sqlx::postgres::PgRow>
+            + Any
+            + Clone
+            + Send
+            + Unpin,
+    >(
+        &self,
+        value: &T,
+        user: Option<(&User, &Group)>,
+        unchanged: impl FnOnce(&T) -> bool,
+    ) -> Result<(), DatabaseError> {
+        if let Some((user, group)) = user {
+            if !value.can_update(user, group) {
+                return Err(DatabaseError::Unauthorized);
+            }
+        }
+        value.before_delete(self).await?;
+
+        let (begin, lock) = self.locking_transaction();
+        execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            let mut transaction = pool.begin_with(begin).timed().await?;
+
+            let mut query = QueryBuilder::select::<T>();
+            query.where_id::<T>(value.id());
+            if let Some((user, group)) = user {
+                query.user_group::<T>(user, group);
+            }
+            query.query_builder.push(lock);
+            let stored = query.query_builder.build_query_as::<T>().fetch_one(&mut *transaction).timed().await?;
+            // the transaction is rolled back when it's dropped
+            if !unchanged(&stored) {
+                return Err(DatabaseError::PreconditionFailed);
+            }
+
+            let mut query = QueryBuilder::delete::<T>();
+            query.where_id::<T>(value.id());
+            query.query_builder.build().execute(&mut *transaction).timed().await?;
+            transaction.commit().timed().await?;
+        });
+
+        self.cache.remove::<T>(value.id()).await;
+        if let Some(session) = (value as &dyn Any).downcast_ref::<Session>() {
+            self.session_cache.remove(&session.token).await;
+        }
+        self.publish_invalidation(T::table_name(), value.id()).await;
+
+        value.after_delete(self).await?;
+        Ok(())
+    }
+
+    /// [`Database::update`], but only if the stored object still passes `unchanged`, in the same way as
+    /// [`Database::remove_unchanged`]
+    pub async fn update_unchanged<
+        T: DbObject
+            + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
+            + for<'r> FromRow<'r, sqlx::postgres::PgRow>
+            + for<'a> IntoArguments<'a, sqlx::Sqlite>
+            + for<'a> IntoArguments<'a, sqlx::Postgres>
+            + Any
+            + Clone
+            + Cachable
+            + Send
+            + Unpin,
+    >(
+        &self,
+        value: &T,
+        user: Option<(&User, &Group)>,
+        unchanged: impl FnOnce(&T) -> bool,
+    ) -> Result<(), DatabaseError> {
+        if let Some((user, group)) = user {
+            if !value.can_update(user, group) {
+                return Err(DatabaseError::Unauthorized);
+            }
+        }
+        value.validate()?;
+        value.before_update(self).await?;
+
+        let (begin, lock) = self.locking_transaction();
+        execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            let mut transaction = pool.begin_with(begin).timed().await?;
+
+            let mut query = QueryBuilder::select::<T>();
+            query.where_id::<T>(value.id());
+            if let Some((user, group)) = user {
+                query.user_group::<T>(user, group);
+            }
+            query.query_builder.push(lock);
+            let stored = query.query_builder.build_query_as::<T>().fetch_one(&mut *transaction).timed().await?;
+            if !unchanged(&stored) {
+                return Err(DatabaseError::PreconditionFailed);
+            }
+
+            let mut query = QueryBuilder::update(value.clone());
+            query.where_id::<T>(value.id());
+            query.query_builder.build().execute(&mut *transaction).timed().await?;
+            transaction.commit().timed().await?;
+        });
+
+        self.cache.insert(value.clone()).await;
+        self.publish_invalidation(T::table_name(), value.id()).await;
+        if let Some(session) = (value as &dyn Any).downcast_ref::<Session>() {
+            self.session_cache
+                .insert(session.token, session.clone())
+                .await;
+        }
+        Ok(())
+    }
+
+    /// how to start a transaction that holds the row it reads until it ends, and what to append to the select for it
+    fn locking_transaction(&self) -> (&'static str, &'static str) {
+        match &self.pool {
+            DatabasePool::Postgres(_) => ("BEGIN", " FOR UPDATE"),
+            DatabasePool::Sqlite(_) => ("BEGIN IMMEDIATE", ""),
+        }
+    }
+
     pub async fn get_one<
         T: DbObject
             + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
@@ -314,6 +919,7 @@ impl Database {
                     .query_builder
                     .build_query_as()
                     .fetch_one(pool)
+                    .timed()
                     .await
                     .map_err(DatabaseError::from)
             })?;
@@ -331,6 +937,61 @@ impl Database {
         Ok(db_object)
     }
 
+    /// gets multiple objects at once. cached objects are taken from the cache, and the rest is fetched in a single query,
+    /// or one for every [`GET_MANY_BATCH_SIZE`] ids.
+    ///
+    /// the objects are returned in the order of `ids`. ids that don't exist or that aren't viewable by the user are skipped
+    pub async fn get_many<
+        T: DbObject
+            + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
+            + for<'r> FromRow<'r, sqlx::postgres::PgRow>
+            + Unpin
+            + Cachable,
+    >(
+        &self,
+        ids: &[Id],
+        user: Option<(&User, &Group)>,
+    ) -> Result<Vec<T>, DatabaseError> {
+        let mut found = HashMap::new();
+        let mut missing = Vec::new();
+        for id in ids {
+            if let Some(cached_object) = self.cache.get::<T>(*id).await {
+                found.insert(*id, cached_object);
+            } else if !missing.contains(id) {
+                missing.push(*id);
+            }
+        }
+
+        for batch in missing.chunks(GET_MANY_BATCH_SIZE) {
+            let db_objects: Vec<T> = execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+                let mut query = QueryBuilder::select::<T>();
+                query.where_in(T::columns()[T::id_column_index()].name(), batch.to_vec());
+                //no user filter in query, it will be done later so it always lands in cache
+                query
+                    .query_builder
+                    .build_query_as()
+                    .fetch_all(pool)
+                    .timed()
+                    .await
+                    .map_err(DatabaseError::from)
+            })?;
+
+            for db_object in db_objects {
+                self.cache.insert(db_object.clone()).await;
+                found.insert(db_object.id(), db_object);
+            }
+        }
+
+        Ok(ids
+            .iter()
+            .filter_map(|id| found.get(id))
+            .filter(|object| {
+                user.is_none_or(|(user, group)| object.viewable_by(user, group))
+            })
+            .cloned()
+            .collect())
+    }
+
     #[async_recursion]
     pub async fn get_recursive<T>(
         &self,
@@ -390,6 +1051,7 @@ impl Database {
                     .query_builder
                     .build_query_as()
                     .fetch_one(pool)
+                    .timed()
                     .await
                     .map_err(DatabaseError::from)
             })?;
@@ -404,10 +1066,60 @@ impl Database {
                 return Err(DatabaseError::NotFound);
             }
         }
+        // the periodic cleanup removes it from the database, see [`Database::remove_expired_sessions`]
+        if db_user.is_expired(Duration::from_secs(crate::config::CONFIG.sessions.lifetime)) {
+            return Err(DatabaseError::NotFound);
+        }
 
         Ok(db_user)
     }
 
+    /// removes the oldest sessions of the user, so only the newest `keep` are left. returns how many were removed
+    pub async fn evict_sessions(&self, user_id: Id, keep: usize) -> Result<usize, DatabaseError> {
+        let sessions: Vec<Session> = execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            let mut query = QueryBuilder::select::<Session>();
+            query.where_("user_id", user_id);
+            query.query_builder.push(format!(" ORDER BY {}", Session::default_sort()));
+            query
+                .query_builder
+                .build_query_as()
+                .fetch_all(pool)
+                .timed()
+                .await
+                .map_err(DatabaseError::from)
+        })?;
+
+        let evicted = sessions.len().saturating_sub(keep);
+        for session in &sessions[..evicted] {
+            debug!("removing the session {} of {user_id}, it has too many", session.id);
+            self.remove(session, None).await?;
+        }
+        Ok(evicted)
+    }
+
+    /// removes the sessions that are older than `lifetime` and expire. returns how many were removed
+    pub async fn remove_expired_sessions(&self, lifetime: Duration) -> Result<usize, DatabaseError> {
+        let cutoff = chrono::Utc::now() - chrono::TimeDelta::from_std(lifetime).unwrap_or(chrono::TimeDelta::MAX);
+        let sessions: Vec<Session> = execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            let mut query = QueryBuilder::select::<Session>();
+            query.where_("expires", true);
+            query.where_less_than("created", cutoff);
+            query
+                .query_builder
+                .build_query_as()
+                .fetch_all(pool)
+                .timed()
+                .await
+                .map_err(DatabaseError::from)
+        })?;
+
+        // one at a time, so they're dropped from the caches too
+        for session in &sessions {
+            self.remove(session, None).await?;
+        }
+        Ok(sessions.len())
+    }
+
     #[async_recursion]
     async fn object_from_field<T>(
         &self,
@@ -444,6 +1156,7 @@ impl Database {
                         "mods" => self.get_recursive::<Mod>(id, user).await?,
                         "versions" => self.get_recursive::<Version>(id, user).await?,
                         "worlds" => self.get_recursive::<World>(id, user).await?,
+                        "world_shares" => self.get_recursive::<WorldShare>(id, user).await?,
                         _ => Err(DatabaseError::InternalServerError("Not Found".to_string()))?,
                     };
 
@@ -471,7 +1184,7 @@ impl Database {
         value: V,
         user: Option<(&User, &Group)>,
     ) -> Result<T, DatabaseError> {
-        execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+        let result = execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
             let mut query = QueryBuilder::select::<T>();
             query.where_(column, value);
             if let Some((user, group)) = user {
@@ -482,9 +1195,21 @@ impl Database {
                 .query_builder
                 .build_query_as()
                 .fetch_one(pool)
+                .timed()
                 .await
                 .map_err(DatabaseError::from)
-        })
+        });
+        // the access is filtered in the query, so a denial can't be told apart from the object not existing
+        if let (Err(DatabaseError::NotFound), Some((user, _))) = (&result, user) {
+            debug!(
+                "{} ({}) found nothing in {} by {column}, it may be hidden by {:?}",
+                user.username,
+                user.id,
+                T::table_name(),
+                T::view_access()
+            );
+        }
+        result
     }
 
     pub async fn get_all<
@@ -506,6 +1231,7 @@ impl Database {
                 .query_builder
                 .build_query_as()
                 .fetch_all(pool)
+                .timed()
                 .await
                 .map_err(DatabaseError::from)
         })?;
@@ -515,6 +1241,63 @@ impl Database {
         Ok(value)
     }
 
+    /// like [`Database::get_all`], but the objects are read as the stream is consumed instead of all at once, so
+    /// tables of any size can be gone through in bounded memory. they aren't cached, and come ordered by id. they are
+    /// read in pages of [`STREAM_PAGE_SIZE`] after the last id of the previous page, so a slow reader doesn't keep a
+    /// connection of the pool. the queries run in a task that stops once the stream is dropped
+    pub fn stream_all<
+        T: DbObject
+            + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
+            + for<'r> FromRow<'r, sqlx::postgres::PgRow>
+            + Send
+            + Unpin
+            + 'static,
+    >(
+        &self,
+        user: Option<(User, Group)>,
+    ) -> impl futures::Stream<Item = Result<T, DatabaseError>> + Send + 'static {
+        let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_BUFFER);
+        let pool = self.pool.clone();
+        tokio::task::spawn(async move {
+            let id_column = T::columns()[T::id_column_index()].name;
+            let mut after: Option<Id> = None;
+            loop {
+                let page = execute_on_enum!(&pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+                    let mut query = QueryBuilder::select::<T>();
+                    if let Some(after) = after {
+                        query.where_greater_than(id_column, after);
+                    }
+                    if let Some((user, group)) = &user {
+                        query.user_group::<T>(user, group);
+                    }
+                    query
+                        .query_builder
+                        .push(format!(" ORDER BY {id_column} LIMIT {STREAM_PAGE_SIZE}"));
+                    query.query_builder.build_query_as::<T>().fetch_all(pool).await
+                });
+                let page = match page {
+                    Ok(page) => page,
+                    Err(err) => {
+                        let _ = sender.send(Err(err.into())).await;
+                        return;
+                    }
+                };
+                let last_page = page.len() < STREAM_PAGE_SIZE;
+                after = page.last().map(DbObject::id);
+                for object in page {
+                    // the receiver is gone, nobody is reading the rows anymore
+                    if sender.send(Ok(object)).await.is_err() {
+                        return;
+                    }
+                }
+                if last_page {
+                    return;
+                }
+            }
+        });
+        tokio_stream::wrappers::ReceiverStream::new(receiver)
+    }
+
     pub async fn get_all_where<
         T: DbObject
             + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
@@ -543,11 +1326,165 @@ impl Database {
                 .query_builder
                 .build_query_as()
                 .fetch_all(pool)
+                .timed()
                 .await
                 .map_err(DatabaseError::from)
         })
     }
 
+    /// like [`Database::get_all_where`], but only returns one page of the results, ordered by the [`Id`]
+    pub async fn get_page_where<
+        T: DbObject
+            + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
+            + for<'r> FromRow<'r, sqlx::postgres::PgRow>
+            + Unpin,
+        V: for<'r> Encode<'r, sqlx::Sqlite>
+            + Type<sqlx::Sqlite>
+            + for<'r> Encode<'r, sqlx::Postgres>
+            + Type<sqlx::Postgres>
+            + Clone,
+    >(
+        &self,
+        column: &str,
+        value: V,
+        user: Option<(&User, &Group)>,
+        pagination: PaginationSettings,
+    ) -> Result<Vec<T>, DatabaseError> {
+        execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            let mut query = QueryBuilder::select::<T>();
+            query.where_(column, value.clone());
+            if let Some((user, group)) = user {
+                query.user_group::<T>(user, group);
+            }
+            query.pagination::<T>(pagination);
+            query
+                .query_builder
+                .build_query_as()
+                .fetch_all(pool)
+                .timed()
+                .await
+                .map_err(DatabaseError::from)
+        })
+    }
+
+    /// runs an aggregate like `COUNT(*)` over the table of `T`, only over the rows with the `value` in the `column` if
+    /// there's a filter, without loading the objects
+    async fn aggregate<
+        T: DbObject,
+        V: for<'r> Encode<'r, sqlx::Sqlite>
+            + Type<sqlx::Sqlite>
+            + for<'r> Encode<'r, sqlx::Postgres>
+            + Type<sqlx::Postgres>
+            + Clone,
+    >(
+        &self,
+        aggregate: &str,
+        filter: Option<(&str, V)>,
+    ) -> Result<i64, DatabaseError> {
+        execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            let mut query = QueryBuilder::new(
+                sqlx::QueryBuilder::new(format!("SELECT {aggregate} FROM {}", T::table_name())),
+                QueryType::Select,
+            );
+            if let Some((column, value)) = filter.clone() {
+                query.where_(column, value);
+            }
+            query
+                .query_builder
+                .build_query_scalar::<i64>()
+                .fetch_one(pool)
+                .timed()
+                .await
+                .map_err(DatabaseError::from)
+        })
+    }
+
+    /// how many objects of `T` there are
+    pub async fn count<T: DbObject>(&self) -> Result<i64, DatabaseError> {
+        self.aggregate::<T, bool>("COUNT(*)", None).await
+    }
+
+    /// how many objects of `T` have the `value` in the `column`
+    pub async fn count_where<
+        T: DbObject,
+        V: for<'r> Encode<'r, sqlx::Sqlite>
+            + Type<sqlx::Sqlite>
+            + for<'r> Encode<'r, sqlx::Postgres>
+            + Type<sqlx::Postgres>
+            + Clone,
+    >(
+        &self,
+        column: &str,
+        value: V,
+    ) -> Result<i64, DatabaseError> {
+        self.aggregate::<T, V>("COUNT(*)", Some((column, value))).await
+    }
+
+    /// the sum of the integer `sum_column` of the objects of `T` with the `value` in the `column`, 0 if there are none
+    pub async fn sum_where<
+        T: DbObject,
+        V: for<'r> Encode<'r, sqlx::Sqlite>
+            + Type<sqlx::Sqlite>
+            + for<'r> Encode<'r, sqlx::Postgres>
+            + Type<sqlx::Postgres>
+            + Clone,
+    >(
+        &self,
+        sum_column: &str,
+        column: &str,
+        value: V,
+    ) -> Result<i64, DatabaseError> {
+        // postgres sums integers into a bigint, sqlite into an integer, both decode as an i64
+        self.aggregate::<T, V>(&format!("CAST(COALESCE(SUM({sum_column}), 0) AS BIGINT)"), Some((column, value)))
+            .await
+    }
+
+    /// the connections of the pool, for the admin overview
+    pub fn pool_stats(&self) -> PoolStats {
+        execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            PoolStats {
+                size: pool.size(),
+                idle: pool.num_idle(),
+                max: pool.options().get_max_connections(),
+            }
+        })
+    }
+
+    /// loads the small tables read by almost every request into the cache, like the groups the access checks need,
+    /// so the first requests after a restart don't all miss. returns how many objects were loaded
+    pub async fn warm_cache(&self) -> Result<u64, DatabaseError> {
+        Ok(self.warm_table::<Group>().await?
+            + self.warm_table::<ModLoader>().await?
+            + self.warm_table::<Version>().await?)
+    }
+
+    /// loads up to [`CACHES_SIZE`] objects of a table into its cache, as more would only evict each other
+    async fn warm_table<
+        T: DbObject
+            + for<'r> FromRow<'r, sqlx::sqlite::SqliteRow>
+            + for<'r> FromRow<'r, sqlx::postgres::PgRow>
+            + Unpin
+            + Cachable,
+    >(
+        &self,
+    ) -> Result<u64, DatabaseError> {
+        let values: Vec<T> = execute_on_enum!(&self.pool; (DatabasePool::Postgres, DatabasePool::Sqlite) |pool| {
+            let mut query = QueryBuilder::select::<T>();
+            query.query_builder.push(format!(" LIMIT {CACHES_SIZE}"));
+            query
+                .query_builder
+                .build_query_as()
+                .fetch_all(pool)
+                .timed()
+                .await
+                .map_err(DatabaseError::from)
+        })?;
+        let warmed = values.len() as u64;
+        debug!("warmed the cache of {} with {warmed} objects", T::table_name());
+        self.cache.insert_all(values).await;
+        Ok(warmed)
+    }
+
     /// This should only be used during testing or during first setup to create an admin account
     pub async fn create_user(&self, username: &str, password: &str) -> color_eyre::Result<User> {
         let user = User {
@@ -566,6 +1503,15 @@ impl Database {
     }
 }
 
+/// see [`Database::pool_stats`]
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PoolStats {
+    /// the open connections, idle or not
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+}
+
 #[derive(Debug, Clone)]
 pub enum DatabasePool {
     Postgres(Pool<sqlx::Postgres>),
@@ -648,6 +1594,15 @@ pub enum QueryType {
     Delete,
 }
 
+/// what [`Database::upsert`] does with an object that's already stored
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OnConflict {
+    /// `DO NOTHING`, the stored object is kept as it is
+    Nothing,
+    /// `DO UPDATE`, the stored object gets the columns of the new one, other than its [`Id`]
+    Update,
+}
+
 #[derive(Copy, Clone)]
 pub enum WhereOperand {
     Equal,
@@ -767,6 +1722,35 @@ where
         }
     }
 
+    /// turns an insert into an upsert matched by the [`DbObject::conflict_column`], returning the object if it was
+    /// inserted or updated. `ON CONFLICT` works the same in Sqlite and Postgres
+    pub fn on_conflict<T: DbObject>(&mut self, on_conflict: OnConflict) {
+        let conflict = T::conflict_column();
+        let id = T::columns()[T::id_column_index()].name;
+        let action = match on_conflict {
+            OnConflict::Nothing => String::from("DO NOTHING"),
+            OnConflict::Update => {
+                let mut set = T::columns()
+                    .iter()
+                    .filter(|column| column.name != id && column.name != conflict)
+                    .map(|column| format!("{name} = excluded.{name}", name = column.name))
+                    .collect::<Vec<_>>();
+                if set.is_empty() {
+                    // there's nothing to update, but DO NOTHING wouldn't return the row
+                    set.push(format!("{conflict} = excluded.{conflict}"));
+                }
+                format!("DO UPDATE SET {}", set.join(", "))
+            }
+        };
+        let columns = T::columns()
+            .iter()
+            .map(|column| column.name.to_string())
+            .collect::<Vec<String>>()
+            .join(",");
+        self.query_builder
+            .push(format!(" ON CONFLICT ({conflict}) {action} RETURNING {columns}"));
+    }
+
     pub fn where_id<T: DbObject>(&mut self, id: Id) {
         self.where_(T::columns()[T::id_column_index()].name(), id)
     }
@@ -825,6 +1809,34 @@ where
         self.where_operand(column, value, WhereOperand::GreaterThanOrEqual);
     }
 
+    pub fn where_in<F: Type<DB> + Encode<'a, DB> + 'a>(&mut self, column: &str, values: Vec<F>) {
+        if values.is_empty() {
+            // "IN ()" is not valid SQL, and nothing would match anyway
+            self.where_false();
+            return;
+        }
+        if self.params > 0 {
+            self.query_builder.push(format!(" AND {column} IN ("));
+        } else {
+            self.query_builder.push(format!(" WHERE {column} IN ("));
+        }
+        self.params += 1;
+        let mut separated = self.query_builder.separated(", ");
+        for value in values {
+            separated.push_bind(value);
+        }
+        self.query_builder.push(")");
+    }
+
+    fn where_false(&mut self) {
+        if self.params > 0 {
+            self.query_builder.push(" AND FALSE ");
+        } else {
+            self.query_builder.push(" WHERE FALSE ");
+        }
+        self.params += 1;
+    }
+
     pub fn where_null(&mut self, column: &str) {
         if self.params > 0 {
             self.query_builder.push(format!(" AND {column} IS NULL "));
@@ -848,6 +1860,32 @@ where
         }
     }
 
+    /// matches rows where any of the `columns` contains `term`, ignoring case
+    pub fn where_search(&mut self, columns: &[&str], term: &str)
+    where
+        String: Type<DB> + Encode<'a, DB>,
+    {
+        if columns.is_empty() {
+            return;
+        }
+        if self.params > 0 {
+            self.query_builder.push(" AND (");
+        } else {
+            self.query_builder.push(" WHERE (");
+        }
+        self.params += 1;
+        let pattern = like_pattern(term);
+        for (n, column) in columns.iter().enumerate() {
+            if n > 0 {
+                self.query_builder.push(" OR ");
+            }
+            self.query_builder.push(format!("LOWER({column}) LIKE "));
+            self.query_builder.push_bind(pattern.clone());
+            self.query_builder.push(" ESCAPE '\\'");
+        }
+        self.query_builder.push(")");
+    }
+
     pub fn user_group<T: DbObject>(&mut self, user: &User, group: &Group) {
         let access = match self.query_type {
             QueryType::Insert => T::create_access(),
@@ -867,21 +1905,128 @@ where
         }
     }
 
+    /// orders the rows by [`DbObject::default_sort`] and limits them to a page, so every row is on exactly one page
     pub fn pagination<T: DbObject>(&mut self, pagination: PaginationSettings) {
         self.query_builder.push(format!(
-            " LIMIT {} OFFSET {}",
+            " ORDER BY {} LIMIT {} OFFSET {}",
+            T::default_sort(),
             pagination.limit,
-            pagination.page * pagination.limit
+            pagination.offset().min(crate::api::handlers::MAX_PAGINATION_OFFSET)
         ));
     }
 }
 
+/// a `LIKE` pattern matching anything containing `term` (lowercased), with its wildcards escaped by `\\`
+fn like_pattern(term: &str) -> String {
+    let escaped = term
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+/// makes sure the query is a single `SELECT` statement, returning it without the trailing semicolon
+fn validate_select(query: &str) -> Result<&str, DatabaseError> {
+    let query = query.trim();
+    let query = query.strip_suffix(';').unwrap_or(query).trim_end();
+
+    // a semicolon anywhere else could start another statement. this also rejects it inside string literals, which is
+    // acceptable for reports
+    if query.contains(';') {
+        return Err(DatabaseError::BadRequest(String::from(
+            "only a single statement can be run",
+        )));
+    }
+    let keyword = query
+        .split(|char: char| char.is_whitespace() || char == '(' || char == '*')
+        .next()
+        .unwrap_or_default();
+    if !keyword.eq_ignore_ascii_case("select") {
+        return Err(DatabaseError::BadRequest(String::from(
+            "only SELECT queries can be run",
+        )));
+    }
+    Ok(query)
+}
+
+fn sqlite_row_to_json(row: &sqlx::sqlite::SqliteRow) -> Value {
+    use sqlx::{Column, Row, ValueRef};
+
+    let mut object = serde_json::Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        let value = match row.try_get_raw(i) {
+            Ok(value) if value.is_null() => Value::Null,
+            // sqlite values can have any type, so try them from the most to the least specific
+            Ok(_) => row
+                .try_get::<i64, _>(i)
+                .map(Value::from)
+                .or_else(|_| row.try_get::<f64, _>(i).map(Value::from))
+                .or_else(|_| row.try_get::<String, _>(i).map(Value::from))
+                .or_else(|_| {
+                    row.try_get::<Vec<u8>, _>(i)
+                        .map(|bytes| Value::from(crate::util::base64::base64_encode(&bytes)))
+                })
+                .unwrap_or(Value::Null),
+            Err(_) => Value::Null,
+        };
+        object.insert(column.name().to_string(), value);
+    }
+    Value::Object(object)
+}
+
+fn pg_row_to_json(row: &sqlx::postgres::PgRow) -> Value {
+    use sqlx::{Column, Row, ValueRef};
+
+    let mut object = serde_json::Map::new();
+    for (i, column) in row.columns().iter().enumerate() {
+        let value = match row.try_get_raw(i) {
+            Ok(value) if value.is_null() => Value::Null,
+            // postgres only decodes into the exact type of the column
+            Ok(_) => row
+                .try_get::<i64, _>(i)
+                .map(Value::from)
+                .or_else(|_| row.try_get::<i32, _>(i).map(Value::from))
+                .or_else(|_| row.try_get::<i16, _>(i).map(Value::from))
+                .or_else(|_| row.try_get::<f64, _>(i).map(Value::from))
+                .or_else(|_| row.try_get::<f32, _>(i).map(Value::from))
+                .or_else(|_| row.try_get::<bool, _>(i).map(Value::from))
+                .or_else(|_| row.try_get::<String, _>(i).map(Value::from))
+                .or_else(|_| row.try_get::<Uuid, _>(i).map(|uuid| Value::from(uuid.to_string())))
+                .or_else(|_| {
+                    row.try_get::<chrono::DateTime<chrono::Utc>, _>(i)
+                        .map(|date| Value::from(date.to_rfc3339()))
+                })
+                .or_else(|_| {
+                    row.try_get::<chrono::NaiveDateTime, _>(i)
+                        .map(|date| Value::from(date.to_string()))
+                })
+                .or_else(|_| {
+                    row.try_get::<Vec<u8>, _>(i)
+                        .map(|bytes| Value::from(crate::util::base64::base64_encode(&bytes)))
+                })
+                .unwrap_or(Value::Null),
+            Err(_) => Value::Null,
+        };
+        object.insert(column.name().to_string(), value);
+    }
+    Value::Object(object)
+}
+
 #[derive(Debug)]
 pub enum DatabaseError {
     Unauthorized,
     NotFound,
     Conflict,
+    /// the request contains an invalid value
+    BadRequest(String),
+    /// the request is valid, but can't be carried out, with a reason that can be shown to the user
+    Unprocessable(String),
     InternalServerError(String),
+    /// the database can't be reached, or no connection became free in time
+    Unavailable,
+    /// the object changed since the request's precondition was checked against it
+    PreconditionFailed,
     SqlxError(sqlx::Error),
 }
 
@@ -893,6 +2038,10 @@ impl Display for DatabaseError {
             DatabaseError::InternalServerError(err) => write!(f, "Internal server error: {err}"),
             DatabaseError::SqlxError(err) => write!(f, "Sqlx Error: {err}"),
             DatabaseError::Conflict => write!(f, "Conflict"),
+            DatabaseError::BadRequest(err) => write!(f, "Bad request: {err}"),
+            DatabaseError::Unprocessable(err) => write!(f, "Unprocessable: {err}"),
+            DatabaseError::Unavailable => write!(f, "Unavailable"),
+            DatabaseError::PreconditionFailed => write!(f, "Precondition failed"),
         }
     }
 }
@@ -903,6 +2052,17 @@ impl From<sqlx::Error> for DatabaseError {
     fn from(error: sqlx::Error) -> Self {
         match error {
             sqlx::Error::RowNotFound => DatabaseError::NotFound,
+            // a value was rejected by a CHECK constraint
+            sqlx::Error::Database(ref err) if err.is_check_violation() => {
+                DatabaseError::BadRequest(err.message().to_string())
+            }
+            // another row already has the value of a unique column, like the name of a group
+            sqlx::Error::Database(ref err) if err.is_unique_violation() => DatabaseError::Conflict,
+            // the pool reconnects on its own, but the query that ran into it is lost
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) => {
+                debug!("database unavailable: {error}");
+                DatabaseError::Unavailable
+            }
             _ => Self::SqlxError(error),
         }
     }
@@ -911,6 +2071,8 @@ impl From<sqlx::Error> for DatabaseError {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ValueType {
     Integer,
+    /// a 64 bit integer, for values that don't fit in [`ValueType::Integer`] on postgres
+    BigInteger,
     Float,
     Text,
     Boolean,
@@ -918,4 +2080,409 @@ pub enum ValueType {
     Id,
     Token,
     Datetime,
+    /// text that can only be one of the listed values
+    Enum(&'static [&'static str]),
+}
+
+#[tokio::test]
+async fn raw_query_test() {
+    use crate::database::testing::memory_pool;
+
+    let pool = memory_pool().await;
+    let database = Database::new(pool.into());
+
+    let (rows, truncated) = database
+        .raw_query("select 1 as one, 'two' as two, null as three;")
+        .await
+        .unwrap();
+    assert!(!truncated);
+    assert_eq!(
+        rows,
+        vec![serde_json::json!({"one": 1, "two": "two", "three": null})]
+    );
+
+    for query in [
+        "DELETE FROM users",
+        "SELECT 1; DROP TABLE users",
+        "WITH x AS (SELECT 1) SELECT * FROM x",
+        "",
+    ] {
+        assert!(matches!(
+            database.raw_query(query).await,
+            Err(DatabaseError::BadRequest(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn search_test() {
+    let database = Database::memory().await;
+
+    for name in ["Fabric", "NeoForge", "100%_vanilla"] {
+        database
+            .insert(
+                &ModLoader {
+                    id: Id::default(),
+                    name: String::from(name),
+                    can_load_mods: true,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    let search = |term: &'static str| {
+        let DatabasePool::Sqlite(pool) = database.pool.clone() else {
+            unreachable!()
+        };
+        async move {
+            let mut query = QueryBuilder::<sqlx::Sqlite>::select::<ModLoader>();
+            query.where_search(&["name"], term);
+            let loaders: Vec<ModLoader> = query.query_builder.build_query_as().fetch_all(&pool).await.unwrap();
+            let mut names: Vec<String> = loaders.into_iter().map(|loader| loader.name).collect();
+            names.sort();
+            names
+        }
+    };
+    assert_eq!(search("FORGE").await, vec!["NeoForge"]);
+    assert_eq!(search("a").await, vec!["100%_vanilla", "Fabric"]);
+    // wildcards are matched literally
+    assert_eq!(search("%_").await, vec!["100%_vanilla"]);
+    assert_eq!(search("_").await, vec!["100%_vanilla"]);
+}
+
+#[tokio::test]
+async fn warm_cache_test() {
+    let database = Database::memory().await;
+
+    let loader = ModLoader {
+        id: Id::default(),
+        name: String::from("Fabric"),
+        can_load_mods: true,
+    };
+    database.insert(&loader, None).await.unwrap();
+    database.cache.clear_all().await;
+
+    assert_eq!(database.warm_cache().await.unwrap(), 1);
+    assert!(database.cache.get::<ModLoader>(loader.id).await.is_some());
+}
+
+#[test]
+fn pagination_order_test() {
+    let mut query = QueryBuilder::<sqlx::Sqlite>::select::<Session>();
+    query.pagination::<Session>(PaginationSettings { page: 2, limit: 10 });
+    assert!(query.query_builder.sql().ends_with(" ORDER BY created, id LIMIT 10 OFFSET 20"));
+
+    let mut query = QueryBuilder::<sqlx::Sqlite>::select::<ModLoader>();
+    query.pagination::<ModLoader>(PaginationSettings { page: 0, limit: 10 });
+    assert!(query.query_builder.sql().ends_with(" ORDER BY id LIMIT 10 OFFSET 0"));
+}
+
+#[tokio::test]
+async fn validate_test() {
+    use crate::database::testing::test_world;
+
+    let database = Database::memory().await;
+
+    let user = User {
+        id: Id::default(),
+        username: String::from("  "),
+        group_id: Id::default(),
+        total_memory_usage: 0,
+        enabled: true,
+        minecraft_username: None,
+        minecraft_uuid: None,
+    };
+    assert!(matches!(database.insert(&user, None).await, Err(DatabaseError::BadRequest(_))));
+    let user = User {
+        username: String::from("user"),
+        total_memory_usage: -1,
+        ..user
+    };
+    assert!(matches!(database.insert(&user, None).await, Err(DatabaseError::BadRequest(_))));
+
+    let world = World {
+        hostname: String::from("My_World"),
+        ..test_world(user.id, Id::default())
+    };
+    assert!(matches!(database.insert(&world, None).await, Err(DatabaseError::BadRequest(_))));
+    let world = World {
+        hostname: String::from("world"),
+        allocated_memory: -1024,
+        ..world
+    };
+    assert!(matches!(database.insert(&world, None).await, Err(DatabaseError::BadRequest(_))));
+
+    let mut group = <Group as crate::database::objects::FromJson>::from_json(
+        &serde_json::from_value(serde_json::json!({"name": "group"})).unwrap(),
+        &user,
+    );
+    database.insert(&group, None).await.unwrap();
+    group.world_limit = Some(-1);
+    assert!(matches!(database.update(&group, None).await, Err(DatabaseError::BadRequest(_))));
+    assert_eq!(database.get_one::<Group>(group.id, None).await.unwrap().world_limit, None);
+}
+
+#[tokio::test]
+async fn health_test() {
+    use crate::database::testing::memory_pool;
+
+    let pool = memory_pool().await;
+    let database = Database::new(pool.clone().into());
+
+    database.ping().await.unwrap();
+    database.check_health(2).await;
+    assert!(database.is_available());
+
+    // the connection is gone, like after the database restarted
+    pool.close().await;
+    assert!(matches!(database.ping().await, Err(DatabaseError::Unavailable)));
+    database.check_health(2).await;
+    assert!(database.is_available());
+    database.check_health(2).await;
+    assert!(!database.is_available());
+    assert_eq!(
+        crate::api::handlers::handle_database_error(DatabaseError::Unavailable),
+        axum::http::StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    assert!(!database.health.record(false, 2));
+    assert!(database.health.record(true, 2));
+    assert!(database.is_available());
+}
+
+#[tokio::test]
+async fn session_limit_test() {
+    use chrono::{TimeDelta, Utc};
+
+    // the sessions belong to users that don't exist
+    let database = Database::memory_unchecked().await;
+
+    let session = |user_id: Id, created, expires| Session {
+        id: Id::new_random(),
+        user_id,
+        token: Uuid::new_v4(),
+        created,
+        expires,
+    };
+    let user_id = Id::new_random();
+    let now = Utc::now();
+    let max = crate::config::CONFIG.sessions.max_per_user;
+    let oldest = session(user_id, now - TimeDelta::hours(1), true);
+    database.insert(&oldest, None).await.unwrap();
+    assert_eq!(database.get_session(oldest.token, None).await.unwrap(), oldest);
+    for minutes in 0..max {
+        database
+            .insert(&session(user_id, now + TimeDelta::minutes(minutes as i64), true), None)
+            .await
+            .unwrap();
+    }
+
+    let sessions: Vec<Session> = database.get_all_where("user_id", user_id, None).await.unwrap();
+    assert_eq!(sessions.len(), max);
+    assert!(!sessions.contains(&oldest));
+    // it's not handed out from the cache either
+    assert!(database.get_session(oldest.token, None).await.is_err());
+    assert_eq!(database.evict_sessions(user_id, max - 1).await.unwrap(), 1);
+    assert_eq!(database.evict_sessions(Id::new_random(), 0).await.unwrap(), 0);
+
+    let lifetime = std::time::Duration::from_secs(crate::config::CONFIG.sessions.lifetime);
+    let old = now - TimeDelta::from_std(lifetime).unwrap() - TimeDelta::hours(1);
+    let expired = session(Id::new_random(), old, true);
+    let kept = session(Id::new_random(), old, false);
+    database.insert(&expired, None).await.unwrap();
+    database.insert(&kept, None).await.unwrap();
+    assert!(expired.is_expired(lifetime) && !kept.is_expired(lifetime));
+    assert!(database.get_session(expired.token, None).await.is_err());
+    assert_eq!(database.remove_expired_sessions(lifetime).await.unwrap(), 1);
+    assert!(database.get_one::<Session>(expired.id, None).await.is_err());
+    assert_eq!(database.get_session(kept.token, None).await.unwrap(), kept);
+}
+
+#[tokio::test]
+async fn aggregate_test() {
+    let database = Database::memory().await;
+
+    assert_eq!(database.sum_where::<ModLoader, _>("1", "can_load_mods", true).await.unwrap(), 0);
+    for (name, can_load_mods) in [("Vanilla", false), ("Fabric", true), ("NeoForge", true)] {
+        database
+            .insert(
+                &ModLoader {
+                    id: Id::default(),
+                    name: String::from(name),
+                    can_load_mods,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(database.count::<ModLoader>().await.unwrap(), 3);
+    assert_eq!(database.count_where::<ModLoader, _>("can_load_mods", true).await.unwrap(), 2);
+    assert_eq!(database.count_where::<ModLoader, _>("name", String::from("Forge")).await.unwrap(), 0);
+    assert_eq!(database.sum_where::<ModLoader, _>("1", "can_load_mods", false).await.unwrap(), 1);
+    assert_eq!(database.pool_stats().max, 1);
+}
+
+#[tokio::test]
+async fn upsert_test() {
+    use crate::database::testing::memory_pool;
+
+    let pool = memory_pool().await;
+    // a table made before the names were unique gets the index from init
+    let descriptor = ModLoader::database_descriptor(&DatabaseType::Sqlite);
+    sqlx::query(&format!("CREATE TABLE mod_loaders ({descriptor});"))
+        .execute(&pool)
+        .await
+        .unwrap();
+    let database = Database::new(pool.into());
+    database.init().await.unwrap();
+
+    let fabric = ModLoader {
+        id: Id::new_random(),
+        name: String::from("Fabric"),
+        can_load_mods: true,
+    };
+    assert_eq!(database.upsert(&fabric, OnConflict::Nothing).await.unwrap(), Some(fabric.clone()));
+
+    let changed = ModLoader {
+        id: Id::new_random(),
+        can_load_mods: false,
+        ..fabric.clone()
+    };
+    assert_eq!(database.upsert(&changed, OnConflict::Nothing).await.unwrap(), None);
+    let stored: ModLoader = database.get_where("name", String::from("Fabric"), None).await.unwrap();
+    assert_eq!(stored, fabric);
+
+    // the stored object keeps its id
+    let updated = database.upsert(&changed, OnConflict::Update).await.unwrap().unwrap();
+    assert_eq!(updated, ModLoader { id: fabric.id, ..changed.clone() });
+
+    // a plain insert of the same name is a conflict, not an internal error
+    let err = database.insert(&changed, None).await.unwrap_err();
+    assert!(matches!(err, DatabaseError::Conflict));
+    assert_eq!(crate::api::handlers::handle_database_error(err), axum::http::StatusCode::CONFLICT);
+    let stored: ModLoader = database.get_one(fabric.id, None).await.unwrap();
+    assert!(!stored.can_load_mods);
+    assert_eq!(database.count::<ModLoader>().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn stream_all_test() {
+    use futures::TryStreamExt;
+    use crate::database::testing::test_group;
+
+    let database = Database::memory().await;
+
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    let mut users = Vec::new();
+    for username in ["alice", "bob", "carol"] {
+        let user = User {
+            username: String::from(username),
+            group_id: group.id,
+            ..Default::default()
+        };
+        database.insert(&user, None).await.unwrap();
+        users.push(user);
+    }
+
+    let streamed: Vec<User> = database.stream_all(None).try_collect().await.unwrap();
+    assert_eq!(streamed.len(), 3);
+    assert!(users.iter().all(|user| streamed.contains(user)));
+
+    // the access filter leaves a regular user only themselves
+    let streamed: Vec<User> = database
+        .stream_all(Some((users[1].clone(), group.clone())))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(streamed, vec![users[1].clone()]);
+
+    // more rows than fit on a page are read over several queries, each row once
+    for n in 0..STREAM_PAGE_SIZE + 10 {
+        let mod_loader = ModLoader { id: Id::new_random(), name: format!("loader {n}"), can_load_mods: true };
+        database.insert(&mod_loader, None).await.unwrap();
+    }
+    let streamed: Vec<ModLoader> = database.stream_all(None).try_collect().await.unwrap();
+    assert_eq!(streamed.len(), STREAM_PAGE_SIZE + 10);
+    assert!(streamed.windows(2).all(|pair| i64::from(pair[0].id) < i64::from(pair[1].id)));
+
+    // dropping the stream early stops the query
+    let mut stream = Box::pin(database.stream_all::<User>(None));
+    assert!(stream.try_next().await.unwrap().is_some());
+    drop(stream);
+    assert_eq!(database.count::<User>().await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn get_many_test() {
+    use crate::database::testing::{test_group, test_world};
+
+    let database = Database::memory_unchecked().await;
+
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    let owner = User { id: Id::new_random(), username: String::from("owner"), group_id: group.id, ..Default::default() };
+    let other = User { id: Id::new_random(), username: String::from("other"), group_id: group.id, ..Default::default() };
+    let version_id = Id::new_random();
+
+    // more than fit in one query
+    let mut owned = Vec::new();
+    for i in 0..GET_MANY_BATCH_SIZE + 5 {
+        let world = World { id: Id::new_random(), hostname: format!("world{i}"), ..test_world(owner.id, version_id) };
+        database.insert(&world, None).await.unwrap();
+        owned.push(world.id);
+    }
+    let others = World { id: Id::new_random(), hostname: String::from("others"), ..test_world(other.id, version_id) };
+    database.insert(&others, None).await.unwrap();
+    database.cache.clear_all().await;
+
+    let mut ids = vec![others.id, Id::new_random()];
+    ids.extend(owned.iter().rev());
+    // a cached one, and one asked for twice
+    database.get_one::<World>(owned[0], None).await.unwrap();
+    ids.push(owned[0]);
+
+    let found: Vec<Id> = database
+        .get_many::<World>(&ids, Some((&owner, &group)))
+        .await
+        .unwrap()
+        .iter()
+        .map(DbObject::id)
+        .collect();
+    let mut expected: Vec<Id> = owned.iter().rev().copied().collect();
+    expected.push(owned[0]);
+    assert_eq!(found, expected);
+
+    // without a user the inaccessible ones are returned too, the missing ones never are
+    let found = database.get_many::<World>(&ids, None).await.unwrap();
+    assert_eq!(found.len(), owned.len() + 2);
+    assert_eq!(found[0].id, others.id);
+    assert!(database.get_many::<World>(&[], None).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn update_unchanged_test() {
+    use crate::database::testing::test_group;
+
+    let database = Database::memory().await;
+    let group = Group { id: Id::new_random(), ..test_group() };
+    database.insert(&group, None).await.unwrap();
+
+    // another update got in after the group was read, writing the copy read before it would undo it
+    let renamed = Group { name: String::from("renamed"), ..group.clone() };
+    database.update(&renamed, None).await.unwrap();
+    let stale = Group { can_upload_mods: true, ..group.clone() };
+    let result = database.update_unchanged(&stale, None, |stored| *stored == group).await;
+    assert!(matches!(result, Err(DatabaseError::PreconditionFailed)));
+    database.cache.clear_all().await;
+    assert_eq!(database.get_one::<Group>(group.id, None).await.unwrap(), renamed);
+
+    let updated = Group { can_upload_mods: true, ..renamed.clone() };
+    database.update_unchanged(&updated, None, |stored| *stored == renamed).await.unwrap();
+    database.cache.clear_all().await;
+    assert_eq!(database.get_one::<Group>(group.id, None).await.unwrap(), updated);
 }
@@ -2,6 +2,7 @@ use crate::database::types::{Access, Column, Id};
 use crate::database::{Database, DatabaseError, DatabaseType};
 use once_cell::sync::Lazy;
 use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
 
 pub mod group;
 pub mod invite_link;
@@ -10,10 +11,12 @@ pub mod modification;
 pub mod user;
 pub mod version;
 pub mod world;
+pub mod world_share;
 
 pub use self::{
     group::Group, invite_link::InviteLink, mod_loader::ModLoader, modification::Mod,
     user::Password, user::Session, user::User, version::Version, world::World,
+    world_share::WorldShare,
 };
 
 /// An object that is meant to be stored in a database
@@ -32,7 +35,7 @@ pub trait DbObject: Clone + Sync + Send {
     ///
     /// see [`Access::can_access`]
     fn viewable_by(&self, user: &User, group: &Group) -> bool {
-        Self::view_access().can_access::<Self>(Some(self), user, group)
+        Self::view_access().check::<Self>("viewing", Some(self), user, group)
     }
     /// whether a user can update this object using the API
     ///
@@ -40,7 +43,7 @@ pub trait DbObject: Clone + Sync + Send {
     ///
     /// see [`Access::can_access`]
     fn can_update(&self, user: &User, group: &Group) -> bool {
-        Self::update_access().can_access::<Self>(Some(self), user, group)
+        Self::update_access().check::<Self>("updating", Some(self), user, group)
     }
     /// whether a user can create this object using the API
     ///
@@ -48,7 +51,12 @@ pub trait DbObject: Clone + Sync + Send {
     ///
     /// see [`Access::can_access`]
     fn can_create(user: &User, group: &Group) -> bool {
-        Self::create_access().can_access::<Self>(None, user, group)
+        Self::create_access().check::<Self>("creating", None, user, group)
+    }
+    /// checks the fields of the object. it's called before the object is inserted or updated, so the checks apply no
+    /// matter if it came from the API or not
+    fn validate(&self) -> Result<(), DatabaseError> {
+        Ok(())
     }
 
     #[allow(unused)]
@@ -103,6 +111,16 @@ pub trait DbObject: Clone + Sync + Send {
     /// the name of the table SQL table the object will be stored in. used also for api routing
     fn table_name() -> &'static str;
 
+    /// description of the table, stored as a `COMMENT ON TABLE` on Postgres
+    fn table_comment() -> Option<&'static str> {
+        None
+    }
+
+    /// the text columns searched by the `q` parameter of list endpoints. if it's empty (the default) `q` is ignored
+    fn searchable_columns() -> &'static [&'static str] {
+        &[]
+    }
+
     /// a vector of [`Column`]s to be stored in the database
     fn columns() -> Lazy<Vec<Column>> {
         Self::COLUMNS
@@ -117,6 +135,16 @@ pub trait DbObject: Clone + Sync + Send {
     fn id_column_index() -> usize {
         0
     }
+    /// the `ORDER BY` of pages of the objects, so the same rows aren't on multiple pages. it has to be unique, as the
+    /// order of rows with the same value isn't guaranteed. default is the [`Id`] column
+    fn default_sort() -> &'static str {
+        Self::columns()[Self::id_column_index()].name
+    }
+    /// the column an upserted object is matched to a stored one by, see [`Database::upsert`]. if it's not the
+    /// [`Id`] column (the default) [`Database::init`] makes it unique
+    fn conflict_column() -> &'static str {
+        Self::columns()[Self::id_column_index()].name
+    }
     fn owner_id(&self) -> Option<Id> {
         None
     }
@@ -146,6 +174,25 @@ pub trait DbObject: Clone + Sync + Send {
             .collect::<Vec<_>>()
             .join(", ")
     }
+
+    /// the `COMMENT ON` statements describing the table and its columns
+    fn comment_statements() -> Vec<String> {
+        let quote = |comment: &str| format!("'{}'", comment.replace('\'', "''"));
+        Self::table_comment()
+            .map(|comment| format!("COMMENT ON TABLE {} IS {};", Self::table_name(), quote(comment)))
+            .into_iter()
+            .chain(Self::columns().iter().filter_map(|column| {
+                column.comment.map(|comment| {
+                    format!(
+                        "COMMENT ON COLUMN {}.{} IS {};",
+                        Self::table_name(),
+                        column.name,
+                        quote(comment)
+                    )
+                })
+            }))
+            .collect()
+    }
 }
 
 pub trait FromJson
@@ -157,6 +204,10 @@ where
     fn from_json(data: &Self::JsonFrom, user: &User) -> Self;
 }
 
+/// an update of an object, applied like a JSON merge patch ([RFC 7386](https://www.rfc-editor.org/rfc/rfc7386)).
+/// every field of [`UpdateJson::JsonUpdate`] should be annotated with
+/// `#[serde(default, deserialize_with = "deserialize_some")]`, so an absent field leaves the value unchanged, `null`
+/// clears it (nullable columns are `Option<Option<T>>`) and anything else sets it
 pub trait UpdateJson
 where
     Self: Sized,
@@ -164,3 +215,30 @@ where
     type JsonUpdate: Clone + DeserializeOwned + Send;
     fn update_with_json(&self, data: &Self::JsonUpdate) -> Self;
 }
+
+/// deserializes a present field as [`Some`], including `null`, see [`UpdateJson`]. together with `#[serde(default)]`
+/// an absent field is [`None`]. `null` is only accepted if `T` is an [`Option`]
+pub fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Deserialize::deserialize(deserializer).map(Some)
+}
+
+#[test]
+fn comment_statements() {
+    let statements = World::comment_statements();
+    assert_eq!(
+        statements[0],
+        "COMMENT ON TABLE worlds IS 'Minecraft servers, each with its own directory in the worlds directory';"
+    );
+    assert!(statements.contains(&String::from(
+        "COMMENT ON COLUMN worlds.allocated_memory IS 'memory given to the server, in MiB';"
+    )));
+    // quotes are escaped
+    assert!(statements.contains(&String::from(
+        "COMMENT ON COLUMN worlds.enabled IS 'whether the server is running, or should be started when it''s accessed';"
+    )));
+    assert!(Version::comment_statements().is_empty());
+}
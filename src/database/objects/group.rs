@@ -1,18 +1,23 @@
+use crate::api::filters::AdminAuth;
 use crate::api::handlers::{
-    ApiCreate, ApiGet, ApiList, ApiObject, ApiRemove, ApiUpdate,
+    handle_database_error, ApiCreate, ApiGet, ApiList, ApiObject, ApiRemove, ApiUpdate,
 };
 use crate::api::serve::AppState;
 use crate::config::CONFIG;
-use crate::database::objects::{DbObject, FromJson, UpdateJson, User};
-use crate::database::types::{Access, Column, Id};
+use crate::database::objects::{DbObject, deserialize_some, FromJson, UpdateJson, User};
+use crate::database::types::{Access, Column, Id, Memory};
 use crate::database::{Cachable, DatabaseError, ValueType};
 use crate::minecraft::server::ServerConfigLimit;
 use async_trait::async_trait;
-use axum::routing::get;
-use axum::Router;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use axum::{Json, Router};
 use duplicate::duplicate_item;
+use log::info;
 use once_cell::sync::Lazy;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Serialize};
 use sqlx::{Arguments, Error, FromRow, IntoArguments, Row};
 use std::any::Any;
 use std::collections::HashMap;
@@ -41,6 +46,15 @@ pub struct Group {
     /// If a value is prepended with < or >, it is considered an integer limit, and only values smaller than or equal, or larger than or equal will be accepted respectively.
     /// if a value is not prepended with < or >, it is considered a value whitelist, and the user will only be able to set this value. multiple allowed values can be set by using | to separate the values (for example "survival|creative")
     pub config_limits: HashMap<String, ServerConfigLimit>,
+    /// console commands members of the group will not be able to run
+    pub console_command_blacklist: Vec<String>,
+    /// console commands that are the only ones members of the group will be able to run
+    pub console_command_whitelist: Vec<String>,
+    /// niceness the servers of the group's users run with, overriding `world.nice` in the config
+    pub nice: Option<i32>,
+    /// how much CPU time each server of the group's users can use, in percent of one core. overrides
+    /// `world.cpu_limit` in the config
+    pub cpu_limit: Option<i32>,
     /// Whether a user can upload mods
     pub can_upload_mods: bool,
     /// whether a user has administrative privileges, this means they can manage other users and create new accounts
@@ -60,22 +74,81 @@ impl DbObject for Group {
         Access::PrivilegedUser
     }
 
+    fn validate(&self) -> Result<(), DatabaseError> {
+        if self.name.trim().is_empty() {
+            return Err(DatabaseError::BadRequest(String::from("the group name can't be empty")));
+        }
+        for (name, limit) in [
+            ("total_memory_limit", self.total_memory_limit),
+            ("per_world_memory_limit", self.per_world_memory_limit),
+            ("world_limit", self.world_limit),
+            ("active_world_limit", self.active_world_limit),
+            ("storage_limit", self.storage_limit),
+        ] {
+            if limit.is_some_and(|limit| limit < 0) {
+                return Err(DatabaseError::BadRequest(format!("{name} can't be negative")));
+            }
+        }
+        for (name, keys) in [("config_blacklist", &self.config_blacklist), ("config_whitelist", &self.config_whitelist)] {
+            if let Some(key) = keys.iter().find(|key| !is_property_key(key)) {
+                return Err(DatabaseError::BadRequest(format!(
+                    "{key:?} in {name} is not a server.properties key"
+                )));
+            }
+        }
+        if let Some(key) = self.config_limits.keys().find(|key| !is_property_key(key)) {
+            return Err(DatabaseError::BadRequest(format!(
+                "{key:?} in config_limits is not a server.properties key"
+            )));
+        }
+        Ok(())
+    }
+
     fn table_name() -> &'static str {
         "groups"
     }
 
+    fn searchable_columns() -> &'static [&'static str] {
+        &["name"]
+    }
+
+    fn conflict_column() -> &'static str {
+        "name"
+    }
+
+    fn table_comment() -> Option<&'static str> {
+        Some("permissions and limits shared by the users in the group")
+    }
+
     const COLUMNS: Lazy<Vec<Column>> = Lazy::new(|| {
         vec![
             Column::new("id", ValueType::Id).primary_key(),
             Column::new("name", ValueType::Text).not_null(),
-            Column::new("total_memory_limit", ValueType::Integer),
-            Column::new("per_world_memory_limit", ValueType::Integer),
-            Column::new("world_limit", ValueType::Integer),
-            Column::new("active_world_limit", ValueType::Integer),
-            Column::new("storage_limit", ValueType::Integer),
+            Column::new("total_memory_limit", ValueType::Integer)
+                .check("total_memory_limit >= 0")
+                .comment("memory all enabled worlds of a user can use together, in MiB. NULL is unlimited"),
+            Column::new("per_world_memory_limit", ValueType::Integer)
+                .check("per_world_memory_limit >= 0")
+                .comment("memory a single world can use, in MiB. NULL is unlimited"),
+            Column::new("world_limit", ValueType::Integer).check("world_limit >= 0"),
+            Column::new("active_world_limit", ValueType::Integer).check("active_world_limit >= 0"),
+            Column::new("storage_limit", ValueType::Integer)
+                .check("storage_limit >= 0")
+                .comment("disk space a user's worlds can use, in MiB. NULL is unlimited"),
             Column::new("config_blacklist", ValueType::Text),
             Column::new("config_whitelist", ValueType::Text),
-            Column::new("config_limits", ValueType::Text),
+            Column::new("config_limits", ValueType::Text)
+                .comment("JSON map of server.properties keys to the values users can set"),
+            Column::new("console_command_blacklist", ValueType::Text)
+                .not_null()
+                .default("'[]'"),
+            Column::new("console_command_whitelist", ValueType::Text)
+                .not_null()
+                .default("'[]'"),
+            Column::new("nice", ValueType::Integer).check("nice BETWEEN -20 AND 19"),
+            Column::new("cpu_limit", ValueType::Integer)
+                .check("cpu_limit > 0")
+                .comment("CPU time a server can use, in percent of one core"),
             Column::new("can_upload_mods", ValueType::Boolean)
                 .not_null()
                 .default("FALSE"),
@@ -90,12 +163,82 @@ impl DbObject for Group {
     }
 }
 
+impl Group {
+    /// whether users of this group can change the `server.properties` key
+    pub fn can_edit_config(&self, key: &str) -> bool {
+        if self.config_whitelist.is_empty() {
+            !self.config_blacklist.iter().any(|k| k == key)
+        } else {
+            self.config_whitelist.iter().any(|k| k == key)
+        }
+    }
+
+    /// whether users of this group can run `command` in the console. the command is matched by its name, see
+    /// [`crate::minecraft::util::command_name`]. privileged users can run any command
+    pub fn can_run_command(&self, command: &str) -> bool {
+        if self.is_privileged {
+            return true;
+        }
+        let name = crate::minecraft::util::command_name(command);
+        let listed = |list: &Vec<String>| {
+            list.iter()
+                .any(|listed| crate::minecraft::util::command_name(listed).eq_ignore_ascii_case(name))
+        };
+        if self.console_command_whitelist.is_empty() {
+            !listed(&self.console_command_blacklist)
+        } else {
+            listed(&self.console_command_whitelist)
+        }
+    }
+}
+
+/// whether the key could be in `server.properties`. servers and mods add their own keys, so there's no list of the
+/// valid ones, only the characters the vanilla keys are made of
+pub fn is_property_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '.' | '_'))
+}
+
+/// trims the keys of a config list, dropping the empty and repeated ones. see [`check_config_keys`] for lists of only
+/// empty keys
+fn normalize_config_keys(keys: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::with_capacity(keys.len());
+    for key in keys.iter().map(|key| key.trim()) {
+        if !key.is_empty() && !normalized.iter().any(|existing| existing == key) {
+            normalized.push(key.to_string());
+        }
+    }
+    normalized
+}
+
+/// fails for a list that has keys, but none once normalized. an empty whitelist allows every key, so a whitelist of
+/// only blank keys would allow everything instead of nothing
+fn check_config_keys(name: &str, keys: Option<&[String]>) -> Result<(), DatabaseError> {
+    match keys {
+        Some(keys) if !keys.is_empty() && normalize_config_keys(keys).is_empty() => Err(DatabaseError::Unprocessable(
+            format!("{name} only has empty keys, leave it empty instead"),
+        )),
+        _ => Ok(()),
+    }
+}
+
 impl Cachable for Group {
     fn into_any(self: Box<Self>) -> Box<dyn Any> {
         self as Box<dyn Any>
     }
 }
 
+/// decodes one of the JSON encoded columns. a malformed value is a decode error of the column instead of a panic, so a
+/// single bad row doesn't take down the request
+fn decode_json<T: serde::de::DeserializeOwned>(column: &str, value: &str) -> Result<T, Error> {
+    serde_json::from_str(value).map_err(|err| Error::ColumnDecode {
+        index: column.to_string(),
+        source: format!("invalid JSON {value:?}: {err}").into(),
+    })
+}
+
 #[duplicate_item(Row; [sqlx::sqlite::SqliteRow]; [sqlx::postgres::PgRow])]
 impl FromRow<'_, Row> for Group {
     fn from_row(row: &'_ Row) -> Result<Self, Error> {
@@ -107,12 +250,20 @@ impl FromRow<'_, Row> for Group {
             world_limit: row.try_get("world_limit")?,
             active_world_limit: row.try_get("active_world_limit")?,
             storage_limit: row.try_get("storage_limit")?,
-            config_blacklist: serde_json::from_str(&row.try_get::<String, _>("config_blacklist")?)
-                .unwrap(),
-            config_whitelist: serde_json::from_str(&row.try_get::<String, _>("config_whitelist")?)
-                .unwrap(),
-            config_limits: serde_json::from_str(&row.try_get::<String, _>("config_limits")?)
-                .unwrap(),
+            config_blacklist: decode_json("config_blacklist", &row.try_get::<String, _>("config_blacklist")?)?,
+            config_whitelist: decode_json("config_whitelist", &row.try_get::<String, _>("config_whitelist")?)?,
+            config_limits: decode_json("config_limits", &row.try_get::<String, _>("config_limits")?)?,
+            console_command_blacklist: decode_json(
+                "console_command_blacklist",
+                // tables upgraded before the column had a default can have NULL in it
+                row.try_get::<Option<String>, _>("console_command_blacklist")?.as_deref().unwrap_or("[]"),
+            )?,
+            console_command_whitelist: decode_json(
+                "console_command_whitelist",
+                row.try_get::<Option<String>, _>("console_command_whitelist")?.as_deref().unwrap_or("[]"),
+            )?,
+            nice: row.try_get("nice")?,
+            cpu_limit: row.try_get("cpu_limit")?,
             can_upload_mods: row.try_get("can_upload_mods")?,
             is_privileged: row.try_get("is_privileged")?,
         })
@@ -127,6 +278,10 @@ impl<'a> IntoArguments<'a, sqlx::Sqlite> for Group {
             serde_json::to_string(&self.config_whitelist).expect("serialization failed");
         let config_limits =
             serde_json::to_string(&self.config_limits).expect("serialization failed");
+        let console_command_blacklist =
+            serde_json::to_string(&self.console_command_blacklist).expect("serialization failed");
+        let console_command_whitelist =
+            serde_json::to_string(&self.console_command_whitelist).expect("serialization failed");
 
         let mut arguments = sqlx::sqlite::SqliteArguments::default();
 
@@ -156,6 +311,16 @@ impl<'a> IntoArguments<'a, sqlx::Sqlite> for Group {
         arguments
             .add(config_limits)
             .expect("Failed to add argument");
+        arguments
+            .add(console_command_blacklist)
+            .expect("Failed to add argument");
+        arguments
+            .add(console_command_whitelist)
+            .expect("Failed to add argument");
+        arguments.add(self.nice).expect("Failed to add argument");
+        arguments
+            .add(self.cpu_limit)
+            .expect("Failed to add argument");
         arguments
             .add(self.can_upload_mods)
             .expect("Failed to add argument");
@@ -174,6 +339,10 @@ impl<'a> IntoArguments<'a, sqlx::Postgres> for Group {
             serde_json::to_string(&self.config_whitelist).expect("serialization failed");
         let config_limits =
             serde_json::to_string(&self.config_limits).expect("serialization failed");
+        let console_command_blacklist =
+            serde_json::to_string(&self.console_command_blacklist).expect("serialization failed");
+        let console_command_whitelist =
+            serde_json::to_string(&self.console_command_whitelist).expect("serialization failed");
 
         let mut arguments = sqlx::postgres::PgArguments::default();
 
@@ -203,6 +372,19 @@ impl<'a> IntoArguments<'a, sqlx::Postgres> for Group {
         arguments
             .add(config_limits)
             .expect("Failed to add argument");
+        arguments
+            .add(console_command_blacklist)
+            .expect("Failed to add argument");
+        arguments
+            .add(console_command_whitelist)
+            .expect("Failed to add argument");
+        arguments.add(self.nice).expect("Failed to add argument");
+        arguments
+            .add(self.cpu_limit)
+            .expect("Failed to add argument");
+        arguments
+            .add(self.can_upload_mods)
+            .expect("Failed to add argument");
         arguments
             .add(self.is_privileged)
             .expect("Failed to add argument");
@@ -214,26 +396,72 @@ impl ApiObject for Group {
     fn routes() -> Router<AppState> {
         Router::new()
             .route("/", get(Self::api_list).post(Self::api_create))
+            .route("/delete", post(Self::api_remove_many))
+            .route("/import", post(Self::api_import))
             .route(
                 "/{id}",
                 get(Self::api_get)
                     .patch(Self::api_update)
                     .delete(Self::api_remove),
             )
+            .route("/{id}/export", get(Self::api_export))
     }
 }
 
+impl Group {
+    /// `GET /api/groups/{id}/export`, the group as a [`GroupTemplate`]
+    async fn api_export(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        AdminAuth(_admin): AdminAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group: Group = state
+            .database
+            .get_one(id, None)
+            .await
+            .map_err(handle_database_error)?;
+        Ok(Json(GroupTemplate::from(&group)))
+    }
+
+    /// `POST /api/groups/import`, creates a group from a [`GroupTemplate`]. it gets the name of the exported group
+    /// unless it's given another with `?name=`
+    async fn api_import(
+        Query(query): Query<ImportQuery>,
+        State(state): State<AppState>,
+        AdminAuth(admin): AdminAuth,
+        Json(template): Json<GroupTemplate>,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = template.into_group(query.name).map_err(handle_database_error)?;
+        state
+            .database
+            .insert(&group, None)
+            .await
+            .map_err(handle_database_error)?;
+        info!("{} imported the group {}", admin.username, group.name);
+        Ok(Json(group))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImportQuery {
+    pub name: Option<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonFrom {
     pub name: String,
-    pub total_memory_limit: Option<u32>,
-    pub per_world_memory_limit: Option<u32>,
+    pub total_memory_limit: Option<Memory>,
+    pub per_world_memory_limit: Option<Memory>,
     pub world_limit: Option<u32>,
     pub active_world_limit: Option<u32>,
     pub storage_limit: Option<u32>,
     pub config_blacklist: Option<Vec<String>>,
     pub config_whitelist: Option<Vec<String>>,
     pub config_limits: Option<HashMap<String, ServerConfigLimit>>,
+    pub console_command_blacklist: Option<Vec<String>>,
+    pub console_command_whitelist: Option<Vec<String>>,
+    pub nice: Option<i8>,
+    pub cpu_limit: Option<u32>,
     pub can_upload_mods: Option<bool>,
     pub is_privileged: Option<bool>,
 }
@@ -244,35 +472,26 @@ impl FromJson for Group {
         Self {
             id: Id::default(),
             name: data.name.clone(),
-            total_memory_limit: data
-                .total_memory_limit
-                .map(|v| v.try_into().unwrap_or(i32::MAX)),
-            per_world_memory_limit: data
-                .per_world_memory_limit
-                .map(|v| v.try_into().unwrap_or(i32::MAX)),
+            total_memory_limit: data.total_memory_limit.map(i32::from),
+            per_world_memory_limit: data.per_world_memory_limit.map(i32::from),
             world_limit: data.world_limit.map(|v| v.try_into().unwrap_or(i32::MAX)),
             active_world_limit: data
                 .active_world_limit
                 .map(|v| v.try_into().unwrap_or(i32::MAX)),
             storage_limit: data.storage_limit.map(|v| v.try_into().unwrap_or(i32::MAX)),
-            config_blacklist: data.config_blacklist.clone().unwrap_or_default(),
-            config_whitelist: data.config_whitelist.clone().unwrap_or_default(),
+            config_blacklist: normalize_config_keys(data.config_blacklist.as_deref().unwrap_or_default()),
+            config_whitelist: normalize_config_keys(data.config_whitelist.as_deref().unwrap_or_default()),
             config_limits: data.config_limits.clone().unwrap_or_default(),
+            console_command_blacklist: data.console_command_blacklist.clone().unwrap_or_default(),
+            console_command_whitelist: data.console_command_whitelist.clone().unwrap_or_default(),
+            nice: data.nice.map(i32::from),
+            cpu_limit: data.cpu_limit.map(|v| v.try_into().unwrap_or(i32::MAX)),
             can_upload_mods: data.can_upload_mods.unwrap_or(false),
             is_privileged: data.is_privileged.unwrap_or(false),
         }
     }
 }
 
-// sqlx::Sqlite value that is present is considered Some value, including null.
-fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
-where
-    T: Deserialize<'de>,
-    D: Deserializer<'de>,
-{
-    Deserialize::deserialize(deserializer).map(Some)
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonUpdate {
     #[serde(default, deserialize_with = "deserialize_some")]
@@ -280,9 +499,9 @@ pub struct JsonUpdate {
     #[serde(default, deserialize_with = "deserialize_some")]
     pub avatar_id: Option<Option<Id>>,
     #[serde(default, deserialize_with = "deserialize_some")]
-    pub total_memory_limit: Option<Option<u32>>,
+    pub total_memory_limit: Option<Option<Memory>>,
     #[serde(default, deserialize_with = "deserialize_some")]
-    pub per_world_memory_limit: Option<Option<u32>>,
+    pub per_world_memory_limit: Option<Option<Memory>>,
     #[serde(default, deserialize_with = "deserialize_some")]
     pub world_limit: Option<Option<u32>>,
     #[serde(default, deserialize_with = "deserialize_some")]
@@ -296,6 +515,14 @@ pub struct JsonUpdate {
     #[serde(default, deserialize_with = "deserialize_some")]
     pub config_limits: Option<HashMap<String, ServerConfigLimit>>,
     #[serde(default, deserialize_with = "deserialize_some")]
+    pub console_command_blacklist: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub console_command_whitelist: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub nice: Option<Option<i8>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub cpu_limit: Option<Option<u32>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub can_upload_mods: Option<bool>,
     #[serde(default, deserialize_with = "deserialize_some")]
     pub is_privileged: Option<bool>,
@@ -307,11 +534,11 @@ impl UpdateJson for Group {
         new.name = data.name.clone().unwrap_or(new.name);
         new.total_memory_limit = data
             .total_memory_limit
-            .map(|v| v.map(|v| v.try_into().unwrap_or(i32::MAX)))
+            .map(|v| v.map(i32::from))
             .unwrap_or(new.total_memory_limit);
         new.per_world_memory_limit = data
             .per_world_memory_limit
-            .map(|v| v.map(|v| v.try_into().unwrap_or(i32::MAX)))
+            .map(|v| v.map(i32::from))
             .unwrap_or(new.per_world_memory_limit);
         new.world_limit = data
             .world_limit
@@ -325,15 +552,30 @@ impl UpdateJson for Group {
             .storage_limit
             .map(|v| v.map(|v| v.try_into().unwrap_or(i32::MAX)))
             .unwrap_or(new.storage_limit);
-        new.config_blacklist = data
-            .config_blacklist
+        // only the lists in the request, normalizing a stored one could change what it allows
+        if let Some(config_blacklist) = &data.config_blacklist {
+            new.config_blacklist = normalize_config_keys(config_blacklist);
+        }
+        if let Some(config_whitelist) = &data.config_whitelist {
+            new.config_whitelist = normalize_config_keys(config_whitelist);
+        }
+        new.config_limits = data.config_limits.clone().unwrap_or(new.config_limits);
+        new.console_command_blacklist = data
+            .console_command_blacklist
             .clone()
-            .unwrap_or(new.config_blacklist);
-        new.config_whitelist = data
-            .config_whitelist
+            .unwrap_or(new.console_command_blacklist);
+        new.console_command_whitelist = data
+            .console_command_whitelist
             .clone()
-            .unwrap_or(new.config_whitelist);
-        new.config_limits = data.config_limits.clone().unwrap_or(new.config_limits);
+            .unwrap_or(new.console_command_whitelist);
+        new.nice = data
+            .nice
+            .map(|v| v.map(i32::from))
+            .unwrap_or(new.nice);
+        new.cpu_limit = data
+            .cpu_limit
+            .map(|v| v.map(|v| v.try_into().unwrap_or(i32::MAX)))
+            .unwrap_or(new.cpu_limit);
         new.can_upload_mods = data.can_upload_mods.unwrap_or(new.can_upload_mods);
         new.is_privileged = data.is_privileged.unwrap_or(new.is_privileged);
         new
@@ -342,8 +584,30 @@ impl UpdateJson for Group {
 
 impl ApiList for Group {}
 impl ApiGet for Group {}
-impl ApiCreate for Group {}
-impl ApiUpdate for Group {}
+#[async_trait]
+impl ApiCreate for Group {
+    async fn before_api_create(
+        _state: AppState,
+        json: &mut Self::JsonFrom,
+        _user: &User,
+    ) -> Result<(), DatabaseError> {
+        check_config_keys("config_blacklist", json.config_blacklist.as_deref())?;
+        check_config_keys("config_whitelist", json.config_whitelist.as_deref())
+    }
+}
+
+#[async_trait]
+impl ApiUpdate for Group {
+    async fn before_api_update(
+        &self,
+        _state: AppState,
+        json: &mut Self::JsonUpdate,
+        _user: &User,
+    ) -> Result<(), DatabaseError> {
+        check_config_keys("config_blacklist", json.config_blacklist.as_deref())?;
+        check_config_keys("config_whitelist", json.config_whitelist.as_deref())
+    }
+}
 
 #[async_trait]
 impl ApiRemove for Group {
@@ -359,3 +623,264 @@ impl ApiRemove for Group {
         }
     }
 }
+
+/// the version of the [`GroupTemplate`] format, raised when it changes in a way older versions can't read
+pub const GROUP_TEMPLATE_VERSION: u32 = 1;
+
+/// the limits and config lists of a [`Group`] without its id, so they can be imported as a new group, on this instance
+/// or another one. see [`Group::api_export`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupTemplate {
+    /// [`GROUP_TEMPLATE_VERSION`] of the instance that exported it
+    pub version: u32,
+    pub name: String,
+    pub total_memory_limit: Option<i32>,
+    pub per_world_memory_limit: Option<i32>,
+    pub world_limit: Option<i32>,
+    pub active_world_limit: Option<i32>,
+    pub storage_limit: Option<i32>,
+    #[serde(default)]
+    pub config_blacklist: Vec<String>,
+    #[serde(default)]
+    pub config_whitelist: Vec<String>,
+    #[serde(default)]
+    pub config_limits: HashMap<String, ServerConfigLimit>,
+    #[serde(default)]
+    pub console_command_blacklist: Vec<String>,
+    #[serde(default)]
+    pub console_command_whitelist: Vec<String>,
+    pub nice: Option<i32>,
+    pub cpu_limit: Option<i32>,
+    #[serde(default)]
+    pub can_upload_mods: bool,
+    #[serde(default)]
+    pub is_privileged: bool,
+}
+
+impl From<&Group> for GroupTemplate {
+    fn from(group: &Group) -> Self {
+        Self {
+            version: GROUP_TEMPLATE_VERSION,
+            name: group.name.clone(),
+            total_memory_limit: group.total_memory_limit,
+            per_world_memory_limit: group.per_world_memory_limit,
+            world_limit: group.world_limit,
+            active_world_limit: group.active_world_limit,
+            storage_limit: group.storage_limit,
+            config_blacklist: group.config_blacklist.clone(),
+            config_whitelist: group.config_whitelist.clone(),
+            config_limits: group.config_limits.clone(),
+            console_command_blacklist: group.console_command_blacklist.clone(),
+            console_command_whitelist: group.console_command_whitelist.clone(),
+            nice: group.nice,
+            cpu_limit: group.cpu_limit,
+            can_upload_mods: group.can_upload_mods,
+            is_privileged: group.is_privileged,
+        }
+    }
+}
+
+impl GroupTemplate {
+    /// a new group made from the template, named `name` or the template's name. the config lists are normalized and
+    /// everything is validated like a created group
+    pub fn into_group(self, name: Option<String>) -> Result<Group, DatabaseError> {
+        if self.version > GROUP_TEMPLATE_VERSION {
+            return Err(DatabaseError::BadRequest(format!(
+                "the template is of version {}, only {GROUP_TEMPLATE_VERSION} and older are supported",
+                self.version
+            )));
+        }
+        check_config_keys("config_blacklist", Some(&self.config_blacklist))?;
+        check_config_keys("config_whitelist", Some(&self.config_whitelist))?;
+        let group = Group {
+            id: Id::new_random(),
+            name: name.unwrap_or(self.name).trim().to_string(),
+            total_memory_limit: self.total_memory_limit,
+            per_world_memory_limit: self.per_world_memory_limit,
+            world_limit: self.world_limit,
+            active_world_limit: self.active_world_limit,
+            storage_limit: self.storage_limit,
+            config_blacklist: normalize_config_keys(&self.config_blacklist),
+            config_whitelist: normalize_config_keys(&self.config_whitelist),
+            config_limits: self.config_limits,
+            console_command_blacklist: self.console_command_blacklist,
+            console_command_whitelist: self.console_command_whitelist,
+            nice: self.nice,
+            cpu_limit: self.cpu_limit,
+            can_upload_mods: self.can_upload_mods,
+            is_privileged: self.is_privileged,
+        };
+        group.validate()?;
+        Ok(group)
+    }
+}
+
+#[tokio::test]
+async fn malformed_json_column() {
+    use crate::database::testing::{memory_pool, test_group};
+
+    let pool = memory_pool().await;
+    let database = crate::database::Database::new(pool.clone().into());
+    database.init().await.expect("failed to init the database");
+
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    sqlx::query("UPDATE groups SET config_limits = '{\"view-distance\": '")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    // read past the cache, straight from the table
+    match sqlx::query_as::<_, Group>("SELECT * FROM groups")
+        .fetch_one(&pool)
+        .await
+    {
+        Err(Error::ColumnDecode { index, source }) => {
+            assert_eq!(index, "config_limits");
+            assert!(source.to_string().contains("view-distance"));
+        }
+        result => panic!("expected a decode error, got {result:?}"),
+    }
+}
+
+#[tokio::test]
+async fn upgraded_table_test() {
+    use crate::database::testing::memory_pool;
+
+    // the groups table as the first versions created it, with a group in it
+    let baseline = async |id: Id| {
+        let pool = memory_pool().await;
+        sqlx::query(
+            "CREATE TABLE groups (id BIGINT PRIMARY KEY, name TEXT NOT NULL, total_memory_limit INTEGER, \
+             per_world_memory_limit INTEGER, world_limit INTEGER, active_world_limit INTEGER, storage_limit INTEGER, \
+             config_blacklist TEXT, config_whitelist TEXT, config_limits TEXT, \
+             can_upload_mods BOOLEAN NOT NULL DEFAULT FALSE, is_privileged BOOLEAN NOT NULL DEFAULT FALSE);",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        sqlx::query(
+            "INSERT INTO groups (id, name, config_blacklist, config_whitelist, config_limits) \
+             VALUES (?, 'default', '[]', '[]', '{}');",
+        )
+        .bind(id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    };
+
+    let id = Id::new_random();
+    let database = crate::database::Database::new(baseline(id).await.into());
+    database.init().await.expect("failed to init the database");
+    let group: Group = database.get_one(id, None).await.unwrap();
+    assert_eq!(group.name, "default");
+    assert!(group.console_command_blacklist.is_empty());
+    assert!(group.console_command_whitelist.is_empty());
+    assert_eq!(group.nice, None);
+
+    // upgraded before the console command columns had a default, so they are NULL
+    let pool = baseline(id).await;
+    for column in ["console_command_blacklist", "console_command_whitelist"] {
+        sqlx::query(&format!("ALTER TABLE groups ADD COLUMN {column} TEXT;"))
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+    let database = crate::database::Database::new(pool.into());
+    database.init().await.expect("failed to init the database");
+    let group: Group = database.get_one(id, None).await.unwrap();
+    assert!(group.console_command_blacklist.is_empty());
+    assert!(group.console_command_whitelist.is_empty());
+}
+
+#[test]
+fn config_keys_test() {
+    let dirty = vec![
+        String::from(" view-distance"),
+        String::new(),
+        String::from("player-idle-timeout"),
+        String::from("view-distance "),
+        String::from("  "),
+        String::from("player-idle-timeout"),
+    ];
+    let user = User {
+        id: Id::new_random(),
+        username: String::from("admin"),
+        group_id: Id::new_random(),
+        total_memory_usage: 0,
+        enabled: true,
+        minecraft_username: None,
+        minecraft_uuid: None,
+    };
+    let json: JsonFrom = serde_json::from_value(serde_json::json!({
+        "name": "dirty",
+        "config_whitelist": dirty,
+    }))
+    .unwrap();
+    let group = Group::from_json(&json, &user);
+    assert_eq!(group.config_whitelist, vec!["view-distance", "player-idle-timeout"]);
+    assert!(group.config_blacklist.is_empty());
+    assert!(group.validate().is_ok());
+
+    let update: JsonUpdate = serde_json::from_value(serde_json::json!({"config_blacklist": ["motd", "motd", ""]})).unwrap();
+    let updated = Group { config_whitelist: dirty.clone(), ..group }.update_with_json(&update);
+    assert_eq!(updated.config_blacklist, vec!["motd"]);
+    // lists that aren't in the update are kept as they are
+    assert_eq!(updated.config_whitelist, dirty);
+
+    // a whitelist of only blank keys would allow every key
+    let blank = vec![String::new(), String::from("  ")];
+    assert!(matches!(check_config_keys("config_whitelist", Some(&blank)), Err(DatabaseError::Unprocessable(_))));
+    assert!(check_config_keys("config_whitelist", Some(&[])).is_ok());
+    assert!(check_config_keys("config_whitelist", None).is_ok());
+
+    let invalid = Group { config_blacklist: vec![String::from("motd=hi")], ..updated };
+    assert!(matches!(invalid.validate(), Err(DatabaseError::BadRequest(_))));
+    assert!(is_property_key("query.port") && !is_property_key("level name") && !is_property_key(""));
+}
+
+#[tokio::test]
+async fn group_template_test() {
+    use crate::database::testing::test_group;
+
+    let database = crate::database::Database::memory().await;
+
+    let group = Group {
+        id: Id::new_random(),
+        name: String::from("players"),
+        total_memory_limit: Some(4096),
+        per_world_memory_limit: Some(2048),
+        world_limit: Some(3),
+        active_world_limit: Some(1),
+        config_whitelist: vec![String::from("motd"), String::from("view-distance")],
+        config_limits: HashMap::from([(String::from("view-distance"), ServerConfigLimit::Range(3, 16))]),
+        console_command_blacklist: vec![String::from("op")],
+        nice: Some(5),
+        cpu_limit: Some(200),
+        can_upload_mods: true,
+        ..test_group()
+    };
+    database.insert(&group, None).await.unwrap();
+
+    let exported = serde_json::to_value(GroupTemplate::from(&group)).unwrap();
+    assert!(exported.get("id").is_none());
+    let template: GroupTemplate = serde_json::from_value(exported).unwrap();
+
+    // the name is taken, so it needs another one
+    assert!(database.insert(&template.clone().into_group(None).unwrap(), None).await.is_err());
+    let imported = template.clone().into_group(Some(String::from("more players"))).unwrap();
+    database.insert(&imported, None).await.unwrap();
+    let imported: Group = database.get_one(imported.id, None).await.unwrap();
+    assert_ne!(imported.id, group.id);
+    assert_eq!(imported.name, "more players");
+    assert_eq!(Group { id: group.id, name: group.name.clone(), ..imported }, group);
+
+    let newer = GroupTemplate { version: GROUP_TEMPLATE_VERSION + 1, ..template.clone() };
+    assert!(matches!(newer.into_group(None), Err(DatabaseError::BadRequest(_))));
+    let invalid = GroupTemplate {
+        config_limits: HashMap::from([(String::from("view distance"), ServerConfigLimit::Range(3, 16))]),
+        ..template
+    };
+    assert!(matches!(invalid.into_group(None), Err(DatabaseError::BadRequest(_))));
+}
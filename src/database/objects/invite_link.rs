@@ -1,20 +1,22 @@
 use crate::api::handlers::{ApiCreate, ApiGet, ApiList, ApiObject, ApiRemove};
 use crate::api::serve::AppState;
+use crate::config::{CONFIG, PublicUrl};
 use crate::database::objects::{DbObject, FromJson, User};
 use crate::database::types::{Access, Column, Id};
 use crate::database::{Cachable, ValueType};
 use axum::Router;
-use axum::routing::get;
+use axum::routing::{get, post};
 use chrono::{DateTime, Utc};
 use duplicate::duplicate_item;
 use once_cell::sync::Lazy;
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use sqlx::{Arguments, FromRow, IntoArguments, Row};
 use std::any::Any;
 use std::fmt::Debug;
 use uuid::Uuid;
 
-#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize)]
 pub struct InviteLink {
     /// Unique [`Id`] of the invite link
     pub id: Id,
@@ -65,6 +67,34 @@ impl DbObject for InviteLink {
     }
 }
 
+impl InviteLink {
+    /// the link to register with the invite, on the frontend at `base`
+    pub fn url_at(&self, base: &PublicUrl) -> String {
+        let mut url = base.page("register");
+        url.query_pairs_mut()
+            .append_pair("token", &self.invite_token.simple().to_string());
+        url.into()
+    }
+
+    /// the link to share, [`None`] without [`crate::config::Config::public_url`]
+    pub fn url(&self) -> Option<String> {
+        CONFIG.public_url.as_ref().map(|base| self.url_at(base))
+    }
+}
+
+/// serialized with the [`InviteLink::url`], which isn't stored
+impl Serialize for InviteLink {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("InviteLink", 5)?;
+        state.serialize_field("id", &self.id)?;
+        state.serialize_field("invite_token", &self.invite_token.simple())?;
+        state.serialize_field("creator_id", &self.creator_id)?;
+        state.serialize_field("created", &self.created)?;
+        state.serialize_field("url", &self.url())?;
+        state.end()
+    }
+}
+
 impl Cachable for InviteLink {
     fn into_any(self: Box<Self>) -> Box<dyn Any> {
         self as Box<dyn Any>
@@ -133,6 +163,7 @@ impl ApiObject for InviteLink {
     fn routes() -> Router<AppState> {
         Router::new()
             .route("/", get(Self::api_list).post(Self::api_create))
+            .route("/delete", post(Self::api_remove_many))
             .route("/{id}", get(Self::api_get).delete(Self::api_remove))
     }
 }
@@ -140,3 +171,23 @@ impl ApiList for InviteLink {}
 impl ApiGet for InviteLink {}
 impl ApiCreate for InviteLink {}
 impl ApiRemove for InviteLink {}
+
+#[test]
+fn invite_url_test() {
+    let link = InviteLink {
+        id: Id::default(),
+        invite_token: Uuid::parse_str("0f5c9b2e-8a1d-4f6b-9c3e-2d7a1b4e6f80").unwrap(),
+        creator_id: Id::default(),
+        created: Utc::now(),
+    };
+    let base = "https://mc.example.net/".parse().unwrap();
+    assert_eq!(
+        link.url_at(&base),
+        "https://mc.example.net/register?token=0f5c9b2e8a1d4f6b9c3e2d7a1b4e6f80"
+    );
+
+    // the token is still sent without dashes, and it can be read back
+    let json = serde_json::to_value(&link).unwrap();
+    assert_eq!(json["invite_token"], "0f5c9b2e8a1d4f6b9c3e2d7a1b4e6f80");
+    assert_eq!(serde_json::from_value::<InviteLink>(json).unwrap(), link);
+}
@@ -1,10 +1,10 @@
 use crate::api::handlers::{ApiCreate, ApiGet, ApiList, ApiObject, ApiRemove, ApiUpdate};
 use crate::api::serve::AppState;
-use crate::database::objects::{DbObject, FromJson, UpdateJson, User};
+use crate::database::objects::{DbObject, deserialize_some, FromJson, UpdateJson, User};
 use crate::database::types::{Access, Column, Id};
 use crate::database::{Cachable, ValueType};
 use axum::Router;
-use axum::routing::get;
+use axum::routing::{get, post};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use sqlx::{Arguments, FromRow, IntoArguments};
@@ -38,6 +38,14 @@ impl DbObject for ModLoader {
         "mod_loaders"
     }
 
+    fn searchable_columns() -> &'static [&'static str] {
+        &["name"]
+    }
+
+    fn conflict_column() -> &'static str {
+        "name"
+    }
+
     const COLUMNS: Lazy<Vec<Column>> = Lazy::new(|| {
         vec![
             Column::new("id", ValueType::Id).primary_key(),
@@ -102,7 +110,9 @@ impl FromJson for ModLoader {
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonUpdate {
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub can_load_mods: Option<bool>,
 }
 
@@ -120,6 +130,7 @@ impl ApiObject for ModLoader {
     fn routes() -> Router<AppState> {
         Router::new()
             .route("/", get(Self::api_list).post(Self::api_create))
+            .route("/delete", post(Self::api_remove_many))
             .route(
                 "/{id}",
                 get(Self::api_get)
@@ -1,17 +1,36 @@
 use crate::api::handlers::{ApiCreate, ApiGet, ApiIcon, ApiList, ApiObject, ApiRemove, ApiUpdate};
 use crate::api::serve::AppState;
-use crate::database::objects::{DbObject, FromJson, UpdateJson, User};
-use crate::database::types::{Access, Column, Id};
-use crate::database::{Cachable, DatabaseError, ValueType};
+use crate::database::objects::{DbObject, deserialize_some, FromJson, Group, ModLoader, UpdateJson, User, Version, World};
+use crate::database::types::{Access, Column, Id, IdList};
+use crate::database::{Cachable, Database, DatabaseError, ValueType};
 use async_trait::async_trait;
 use axum::Router;
 use axum::routing::{get, post};
 use once_cell::sync::Lazy;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Serialize};
 use sqlx::{Arguments, FromRow, IntoArguments};
 use std::any::Any;
+use std::collections::HashMap;
 use std::fmt::Debug;
-use axum::extract::DefaultBodyLimit;
+use axum::extract::{DefaultBodyLimit, Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use log::{debug, error, info, warn};
+use std::path::PathBuf;
+use crate::api::filters::{FileUpload, UserAuth};
+use crate::api::handlers;
+use crate::api::handlers::handle_database_error;
+use crate::config::CONFIG;
+use crate::minecraft::jar;
+use crate::minecraft::jar::ScanError;
+use crate::minecraft::modrinth;
+use crate::util;
+
+/// largest mod jar that can be uploaded, in bytes
+const MAX_JAR_SIZE: usize = 64 * 1024 * 1024;
+/// how deep [`Mod::dependency_tree`] follows the dependencies of dependencies
+const MAX_DEPENDENCY_DEPTH: usize = 8;
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, FromRow)]
 pub struct Mod {
@@ -29,6 +48,9 @@ pub struct Mod {
     pub modrinth_id: Option<String>,
     /// Whether the mod is accessible to all user, or just the owner
     pub public: bool,
+    /// [`Mod`]s that have to be installed alongside this one
+    #[sqlx(try_from = "String")]
+    pub dependencies: IdList,
 }
 
 impl DbObject for Mod {
@@ -50,6 +72,10 @@ impl DbObject for Mod {
         "mods"
     }
 
+    fn searchable_columns() -> &'static [&'static str] {
+        &["name", "description"]
+    }
+
     const COLUMNS: Lazy<Vec<Column>> = Lazy::new(|| {
         vec![
             Column::new("id", ValueType::Id).primary_key(),
@@ -63,9 +89,19 @@ impl DbObject for Mod {
             Column::new("description", ValueType::Text).not_null(),
             Column::new("modrinth_id", ValueType::Text),
             Column::new("public", ValueType::Boolean).not_null(),
+            Column::new("dependencies", ValueType::Text)
+                .not_null()
+                .default("''"),
         ]
     });
 
+    fn validate(&self) -> Result<(), DatabaseError> {
+        if self.dependencies.contains(&self.id) {
+            return Err(DatabaseError::BadRequest(String::from("a mod can't depend on itself")));
+        }
+        Ok(())
+    }
+
     fn id(&self) -> Id {
         self.id
     }
@@ -103,6 +139,9 @@ impl<'a> IntoArguments<'a, sqlx::Sqlite> for Mod {
             .expect("Failed to add argument");
         arguments.add(self.public).expect("Failed to add argument");
         arguments
+            .add(self.dependencies.to_string())
+            .expect("Failed to add argument");
+        arguments
     }
 }
 
@@ -125,18 +164,12 @@ impl<'a> IntoArguments<'a, sqlx::Postgres> for Mod {
             .expect("Failed to add argument");
         arguments.add(self.public).expect("Failed to add argument");
         arguments
+            .add(self.dependencies.to_string())
+            .expect("Failed to add argument");
+        arguments
     }
 }
 
-// sqlx::Sqlite value that is present is considered Some value, including null.
-fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
-where
-    T: Deserialize<'de>,
-    D: Deserializer<'de>,
-{
-    Deserialize::deserialize(deserializer).map(Some)
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonFrom {
     pub version_id: Id,
@@ -144,6 +177,7 @@ pub struct JsonFrom {
     pub description: Option<String>,
     pub modrinth_id: Option<String>,
     pub public: Option<bool>,
+    pub dependencies: Option<IdList>,
 }
 
 impl FromJson for Mod {
@@ -158,18 +192,25 @@ impl FromJson for Mod {
             modrinth_id: data.modrinth_id.clone(),
             public: data.public.unwrap_or(false),
             owner_id: user.id,
+            dependencies: data.dependencies.clone().unwrap_or_default(),
         }
     }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonUpdate {
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub version_id: Option<Id>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub description: Option<String>,
-    #[serde(deserialize_with = "deserialize_some")]
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub modrinth_id: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub public: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub dependencies: Option<IdList>,
 }
 
 impl UpdateJson for Mod {
@@ -181,6 +222,7 @@ impl UpdateJson for Mod {
         new.name = data.name.clone().unwrap_or(new.name);
         new.modrinth_id = data.modrinth_id.clone().unwrap_or(new.modrinth_id);
         new.public = data.public.unwrap_or(new.public);
+        new.dependencies = data.dependencies.clone().unwrap_or(new.dependencies);
         new
     }
 }
@@ -189,12 +231,18 @@ impl ApiObject for Mod {
     fn routes() -> Router<AppState> {
         Router::new()
             .route("/", get(Self::api_list).post(Self::api_create))
+            .route("/delete", post(Self::api_remove_many))
             .route(
                 "/{id}",
                 get(Self::api_get)
                     .patch(Self::api_update)
                     .delete(Self::api_remove),
             )
+            .route("/{id}/dependencies", get(Self::get_dependencies))
+            .route(
+                "/{id}/jar",
+                post(Self::upload_jar).layer(DefaultBodyLimit::max(MAX_JAR_SIZE)),
+            )
             .route(
                 "/{id}/icon",
                 post(Self::upload_icon)
@@ -208,7 +256,32 @@ impl ApiObject for Mod {
     }
 }
 
-impl ApiList for Mod {}
+#[async_trait]
+impl ApiList for Mod {
+    /// `compatible_with=<world id>` only lists the mods for the world's Minecraft version and mod loader
+    async fn list_constraints(
+        state: &AppState,
+        user: &User,
+        filters: &mut Vec<(String, String)>,
+    ) -> Result<Vec<(&'static str, Vec<Id>)>, StatusCode> {
+        let Some(index) = filters.iter().position(|(column, _)| column == "compatible_with") else {
+            return Ok(Vec::new());
+        };
+        let (_, world_id) = filters.remove(index);
+        let world_id = Id::from_string(&world_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+        let group = user.group(state.database.clone(), None).await;
+        let world: World = state
+            .database
+            .get_one(world_id, Some((user, &group)))
+            .await
+            .map_err(handle_database_error)?;
+        let versions = Self::compatible_versions(&state.database, &world)
+            .await
+            .map_err(handle_database_error)?;
+        Ok(vec![("version_id", versions)])
+    }
+}
 impl ApiGet for Mod {}
 #[async_trait]
 impl ApiCreate for Mod {
@@ -217,7 +290,7 @@ impl ApiCreate for Mod {
         json: &mut Self::JsonFrom,
         user: &User,
     ) -> Result<(), DatabaseError> {
-        let group = user.group(state.database, None).await;
+        let group = user.group(state.database.clone(), None).await;
 
         if !group.can_upload_mods {
             return Err(DatabaseError::Unauthorized);
@@ -227,6 +300,21 @@ impl ApiCreate for Mod {
             json.public = Some(false);
         }
 
+        Version::check_exists(&state.database, json.version_id, user, &group).await?;
+        if let Some(dependencies) = &json.dependencies {
+            check_dependencies(&state, dependencies, user, &group).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn after_api_create(
+        &self,
+        _state: AppState,
+        _json: &mut Self::JsonFrom,
+        _user: &User,
+    ) -> Result<(), DatabaseError> {
+        self.fetch_icon();
         Ok(())
     }
 }
@@ -238,16 +326,381 @@ impl ApiUpdate for Mod {
         json: &mut Self::JsonUpdate,
         user: &User,
     ) -> Result<(), DatabaseError> {
-        let group = user.group(state.database, None).await;
+        let group = user.group(state.database.clone(), None).await;
         if !group.is_privileged {
             json.public = Some(false);
         }
 
+        if let Some(version_id) = json.version_id
+            && version_id != self.version_id
+        {
+            Version::check_exists(&state.database, version_id, user, &group).await?;
+        }
+        if let Some(dependencies) = &json.dependencies {
+            check_dependencies(&state, dependencies, user, &group).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn after_api_update(
+        &self,
+        _state: AppState,
+        json: &mut Self::JsonUpdate,
+        _user: &User,
+    ) -> Result<(), DatabaseError> {
+        if matches!(json.modrinth_id, Some(Some(_))) {
+            self.fetch_icon();
+        }
+        Ok(())
+    }
+}
+#[async_trait]
+impl ApiRemove for Mod {
+    async fn before_api_delete(&self, _state: AppState, _user: &User) -> Result<(), DatabaseError> {
+        let path = self.jar_path();
+        if path.exists() {
+            std::fs::remove_file(path)
+                .map_err(|err| DatabaseError::InternalServerError(err.to_string()))?;
+        }
         Ok(())
     }
 }
-impl ApiRemove for Mod {}
+
+/// fails if any of the `dependencies` doesn't exist, or the user can't see it
+async fn check_dependencies(
+    state: &AppState,
+    dependencies: &[Id],
+    user: &User,
+    group: &Group,
+) -> Result<(), DatabaseError> {
+    let found: Vec<Mod> = state.database.get_many(dependencies, Some((user, group))).await?;
+    match dependencies.iter().find(|id| !found.iter().any(|found| found.id == **id)) {
+        Some(missing) => Err(DatabaseError::BadRequest(format!("the dependency {missing} doesn't exist"))),
+        None => Ok(()),
+    }
+}
+
+/// whether a dependency in a [`DependencyTree`] could be resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyStatus {
+    Resolved,
+    /// the mod doesn't exist, or the user can't see it
+    Missing,
+    /// the mod already appears higher up in the tree, its dependencies aren't listed again
+    Cycle,
+    /// the tree is deeper than [`MAX_DEPENDENCY_DEPTH`], the mod's dependencies aren't listed
+    TooDeep,
+}
+
+/// a mod and everything it depends on
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyTree {
+    pub id: Id,
+    /// [`None`] if the mod is [`DependencyStatus::Missing`]
+    pub name: Option<String>,
+    pub status: DependencyStatus,
+    pub dependencies: Vec<DependencyTree>,
+}
+
+impl DependencyTree {
+    /// the tree of the mod with the `id`, out of the known `mods`. `path` holds the mods above it
+    fn build(id: Id, mods: &HashMap<Id, Mod>, path: &mut Vec<Id>) -> Self {
+        let Some(modification) = mods.get(&id) else {
+            return Self { id, name: None, status: DependencyStatus::Missing, dependencies: Vec::new() };
+        };
+        let leaf = |status| Self {
+            id,
+            name: Some(modification.name.clone()),
+            status,
+            dependencies: Vec::new(),
+        };
+        if path.contains(&id) {
+            return leaf(DependencyStatus::Cycle);
+        }
+        if path.len() >= MAX_DEPENDENCY_DEPTH {
+            return leaf(DependencyStatus::TooDeep);
+        }
+
+        path.push(id);
+        let dependencies = modification
+            .dependencies
+            .iter()
+            .map(|dependency| Self::build(*dependency, mods, path))
+            .collect();
+        path.pop();
+        Self { dependencies, ..leaf(DependencyStatus::Resolved) }
+    }
+
+    /// the mods in the tree that couldn't be found
+    pub fn missing(&self) -> Vec<Id> {
+        let mut missing = Vec::new();
+        if self.status == DependencyStatus::Missing {
+            missing.push(self.id);
+        }
+        for dependency in &self.dependencies {
+            for id in dependency.missing() {
+                if !missing.contains(&id) {
+                    missing.push(id);
+                }
+            }
+        }
+        missing
+    }
+}
+
+impl Mod {
+    /// the versions mods can be made for to be installable on the world, the ones with its Minecraft version and mod
+    /// loader
+    pub async fn compatible_versions(database: &Database, world: &World) -> Result<Vec<Id>, DatabaseError> {
+        let version: Version = database.get_one(world.version_id, None).await?;
+        Ok(database
+            .get_all_where::<Version, _>("minecraft_version", version.minecraft_version.clone(), None)
+            .await?
+            .into_iter()
+            .filter(|candidate| candidate.mod_loader_id == version.mod_loader_id)
+            .map(|candidate| candidate.id)
+            .collect())
+    }
+
+    /// resolves the dependencies of the mod the `user` can see, down to [`MAX_DEPENDENCY_DEPTH`]
+    pub async fn dependency_tree(
+        &self,
+        state: &AppState,
+        user: &User,
+        group: &Group,
+    ) -> Result<DependencyTree, DatabaseError> {
+        let mut mods = HashMap::from([(self.id, self.clone())]);
+        let mut next: Vec<Id> = self.dependencies.to_vec();
+        // one level of the tree at a time, there's no need to load what's below the deepest level shown
+        for _ in 0..MAX_DEPENDENCY_DEPTH {
+            next.retain(|id| !mods.contains_key(id));
+            if next.is_empty() {
+                break;
+            }
+            let found: Vec<Mod> = state.database.get_many(&next, Some((user, group))).await?;
+            next = found.iter().flat_map(|found| found.dependencies.iter().copied()).collect();
+            mods.extend(found.into_iter().map(|found| (found.id, found)));
+        }
+        Ok(DependencyTree::build(self.id, &mods, &mut Vec::new()))
+    }
+
+    /// `GET /api/mods/{id}/dependencies`, the [`DependencyTree`] of the mod
+    async fn get_dependencies(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<Json<DependencyTree>, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let modification: Self = state
+            .database
+            .get_one(id, Some((&user, &group)))
+            .await
+            .map_err(handle_database_error)?;
+        let tree = modification
+            .dependency_tree(&state, &user, &group)
+            .await
+            .map_err(handle_database_error)?;
+        let missing = tree.missing();
+        if !missing.is_empty() {
+            warn!("mod {id} is missing the dependencies {missing:?}");
+        }
+        Ok(Json(tree))
+    }
+
+    /// gives the mod the icon of its Modrinth project in the background, unless it already has one
+    fn fetch_icon(&self) {
+        let Some(project) = self.modrinth_id.clone() else {
+            return;
+        };
+        if !CONFIG.modrinth.fetch_icons || handlers::has_icon(Self::table_name(), self.id) {
+            return;
+        }
+        let id = self.id;
+        tokio::task::spawn(async move {
+            let (bytes, format) = match modrinth::icon(&project).await {
+                Ok(Some(icon)) => icon,
+                Ok(None) => return debug!("the Modrinth project {project} of mod {id} has no icon"),
+                Err(err) => return warn!("could not fetch the icon of mod {id} from Modrinth: {err}"),
+            };
+            // the icon could have been uploaded while it was downloading
+            if handlers::has_icon(Self::table_name(), id) {
+                return;
+            }
+            match tokio::task::spawn_blocking(move || handlers::save_icon(Self::table_name(), id, &bytes, format)).await {
+                Ok(Ok(_)) => info!("fetched the icon of mod {id} from Modrinth"),
+                Ok(Err(status)) => warn!("could not store the Modrinth icon of mod {id}: {status}"),
+                Err(err) => error!("{err}"),
+            }
+        });
+    }
+
+    /// where the mod's uploaded jar is stored
+    pub fn jar_path(&self) -> PathBuf {
+        util::dirs::mod_jars_dir().join(format!("{}.jar", self.id))
+    }
+
+    /// stores the uploaded jar of the mod, if it passes the configured [`jar::JarScanner`]s
+    async fn upload_jar(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        file: FileUpload,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let modification: Self = state
+            .database
+            .get_one(id, Some((&user, &group)))
+            .await
+            .map_err(handle_database_error)?;
+
+        if !group.can_upload_mods || !modification.can_update(&user, &group) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        let version: Version = state
+            .database
+            .get_one(modification.version_id, None)
+            .await
+            .map_err(handle_database_error)?;
+        let loader: ModLoader = state
+            .database
+            .get_one(version.mod_loader_id, None)
+            .await
+            .map_err(handle_database_error)?;
+        if !loader.can_load_mods {
+            return Ok((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("{} can't load mods", loader.name),
+            )
+                .into_response());
+        }
+
+        let path = modification.jar_path();
+        // scanned under a different name, so a rejected upload never replaces a good jar
+        let partial = path.with_extension("jar.part");
+        tokio::fs::write(&partial, &file.bytes).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let result = tokio::task::spawn_blocking({
+            let partial = partial.clone();
+            move || jar::scan(&partial, &jar::scanners(Some(&loader.name)))
+        })
+        .await
+        .map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        if let Err(err) = result {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return match err {
+                ScanError::Rejected(reason) => {
+                    info!("rejected jar uploaded by {} for mod {}: {reason}", user.id, modification.id);
+                    Ok((StatusCode::UNPROCESSABLE_ENTITY, reason).into_response())
+                }
+                ScanError::Internal(err) => {
+                    error!("could not scan jar of mod {}: {err}", modification.id);
+                    Err(StatusCode::INTERNAL_SERVER_ERROR)
+                }
+            };
+        }
+
+        tokio::fs::rename(&partial, &path).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(StatusCode::NO_CONTENT.into_response())
+    }
+}
 impl ApiIcon for Mod {
     const DEFAULT_ICON_BYTES: &'static [u8] = include_bytes!("../../resources/icons/mod_default.png");
     const DEFAULT_ICON_MIME: &'static str = "image/png";
 }
+
+#[test]
+fn dependency_tree_test() {
+    use std::str::FromStr;
+
+    let ids: Vec<Id> = (0..4).map(|_| Id::new_random()).collect();
+    let modification = |index: usize, dependencies: &[usize]| Mod {
+        id: ids[index],
+        owner_id: Id::new_random(),
+        version_id: Id::new_random(),
+        name: format!("mod {index}"),
+        description: String::new(),
+        modrinth_id: None,
+        public: true,
+        dependencies: IdList(dependencies.iter().map(|index| ids[*index]).collect()),
+    };
+    let missing = Id::new_random();
+    let mut mods = HashMap::new();
+    for modification in [modification(0, &[1, 2]), modification(1, &[2]), modification(2, &[0]), modification(3, &[])] {
+        mods.insert(modification.id, modification);
+    }
+    mods.get_mut(&ids[1]).unwrap().dependencies.0.push(missing);
+
+    let tree = DependencyTree::build(ids[0], &mods, &mut Vec::new());
+    assert_eq!(tree.status, DependencyStatus::Resolved);
+    assert_eq!(tree.dependencies.len(), 2);
+    let first = &tree.dependencies[0];
+    assert_eq!((first.id, first.status), (ids[1], DependencyStatus::Resolved));
+    // 0 -> 1 -> 2 -> 0 is a cycle
+    assert_eq!(first.dependencies[0].dependencies[0].status, DependencyStatus::Cycle);
+    assert_eq!(first.dependencies[1].status, DependencyStatus::Missing);
+    assert_eq!(tree.missing(), vec![missing]);
+
+    // a chain longer than the depth limit is cut off
+    let chain: Vec<Id> = (0..=MAX_DEPENDENCY_DEPTH).map(|_| Id::new_random()).collect();
+    let mut mods = HashMap::new();
+    for (index, id) in chain.iter().enumerate() {
+        let mut link = modification(3, &[]);
+        link.id = *id;
+        link.dependencies = IdList(chain.get(index + 1).copied().into_iter().collect());
+        mods.insert(*id, link);
+    }
+    let mut tree = DependencyTree::build(chain[0], &mods, &mut Vec::new());
+    for _ in 0..MAX_DEPENDENCY_DEPTH {
+        assert_eq!(tree.status, DependencyStatus::Resolved);
+        tree = tree.dependencies.remove(0);
+    }
+    assert_eq!(tree.status, DependencyStatus::TooDeep);
+
+    assert_eq!(IdList::from_str("").unwrap(), IdList::default());
+    let list = IdList(vec![ids[0], ids[1]]);
+    assert_eq!(IdList::from_str(&list.to_string()).unwrap(), list);
+    assert!(modification(0, &[0]).validate().is_err());
+}
+
+#[tokio::test]
+async fn compatible_versions_test() {
+    use crate::database::objects::version::VersionKind;
+    use crate::database::testing::test_world;
+
+    // the world's owner doesn't exist
+    let database = Database::memory_unchecked().await;
+
+    let (fabric, forge) = (Id::new_random(), Id::new_random());
+    let version = |minecraft_version: &str, mod_loader_id: Id| Version {
+        id: Id::new_random(),
+        minecraft_version: minecraft_version.to_string(),
+        mod_loader_id,
+        min_java: None,
+        required_java: None,
+        kind: VersionKind::Release,
+        jar_sha1: None,
+    };
+    let versions = [version("1.21", fabric), version("1.21", forge), version("1.20.6", fabric)];
+    for version in &versions {
+        database.insert(version, None).await.unwrap();
+    }
+    let world = World {
+        id: Id::new_random(),
+        ..test_world(Id::new_random(), versions[0].id)
+    };
+
+    assert_eq!(Mod::compatible_versions(&database, &world).await.unwrap(), vec![versions[0].id]);
+}
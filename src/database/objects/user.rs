@@ -1,21 +1,26 @@
 pub use self::{password::Password, session::Session};
+use crate::api::filters::{AdminAuth, UserAuth};
 use crate::api::handlers::{
-    ApiCreate, ApiGet, ApiIcon, ApiList, ApiObject, ApiRemove, ApiUpdate,
+    handle_database_error, ApiCreate, ApiGet, ApiIcon, ApiList, ApiObject, ApiRemove, ApiUpdate,
 };
 use crate::api::serve::AppState;
 use crate::config::CONFIG;
 use crate::database;
-use crate::database::objects::{DbObject, FromJson, Group, Mod, UpdateJson, World};
+use crate::database::objects::{DbObject, deserialize_some, FromJson, Group, Mod, UpdateJson, World};
 use crate::database::types::{Access, Column, Id};
 use crate::database::{Cachable, Database, DatabaseError, ValueType};
+use crate::minecraft::account;
 use async_trait::async_trait;
-use axum::extract::DefaultBodyLimit;
-use axum::routing::{get, post};
-use axum::Router;
+use axum::extract::{DefaultBodyLimit, Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
 use futures::future;
 use log::{error, info, warn};
 use once_cell::sync::Lazy;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::{Arguments, FromRow, IntoArguments};
 use std::any::Any;
 
@@ -31,6 +36,12 @@ pub struct User {
     pub total_memory_usage: i64,
     /// whether the user can access the API
     pub enabled: bool,
+    /// the user's Minecraft account, which they're made an operator as on their worlds, see
+    /// [`crate::database::objects::World`]'s `/op`
+    pub minecraft_username: Option<String>,
+    /// the UUID of [`User::minecraft_username`], set when the account was looked up or linked as an offline one, see
+    /// [`crate::minecraft::account`]
+    pub minecraft_uuid: Option<uuid::Uuid>,
 }
 
 impl DbObject for User {
@@ -47,6 +58,23 @@ impl DbObject for User {
         Access::PrivilegedUser
     }
 
+    fn validate(&self) -> Result<(), DatabaseError> {
+        if self.username.trim().is_empty() {
+            return Err(DatabaseError::BadRequest(String::from("the username can't be empty")));
+        }
+        if self.total_memory_usage < 0 {
+            return Err(DatabaseError::BadRequest(String::from(
+                "the total memory usage can't be negative",
+            )));
+        }
+        if let Some(name) = &self.minecraft_username
+            && !crate::minecraft::util::is_player_name(name)
+        {
+            return Err(DatabaseError::BadRequest(format!("{name:?} is not a Minecraft username")));
+        }
+        Ok(())
+    }
+
     // delete passwords and sessions. worlds and mods are handled asynchronously through `before_api_remove()`
     async fn before_delete(&self, database: &database::Database) -> Result<(), DatabaseError> {
         if let Ok(passwords) = database.get_all_where::<Password, _>("user_id", self.id, None/*in theory here the access restriction should be put but i couldn't be bothered with that*/).await {
@@ -68,6 +96,14 @@ impl DbObject for User {
         "users"
     }
 
+    fn searchable_columns() -> &'static [&'static str] {
+        &["username"]
+    }
+
+    fn table_comment() -> Option<&'static str> {
+        Some("accounts of the users, the passwords are stored separately")
+    }
+
     const COLUMNS: Lazy<Vec<Column>> = Lazy::new(|| {
         vec![
             Column::new("id", ValueType::Id).primary_key(),
@@ -77,10 +113,14 @@ impl DbObject for User {
                 .references("groups(id)"),
             Column::new("total_memory_usage", ValueType::Integer)
                 .not_null()
-                .default("0"),
+                .default("0")
+                .check("total_memory_usage >= 0")
+                .comment("memory allocated to the user's enabled worlds, in MiB"),
             Column::new("enabled", ValueType::Boolean)
                 .not_null()
                 .default("true"),
+            Column::new("minecraft_username", ValueType::Text),
+            Column::new("minecraft_uuid", ValueType::Token),
         ]
     });
 
@@ -109,6 +149,10 @@ impl<'a> IntoArguments<'a, sqlx::Sqlite> for User {
             .add(self.total_memory_usage)
             .expect("Failed to argument");
         arguments.add(self.enabled).expect("Failed to argument");
+        arguments
+            .add(self.minecraft_username)
+            .expect("Failed to argument");
+        arguments.add(self.minecraft_uuid).expect("Failed to argument");
         arguments
     }
 }
@@ -123,6 +167,10 @@ impl<'a> IntoArguments<'a, sqlx::Postgres> for User {
             .add(self.total_memory_usage)
             .expect("Failed to argument");
         arguments.add(self.enabled).expect("Failed to argument");
+        arguments
+            .add(self.minecraft_username)
+            .expect("Failed to argument");
+        arguments.add(self.minecraft_uuid).expect("Failed to argument");
         arguments
     }
 }
@@ -135,19 +183,12 @@ impl Default for User {
             group_id: CONFIG.user_defaults.group_id,
             total_memory_usage: 0,
             enabled: true,
+            minecraft_username: None,
+            minecraft_uuid: None,
         }
     }
 }
 
-// sqlx::Sqlite value that is present is considered Some value, including null.
-fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
-where
-    T: Deserialize<'de>,
-    D: Deserializer<'de>,
-{
-    Deserialize::deserialize(deserializer).map(Some)
-}
-
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonFrom {
     pub username: String,
@@ -155,6 +196,7 @@ pub struct JsonFrom {
     #[serde(default, deserialize_with = "deserialize_some")]
     pub group_id: Option<Id>,
     pub enabled: Option<bool>,
+    pub minecraft_username: Option<String>,
 }
 
 impl FromJson for User {
@@ -166,6 +208,8 @@ impl FromJson for User {
             group_id: data.group_id.unwrap_or(CONFIG.user_defaults.group_id),
             total_memory_usage: 0,
             enabled: data.enabled.unwrap_or(true),
+            minecraft_username: data.minecraft_username.clone(),
+            minecraft_uuid: None,
         }
     }
 }
@@ -180,6 +224,8 @@ pub struct JsonUpdate {
     pub group_id: Option<Id>,
     #[serde(default, deserialize_with = "deserialize_some")]
     pub enabled: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub minecraft_username: Option<Option<String>>,
 }
 impl UpdateJson for User {
     type JsonUpdate = JsonUpdate;
@@ -189,6 +235,14 @@ impl UpdateJson for User {
         new.username = data.username.clone().unwrap_or(new.username);
         new.group_id = data.group_id.unwrap_or(new.group_id);
         new.enabled = data.enabled.unwrap_or(new.enabled);
+        new.minecraft_username = data
+            .minecraft_username
+            .clone()
+            .unwrap_or(new.minecraft_username);
+        // the UUID belongs to the old account
+        if new.minecraft_username != self.minecraft_username {
+            new.minecraft_uuid = None;
+        }
         new
     }
 }
@@ -197,12 +251,20 @@ impl ApiObject for User {
     fn routes() -> Router<AppState> {
         Router::new()
             .route("/", get(Self::api_list).post(Self::api_create))
+            .route("/export.csv", get(Self::api_export_csv))
+            .route("/delete", post(Self::api_remove_many))
             .route(
                 "/{id}",
                 get(Self::api_get)
                     .patch(Self::api_update)
                     .delete(Self::api_remove),
             )
+            .route("/{id}/recalculate-memory", post(Self::api_recalculate_memory))
+            .route("/{id}/deletion-impact", get(Self::api_deletion_impact))
+            .route("/{id}/reset-password", post(Self::api_reset_password))
+            .route("/{id}/minecraft-account", put(Self::api_set_minecraft_account))
+            .route("/{id}/worlds/enable", post(Self::api_enable_worlds))
+            .route("/{id}/worlds/disable", post(Self::api_disable_worlds))
             .route(
                 "/{id}/icon",
                 post(Self::upload_icon)
@@ -242,26 +304,8 @@ impl ApiUpdate for User {
         json: &mut Self::JsonUpdate,
         _user: &User,
     ) -> Result<(), DatabaseError> {
-        //the the password is first created then recreated so it can handle a missing password entry for the user
-        if let Some(password) = json.password.clone() {
-            match state.database.get_one::<Password>(self.id, None).await {
-                Ok(password) => {
-                    state
-                        .database
-                        .remove(&password, None)
-                        .await
-                        .expect("failed to remove the password for the user");
-                }
-                Err(err) => {
-                    warn!("password not found for the user {}: {}", self.username, err);
-                }
-            }
-
-            state
-                .database
-                .insert(&Password::new(self.id, &password), None)
-                .await
-                .expect("update the password for the user");
+        if let Some(password) = &json.password {
+            self.set_password(&state.database, password).await?;
         }
         Ok(())
     }
@@ -269,8 +313,12 @@ impl ApiUpdate for User {
 #[async_trait]
 impl ApiRemove for User {
     async fn before_api_delete(&self, state: AppState, user: &User) -> Result<(), DatabaseError> {
+        if self.is_last_privileged(&state.database).await? {
+            warn!("not removing user {}, it's the last privileged one", self.id);
+            return Err(DatabaseError::Conflict);
+        }
         info!("removing user {}", self.id);
-        let worlds = state.database.get_all_where::<World, _>("owner_id", self.id, None/*in theory here the access restriction should be put but i couldn't be bothered with that*/).await?;
+        let (worlds, mods) = self.owned_objects(&state.database).await?;
         let worlds_task = async {
             let tasks = worlds.iter().map(|world| async {
                 if let Err(err) = world.before_api_delete(state.clone(), user).await {
@@ -286,7 +334,6 @@ impl ApiRemove for User {
             future::join_all(tasks).await
         };
 
-        let mods = state.database.get_all_where::<Mod, _>("owner_id", self.id, None/*in theory here the access restriction should be put but i couldn't be bothered with that*/).await?;
         let mods_task = async {
             let database = state.clone();
             let tasks = mods.iter().map(|mcmod| async {
@@ -316,6 +363,28 @@ impl ApiIcon for User {
     const DEFAULT_ICON_MIME: &'static str = "image/png";
 }
 
+/// result of [`User::recalculate_memory_usage`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemoryRecalculation {
+    pub user_id: Id,
+    pub before: i64,
+    pub after: i64,
+}
+
+/// what removing a user removes with it, see [`User::deletion_impact`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeletionImpact {
+    pub user_id: Id,
+    pub world_count: usize,
+    pub world_names: Vec<String>,
+    pub mod_count: usize,
+    pub session_count: usize,
+    /// bytes of the worlds, their backups and the mod jars
+    pub disk_bytes: u64,
+    /// memory allocated to the enabled worlds, in MiB
+    pub memory: i64,
+}
+
 impl User {
     pub async fn group(&self, database: Database, user: Option<(&User, &Group)>) -> Group {
         database
@@ -323,6 +392,450 @@ impl User {
             .await
             .unwrap_or_else(|_| panic!("couldn't find group with id {}", self.group_id))
     }
+
+    /// whether the user is in a privileged group and no other user is, so removing it would leave no one to manage the
+    /// instance
+    pub async fn is_last_privileged(&self, database: &Database) -> Result<bool, DatabaseError> {
+        if !self.group(database.clone(), None).await.is_privileged {
+            return Ok(false);
+        }
+        for group in database.get_all_where::<Group, bool>("is_privileged", true, None).await? {
+            let users = database.count_where::<User, Id>("group_id", group.id).await?;
+            let others = if group.id == self.group_id { users - 1 } else { users };
+            if others > 0 {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// overwrites the stored `total_memory_usage` with the actual memory usage of the user's worlds
+    pub async fn recalculate_memory_usage(
+        &self,
+        database: &Database,
+    ) -> Result<MemoryRecalculation, DatabaseError> {
+        let mut user = self.clone();
+        user.total_memory_usage = self.calculate_memory_usage(database).await?;
+        if user.total_memory_usage != self.total_memory_usage {
+            warn!(
+                "memory usage of {} drifted from {} to {}",
+                self.username, self.total_memory_usage, user.total_memory_usage
+            );
+            database.update(&user, None).await?;
+        }
+        Ok(MemoryRecalculation {
+            user_id: self.id,
+            before: self.total_memory_usage,
+            after: user.total_memory_usage,
+        })
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    async fn api_recalculate_memory(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        _: AdminAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let user = state
+            .database
+            .get_one::<User>(id, None)
+            .await
+            .map_err(handle_database_error)?;
+        Ok(Json(
+            user.recalculate_memory_usage(&state.database)
+                .await
+                .map_err(handle_database_error)?,
+        ))
+    }
+
+    /// `GET /api/users/{id}/deletion-impact`, see [`User::deletion_impact`]
+    async fn api_deletion_impact(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        _: AdminAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let user = state
+            .database
+            .get_one::<User>(id, None)
+            .await
+            .map_err(handle_database_error)?;
+        Ok(Json(
+            user.deletion_impact(&state.database)
+                .await
+                .map_err(handle_database_error)?,
+        ))
+    }
+
+    /// `POST /api/users/{id}/reset-password`, see [`User::reset_password`]. the password is only in the response if it
+    /// was generated
+    async fn api_reset_password(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        AdminAuth(admin): AdminAuth,
+        Json(json): Json<ResetPasswordJson>,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        if let Some(password) = &json.password
+            && (password.is_empty() || password.len() > MAX_PASSWORD_LENGTH)
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let user = state
+            .database
+            .get_one::<User>(id, None)
+            .await
+            .map_err(handle_database_error)?;
+
+        let generated = json.password.is_none();
+        let (password, logged_out) = user
+            .reset_password(&state.database, json.password, json.logout)
+            .await
+            .map_err(handle_database_error)?;
+        info!(
+            "{} reset the password of {}, removing {logged_out} of their sessions",
+            admin.username, user.username
+        );
+
+        Ok(Json(json!({
+            "password": generated.then_some(password),
+            "sessions_removed": logged_out,
+        })))
+    }
+
+    /// `PUT /api/users/{id}/minecraft-account`, links a Minecraft account to the user, or unlinks it with `null`.
+    /// users can change their own, which the update access doesn't let them do. the account is looked up to check it
+    /// exists and get its UUID, unless it's `offline`, in which case the UUID offline-mode servers use is stored
+    async fn api_set_minecraft_account(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        Json(json): Json<MinecraftAccountJson>,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let target = state
+            .database
+            .get_one::<User>(id, Some((&user, &group)))
+            .await
+            .map_err(handle_database_error)?;
+
+        let (minecraft_username, minecraft_uuid) = match json.minecraft_username.map(|name| name.trim().to_string()) {
+            None => (None, None),
+            Some(name) if !crate::minecraft::util::is_player_name(&name) => return Err(StatusCode::BAD_REQUEST),
+            Some(name) if json.offline => {
+                let uuid = account::offline_uuid(&name);
+                (Some(name), Some(uuid))
+            }
+            Some(name) if CONFIG.accounts.verify => match account::lookup(&name).await {
+                Ok(Some(profile)) => (Some(profile.name), Some(profile.id)),
+                Ok(None) => return Err(StatusCode::UNPROCESSABLE_ENTITY),
+                Err(err) => {
+                    warn!("could not look up the Minecraft account {name}: {err}");
+                    return Err(StatusCode::BAD_GATEWAY);
+                }
+            },
+            Some(name) => (Some(name), None),
+        };
+        let target = User {
+            minecraft_username,
+            minecraft_uuid,
+            ..target
+        };
+        state
+            .database
+            .update(&target, None)
+            .await
+            .map_err(handle_database_error)?;
+        Ok(Json(target))
+    }
+
+    async fn api_enable_worlds(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        AdminAuth(admin): AdminAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        Self::set_worlds_enabled(id, &state, &admin, true).await
+    }
+
+    async fn api_disable_worlds(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        AdminAuth(admin): AdminAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        Self::set_worlds_enabled(id, &state, &admin, false).await
+    }
+
+    /// enables or disables all the worlds of a user, returning the worlds that changed. when enabling, worlds that
+    /// would go over the limits of the user's group are left disabled. the memory usage is recalculated once at the end
+    async fn set_worlds_enabled(
+        id: Id,
+        state: &AppState,
+        admin: &User,
+        enabled: bool,
+    ) -> Result<Json<Vec<World>>, StatusCode> {
+        let mut user = state
+            .database
+            .get_one::<User>(id, None)
+            .await
+            .map_err(handle_database_error)?;
+        let group = user.group(state.database.clone(), None).await;
+        let worlds = state
+            .database
+            .get_all_where::<World, _>("owner_id", user.id, None)
+            .await
+            .map_err(handle_database_error)?;
+
+        info!(
+            "{} is {} the worlds of {}",
+            admin.username,
+            if enabled { "enabling" } else { "disabling" },
+            user.username
+        );
+
+        let mut active_worlds = worlds.iter().filter(|world| world.enabled).count();
+        let mut memory_usage: i64 = worlds
+            .iter()
+            .filter(|world| world.enabled)
+            .map(|world| i64::from(world.allocated_memory))
+            .sum();
+
+        // the worlds enabled here are added to it as they go, so they're checked together against the instance limits
+        let mut instance_usage = if enabled {
+            crate::minecraft::capacity::enable_usage(state)
+                .await
+                .map_err(handle_database_error)?
+        } else {
+            None
+        };
+
+        let mut affected = Vec::new();
+        for mut world in worlds {
+            if world.enabled == enabled {
+                continue;
+            }
+            if enabled {
+                let memory = i64::from(world.allocated_memory);
+                let within_limits = instance_usage
+                    .is_none_or(|usage| usage.check_enable(&CONFIG.instance, true, memory).is_ok())
+                    && group
+                    .active_world_limit
+                    .is_none_or(|limit| active_worlds < usize::try_from(limit).unwrap_or_default())
+                    && memory >= i64::from(CONFIG.world.minimum_memory)
+                    && group
+                        .per_world_memory_limit
+                        .is_none_or(|limit| memory <= i64::from(limit))
+                    && group
+                        .total_memory_limit
+                        .is_none_or(|limit| memory_usage + memory <= i64::from(limit));
+                if !within_limits {
+                    continue;
+                }
+                active_worlds += 1;
+                memory_usage += memory;
+                if let Some(usage) = &mut instance_usage {
+                    usage.add_enabled(memory);
+                }
+            }
+
+            world.enabled = enabled;
+            state
+                .database
+                .update(&world, None)
+                .await
+                .map_err(handle_database_error)?;
+
+            // the world stays changed even if its server fails, its status shows the failure
+            match state.servers.get_or_create_server(&world).await {
+                Ok(server) => {
+                    if let Err(err) = server.lock().await.update_world(world.clone()).await {
+                        error!("could not update the server of {}: {err}", world.id);
+                    }
+                }
+                Err(err) => error!("could not get the server of {}: {err}", world.id),
+            }
+            affected.push(world);
+        }
+        crate::minecraft::capacity::invalidate().await;
+
+        user.total_memory_usage = user
+            .calculate_memory_usage(&state.database)
+            .await
+            .map_err(handle_database_error)?;
+        state
+            .database
+            .update(&user, None)
+            .await
+            .map_err(handle_database_error)?;
+
+        Ok(Json(affected))
+    }
+
+    /// replaces the user's password. the old one is removed first, so a user without one gets it too
+    pub async fn set_password(&self, database: &Database, password: &str) -> Result<(), DatabaseError> {
+        match database.get_one::<Password>(self.id, None).await {
+            Ok(password) => database.remove(&password, None).await?,
+            Err(err) => warn!("password not found for the user {}: {}", self.username, err),
+        }
+        database.insert(&Password::new(self.id, password), None).await
+    }
+
+    /// sets the password to `password`, or a generated one without it. with `logout` the user's sessions are removed,
+    /// so anyone logged in as them has to use the new password. returns the password and how many sessions were removed
+    pub async fn reset_password(
+        &self,
+        database: &Database,
+        password: Option<String>,
+        logout: bool,
+    ) -> Result<(String, usize), DatabaseError> {
+        let password = password.unwrap_or_else(generate_password);
+        self.set_password(database, &password).await?;
+        let logged_out = if logout { database.evict_sessions(self.id, 0).await? } else { 0 };
+        Ok((password, logged_out))
+    }
+
+    /// sums up the memory allocated to the user's enabled worlds
+    /// the worlds and mods of the user, which are removed with it
+    async fn owned_objects(&self, database: &Database) -> Result<(Vec<World>, Vec<Mod>), DatabaseError> {
+        let worlds = database.get_all_where::<World, _>("owner_id", self.id, None/*in theory here the access restriction should be put but i couldn't be bothered with that*/).await?;
+        let mods = database.get_all_where::<Mod, _>("owner_id", self.id, None/*in theory here the access restriction should be put but i couldn't be bothered with that*/).await?;
+        Ok((worlds, mods))
+    }
+
+    /// what removing the user would remove, without removing anything
+    pub async fn deletion_impact(&self, database: &Database) -> Result<DeletionImpact, DatabaseError> {
+        let (worlds, mods) = self.owned_objects(database).await?;
+        let sessions = database.get_all_where::<Session, _>("user_id", self.id, None).await?;
+
+        let mut disk_bytes = 0;
+        for world in &worlds {
+            let directory = crate::util::dirs::worlds_dir().join(format!("{}/{}", world.owner_id, world.id));
+            for path in [directory, crate::minecraft::backup::world_backups_dir(world.id)] {
+                if path.exists() {
+                    disk_bytes += crate::util::dir_size(path)
+                        .map_err(|err| DatabaseError::InternalServerError(err.to_string()))?;
+                }
+            }
+        }
+        for mcmod in &mods {
+            disk_bytes += std::fs::metadata(mcmod.jar_path()).map(|metadata| metadata.len()).unwrap_or_default();
+        }
+
+        Ok(DeletionImpact {
+            user_id: self.id,
+            world_count: worlds.len(),
+            world_names: worlds.iter().map(|world| world.name.clone()).collect(),
+            mod_count: mods.len(),
+            session_count: sessions.len(),
+            disk_bytes,
+            memory: worlds
+                .iter()
+                .filter(|world| world.enabled)
+                .map(|world| i64::from(world.allocated_memory))
+                .sum(),
+        })
+    }
+
+    pub async fn calculate_memory_usage(&self, database: &Database) -> Result<i64, DatabaseError> {
+        Ok(database
+            .get_all_where::<World, _>("owner_id", self.id, None)
+            .await?
+            .iter()
+            .filter(|world| world.enabled)
+            .map(|world| i64::from(world.allocated_memory))
+            .sum())
+    }
+}
+
+/// the longest password users can have
+const MAX_PASSWORD_LENGTH: usize = 256;
+
+/// how long the passwords [`User::reset_password`] generates are
+const GENERATED_PASSWORD_LENGTH: usize = 20;
+
+fn generate_password() -> String {
+    use rand::Rng;
+    rand::rng()
+        .sample_iter(rand::distr::Alphanumeric)
+        .take(GENERATED_PASSWORD_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResetPasswordJson {
+    /// the new password, one is generated without it
+    #[serde(default)]
+    pub password: Option<String>,
+    /// whether to remove the user's sessions
+    #[serde(default = "logout_default")]
+    pub logout: bool,
+}
+
+fn logout_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MinecraftAccountJson {
+    pub minecraft_username: Option<String>,
+    /// the account is only used on offline-mode servers, so it isn't looked up
+    #[serde(default)]
+    pub offline: bool,
+}
+
+#[tokio::test]
+async fn recalculate_memory_usage() {
+    use crate::database::testing::{insert_test_version, test_group};
+    use pretty_assertions::assert_eq;
+
+    let database = Database::memory().await;
+
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+
+    let user = User {
+        username: String::from("test"),
+        group_id: group.id,
+        // corrupted value
+        total_memory_usage: 12345,
+        ..Default::default()
+    };
+    database.insert(&user, None).await.unwrap();
+
+    for (allocated_memory, enabled) in [(1024, true), (2048, true), (4096, false)] {
+        let world = World {
+            id: Id::default(),
+            owner_id: user.id,
+            name: String::from("world"),
+            hostname: format!("world{allocated_memory}"),
+            allocated_memory,
+            version_id: version.id,
+            enabled,
+            backup_schedule: None,
+            setup_commands: String::new(),
+            initialized: false,
+            seed: None,
+            forward_player_ip: None,
+        };
+        database.insert(&world, None).await.unwrap();
+    }
+
+    let recalculation = user.recalculate_memory_usage(&database).await.unwrap();
+    assert_eq!(
+        recalculation,
+        MemoryRecalculation {
+            user_id: user.id,
+            before: 12345,
+            after: 3072,
+        }
+    );
+    assert_eq!(
+        database
+            .get_one::<User>(user.id, None)
+            .await
+            .unwrap()
+            .total_memory_usage,
+        3072
+    );
 }
 
 pub mod password {
@@ -450,7 +963,7 @@ pub mod session {
     use crate::api::serve::AppState;
     use crate::database::objects::{DbObject, FromJson, User};
     use crate::database::types::{Access, Column, Id};
-    use crate::database::{Cachable, ValueType};
+    use crate::database::{Cachable, Database, DatabaseError, ValueType};
     
     use axum::Router;
     
@@ -499,6 +1012,10 @@ pub mod session {
             "sessions"
         }
 
+        fn default_sort() -> &'static str {
+            "created, id"
+        }
+
         const COLUMNS: Lazy<Vec<Column>> = Lazy::new(|| {
             vec![
                 Column::new("id", ValueType::Id).unique(),
@@ -520,6 +1037,21 @@ pub mod session {
         fn owner_id(&self) -> Option<Id> {
             Some(self.user_id)
         }
+
+        async fn after_create(&self, database: &Database) -> Result<(), DatabaseError> {
+            database
+                .evict_sessions(self.user_id, crate::config::CONFIG.sessions.max_per_user)
+                .await?;
+            Ok(())
+        }
+    }
+
+    impl Session {
+        /// whether the session ran out, `lifetime` after it was created. sessions that don't expire never do
+        pub fn is_expired(&self, lifetime: std::time::Duration) -> bool {
+            let lifetime = chrono::TimeDelta::from_std(lifetime).unwrap_or(chrono::TimeDelta::MAX);
+            self.expires && self.created.checked_add_signed(lifetime).is_some_and(|end| end <= Utc::now())
+        }
     }
 
     impl Cachable for Session {
@@ -623,3 +1155,200 @@ pub mod session {
     impl ApiCreate for Session {}
     impl ApiRemove for Session {}
 }
+
+#[tokio::test]
+async fn reset_password_test() {
+    use argon2::PasswordVerifier;
+
+    // the user's group doesn't exist
+    let database = Database::memory_unchecked().await;
+
+    let user = User {
+        username: String::from("test"),
+        ..Default::default()
+    };
+    database.insert(&user, None).await.unwrap();
+    for _ in 0..3 {
+        let session = Session {
+            id: Id::new_random(),
+            user_id: user.id,
+            token: uuid::Uuid::new_v4(),
+            created: chrono::Utc::now(),
+            expires: true,
+        };
+        database.insert(&session, None).await.unwrap();
+    }
+    let verifies = |hash: &Password, password: &str| {
+        argon2::Argon2::default()
+            .verify_password(password.as_bytes(), &hash.hash.password_hash())
+            .is_ok()
+    };
+
+    // the user doesn't have a password yet
+    let (password, logged_out) = user.reset_password(&database, None, false).await.unwrap();
+    assert_eq!(password.len(), GENERATED_PASSWORD_LENGTH);
+    assert_eq!(logged_out, 0);
+    assert!(verifies(&database.get_one(user.id, None).await.unwrap(), &password));
+
+    let (_, logged_out) = user
+        .reset_password(&database, Some(String::from("hunter2")), true)
+        .await
+        .unwrap();
+    assert_eq!(logged_out, 3);
+    let hash: Password = database.get_one(user.id, None).await.unwrap();
+    assert!(verifies(&hash, "hunter2") && !verifies(&hash, &password));
+    assert!(database.get_all_where::<Session, _>("user_id", user.id, None).await.unwrap().is_empty());
+}
+
+#[test]
+fn minecraft_username_test() {
+    let user = User::default();
+    let linked = user.update_with_json(
+        &serde_json::from_value(json!({"username": "someone", "minecraft_username": "Notch"})).unwrap(),
+    );
+    assert_eq!(linked.minecraft_username.as_deref(), Some("Notch"));
+    assert!(linked.validate().is_ok());
+    // leaving it out keeps it, null unlinks it
+    let renamed = linked.update_with_json(&serde_json::from_value(json!({"username": "other"})).unwrap());
+    assert_eq!(renamed.minecraft_username.as_deref(), Some("Notch"));
+    let unlinked = renamed.update_with_json(&serde_json::from_value(json!({"minecraft_username": null})).unwrap());
+    assert_eq!(unlinked.minecraft_username, None);
+
+    // changing the account drops the UUID of the old one
+    let verified = User { minecraft_uuid: Some(account::offline_uuid("Notch")), ..renamed.clone() };
+    let kept = verified.update_with_json(&serde_json::from_value(json!({"minecraft_username": "Notch"})).unwrap());
+    assert!(kept.minecraft_uuid.is_some());
+    let changed = verified.update_with_json(&serde_json::from_value(json!({"minecraft_username": "jeb_"})).unwrap());
+    assert_eq!(changed.minecraft_uuid, None);
+
+    let invalid = User { minecraft_username: Some(String::from("op Notch")), ..unlinked };
+    assert!(matches!(invalid.validate(), Err(DatabaseError::BadRequest(_))));
+}
+
+#[tokio::test]
+async fn deletion_impact_test() {
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+
+    let user = database
+        .create_user_from(User { username: String::from("leaving"), group_id: group.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+    let other = database
+        .create_user_from(User { username: String::from("staying"), group_id: group.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+    let mut worlds = Vec::new();
+    for (owner, name) in [(&user, "first"), (&user, "second"), (&other, "third")] {
+        // disabled, so removing them doesn't need a server to stop
+        let world = World {
+            name: String::from(name),
+            hostname: String::from(name),
+            ..test_world(owner.id, version.id)
+        };
+        database.insert(&world, None).await.unwrap();
+        worlds.push(world);
+    }
+    let world_dir = |world: &World| crate::util::dirs::worlds_dir().join(format!("{}/{}", world.owner_id, world.id));
+    let write = |path: std::path::PathBuf, bytes: usize| {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, vec![0u8; bytes]).unwrap();
+    };
+    // the files of the leaving user's worlds and their backups are counted, nested ones too, the other user's aren't
+    write(world_dir(&worlds[0]).join("world/level.dat"), 100);
+    write(world_dir(&worlds[0]).join("server.properties"), 20);
+    write(crate::minecraft::backup::world_backups_dir(worlds[1].id).join("backup.tar.gz"), 300);
+    write(world_dir(&worlds[2]).join("world/level.dat"), 1000);
+    let mcmod = Mod {
+        id: Id::default(),
+        owner_id: user.id,
+        version_id: version.id,
+        name: String::from("mod"),
+        description: String::new(),
+        modrinth_id: None,
+        public: false,
+        dependencies: crate::database::types::IdList::default(),
+    };
+    database.insert(&mcmod, None).await.unwrap();
+    write(mcmod.jar_path(), 4);
+    for owner in [&user, &user, &other] {
+        let session = Session {
+            id: Id::default(),
+            user_id: owner.id,
+            token: uuid::Uuid::new_v4(),
+            created: chrono::Utc::now(),
+            expires: true,
+        };
+        database.insert(&session, None).await.unwrap();
+    }
+
+    let impact = user.deletion_impact(&database).await.unwrap();
+    let mut names = impact.world_names.clone();
+    names.sort();
+    assert_eq!(names, vec!["first", "second"]);
+    assert_eq!((impact.world_count, impact.mod_count, impact.session_count), (2, 1, 2));
+    assert_eq!((impact.disk_bytes, impact.memory), (424, 0));
+
+    let counts = async || {
+        (
+            database.get_all::<World>(None).await.unwrap().len(),
+            database.get_all::<Mod>(None).await.unwrap().len(),
+            database.get_all::<Session>(None).await.unwrap().len(),
+        )
+    };
+    let before = counts().await;
+    user.before_api_delete(state.clone(), &user).await.unwrap();
+    database.remove(&user, None).await.unwrap();
+    let after = counts().await;
+    assert_eq!(
+        (before.0 - after.0, before.1 - after.1, before.2 - after.2),
+        (impact.world_count, impact.mod_count, impact.session_count)
+    );
+    assert!(database.get_one::<User>(other.id, None).await.is_ok());
+    // the counted files are the ones that were removed
+    assert!(!world_dir(&worlds[0]).exists());
+    assert!(!crate::minecraft::backup::world_backups_dir(worlds[1].id).exists());
+    assert!(!mcmod.jar_path().exists());
+    assert!(world_dir(&worlds[2]).exists());
+    std::fs::remove_dir_all(world_dir(&worlds[2]).parent().unwrap()).unwrap();
+    let _ = std::fs::remove_dir(world_dir(&worlds[0]).parent().unwrap());
+}
+
+#[tokio::test]
+async fn last_privileged_test() {
+    use crate::api::handlers::ApiRemove;
+    use crate::database::testing::{test_group, test_state};
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let admins = Group { id: Id::new_random(), name: String::from("admins"), is_privileged: true, ..test_group() };
+    let players = Group { id: Id::new_random(), name: String::from("players"), ..test_group() };
+    database.insert(&admins, None).await.unwrap();
+    database.insert(&players, None).await.unwrap();
+    let mut users = vec![];
+    for (username, group) in [("first", &admins), ("second", &admins), ("player", &players)] {
+        let user = User { username: String::from(username), group_id: group.id, ..Default::default() };
+        users.push(database.create_user_from(user, "hunter2").await.unwrap());
+    }
+
+    // removing both admins at once removes only the first, the second is the last one left
+    let ids = vec![users[0].id, users[1].id];
+    let response = User::api_remove_many(State(state.clone()), UserAuth(users[0].clone()), Json(ids))
+        .await
+        .unwrap()
+        .into_response();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let results: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(results[0]["status"], 204);
+    assert_eq!(results[1]["status"], 409);
+    assert!(database.get_one::<User>(users[1].id, None).await.is_ok());
+    assert!(users[1].is_last_privileged(&database).await.unwrap());
+    assert!(!users[2].is_last_privileged(&database).await.unwrap());
+}
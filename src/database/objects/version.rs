@@ -1,14 +1,77 @@
-use crate::api::handlers::{ApiCreate, ApiGet, ApiList, ApiObject, ApiRemove, ApiUpdate};
+use crate::api::filters::UserAuth;
+use crate::api::handlers::{ApiCreate, ApiGet, ApiList, ApiObject, ApiRemove, ApiUpdate, PaginationQuery};
 use crate::api::serve::AppState;
-use crate::database::objects::{DbObject, FromJson, UpdateJson, User};
+use crate::database::objects::{DbObject, deserialize_some, FromJson, Group, Mod, UpdateJson, User, World};
 use crate::database::types::{Access, Column, Id};
-use crate::database::{Cachable, ValueType};
+use crate::database::{Cachable, Database, DatabaseError, ValueType};
 use axum::Router;
-use axum::routing::get;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use sqlx::{Arguments, FromRow, IntoArguments};
 use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+use crate::config::CONFIG;
+use crate::util;
+use log::error;
+
+/// what kind of server a [`Version`] is, so the versions can be grouped and filtered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VersionKind {
+    /// a full Minecraft release
+    Release,
+    /// a snapshot, pre-release or release candidate
+    Snapshot,
+    /// a vanilla version with a mod loader
+    Modded,
+    /// anything else, like a plugin server or a custom jar
+    #[default]
+    Custom,
+}
+
+impl VersionKind {
+    /// how the kinds are stored in the database
+    pub const VALUES: &'static [&'static str] = &["release", "snapshot", "modded", "custom"];
+}
+
+impl Display for VersionKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionKind::Release => f.write_str("release"),
+            VersionKind::Snapshot => f.write_str("snapshot"),
+            VersionKind::Modded => f.write_str("modded"),
+            VersionKind::Custom => f.write_str("custom"),
+        }
+    }
+}
+
+impl FromStr for VersionKind {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "release" => Ok(VersionKind::Release),
+            "snapshot" => Ok(VersionKind::Snapshot),
+            "modded" => Ok(VersionKind::Modded),
+            "custom" => Ok(VersionKind::Custom),
+            _ => Err(color_eyre::eyre::eyre!("invalid version kind: {s}")),
+        }
+    }
+}
+
+impl TryFrom<String> for VersionKind {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::from_str(&value)
+    }
+}
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, FromRow)]
 #[allow(clippy::struct_field_names)]
@@ -19,6 +82,17 @@ pub struct Version {
     pub minecraft_version: String,
     /// which [`ModLoader`] does the version use
     pub mod_loader_id: Id,
+    /// the lowest major Java version the server runs on. [`None`] if it's not known
+    pub min_java: Option<i32>,
+    /// major Java version the server has to be run with, see `world.java_runtimes` in the config. [`None`] runs it
+    /// with the default `java`
+    pub required_java: Option<i32>,
+    /// what kind of server the version is
+    #[sqlx(try_from = "String")]
+    pub kind: VersionKind,
+    /// the SHA-1 of the server jar, in lowercase hex. the jar is checked against it before a server is started with it.
+    /// [`None`] doesn't check it
+    pub jar_sha1: Option<String>,
 }
 
 impl DbObject for Version {
@@ -37,6 +111,10 @@ impl DbObject for Version {
         "versions"
     }
 
+    fn searchable_columns() -> &'static [&'static str] {
+        &["minecraft_version"]
+    }
+
     const COLUMNS: Lazy<Vec<Column>> = Lazy::new(|| {
         vec![
             Column::new("id", ValueType::Id).primary_key(),
@@ -44,14 +122,52 @@ impl DbObject for Version {
             Column::new("mod_loader_id", ValueType::Id)
                 .not_null()
                 .references("mod_loaders(id)"),
+            Column::new("min_java", ValueType::Integer).check("min_java >= 0"),
+            Column::new("required_java", ValueType::Integer).check("required_java >= 0"),
+            Column::new("kind", ValueType::Enum(VersionKind::VALUES))
+                .not_null()
+                .default("'custom'"),
+            Column::new("jar_sha1", ValueType::Text),
         ]
     });
 
+    fn validate(&self) -> Result<(), DatabaseError> {
+        if let Some(sha1) = &self.jar_sha1
+            && (sha1.len() != 40 || !sha1.chars().all(|char| matches!(char, '0'..='9' | 'a'..='f')))
+        {
+            return Err(DatabaseError::BadRequest(format!("\"{sha1}\" is not a SHA-1 hash")));
+        }
+        Ok(())
+    }
+
     fn id(&self) -> Id {
         self.id
     }
 }
 
+impl Version {
+    /// where the server jar of the version is stored
+    pub fn jar_path(&self) -> PathBuf {
+        util::dirs::versions_dir().join(format!("{}.jar", self.id))
+    }
+
+    /// fails if the version with the `id` doesn't exist or the user can't see it, for objects referencing it
+    pub(crate) async fn check_exists(
+        database: &Database,
+        id: Id,
+        user: &User,
+        group: &Group,
+    ) -> Result<(), DatabaseError> {
+        match database.get_one::<Self>(id, Some((user, group))).await {
+            Ok(_) => Ok(()),
+            Err(DatabaseError::NotFound | DatabaseError::Unauthorized) => {
+                Err(DatabaseError::Unprocessable(String::from("version not found")))
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
 impl Cachable for Version {
     fn into_any(self: Box<Self>) -> Box<dyn Any> {
         self as Box<dyn Any>
@@ -68,6 +184,12 @@ impl<'a> IntoArguments<'a, sqlx::Sqlite> for Version {
         arguments
             .add(self.mod_loader_id)
             .expect("Failed to argument");
+        arguments.add(self.min_java).expect("Failed to argument");
+        arguments
+            .add(self.required_java)
+            .expect("Failed to argument");
+        arguments.add(self.kind.to_string()).expect("Failed to argument");
+        arguments.add(self.jar_sha1).expect("Failed to argument");
         arguments
     }
 }
@@ -82,6 +204,12 @@ impl<'a> IntoArguments<'a, sqlx::Postgres> for Version {
         arguments
             .add(self.mod_loader_id)
             .expect("Failed to argument");
+        arguments.add(self.min_java).expect("Failed to argument");
+        arguments
+            .add(self.required_java)
+            .expect("Failed to argument");
+        arguments.add(self.kind.to_string()).expect("Failed to argument");
+        arguments.add(self.jar_sha1).expect("Failed to argument");
         arguments
     }
 }
@@ -91,6 +219,11 @@ impl<'a> IntoArguments<'a, sqlx::Postgres> for Version {
 pub struct JsonFrom {
     pub minecraft_version: String,
     pub mod_loader_id: Id,
+    pub min_java: Option<u8>,
+    pub required_java: Option<u8>,
+    #[serde(default)]
+    pub kind: VersionKind,
+    pub jar_sha1: Option<String>,
 }
 
 impl FromJson for Version {
@@ -101,14 +234,28 @@ impl FromJson for Version {
             id: Id::default(),
             minecraft_version: data.minecraft_version.clone(),
             mod_loader_id: data.mod_loader_id,
+            min_java: data.min_java.map(i32::from),
+            required_java: data.required_java.map(i32::from),
+            kind: data.kind,
+            jar_sha1: data.jar_sha1.as_deref().map(str::to_ascii_lowercase),
         }
     }
 }
 #[allow(clippy::struct_field_names)]
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonUpdate {
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub minecraft_version: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub mod_loader_id: Option<Id>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub min_java: Option<Option<u8>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub required_java: Option<Option<u8>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub kind: Option<VersionKind>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub jar_sha1: Option<Option<String>>,
 }
 
 impl UpdateJson for Version {
@@ -120,6 +267,20 @@ impl UpdateJson for Version {
             .clone()
             .unwrap_or(new.minecraft_version);
         new.mod_loader_id = data.mod_loader_id.unwrap_or(new.mod_loader_id);
+        new.min_java = data
+            .min_java
+            .map(|min_java| min_java.map(i32::from))
+            .unwrap_or(new.min_java);
+        new.required_java = data
+            .required_java
+            .map(|required_java| required_java.map(i32::from))
+            .unwrap_or(new.required_java);
+        new.kind = data.kind.unwrap_or(new.kind);
+        new.jar_sha1 = data
+            .jar_sha1
+            .as_ref()
+            .map(|sha1| sha1.as_deref().map(str::to_ascii_lowercase))
+            .unwrap_or(new.jar_sha1);
         new
     }
 }
@@ -128,17 +289,224 @@ impl ApiObject for Version {
     fn routes() -> Router<AppState> {
         Router::new()
             .route("/", get(Self::api_list).post(Self::api_create))
+            .route("/delete", post(Self::api_remove_many))
             .route(
                 "/{id}",
                 get(Self::api_get)
                     .patch(Self::api_update)
                     .delete(Self::api_remove),
             )
+            .route("/{id}/worlds", get(Self::list_worlds))
+            .route("/{id}/mods", get(Self::list_mods))
+            .route("/{id}/download", get(Self::download_jar))
     }
 }
 
+impl Version {
+    /// `GET /api/versions/{id}/download`, the server jar of the version. the `ETag` is its SHA-1, and `Range` requests
+    /// are answered so an interrupted download can be resumed
+    async fn download_jar(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        headers: HeaderMap,
+    ) -> Result<Response, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        if CONFIG.world.privileged_jar_downloads && !group.is_privileged {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let version: Self = state
+            .database
+            .get_one(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let path = version.jar_path();
+        if !path.exists() {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        let sha1 = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || crate::minecraft::server::jar_sha1(&path)
+        })
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|err| {
+            error!("could not hash the jar of version {id}: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let name: String = version
+            .minecraft_version
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+            .collect();
+        serve_jar(&path, &sha1, &format!("{name}.jar"), headers).await
+    }
+
+    /// the worlds running the version, that the user can see
+    async fn list_worlds(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        Query(pagination): Query<PaginationQuery>,
+    ) -> Result<axum::Json<Vec<World>>, StatusCode> {
+        Self::referencing(id, &state, &user, pagination).await.map(axum::Json)
+    }
+
+    /// the mods made for the version, that the user can see
+    async fn list_mods(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        Query(pagination): Query<PaginationQuery>,
+    ) -> Result<axum::Json<Vec<Mod>>, StatusCode> {
+        Self::referencing(id, &state, &user, pagination).await.map(axum::Json)
+    }
+
+    /// a page of the objects of type `T` with `version_id` set to the version
+    async fn referencing<T>(
+        id: Id,
+        state: &AppState,
+        user: &User,
+        pagination: PaginationQuery,
+    ) -> Result<Vec<T>, StatusCode>
+    where
+        T: DbObject
+            + for<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow>
+            + for<'r> sqlx::FromRow<'r, sqlx::postgres::PgRow>
+            + Unpin,
+    {
+        let pagination = pagination.validate()?;
+        let group = user.group(state.database.clone(), None).await;
+        state
+            .database
+            .get_one::<Self>(id, Some((user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        state
+            .database
+            .get_page_where("version_id", id, Some((user, &group)), pagination)
+            .await
+            .map_err(crate::api::handlers::handle_database_error)
+    }
+}
+
+/// answers with the jar at `path`, as a download named `filename`. its `sha1` is the `ETag`, so a client that has it
+/// gets `304 Not Modified`, and a range of a jar that changed since isn't spliced into the old one
+async fn serve_jar(
+    path: &std::path::Path,
+    sha1: &str,
+    filename: &str,
+    mut headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    use crate::api::handlers::{matches_etag, stream_file_with_range};
+
+    let etag = format!("\"{sha1}\"");
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH).and_then(|value| value.to_str().ok())
+        && matches_etag(if_none_match, &etag)
+    {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    // the range is only sent if it's of this jar
+    if headers.get(header::IF_RANGE).is_some_and(|if_range| *if_range == etag) {
+        headers.remove(header::IF_RANGE);
+    }
+
+    let response = stream_file_with_range(path, "application/java-archive", &headers).await?;
+    let disposition = [
+        (header::ETAG, etag),
+        (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")),
+    ];
+    Ok((disposition, response).into_response())
+}
+
 impl ApiList for Version {}
 impl ApiGet for Version {}
 impl ApiCreate for Version {}
 impl ApiUpdate for Version {}
 impl ApiRemove for Version {}
+
+#[tokio::test]
+async fn version_kind_test() {
+    use crate::database::Database;
+    use crate::database::objects::ModLoader;
+    use crate::database::testing::memory_pool;
+
+    for kind in [VersionKind::Release, VersionKind::Snapshot, VersionKind::Modded, VersionKind::Custom] {
+        assert_eq!(VersionKind::from_str(&kind.to_string()).unwrap(), kind);
+        assert!(VersionKind::VALUES.contains(&kind.to_string().as_str()));
+    }
+    assert!(VersionKind::from_str("beta").is_err());
+
+    // a table created before versions had a kind
+    let pool = memory_pool().await;
+    sqlx::query(
+        "CREATE TABLE versions (id INTEGER PRIMARY KEY, minecraft_version TEXT NOT NULL, mod_loader_id INTEGER NOT NULL, \
+        min_java INTEGER, required_java INTEGER);",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let database = Database::new(pool.clone().into());
+    let loader = ModLoader {
+        id: Id::default(),
+        name: String::from("Vanilla"),
+        can_load_mods: false,
+    };
+    sqlx::query("INSERT INTO versions VALUES ($1, '1.21', $2, NULL, NULL);")
+        .bind(Id::new_random())
+        .bind(loader.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    database.init().await.unwrap();
+    database.insert(&loader, None).await.unwrap();
+
+    let versions = database.get_all::<Version>(None).await.unwrap();
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0].kind, VersionKind::Custom);
+}
+
+#[tokio::test]
+async fn serve_jar_test() {
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("server.jar");
+    let contents: Vec<u8> = (0..=255).collect();
+    std::fs::write(&path, &contents).unwrap();
+    let sha1 = crate::minecraft::server::jar_sha1(&path).unwrap();
+    let etag = format!("\"{sha1}\"");
+
+    let headers = |headers: &[(header::HeaderName, &str)]| {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(name, value.parse().unwrap());
+        }
+        map
+    };
+    let body = |response: Response| async {
+        axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+    };
+
+    let response = serve_jar(&path, &sha1, "1.21.jar", headers(&[])).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "application/java-archive");
+    assert_eq!(response.headers()[header::CONTENT_DISPOSITION], "attachment; filename=\"1.21.jar\"");
+    assert_eq!(body(response).await, contents);
+
+    // resuming the download
+    let response = serve_jar(&path, &sha1, "1.21.jar", headers(&[(header::RANGE, "bytes=100-"), (header::IF_RANGE, &etag)])).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body(response).await, contents[100..]);
+    // the jar changed since the download started, so all of it is sent
+    let response = serve_jar(&path, &sha1, "1.21.jar", headers(&[(header::RANGE, "bytes=100-"), (header::IF_RANGE, "\"old\"")])).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body(response).await, contents);
+
+    let response = serve_jar(&path, &sha1, "1.21.jar", headers(&[(header::IF_NONE_MATCH, &etag)])).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
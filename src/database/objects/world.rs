@@ -1,25 +1,32 @@
-use crate::api::filters::UserAuth;
+use crate::api::filters::{AdminAuth, UserAuth};
 use crate::api::handlers::{ApiCreate, ApiGet, ApiIcon, ApiList, ApiObject, ApiRemove, ApiUpdate};
 use crate::api::serve::AppState;
 use crate::config::CONFIG;
 use crate::database::objects::group::Group;
-use crate::database::objects::{DbObject, FromJson, UpdateJson, User, Version};
-use crate::database::types::{Access, Column, Id};
+use crate::database::objects::world_share::SharePermission;
+use crate::database::objects::{DbObject, deserialize_some, FromJson, UpdateJson, User, Version, WorldShare};
+use crate::database::types::{Access, Column, Id, Memory};
 use crate::database::{Cachable, Database, DatabaseError, ValueType};
-use crate::minecraft::server::{MinecraftServerStatus, ServerConfigLimit};
+use crate::minecraft::datapack;
+use crate::minecraft::pregeneration::{ChunkyEvent, Pregeneration, parse_chunky};
+use crate::minecraft::server::{
+    CONSOLE_HISTORY_SIZE, McStdout, MinecraftServerStatus, ServerConfigLimit, file_error, server_error,
+};
+use crate::minecraft::util::{Difficulty, GameMode, QuickSetting, Time, Weather};
 use async_trait::async_trait;
 use axum::Router;
-use axum::extract::{DefaultBodyLimit, Path, State};
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
-use axum::routing::{get, post};
-use log::{debug, error, info};
+use axum::extract::{DefaultBodyLimit, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use log::{debug, error, info, warn};
 use once_cell::sync::Lazy;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Serialize};
 use sqlx::{Arguments, FromRow, IntoArguments};
 use std::collections::HashMap;
 use image::DynamicImage;
 use serde_json::json;
+use uuid::Uuid;
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, FromRow)]
 pub struct World {
@@ -37,6 +44,17 @@ pub struct World {
     pub version_id: Id,
     /// whether a server hosting this world should be running or not
     pub enabled: bool,
+    /// cron expression of when the world should be backed up automatically. [`None`] disables automatic backups
+    pub backup_schedule: Option<String>,
+    /// console commands, one per line, run the first time the server finishes starting
+    pub setup_commands: String,
+    /// whether the [`World::setup_commands`] have been run. setting it back to false runs them again on the next start
+    pub initialized: bool,
+    /// the seed of the generated world, once it was looked up. [`None`] if it's not known yet
+    pub seed: Option<i64>,
+    /// whether the proxy sends the address of players to the server with the PROXY protocol. [`None`] uses
+    /// [`crate::config::ProxyConfig::forward_player_ip`]
+    pub forward_player_ip: Option<bool>,
 }
 
 impl DbObject for World {
@@ -52,10 +70,52 @@ impl DbObject for World {
         Access::User
     }
 
+    fn validate(&self) -> Result<(), DatabaseError> {
+        if !is_valid_hostname(&self.hostname) {
+            return Err(DatabaseError::BadRequest(format!(
+                "\"{}\" is not a valid hostname",
+                self.hostname
+            )));
+        }
+        if self.allocated_memory < 0 {
+            return Err(DatabaseError::BadRequest(String::from(
+                "the allocated memory can't be negative",
+            )));
+        }
+        Ok(())
+    }
+
+    async fn before_create(&self, database: &Database) -> Result<(), DatabaseError> {
+        self.check_hostname(database).await
+    }
+
+    async fn before_update(&self, database: &Database) -> Result<(), DatabaseError> {
+        self.check_hostname(database).await
+    }
+
+    // remove the shares, as they would otherwise point to a nonexistent world
+    async fn before_delete(&self, database: &Database) -> Result<(), DatabaseError> {
+        for share in database
+            .get_all_where::<WorldShare, _>("world_id", self.id, None)
+            .await?
+        {
+            database.remove(&share, None).await?;
+        }
+        Ok(())
+    }
+
     fn table_name() -> &'static str {
         "worlds"
     }
 
+    fn searchable_columns() -> &'static [&'static str] {
+        &["name", "hostname"]
+    }
+
+    fn table_comment() -> Option<&'static str> {
+        Some("Minecraft servers, each with its own directory in the worlds directory")
+    }
+
     const COLUMNS: Lazy<Vec<Column>> = Lazy::new(|| {
         vec![
             Column::new("id", ValueType::Id).primary_key(),
@@ -63,14 +123,34 @@ impl DbObject for World {
                 .not_null()
                 .references("users(id)"),
             Column::new("name", ValueType::Text).not_null(),
-            Column::new("hostname", ValueType::Text).not_null().unique(),
-            Column::new("allocated_memory", ValueType::Integer).not_null(),
+            Column::new("hostname", ValueType::Text)
+                .not_null()
+                .unique()
+                .comment("subdomain the proxy routes to the server"),
+            Column::new("allocated_memory", ValueType::Integer)
+                .not_null()
+                .check("allocated_memory >= 0")
+                .comment("memory given to the server, in MiB"),
             Column::new("version_id", ValueType::Id)
                 .not_null()
                 .references("versions(id)"),
             Column::new("enabled", ValueType::Boolean)
                 .not_null()
-                .default("false"),
+                .default("false")
+                .comment("whether the server is running, or should be started when it's accessed"),
+            Column::new("backup_schedule", ValueType::Text).comment("cron expression of the automatic backups"),
+            Column::new("setup_commands", ValueType::Text)
+                .not_null()
+                .default("''")
+                .comment("console commands run the first time the server starts, one per line"),
+            Column::new("initialized", ValueType::Boolean)
+                .not_null()
+                .default("false")
+                .comment("whether the setup commands have been run"),
+            Column::new("seed", ValueType::BigInteger)
+                .comment("seed of the generated world, kept once it's looked up"),
+            Column::new("forward_player_ip", ValueType::Boolean)
+                .comment("whether the proxy sends the players' addresses to the server, the instance's setting if null"),
         ]
     });
 
@@ -107,6 +187,15 @@ impl<'a> IntoArguments<'a, sqlx::Sqlite> for World {
             .add(self.version_id)
             .expect("Failed to add argument");
         arguments.add(self.enabled).expect("Failed to add argument");
+        arguments
+            .add(self.backup_schedule)
+            .expect("Failed to add argument");
+        arguments
+            .add(self.setup_commands)
+            .expect("Failed to add argument");
+        arguments.add(self.initialized).expect("Failed to add argument");
+        arguments.add(self.seed).expect("Failed to add argument");
+        arguments.add(self.forward_player_ip).expect("Failed to add argument");
         arguments
     }
 }
@@ -129,31 +218,62 @@ impl<'a> IntoArguments<'a, sqlx::Postgres> for World {
             .add(self.version_id)
             .expect("Failed to add argument");
         arguments.add(self.enabled).expect("Failed to add argument");
+        arguments
+            .add(self.backup_schedule)
+            .expect("Failed to add argument");
+        arguments
+            .add(self.setup_commands)
+            .expect("Failed to add argument");
+        arguments.add(self.initialized).expect("Failed to add argument");
+        arguments.add(self.seed).expect("Failed to add argument");
+        arguments.add(self.forward_player_ip).expect("Failed to add argument");
         arguments
     }
 }
 
-#[allow(unused)]
+/// the characters a world's hostname can consist of
+pub(crate) const HOSTNAME_CHARS: &str = "abcdefghijklmnopqrstuvwxyz01234567890-";
+
 fn is_valid_hostname(hostname: &str) -> bool {
     //in theory this could be done through regex, but this is simpler and i don't want to add a new dependency just for this
-    const ALLOWED_CHARS: &str = "abcdefghijklmnopqrstuvwzyz01234567890";
-
+    if hostname.is_empty() {
+        return false;
+    }
     for char in hostname.chars() {
-        if !ALLOWED_CHARS.contains(char) {
+        if !HOSTNAME_CHARS.contains(char) {
             return false;
         }
     }
     true
 }
 
-fn into_valid_hostname(hostname: &str) -> String {
-    const ALLOWED_CHARS: &str = "abcdefghijklmnopqrstuvwzyz01234567890-";
+/// whether the hostname is one of `reserved` or the proxy's own hostname, ignoring case
+pub(crate) fn is_reserved_hostname(hostname: &str, reserved: &[String], proxy_hostname: &str) -> bool {
+    hostname.eq_ignore_ascii_case(proxy_hostname)
+        || reserved
+            .iter()
+            .any(|reserved| hostname.eq_ignore_ascii_case(reserved))
+}
+
+/// the address players type into Minecraft to join a world through the proxy. the port is left out if it's the
+/// default one
+fn world_address(hostname: &str, proxy_hostname: &str, proxy_port: u16) -> String {
+    if proxy_port == DEFAULT_MINECRAFT_PORT {
+        format!("{hostname}.{proxy_hostname}")
+    } else {
+        format!("{hostname}.{proxy_hostname}:{proxy_port}")
+    }
+}
+
+/// the port Minecraft connects to when the address doesn't have one
+const DEFAULT_MINECRAFT_PORT: u16 = 25565;
 
+fn into_valid_hostname(hostname: &str) -> String {
     let hostname = hostname.to_ascii_lowercase();
     let mut new_hostname = String::with_capacity(hostname.len());
 
     for char in hostname.chars() {
-        if ALLOWED_CHARS.contains(char) {
+        if HOSTNAME_CHARS.contains(char) {
             new_hostname.push(char);
         } else if char.is_whitespace() {
             new_hostname.push('-');
@@ -162,21 +282,392 @@ fn into_valid_hostname(hostname: &str) -> String {
     new_hostname
 }
 
-// Any value that is present is considered Some value, including null.
-fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
-where
-    T: Deserialize<'de>,
-    D: Deserializer<'de>,
-{
-    Deserialize::deserialize(deserializer).map(Some)
+/// largest resource pack the client accepts, in bytes
+const MAX_RESOURCE_PACK_SIZE: u64 = 250 * 1024 * 1024;
+/// how long each request of a resource pack download can take, including reading the body
+const RESOURCE_PACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(120);
+/// how many redirects are followed when downloading a resource pack
+const MAX_RESOURCE_PACK_REDIRECTS: usize = 5;
+/// `server.properties` keys that configure the resource pack
+const RESOURCE_PACK_KEYS: [&str; 4] = [
+    "resource-pack",
+    "resource-pack-sha1",
+    "resource-pack-prompt",
+    "require-resource-pack",
+];
+
+/// whether the address is reachable from the internet. the loopback, private, link-local and other special ranges
+/// aren't, so the resource pack download can't be pointed at the host or its network
+fn is_public_address(ip: std::net::IpAddr) -> bool {
+    match ip {
+        std::net::IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || first == 0
+                // carrier-grade NAT, 100.64.0.0/10
+                || (first == 100 && second & 0xc0 == 64))
+        }
+        std::net::IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public_address(ip.into());
+            }
+            let first = ip.segments()[0];
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local, fc00::/7
+                || first & 0xfe00 == 0xfc00
+                // link-local, fe80::/10
+                || first & 0xffc0 == 0xfe80)
+        }
+    }
+}
+
+/// resolves the host of the url. fails if the url isn't http or https, or if any of the addresses isn't public
+async fn public_addresses(url: &url::Url) -> Result<Vec<std::net::SocketAddr>, String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(String::from("the url has to be an http or https url"));
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addresses: Vec<std::net::SocketAddr> = match url.host() {
+        Some(url::Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|err| format!("could not resolve {domain}: {err}"))?
+            .collect(),
+        Some(url::Host::Ipv4(ip)) => vec![(ip, port).into()],
+        Some(url::Host::Ipv6(ip)) => vec![(ip, port).into()],
+        None => return Err(String::from("the url has no host")),
+    };
+    if addresses.is_empty() || !addresses.iter().all(|address| is_public_address(address.ip())) {
+        return Err(String::from("the url doesn't point to a public address"));
+    }
+    Ok(addresses)
+}
+
+/// requests the resource pack, following the redirects itself so every host it's sent to is checked with
+/// [`public_addresses`]. the client connects to the addresses that were checked, so the host can't resolve to a
+/// different one in between
+async fn request_resource_pack(mut url: url::Url) -> Result<reqwest::Response, String> {
+    for _ in 0..=MAX_RESOURCE_PACK_REDIRECTS {
+        let addresses = public_addresses(&url).await?;
+        let mut client = reqwest::Client::builder()
+            .timeout(RESOURCE_PACK_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none());
+        if let Some(url::Host::Domain(domain)) = url.host() {
+            client = client.resolve_to_addrs(domain, &addresses);
+        }
+        let client = client
+            .build()
+            .map_err(|err| format!("could not download the resource pack: {err}"))?;
+        let response = client
+            .get(url.clone())
+            .send()
+            .await
+            .map_err(|err| format!("could not download the resource pack: {err}"))?;
+        if !response.status().is_redirection() {
+            return response
+                .error_for_status()
+                .map_err(|err| format!("could not download the resource pack: {err}"));
+        }
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or_else(|| String::from("the resource pack url redirects without a location"))?;
+        url = url
+            .join(location)
+            .map_err(|err| format!("the resource pack url redirects to an invalid url: {err}"))?;
+    }
+    Err(String::from("the resource pack url redirects too many times"))
+}
+
+/// downloads a resource pack, returning its SHA-1 and size. the error is a reason that can be shown to the user
+async fn hash_resource_pack(url: url::Url) -> Result<(String, u64), String> {
+    use sha1::{Digest, Sha1};
+
+    let mut response = request_resource_pack(url).await?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_RESOURCE_PACK_SIZE)
+    {
+        return Err(String::from("the resource pack is too large"));
+    }
+
+    let mut hasher = Sha1::new();
+    let mut size = 0;
+    let mut magic: Vec<u8> = Vec::with_capacity(4);
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|err| format!("could not download the resource pack: {err}"))?
+    {
+        size += chunk.len() as u64;
+        if size > MAX_RESOURCE_PACK_SIZE {
+            return Err(String::from("the resource pack is too large"));
+        }
+        if magic.len() < 4 {
+            magic.extend(chunk.iter().take(4 - magic.len()));
+        }
+        hasher.update(&chunk);
+    }
+    if magic != *b"PK\x03\x04" {
+        return Err(String::from("the resource pack is not a zip file"));
+    }
+
+    Ok((format!("{:x}", hasher.finalize()), size))
+}
+
+/// fails if the server uses more memory than it would get. `used` is [`None`] if the usage isn't known
+fn check_memory_reduction(used: Option<u64>, allocated_memory: Memory) -> Result<(), DatabaseError> {
+    match used {
+        Some(used) if used > u64::from(allocated_memory.mib()) => Err(DatabaseError::Unprocessable(format!(
+            "the server is using {used} MiB of memory, more than the {} MiB it would get. set force_memory to lower it \
+             anyway",
+            allocated_memory.mib()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// what happens to a `server.properties` value set by a user
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ConfigValue {
+    Accepted(String),
+    /// the value was over a limit, so it was set to the limit instead
+    Clamped(String),
+    /// the value can't be set, for the given reason
+    Rejected(&'static str),
+}
+
+/// checks a `server.properties` value against the config limits of the group, and the values the instance forces
+fn limit_config_value(group: &Group, key: &str, value: String) -> ConfigValue {
+    if let Some(forced) = crate::minecraft::util::forced_property(key) {
+        return if value.trim() == forced {
+            ConfigValue::Accepted(forced)
+        } else {
+            ConfigValue::Rejected("the instance forces the value of the key")
+        };
+    }
+    if !group.can_edit_config(key) {
+        return ConfigValue::Rejected("the key can't be edited");
+    }
+    match group.config_limits.get(key) {
+        None => ConfigValue::Accepted(value),
+        Some(ServerConfigLimit::Whitelist(whitelist)) => {
+            if whitelist.contains(&value) {
+                ConfigValue::Accepted(value)
+            } else {
+                ConfigValue::Rejected("the value is not allowed")
+            }
+        }
+        Some(limit @ (ServerConfigLimit::MoreThan(bound) | ServerConfigLimit::LessThan(bound))) => {
+            let Ok(value) = value.trim().parse::<i64>() else {
+                return ConfigValue::Rejected("the value is not a number");
+            };
+            let over_limit = match limit {
+                ServerConfigLimit::MoreThan(_) => value < *bound,
+                _ => value > *bound,
+            };
+            if over_limit {
+                ConfigValue::Clamped(bound.to_string())
+            } else {
+                ConfigValue::Accepted(value.to_string())
+            }
+        }
+        Some(ServerConfigLimit::Regex(pattern)) => {
+            if pattern.is_match(&value) {
+                ConfigValue::Accepted(value)
+            } else {
+                ConfigValue::Rejected("the value doesn't match the allowed pattern")
+            }
+        }
+        Some(ServerConfigLimit::Range(min, max)) => {
+            let Ok(value) = value.trim().parse::<i64>() else {
+                return ConfigValue::Rejected("the value is not a number");
+            };
+            let clamped = value.clamp(*min, *max);
+            if clamped == value {
+                ConfigValue::Accepted(value.to_string())
+            } else {
+                ConfigValue::Clamped(clamped.to_string())
+            }
+        }
+    }
+}
+
+/// [`limit_config_value`] for a value set on its own, by an endpoint other than the config one. a value the limits
+/// would change is rejected instead, the user asked for that exact value
+fn exact_config_value(group: &Group, key: &str, value: String) -> Result<String, &'static str> {
+    match limit_config_value(group, key, value) {
+        ConfigValue::Accepted(value) => Ok(value),
+        ConfigValue::Clamped(_) => Err("the value is over a limit"),
+        ConfigValue::Rejected(reason) => Err(reason),
+    }
+}
+
+/// what happened to the values of a config applied with [`apply_config`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+struct ConfigChanges {
+    /// the config with the values applied
+    config: HashMap<String, String>,
+    accepted: Vec<String>,
+    /// the values that were set to the limit instead
+    clamped: HashMap<String, String>,
+    /// why the values that weren't set were rejected
+    rejected: HashMap<String, &'static str>,
+}
+
+/// the keys of the config the users of the group can see, the others aren't returned or kept when it's set
+fn visible_config(group: &Group, mut config: HashMap<String, String>) -> HashMap<String, String> {
+    if group.config_whitelist.is_empty() {
+        for key in &group.config_blacklist {
+            config.remove(key.as_str());
+        }
+        config
+    } else {
+        group
+            .config_whitelist
+            .iter()
+            .filter_map(|key| Some((key.clone(), config.get(key.as_str())?.clone())))
+            .collect()
+    }
+}
+
+/// sets the `values` on the config, limited by [`limit_config_value`]
+fn apply_config(group: &Group, mut config: HashMap<String, String>, values: HashMap<String, String>) -> ConfigChanges {
+    let mut accepted = Vec::new();
+    let mut clamped = HashMap::new();
+    let mut rejected = HashMap::new();
+    for (key, value) in values {
+        match limit_config_value(group, &key, value) {
+            ConfigValue::Accepted(value) => {
+                accepted.push(key.clone());
+                config.insert(key, value);
+            }
+            ConfigValue::Clamped(value) => {
+                clamped.insert(key.clone(), value.clone());
+                config.insert(key, value);
+            }
+            ConfigValue::Rejected(reason) => {
+                rejected.insert(key, reason);
+            }
+        }
+    }
+    accepted.sort();
+    ConfigChanges { config, accepted, clamped, rejected }
+}
+
+/// what users of a group can set a `server.properties` key to, matching what [`limit_config_value`] enforces
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+struct ConfigKeyLimits {
+    editable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<i64>,
+    /// the only values that are accepted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed: Option<Vec<String>>,
+    /// pattern the whole value has to match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pattern: Option<String>,
+    /// the value the instance forces, which nobody can change
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forced: Option<String>,
+}
+
+fn config_key_limits(group: &Group, key: &str) -> ConfigKeyLimits {
+    if let Some(forced) = crate::minecraft::util::forced_property(key) {
+        return ConfigKeyLimits {
+            forced: Some(forced),
+            ..ConfigKeyLimits::default()
+        };
+    }
+    if !group.can_edit_config(key) {
+        return ConfigKeyLimits::default();
+    }
+    let limits = ConfigKeyLimits {
+        editable: true,
+        ..ConfigKeyLimits::default()
+    };
+    match group.config_limits.get(key) {
+        None => limits,
+        Some(ServerConfigLimit::MoreThan(min)) => ConfigKeyLimits {
+            min: Some(*min),
+            ..limits
+        },
+        Some(ServerConfigLimit::LessThan(max)) => ConfigKeyLimits {
+            max: Some(*max),
+            ..limits
+        },
+        Some(ServerConfigLimit::Range(min, max)) => ConfigKeyLimits {
+            min: Some(*min),
+            max: Some(*max),
+            ..limits
+        },
+        Some(ServerConfigLimit::Regex(pattern)) => ConfigKeyLimits {
+            pattern: Some(pattern.pattern().to_string()),
+            ..limits
+        },
+        Some(ServerConfigLimit::Whitelist(whitelist)) => ConfigKeyLimits {
+            allowed: Some(whitelist.clone()),
+            ..limits
+        },
+    }
+}
+
+fn validate_backup_schedule(schedule: &str) -> Result<(), DatabaseError> {
+    crate::minecraft::backup::parse_schedule(schedule)
+        .map(|_| ())
+        .map_err(|err| DatabaseError::BadRequest(format!("invalid backup schedule: {err}")))
+}
+
+/// the most setup commands a world can have
+const MAX_SETUP_COMMANDS: usize = 64;
+
+/// cleans up the setup commands the same way commands sent to the console are, leaving out blank lines. every command
+/// has to be one the group can run
+fn validate_setup_commands(commands: &str, group: &Group) -> Result<String, DatabaseError> {
+    let commands: Vec<String> = commands
+        .lines()
+        .filter(|command| !command.trim().is_empty())
+        .map(|command| {
+            let command =
+                crate::minecraft::util::console_argument(command, crate::minecraft::util::MAX_COMMAND_LENGTH)
+                    .map_err(|reason| DatabaseError::BadRequest(format!("invalid setup command: {reason}")))?;
+            // the console doesn't want the slash players type
+            let command = command.strip_prefix('/').unwrap_or(&command).to_string();
+            if !group.can_run_command(&command) {
+                return Err(DatabaseError::BadRequest(format!(
+                    "the setup command \"{command}\" isn't allowed"
+                )));
+            }
+            Ok(command)
+        })
+        .collect::<Result<_, _>>()?;
+    if commands.len() > MAX_SETUP_COMMANDS {
+        return Err(DatabaseError::BadRequest(format!(
+            "a world can't have more than {MAX_SETUP_COMMANDS} setup commands"
+        )));
+    }
+    Ok(commands.join("\n"))
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonFrom {
     pub name: String,
     pub hostname: String,
-    pub allocated_memory: Option<u32>,
+    pub allocated_memory: Option<Memory>,
     pub version_id: Id,
+    pub backup_schedule: Option<String>,
+    pub setup_commands: Option<String>,
+    pub forward_player_ip: Option<bool>,
 }
 
 impl FromJson for World {
@@ -190,22 +681,103 @@ impl FromJson for World {
             allocated_memory: data
                 .allocated_memory
                 .unwrap_or(crate::config::CONFIG.world_defaults.allocated_memory)
-                .try_into()
-                .unwrap_or(i32::MAX),
+                .into(),
             version_id: data.version_id,
             enabled: false,
+            backup_schedule: data.backup_schedule.clone(),
+            setup_commands: data.setup_commands.clone().unwrap_or_default(),
+            initialized: false,
+            seed: None,
+            forward_player_ip: data.forward_player_ip,
         }
     }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct JsonUpdate {
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub name: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub hostname: Option<String>,
     #[serde(default, deserialize_with = "deserialize_some")]
-    pub allocated_memory: Option<u32>,
+    pub allocated_memory: Option<Memory>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub version_id: Option<Id>,
+    #[serde(default, deserialize_with = "deserialize_some")]
     pub enabled: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub backup_schedule: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub setup_commands: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub initialized: Option<bool>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub forward_player_ip: Option<Option<bool>>,
+    /// leaves changes that need a restart of the running server (like the memory) for its next start
+    #[serde(default)]
+    pub defer_restart: bool,
+    /// lowers the memory of a running server even if it's using more than it would get
+    #[serde(default)]
+    pub force_memory: bool,
+    /// what happens to the running server, set by [`ApiUpdate::after_api_update`]
+    #[serde(skip)]
+    pub restart: Option<RestartPlan>,
+}
+
+/// how a running server gets the changes of its world that need it to restart, see [`JsonUpdate::defer_restart`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum RestartPlan {
+    /// the players are warned, and it's restarted after `delay` seconds
+    Scheduled { delay: u64 },
+    /// the changes apply the next time it starts
+    Deferred,
+    /// restarted right away
+    Immediate,
+}
+
+/// the pending [`RestartPlan::Scheduled`] restart of each world, shared with the api. a world has at most one,
+/// scheduling another replaces it
+pub type ScheduledRestarts = std::sync::Arc<std::sync::Mutex<HashMap<Id, tokio::task::AbortHandle>>>;
+
+impl World {
+    /// applies the world to its server after `delay` seconds, restarting it if needed, see [`RestartPlan::Scheduled`].
+    /// a restart the world already had scheduled is cancelled, this one applies its changes too
+    fn schedule_restart(state: &AppState, id: Id, delay: u64) {
+        let mut restarts = state.scheduled_restarts.lock().expect("the scheduled restarts lock is poisoned");
+        let task = tokio::task::spawn(Self::update_server_later(state.clone(), id, delay));
+        if let Some(previous) = restarts.insert(id, task.abort_handle()) {
+            previous.abort();
+        }
+    }
+
+    async fn update_server_later(state: AppState, id: Id, delay: u64) {
+        tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
+        // taken out before the server is touched, so it can't be cancelled halfway through the restart
+        {
+            let mut restarts = state.scheduled_restarts.lock().expect("the scheduled restarts lock is poisoned");
+            match restarts.get(&id) {
+                Some(restart) if restart.id() == tokio::task::id() => restarts.remove(&id),
+                _ => return,
+            };
+        }
+        // read again, it could have changed in the meantime
+        let world: World = match state.database.get_one(id, None).await {
+            Ok(world) => world,
+            Err(err) => {
+                error!("could not apply the changes of {id}: {err}");
+                return;
+            }
+        };
+        match state.servers.get_or_create_server(&world).await {
+            Ok(server) => {
+                if let Err(err) = server.lock().await.update_world(world).await {
+                    error!("could not update the server of {id}: {err}");
+                }
+            }
+            Err(err) => error!("could not get the server of {id}: {err}"),
+        }
+    }
 }
 impl UpdateJson for World {
     type JsonUpdate = JsonUpdate;
@@ -215,10 +787,14 @@ impl UpdateJson for World {
         new.hostname = data.hostname.clone().unwrap_or(new.hostname);
         new.allocated_memory = data
             .allocated_memory
-            .map(|v| v.try_into().unwrap_or(i32::MAX))
+            .map(i32::from)
             .unwrap_or(new.allocated_memory);
         new.version_id = data.version_id.unwrap_or(new.version_id);
         new.enabled = data.enabled.unwrap_or(new.enabled);
+        new.backup_schedule = data.backup_schedule.clone().unwrap_or(new.backup_schedule);
+        new.setup_commands = data.setup_commands.clone().unwrap_or(new.setup_commands);
+        new.initialized = data.initialized.unwrap_or(new.initialized);
+        new.forward_player_ip = data.forward_player_ip.unwrap_or(new.forward_player_ip);
         new
     }
 }
@@ -227,6 +803,8 @@ impl ApiObject for World {
     fn routes() -> Router<AppState> {
         Router::new()
             .route("/", get(Self::api_list).post(Self::api_create))
+            .route("/export.csv", get(Self::api_export_csv))
+            .route("/delete", post(Self::api_remove_many))
             .route(
                 "/{id}",
                 get(Self::api_get)
@@ -239,11 +817,58 @@ impl ApiObject for World {
                     .patch(Self::set_server_config)
                     .post(Self::set_server_config),
             )
+            .route("/{id}/config/import", post(Self::import_server_config))
+            .route("/{id}/config/validate", post(Self::validate_server_config))
+            .route("/{id}/config/limits", get(Self::get_config_limits))
+            .route(
+                "/{id}/resource-pack",
+                get(Self::get_resource_pack)
+                    .put(Self::set_resource_pack)
+                    .delete(Self::remove_resource_pack),
+            )
             .route("/{id}/status", get(Self::world_get_status))
+            .route("/{id}/address", get(Self::get_address))
+            .route("/{id}/world-info", get(Self::get_world_info))
+            .route("/{id}/seed", get(Self::get_seed))
+            .route("/{id}/datapacks", get(Self::list_datapacks))
+            .route(
+                "/{id}/datapacks/{name}",
+                post(Self::enable_datapack).delete(Self::disable_datapack),
+            )
+            .route(
+                "/{id}/backups",
+                get(Self::list_backups).post(Self::create_backup)
+            )
+            .route(
+                "/{id}/backups/{name}",
+                get(Self::download_backup).delete(Self::remove_backup)
+            )
+            .route("/{id}/performance", get(Self::get_performance))
+            .route("/{id}/regenerate", post(Self::regenerate))
+            .route("/{id}/save", post(Self::save_world))
+            .route("/{id}/broadcast", post(Self::broadcast))
+            .route("/{id}/op", post(Self::op_player))
+            .route("/{id}/gamerules", get(Self::get_gamerules))
+            .route("/{id}/gamerule", post(Self::set_gamerule))
+            .route(
+                "/{id}/pregenerate",
+                get(Self::get_pregeneration)
+                    .post(Self::pregenerate)
+                    .delete(Self::cancel_pregeneration),
+            )
+            .route("/{id}/save-off", post(Self::save_off))
+            .route("/{id}/save-on", post(Self::save_on))
+            .route("/{id}/difficulty", put(Self::quick_set::<Difficulty>))
+            .route("/{id}/gamemode", put(Self::quick_set::<GameMode>))
+            .route("/{id}/time", put(Self::quick_set::<Time>))
+            .route("/{id}/weather", put(Self::quick_set::<Weather>))
+            .route("/{id}/transfer", post(Self::transfer_ownership))
+            .route("/{id}/version", post(Self::change_version))
             .route(
                 "/{id}/log",
                     get(Self::get_server_log)
             )
+            .route("/{id}/console/history", get(Self::get_console_history))
             .route(
                 "/{id}/icon",
                 post(Self::upload_icon)
@@ -267,12 +892,20 @@ impl ApiCreate for World {
         json: &mut Self::JsonFrom,
         user: &User,
     ) -> Result<(), DatabaseError> {
+        if let Some(schedule) = &json.backup_schedule {
+            validate_backup_schedule(schedule)?;
+        }
+
         let group = user.group(state.database.clone(), None).await;
         let user_worlds: Vec<World> = state
             .database
             .get_all_where("owner_id", user.id, Some((user, &group)))
             .await?;
         let group = user.group(state.database.clone(), None).await;
+        if let Some(commands) = &json.setup_commands {
+            json.setup_commands = Some(validate_setup_commands(commands, &group)?);
+        }
+        Version::check_exists(&state.database, json.version_id, user, &group).await?;
 
         //enforce the world limit
         if let Some(world_limit) = group.world_limit {
@@ -280,17 +913,10 @@ impl ApiCreate for World {
                 return Err(DatabaseError::Unauthorized);
             }
         }
+        crate::minecraft::capacity::check_create(&state).await?;
 
+        // uniqueness is checked by the database layer, see `World::check_hostname`
         json.hostname = into_valid_hostname(&json.hostname);
-        if !state
-            .database
-            .get_all_where::<World, _>("hostname", json.hostname.clone(), None)
-            .await?
-            .is_empty()
-        {
-            debug!("hostname already used. adding a random value to it");
-            json.hostname += &rand::random_range(0..100000).to_string();
-        }
 
         json.allocated_memory = Some(
             json.allocated_memory
@@ -299,11 +925,11 @@ impl ApiCreate for World {
 
         //enforce memory limit
         if let Some(allocated_memory) = json.allocated_memory {
-            if allocated_memory < CONFIG.world.minimum_memory {
+            if allocated_memory.mib() < CONFIG.world.minimum_memory {
                 return Err(DatabaseError::Unauthorized);
             }
             if let Some(group_mem_limit) = group.per_world_memory_limit {
-                if allocated_memory as u64 > group_mem_limit as u64 {
+                if i64::from(allocated_memory) > i64::from(group_mem_limit) {
                     return Err(DatabaseError::Unauthorized);
                 }
             }
@@ -319,12 +945,23 @@ impl ApiCreate for World {
         _json: &mut Self::JsonFrom,
         _user: &User,
     ) -> Result<(), DatabaseError> {
-        let _ = appstate.servers.get_or_create_server(self).await;
+        crate::minecraft::capacity::invalidate().await;
+        if let Ok(server) = appstate.servers.get_or_create_server(self).await
+            && let Err(err) = server.lock().await.prepare().await
+        {
+            error!("could not create the files of {}: {err}", self.id);
+        }
         Ok(())
     }
 }
 #[async_trait]
 impl ApiUpdate for World {
+    fn update_details(json: &Self::JsonUpdate) -> serde_json::Map<String, serde_json::Value> {
+        json.restart
+            .map(|restart| serde_json::Map::from_iter([(String::from("restart"), json!(restart))]))
+            .unwrap_or_default()
+    }
+
     //TODO: this needs a rewrite, too much repeated code
     async fn before_api_update(
         &self,
@@ -332,7 +969,19 @@ impl ApiUpdate for World {
         json: &mut Self::JsonUpdate,
         user: &User,
     ) -> Result<(), DatabaseError> {
+        if let Some(Some(schedule)) = &json.backup_schedule {
+            validate_backup_schedule(schedule)?;
+        }
+
         let group = user.group(state.database.clone(), None).await;
+        if let Some(commands) = &json.setup_commands {
+            json.setup_commands = Some(validate_setup_commands(commands, &group)?);
+        }
+        if let Some(version_id) = json.version_id
+            && version_id != self.version_id
+        {
+            Version::check_exists(&state.database, version_id, user, &group).await?;
+        }
         let user_worlds: Vec<World> = state
             .database
             .get_all_where("owner_id", user.id, Some((user, &group)))
@@ -351,57 +1000,71 @@ impl ApiUpdate for World {
             }
         }
 
-        //adjust hostname, so it's a valid, unique subdomain
+        //adjust hostname, so it's a valid subdomain. uniqueness is checked by the database layer
         if let Some(hostname) = &json.hostname {
             json.hostname = Some(into_valid_hostname(hostname))
         }
-        if let Some(hostname) = &json.hostname {
-            if match state
-                .database
-                .get_where::<World, _>("hostname", hostname.clone(), None)
-                .await
-            {
-                Ok(world) => world.id != self.id,
-                Err(_) => false,
-            } {
-                debug!("hostname already used. adding a random value to it");
-                json.hostname = Some(hostname.clone() + &rand::random_range(0..100000).to_string());
+
+        let allocated_memory = match json.allocated_memory {
+            Some(allocated_memory) => allocated_memory,
+            None => Memory::try_from(self.allocated_memory)
+                .map_err(|err| DatabaseError::InternalServerError(err.to_string()))?,
+        };
+        json.allocated_memory = Some(allocated_memory);
+        let enabled = json.enabled.unwrap_or(self.enabled);
+
+        // the server would run out of memory as soon as it's restarted with less than it's using
+        if self.enabled && enabled && i32::from(allocated_memory) < self.allocated_memory && !json.force_memory {
+            if let Some(server) = state.servers.get_server(self.id).await {
+                let used = server.lock().await.memory_usage().await;
+                check_memory_reduction(used, allocated_memory)?;
             }
         }
 
-        json.allocated_memory = Some(
-            json.allocated_memory
-                .unwrap_or(self.allocated_memory as u32),
-        );
-        let allocated_memory = json.allocated_memory.unwrap();
-        let enabled = json.enabled.unwrap_or(self.enabled);
+        if let Some(window) = &CONFIG.world.start_window
+            && enabled
+            && !self.enabled
+            && !Access::User
+                .during(window.clone())
+                .or(Access::PrivilegedUser)
+                .can_access(Some(self), user, &group)
+        {
+            return Err(DatabaseError::Unprocessable(format!(
+                "worlds can only be started during {window}"
+            )));
+        }
 
         //enforce memory limit
         if enabled {
-            if allocated_memory < CONFIG.world.minimum_memory {
+            if allocated_memory.mib() < CONFIG.world.minimum_memory {
                 return Err(DatabaseError::Unauthorized);
             }
             if let Some(group_mem_limit) = group.per_world_memory_limit {
-                if allocated_memory as u64 > group_mem_limit as u64 {
+                if i64::from(allocated_memory) > i64::from(group_mem_limit) {
                     return Err(DatabaseError::Unauthorized);
                 }
             }
 
             if let Some(memory_limit) = group.total_memory_limit {
-                if allocated_memory != self.allocated_memory as u32 {
+                if i32::from(allocated_memory) != self.allocated_memory {
                     // sum of total users memory usage plus the new usage
-                    let mut total_memory = user.total_memory_usage + allocated_memory as i64;
+                    let mut total_memory = user.total_memory_usage + i64::from(allocated_memory);
 
                     //if the world was previously enabled, it means it contributed to total allocated memory, and we don't want to include it
                     if self.enabled {
-                        total_memory -= self.allocated_memory as i64;
+                        total_memory -= i64::from(self.allocated_memory);
                     }
 
-                    if total_memory > memory_limit as i64 {
+                    if total_memory > i64::from(memory_limit) {
                         return Err(DatabaseError::Unauthorized);
                     }
                 }
             }
+
+            // the memory of a world that's already enabled is already counted
+            let counted_memory = if self.enabled { i64::from(self.allocated_memory) } else { 0 };
+            let added_memory = i64::from(allocated_memory) - counted_memory;
+            crate::minecraft::capacity::check_enable(&state, !self.enabled, added_memory).await?;
         }
 
         Ok(())
@@ -409,20 +1072,48 @@ impl ApiUpdate for World {
     async fn after_api_update(
         &self,
         app_state: AppState,
-        _json: &mut Self::JsonUpdate,
+        json: &mut Self::JsonUpdate,
         user: &User,
     ) -> Result<(), DatabaseError> {
+        crate::minecraft::capacity::invalidate().await;
         let server = app_state
             .servers
             .get_or_create_server(self)
             .await
-            .map_err(|err| DatabaseError::InternalServerError(err.to_string()))?;
-        let mut server = server.lock().await;
-
-        server
-            .update_world(self.clone())
-            .await
-            .map_err(|err| DatabaseError::InternalServerError(err.to_string()))?;
+            .map_err(server_error)?;
+        {
+            let mut server = server.lock().await;
+            let old = server.world();
+            let restart = self.enabled
+                && (old.allocated_memory != self.allocated_memory || old.version_id != self.version_id)
+                && matches!(server.status().await, Ok(MinecraftServerStatus::Running));
+            let delay = CONFIG.world.restart_warning;
+            // lowered memory is never applied with an unannounced restart, it waits for the next start instead
+            let lowered = self.allocated_memory < old.allocated_memory;
+
+            if restart && (json.defer_restart || (lowered && delay == 0)) {
+                json.restart = Some(RestartPlan::Deferred);
+                server
+                    .update_world_without_restart(self.clone())
+                    .await
+                    .map_err(server_error)?;
+            } else if restart && delay > 0 {
+                json.restart = Some(RestartPlan::Scheduled { delay });
+                server
+                    .write_console(format!("say Server restarting in {delay} seconds to apply changes\n"))
+                    .await
+                    .map_err(server_error)?;
+                Self::schedule_restart(&app_state, self.id, delay);
+            } else {
+                if restart {
+                    json.restart = Some(RestartPlan::Immediate);
+                }
+                server
+                    .update_world(self.clone())
+                    .await
+                    .map_err(server_error)?;
+            }
+        }
 
         let user_enabled_worlds = app_state
             .database
@@ -435,11 +1126,11 @@ impl ApiUpdate for World {
 
         let mut total_memory_usage = 0;
         for world in &user_enabled_worlds {
-            total_memory_usage += world.allocated_memory;
+            total_memory_usage += i64::from(world.allocated_memory);
         }
         let mut user = user.clone();
 
-        user.total_memory_usage = total_memory_usage as i64;
+        user.total_memory_usage = total_memory_usage;
 
         app_state.database.update(&user, None).await?;
 
@@ -454,15 +1145,25 @@ impl ApiRemove for World {
         _user: &User,
     ) -> Result<(), DatabaseError> {
         info!("removing world {}", self.id);
+        // the row is only deleted once the server has exited and its files are gone. if it can't be stopped the
+        // error keeps the row, so the still running server isn't left without a world
         match app_state.servers.get_or_create_server(self).await {
             Ok(server) => server
                 .lock()
                 .await
                 .remove()
                 .await
-                .map_err(|err| DatabaseError::InternalServerError(err.to_string())),
-            Err(err) => Err(DatabaseError::InternalServerError(err.to_string())),
+                .map_err(|err| DatabaseError::InternalServerError(format!("{err:#}")))?,
+            Err(err) => return Err(DatabaseError::InternalServerError(err.to_string())),
+        }
+        app_state.servers.remove_server(&self.id).await;
+
+        let backups_dir = crate::minecraft::backup::world_backups_dir(self.id);
+        if backups_dir.exists() {
+            std::fs::remove_dir_all(backups_dir)
+                .map_err(|err| DatabaseError::InternalServerError(err.to_string()))?;
         }
+        Ok(())
     }
 }
 
@@ -478,13 +1179,203 @@ impl ApiIcon for World {
     }
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransferJson {
+    pub new_owner_id: Id,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChangeVersionJson {
+    pub version_id: Id,
+    /// back the world up before changing the version
+    #[serde(default)]
+    pub backup: bool,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ForceQuery {
+    /// allow downgrading the world
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// body of `PUT /api/worlds/{id}/resource-pack`
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourcePackJson {
+    /// where the clients download the pack from
+    pub url: String,
+    /// message shown to the players when they are asked to download the pack
+    pub prompt: Option<String>,
+    /// whether players that decline the pack are kicked
+    pub required: Option<bool>,
+}
+
+/// body of the quick-set endpoints, like `PUT /api/worlds/{id}/difficulty`
+#[derive(Debug, Clone, Deserialize)]
+pub struct QuickSetJson<S> {
+    pub value: S,
+}
+
+/// body of `POST /api/worlds/{id}/broadcast`
+#[derive(Debug, Clone, Deserialize)]
+pub struct BroadcastJson {
+    pub message: String,
+}
+
+/// body of `POST /api/worlds/{id}/op`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OpJson {
+    /// the player to op, the owner's [`User::minecraft_username`] without it
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// lets endpoints be accessed through a [`WorldShare`] token instead of a session
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ShareQuery {
+    pub share: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RegenerateQuery {
+    #[serde(default)]
+    pub confirm: bool,
+    /// whether to back the world up before it's removed
+    #[serde(default = "regenerate_backup_default")]
+    pub backup: bool,
+}
+
+fn regenerate_backup_default() -> bool {
+    true
+}
+
+/// the longest `GET /api/worlds/{id}/status` waits for the status to change
+const MAX_STATUS_WAIT: std::time::Duration = std::time::Duration::from_secs(60);
+/// how often a waiting `GET /api/worlds/{id}/status` checks whether a world without a server got one
+const STATUS_SERVER_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct StatusWaitQuery {
+    /// how many seconds to wait for the status to change
+    pub wait: Option<u64>,
+}
+
+/// see [`World::world_get_status`]
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusJson {
+    #[serde(flatten)]
+    pub status: MinecraftServerStatusJson,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changed: Option<bool>,
+    /// the chunks of the world being pregenerated, see [`World::pregenerate`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pregeneration: Option<Pregeneration>,
+}
+
+/// how many lines of console output `GET /api/worlds/{id}/console/history` returns when `lines` isn't set
+const DEFAULT_CONSOLE_HISTORY_LINES: usize = 100;
+
+/// how long the server has to answer the `tps` and `mspt` commands
+const PERFORMANCE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// how long to wait for the output of the `seed` command
+const SEED_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// how long `POST /api/worlds/{id}/op` waits for the server to answer `op`
+const OP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// how long the gamerule endpoints wait for the server to answer `gamerule`
+const GAMERULE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// how long Chunky has to answer each of the commands starting and cancelling a pregeneration
+const PREGENERATION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// body of `POST /api/worlds/{id}/pregenerate`
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PregenerateJson {
+    /// in blocks, at most [`crate::config::WorldConfig::max_pregeneration_radius`]
+    pub radius: u32,
+    #[serde(default)]
+    pub center_x: i32,
+    #[serde(default)]
+    pub center_z: i32,
+}
+
+/// body of `POST /api/worlds/{id}/gamerule`
+#[derive(Debug, Clone, Deserialize)]
+pub struct GameruleJson {
+    /// one of the [`crate::minecraft::util::GAMERULES`]
+    pub rule: String,
+    pub value: crate::minecraft::util::GameruleValue,
+}
+
+/// see [`World::get_performance`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PerformanceReport {
+    /// whether the server can report its performance. the other fields are null if it can't
+    pub supported: bool,
+    pub tps_1m: Option<f64>,
+    pub tps_5m: Option<f64>,
+    pub tps_15m: Option<f64>,
+    /// the average milliseconds per tick over the last minute
+    pub mspt: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ConsoleHistoryQuery {
+    /// capped at [`CONSOLE_HISTORY_SIZE`]
+    pub lines: Option<usize>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct MinecraftServerStatusJson {
     pub(crate) status: String,
     pub(crate) code: u32,
+    /// the signal that killed the server, if it was killed by one
+    #[serde(default)]
+    pub(crate) signal: Option<u8>,
+    /// the name of the [`MinecraftServerStatusJson::signal`], like `SIGKILL`
+    #[serde(default)]
+    pub(crate) signal_name: Option<String>,
+    /// local port the server listens on. [`None`] when it's stopped
+    #[serde(default)]
+    pub(crate) port: Option<u16>,
+    /// hostname the proxy routes to the server. [`None`] when it's stopped
+    #[serde(default)]
+    pub(crate) hostname: Option<String>,
+    /// when the server was started. [`None`] when it's stopped
+    #[serde(default)]
+    pub(crate) started_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// how long the server has been running, in seconds. [`None`] when it's stopped
+    #[serde(default)]
+    pub(crate) uptime: Option<u64>,
 }
 
 impl World {
+    /// makes sure no other world uses the hostname and it isn't reserved. its characters are checked by
+    /// [`DbObject::validate`]
+    async fn check_hostname(&self, database: &Database) -> Result<(), DatabaseError> {
+        if is_reserved_hostname(
+            &self.hostname,
+            &CONFIG.proxy.reserved_hostnames,
+            &CONFIG.proxy.hostname,
+        ) {
+            return Err(DatabaseError::BadRequest(format!(
+                "the hostname \"{}\" is reserved",
+                self.hostname
+            )));
+        }
+        match database
+            .get_where::<World, _>("hostname", self.hostname.clone(), None)
+            .await
+        {
+            Ok(world) if world.id != self.id => {
+                debug!("hostname {} is already used by world {}", self.hostname, world.id);
+                Err(DatabaseError::Conflict)
+            }
+            Ok(_) | Err(DatabaseError::NotFound) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
     pub async fn version(&self, database: Database, user: Option<(&User, &Group)>) -> Version {
         database
             .get_one(self.version_id, user)
@@ -499,36 +1390,363 @@ impl World {
             .unwrap_or_else(|_| panic!("couldn't find user with id {}", self.owner_id))
     }
 
-    #[allow(clippy::needless_pass_by_value)]
-    async fn world_get_status(
-        id: Path<Id>,
-        state: State<AppState>,
-        user: UserAuth,
-    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
-        let id = id.0;
-        let state = state.0;
-        let user = user.0;
+    /// gives the world to another user, moving its files and updating both users' memory usage
+    async fn transfer_ownership(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        AdminAuth(admin): AdminAuth,
+        axum::Json(json): axum::Json<TransferJson>,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let world = state
+            .database
+            .get_one::<Self>(id, None)
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        if world.owner_id == json.new_owner_id {
+            return Ok(axum::Json(world));
+        }
 
-        {
-            let group = user.group(state.database.clone(), None).await;
-            state
+        let new_owner = state
+            .database
+            .get_one::<User>(json.new_owner_id, None)
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        let group = new_owner.group(state.database.clone(), None).await;
+
+        //make sure the world fits within the new owner's limits
+        if let Some(world_limit) = group.world_limit {
+            let owned_worlds = state
                 .database
-                .get_one::<Self>(id, Some((&user, &group)))
+                .get_all_where::<World, _>("owner_id", new_owner.id, None)
                 .await
-                .map_err(crate::api::handlers::handle_database_error)?;
+                .map_err(crate::api::handlers::handle_database_error)?
+                .len();
+            if owned_worlds >= world_limit as usize {
+                return Err(StatusCode::CONFLICT);
+            }
+        }
+        if let Some(per_world_memory_limit) = group.per_world_memory_limit
+            && world.allocated_memory > per_world_memory_limit
+        {
+            return Err(StatusCode::CONFLICT);
+        }
+        if world.enabled
+            && let Some(total_memory_limit) = group.total_memory_limit
+            && new_owner.total_memory_usage + i64::from(world.allocated_memory)
+                > i64::from(total_memory_limit)
+        {
+            return Err(StatusCode::CONFLICT);
+        }
+
+        info!(
+            "{} is transferring world {} from {} to {}",
+            admin.username, world.id, world.owner_id, new_owner.id
+        );
+
+        // get the server before updating the database, so it still points to the old directory
+        let server = state
+            .servers
+            .get_or_create_server(&world)
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        let mut new_world = world.clone();
+        new_world.owner_id = new_owner.id;
+        state
+            .database
+            .update(&new_world, None)
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let mut server = server.lock().await;
+        if let Err(err) = server.update_world(new_world.clone()).await {
+            error!("{err}");
+            // the server only takes the new owner once its directory was moved, until then the files are still where
+            // the old owner points
+            if server.world().owner_id == world.owner_id {
+                state
+                    .database
+                    .update(&world, None)
+                    .await
+                    .map_err(crate::api::handlers::handle_database_error)?;
+            }
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        drop(server);
+
+        for user_id in [world.owner_id, new_owner.id] {
+            // the previous owner might not exist anymore
+            if let Ok(mut user) = state.database.get_one::<User>(user_id, None).await {
+                user.total_memory_usage = user
+                    .calculate_memory_usage(&state.database)
+                    .await
+                    .map_err(crate::api::handlers::handle_database_error)?;
+                state
+                    .database
+                    .update(&user, None)
+                    .await
+                    .map_err(crate::api::handlers::handle_database_error)?;
+            }
+        }
+
+        Ok(axum::Json(new_world))
+    }
+
+    /// changes the world's version, checking the new version can be used first. downgrades, which can corrupt the
+    /// world, have to be forced with `?force=true`
+    async fn change_version(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        Query(force): Query<ForceQuery>,
+        axum::Json(json): axum::Json<ChangeVersionJson>,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        if !world.can_update(&user, &group) {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+
+        let from = state
+            .database
+            .get_one::<Version>(world.version_id, None)
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        let to = state
+            .database
+            .get_one::<Version>(json.version_id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        if !to.jar_path().exists() {
+            return Ok((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("the server jar of {} is not available", to.minecraft_version),
+            )
+                .into_response());
+        }
+
+        let mut warnings = Vec::new();
+        match crate::minecraft::util::compare_minecraft_versions(
+            &from.minecraft_version,
+            &to.minecraft_version,
+        ) {
+            Some(std::cmp::Ordering::Greater) if !force.force => {
+                return Ok((
+                    StatusCode::CONFLICT,
+                    format!(
+                        "{} is older than {}, downgrading can corrupt the world. use ?force=true to do it anyway",
+                        to.minecraft_version, from.minecraft_version
+                    ),
+                )
+                    .into_response());
+            }
+            Some(std::cmp::Ordering::Greater) => {
+                warnings.push(String::from("the world was downgraded"));
+            }
+            Some(_) => {}
+            None => warnings.push(format!(
+                "could not compare {} with {}, make sure this is not a downgrade",
+                from.minecraft_version, to.minecraft_version
+            )),
+        }
+
+        let backup = if json.backup {
+            Some(
+                crate::minecraft::backup::backup_world(&state, &world)
+                    .await
+                    .map_err(|err| {
+                        error!("backup of world {} failed: {err}", world.id);
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    })?,
+            )
+        } else {
+            None
+        };
+
+        info!(
+            "changing the version of world {} from {} to {}",
+            world.id, from.minecraft_version, to.minecraft_version
+        );
+
+        let mut new_world = world.clone();
+        new_world.version_id = to.id;
+        state
+            .database
+            .update(&new_world, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        // restarts the server with the new jar if it's enabled
+        let server = state
+            .servers
+            .get_or_create_server(&world)
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        if let Err(err) = server.lock().await.update_world(new_world.clone()).await {
+            return Ok(crate::api::handlers::database_error_response(server_error(err)));
         }
 
-        let server = state.servers.get_server(id);
-        let status = match server {
-            Some(server) => server.lock().await.status().await,
-            None => Ok(MinecraftServerStatus::Exited(0)),
+        Ok(axum::Json(json!({
+            "from": from,
+            "to": to,
+            "backup": backup,
+            "warnings": warnings,
+            "world": new_world,
+        }))
+        .into_response())
+    }
+
+    /// gets the world either as the authenticated user, or through a [`WorldShare`] token granting `permission`
+    pub async fn get_shared(
+        state: &AppState,
+        id: Id,
+        user: Result<UserAuth, StatusCode>,
+        share: Option<Uuid>,
+        permission: SharePermission,
+    ) -> Result<Self, StatusCode> {
+        if let Some(token) = share {
+            let share = WorldShare::from_token(&state.database, token)
+                .await
+                .map_err(|_| StatusCode::UNAUTHORIZED)?;
+            // the token is valid, it just doesn't allow this
+            if !share.grants(id, permission) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            return state
+                .database
+                .get_one::<Self>(id, None)
+                .await
+                .map_err(crate::api::handlers::handle_database_error);
         }
-        .map_err(|err| {
+
+        let user = user?.0;
+        let group = user.group(state.database.clone(), None).await;
+        state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)
+    }
+
+    /// the address players connect to the world through the proxy with, and whether it's running
+    async fn get_address(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        Query(share): Query<ShareQuery>,
+        user: Result<UserAuth, StatusCode>,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let world = Self::get_shared(&state, id, user, share.share, SharePermission::View).await?;
+
+        let reachable = match state.servers.get_server(id).await {
+            Some(server) => {
+                let server = server.lock().await;
+                let status = server.status().await.map_err(|err| {
+                    error!("{err}");
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+                matches!(status, MinecraftServerStatus::Running) && server.port().is_some()
+            }
+            None => false,
+        };
+
+        Ok(axum::Json(json!({
+            "address": world_address(&world.hostname, &CONFIG.proxy.hostname, CONFIG.proxy.port),
+            "hostname": format!("{}.{}", world.hostname, CONFIG.proxy.hostname),
+            "port": CONFIG.proxy.port,
+            "reachable": reachable,
+        })))
+    }
+
+    #[allow(clippy::needless_pass_by_value)]
+    /// the status of the world's server, with where it can be reached and for how long it has been running
+    async fn server_status(
+        server: &crate::minecraft::server::ServerMutex,
+    ) -> Result<MinecraftServerStatusJson, StatusCode> {
+        let server = server.lock().await;
+        let status = server.status().await.map_err(|err| {
             error!("{err}");
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
+        Ok(MinecraftServerStatusJson::from(status)
+            .with_address(server.port(), server.hostname())
+            .with_uptime(server.started_at(), server.uptime()))
+    }
+
+    /// the status of the world's server, [`MinecraftServerStatus::Exited`] if it doesn't have one
+    async fn current_status(state: &AppState, id: Id) -> Result<MinecraftServerStatusJson, StatusCode> {
+        match state.servers.get_server(id).await {
+            Some(server) => Self::server_status(&server).await,
+            None => Ok(MinecraftServerStatusJson::from(MinecraftServerStatus::Exited(0))),
+        }
+    }
+
+    /// `GET /api/worlds/{id}/status`. with `wait` (in seconds, at most [`MAX_STATUS_WAIT`]) the request is held until
+    /// the status changes or the time runs out, for clients that can't use the console socket. the response then says
+    /// whether it `changed`. a client disconnecting drops the request, which ends the wait
+    async fn world_get_status(
+        id: Path<Id>,
+        state: State<AppState>,
+        Query(share): Query<ShareQuery>,
+        Query(wait): Query<StatusWaitQuery>,
+        user: Result<UserAuth, StatusCode>,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let id = id.0;
+        let state = state.0;
+
+        Self::get_shared(&state, id, user, share.share, SharePermission::View).await?;
+
+        let status = Self::current_status(&state, id).await?;
+        let Some(wait) = wait.wait else {
+            let pregeneration = Self::running_pregeneration(&state, id).await;
+            return Ok(axum::Json(StatusJson { status, changed: None, pregeneration }));
+        };
+
+        // waiting doesn't create the server, anyone with a share could otherwise load any world. a world without one
+        // is stopped, until it's started and gets one
+        let wait = std::time::Duration::from_secs(wait).min(MAX_STATUS_WAIT);
+        let current = (status.status.clone(), status.code);
+        let change = async {
+            let server = loop {
+                if let Some(server) = state.servers.get_server(id).await {
+                    break server;
+                }
+                tokio::time::sleep(STATUS_SERVER_POLL_INTERVAL).await;
+            };
+            // subscribed before reading the status again, so a change in between isn't missed
+            let mut stdout = server.lock().await.stdout();
+            if let Ok(status) = Self::server_status(&server).await
+                && (status.status.clone(), status.code) != current
+            {
+                return true;
+            }
+            loop {
+                match stdout.recv().await {
+                    Ok(McStdout::Status(status)) if (status.status.clone(), status.code) != current => return true,
+                    Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                    // the server was removed, and the world is stopped again
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => {
+                        let exited = MinecraftServerStatusJson::from(MinecraftServerStatus::Exited(0));
+                        return current != (exited.status, exited.code);
+                    }
+                }
+            }
+        };
+        let changed = tokio::time::timeout(wait, change).await.unwrap_or(false);
 
-        Ok(axum::Json(MinecraftServerStatusJson::from(status)))
+        let status = if changed { Self::current_status(&state, id).await? } else { status };
+        let pregeneration = Self::running_pregeneration(&state, id).await;
+        Ok(axum::Json(StatusJson { status, changed: Some(changed), pregeneration }))
     }
 
     #[allow(clippy::needless_pass_by_value)]
@@ -562,25 +1780,47 @@ impl World {
 
         let server = server.lock().await;
 
-        let mut config = server.config().await.map_err(|err| {
+        let config = server.config().await.map_err(|err| {
             error!("{err}");
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
-        if group.config_whitelist.is_empty() {
-            for key in group.config_blacklist {
-                config.remove(&key);
-            }
-        } else {
-            let mut new_config = HashMap::new();
-            for key in group.config_whitelist {
-                if let Some(value) = config.get(&key) {
-                    new_config.insert(key, value.clone());
-                }
-            }
-            config = new_config
-        }
 
-        Ok(axum::Json(config))
+        Ok(axum::Json(visible_config(&group, config)))
+    }
+
+    /// the limits of every `server.properties` key the world has, or the user's group has limits for
+    async fn get_config_limits(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let server = state
+            .servers
+            .get_or_create_server(&world)
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let config = server.lock().await.config().await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let limits = config
+            .keys()
+            .chain(group.config_limits.keys())
+            .chain(&group.config_whitelist)
+            .map(|key| (key.clone(), config_key_limits(&group, key)))
+            .collect::<std::collections::BTreeMap<_, _>>();
+        Ok(axum::Json(limits))
     }
 
     #[allow(clippy::needless_pass_by_value)]
@@ -616,63 +1856,11 @@ impl World {
 
         let mut server = server.lock().await;
 
-        let mut config = server.config().await.map_err(|err| {
+        let config = server.config().await.map_err(|err| {
             error!("{err}");
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
-        if group.config_whitelist.is_empty() {
-            for key in &group.config_blacklist {
-                config.remove(key.as_str());
-            }
-        } else {
-            let mut new_config = HashMap::new();
-            for key in &group.config_whitelist {
-                if let Some(value) = config.get(key.as_str()) {
-                    new_config.insert(key.clone(), value.clone());
-                }
-            }
-            config = new_config
-        }
-
-        for (key, value) in new_config {
-            let mut editable = true;
-            if group.config_whitelist.is_empty() {
-                if group.config_blacklist.contains(&key) {
-                    editable = false;
-                }
-            } else if !group.config_whitelist.contains(&key) {
-                editable = false;
-            }
-
-            if editable {
-                if let Some(config_limit) = group.config_limits.get(&key) {
-                    match config_limit {
-                        ServerConfigLimit::MoreThan(limit) | ServerConfigLimit::LessThan(limit) => {
-                            if let Ok(value) = value.parse::<i64>() {
-                                let over_limit = match config_limit {
-                                    ServerConfigLimit::MoreThan(limit) => value < *limit,
-                                    ServerConfigLimit::LessThan(limit) => value > *limit,
-                                    _ => true, //huh
-                                };
-                                if over_limit {
-                                    config.insert(key, limit.to_string());
-                                } else {
-                                    config.insert(key, value.to_string());
-                                }
-                            }
-                            //if the value is invalid don't set it
-                        }
-                        ServerConfigLimit::Whitelist(whitelist) => {
-                            if whitelist.contains(&value) {
-                                config.insert(key, value);
-                            }
-                        }
-                    }
-                } else {
-                    config.insert(key, value);
-                }
-            }
-        }
+        let config = apply_config(&group, visible_config(&group, config), new_config).config;
 
         server.set_config(config.clone()).await.map_err(|err| {
             error!("{err}");
@@ -682,37 +1870,1649 @@ impl World {
         Ok(axum::Json(config))
     }
 
-    async fn get_server_log(
-        id: Path<Id>,
-        state: State<AppState>,
-        user: UserAuth,
-    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
-        let id = id.0;
-        let state = state.0;
-        let user = user.0;
+    /// merges a whole `server.properties` file onto the world's config, reporting what happened to each key
+    async fn import_server_config(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        body: String,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let imported = match crate::minecraft::util::parse_minecraft_properties_strict(&body) {
+            Ok(imported) => imported,
+            Err(err) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, err).into_response()),
+        };
+
+        let group = user.group(state.database.clone(), None).await;
+        let world: Self = state
+            .database
+            .get_one(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        let server = state.servers.get_or_create_server(&world).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let mut server = server.lock().await;
+
+        let config = server.config().await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let changes = apply_config(&group, config, imported);
+
+        server.set_config(changes.config).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(axum::Json(json!({
+            "accepted": changes.accepted,
+            "clamped": changes.clamped,
+            "rejected": changes.rejected,
+        }))
+        .into_response())
+    }
 
+    /// what [`Self::set_server_config`] would do with the config, without applying it
+    async fn validate_server_config(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        axum::Json(proposed): axum::Json<HashMap<String, String>>,
+    ) -> Result<impl IntoResponse, StatusCode> {
         let group = user.group(state.database.clone(), None).await;
+        let world: Self = state
+            .database
+            .get_one(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        let server = state.servers.get_or_create_server(&world).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-        let world = {
-            state
-                .database
-                .get_one::<Self>(id, Some((&user, &group)))
-                .await
-                .map_err(crate::api::handlers::handle_database_error)?
+        let config = server.lock().await.config().await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(axum::Json(apply_config(&group, visible_config(&group, config), proposed)))
+    }
+
+    /// the resource pack settings of the world
+    async fn get_resource_pack(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let world: Self = state
+            .database
+            .get_one(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        let server = state.servers.get_or_create_server(&world).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let config = server.lock().await.config().await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let pack: HashMap<&str, String> = RESOURCE_PACK_KEYS
+            .iter()
+            .filter_map(|key| Some((*key, config.get(*key)?.clone())))
+            .collect();
+        Ok(axum::Json(pack))
+    }
+
+    /// downloads the resource pack at the given url and sets it, together with its SHA-1, as the world's resource pack
+    async fn set_resource_pack(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        axum::Json(json): axum::Json<ResourcePackJson>,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let world: Self = state
+            .database
+            .get_one(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let mut properties = vec![("resource-pack", json.url.clone())];
+        if let Some(prompt) = &json.prompt {
+            properties.push(("resource-pack-prompt", prompt.clone()));
+        }
+        if let Some(required) = json.required {
+            properties.push(("require-resource-pack", required.to_string()));
+        }
+        if !properties
+            .iter()
+            .all(|(key, _)| group.can_edit_config(key))
+            || !group.can_edit_config("resource-pack-sha1")
+        {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        // checked before downloading the pack, the hash is checked once it's known
+        let mut limited = Vec::with_capacity(properties.len() + 1);
+        for (key, value) in properties {
+            match exact_config_value(&group, key, value) {
+                Ok(value) => limited.push((key, value)),
+                Err(reason) => return Ok((StatusCode::FORBIDDEN, format!("{key}: {reason}")).into_response()),
+            }
+        }
+
+        let url = match url::Url::parse(&json.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => url,
+            _ => {
+                return Ok((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "the url has to be an http or https url",
+                )
+                    .into_response());
+            }
         };
+        let (sha1, size) = match hash_resource_pack(url).await {
+            Ok(hash) => hash,
+            Err(reason) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, reason).into_response()),
+        };
+        match exact_config_value(&group, "resource-pack-sha1", sha1.clone()) {
+            Ok(sha1) => limited.push(("resource-pack-sha1", sha1)),
+            Err(reason) => {
+                return Ok((StatusCode::FORBIDDEN, format!("resource-pack-sha1: {reason}")).into_response());
+            }
+        }
 
-        let server = state
-            .servers
-            .get_or_create_server(&world)
+        let server = state.servers.get_or_create_server(&world).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let mut server = server.lock().await;
+        let mut config = server.config().await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        for (key, value) in limited {
+            config.insert(key.to_string(), value);
+        }
+        // all the keys are written at once, so the hash can't get out of sync with the url
+        server.set_config(config).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let running = matches!(server.status().await, Ok(MinecraftServerStatus::Running));
+
+        Ok(axum::Json(json!({
+            "resource-pack": json.url,
+            "resource-pack-sha1": sha1,
+            "size": size,
+            // server.properties is only read on startup
+            "restart_required": running,
+        }))
+        .into_response())
+    }
+
+    /// removes the world's resource pack
+    async fn remove_resource_pack(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let world: Self = state
+            .database
+            .get_one(id, Some((&user, &group)))
             .await
-            .map_err(|err| {
-                error!("{err}");
-                StatusCode::INTERNAL_SERVER_ERROR
-            })?;
+            .map_err(crate::api::handlers::handle_database_error)?;
+        if !group.can_edit_config("resource-pack") || !group.can_edit_config("resource-pack-sha1") {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
 
+        let server = state.servers.get_or_create_server(&world).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
         let mut server = server.lock().await;
+        let mut config = server.config().await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        config.insert(String::from("resource-pack"), String::new());
+        config.insert(String::from("resource-pack-sha1"), String::new());
+        server.set_config(config).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(StatusCode::NO_CONTENT)
+    }
 
-        Ok(axum::Json(json!({"log": server.latest_log().await.unwrap_or_default()})))
+    async fn list_backups(
+        id: Path<Id>,
+        state: State<AppState>,
+        user: UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let group = user.0.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id.0, Some((&user.0, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let backups = crate::minecraft::backup::list(world.id).map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(axum::Json(backups))
+    }
+
+    async fn create_backup(
+        id: Path<Id>,
+        state: State<AppState>,
+        user: UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let group = user.0.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id.0, Some((&user.0, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let backup = crate::minecraft::backup::backup_world(&state.0, &world)
+            .await
+            .map_err(|err| {
+                error!("backup of world {} failed: {err}", world.id);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(axum::Json(backup))
+    }
+
+    /// the backup as a gzipped tarball, or a `Range` of it to resume a download
+    async fn download_backup(
+        Path((id, name)): Path<(Id, String)>,
+        state: State<AppState>,
+        user: UserAuth,
+        headers: HeaderMap,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let group = user.0.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user.0, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let path = crate::minecraft::backup::path(world.id, &name).ok_or(StatusCode::NOT_FOUND)?;
+        let response = crate::api::handlers::stream_file_with_range(&path, "application/gzip", &headers).await?;
+        let disposition = [(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}-{name}\"", world.hostname),
+        )];
+        Ok((disposition, response))
+    }
+
+    async fn remove_backup(
+        Path((id, name)): Path<(Id, String)>,
+        state: State<AppState>,
+        user: UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let group = user.0.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user.0, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let removed = crate::minecraft::backup::remove(world.id, &name).map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        if !removed {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        info!("removed backup {name} of world {}", world.id);
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    /// `POST /api/worlds/{id}/regenerate?confirm=true`, replaces the world's map with a newly generated one. the
+    /// server is stopped, backed up (unless `backup=false`), its level removed (see
+    /// [`crate::minecraft::util::remove_level`]) and started again if the world is enabled. the config and mods are kept
+    async fn regenerate(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        Query(query): Query<RegenerateQuery>,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        if !query.confirm {
+            return Ok((StatusCode::BAD_REQUEST, "regenerating removes the world, confirm it with ?confirm=true")
+                .into_response());
+        }
+        let group = user.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        let server = state
+            .servers
+            .get_or_create_server(&world)
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let Some(directory) = server.lock().await.directory() else {
+            return Err(StatusCode::CONFLICT);
+        };
+
+        // backed up while it's still running, so it's saved first. it's pruned with the owner's storage limit
+        let backup = if query.backup {
+            Some(crate::minecraft::backup::backup_world(&state, &world).await.map_err(|err| {
+                error!("backup of world {} failed, not regenerating it: {err}", world.id);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?)
+        } else {
+            None
+        };
+
+        let mut server = server.lock().await;
+        let level = server.level_directory().await.map_err(file_error)?;
+        // stopped like a disabled world, and started again by going back to the actual one
+        let stopped = World { enabled: false, ..world.clone() };
+        server.update_world(stopped).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let removed = crate::minecraft::util::remove_level(&directory, &level).map_err(|err| {
+            error!("could not remove the level of {}: {err}", world.id);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        info!("{} regenerated world {}, removing {removed:?}", user.username, world.id);
+        // the new level gets its own seed
+        let world = World { seed: None, ..world };
+        state
+            .database
+            .update(&world, None)
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        server.update_world(world.clone()).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(axum::Json(json!({
+            "world": world,
+            "backup": backup,
+            "removed": removed,
+        }))
+        .into_response())
+    }
+
+    /// gets the world's server, making sure it is running
+    async fn running_server(
+        state: &AppState,
+        id: Id,
+        user: &User,
+    ) -> Result<crate::minecraft::server::ServerMutex, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let server = state
+            .servers
+            .get_or_create_server(&world)
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        if !matches!(server.lock().await.status().await, Ok(MinecraftServerStatus::Running)) {
+            return Err(StatusCode::CONFLICT);
+        }
+        Ok(server)
+    }
+
+    async fn save_world(
+        id: Path<Id>,
+        state: State<AppState>,
+        user: UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let group = user.0.group(state.database.clone(), None).await;
+        if !group.can_run_command("save-all") {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let server = Self::running_server(&state.0, id.0, &user.0).await?;
+
+        let stdout = server.lock().await.save().await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        if !crate::minecraft::server::wait_for_save(stdout, std::time::Duration::from_secs(30)).await {
+            return Err(StatusCode::GATEWAY_TIMEOUT);
+        }
+        Ok(axum::Json(json!({"saved": true})))
+    }
+
+    /// runs the console command and waits for the output `parse` accepts. [`None`] if it didn't come in time
+    async fn command_output<T>(
+        server: &crate::minecraft::server::ServerMutex,
+        command: &str,
+        timeout: std::time::Duration,
+        parse: impl FnMut(&str) -> Option<T>,
+    ) -> Result<Option<T>, StatusCode> {
+        let stdout = {
+            let mut server = server.lock().await;
+            let stdout = server.stdout();
+            server.write_console(format!("{command}\n")).await.map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            stdout
+        };
+        Ok(crate::minecraft::server::wait_for_output(stdout, timeout, parse).await)
+    }
+
+    /// `GET /api/worlds/{id}/performance`, the TPS and milliseconds per tick of a running server, read with Paper's
+    /// `tps` and `mspt` commands. servers that don't have them, like vanilla ones, are reported as not supported
+    async fn get_performance(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<axum::Json<PerformanceReport>, StatusCode> {
+        use crate::minecraft::util::{is_mspt_header, is_unknown_command, parse_mspt, parse_tps};
+
+        let server = Self::running_server(&state, id, &user).await?;
+        let tps = Self::command_output(&server, "tps", PERFORMANCE_TIMEOUT, |line| {
+            if is_unknown_command(line) {
+                return Some(None);
+            }
+            parse_tps(line).map(Some)
+        })
+        .await?
+        .ok_or(StatusCode::GATEWAY_TIMEOUT)?;
+        let Some([tps_1m, tps_5m, tps_15m]) = tps else {
+            return Ok(axum::Json(PerformanceReport::default()));
+        };
+
+        // older Paper versions don't have `mspt`, the TPS are still worth returning without it
+        let mut header = false;
+        let mspt = Self::command_output(&server, "mspt", PERFORMANCE_TIMEOUT, |line| {
+            if is_unknown_command(line) {
+                return Some(None);
+            }
+            if header {
+                return parse_mspt(line).map(Some);
+            }
+            header = is_mspt_header(line);
+            None
+        })
+        .await?
+        .flatten();
+
+        Ok(axum::Json(PerformanceReport {
+            supported: true,
+            tps_1m: Some(tps_1m),
+            tps_5m: Some(tps_5m),
+            tps_15m: Some(tps_15m),
+            mspt,
+        }))
+    }
+
+    /// sends a chat message to everyone on the server with `say`
+    async fn broadcast(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        axum::Json(json): axum::Json<BroadcastJson>,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        if !group.can_run_command("say") {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let message = match crate::minecraft::util::console_argument(
+            &json.message,
+            crate::minecraft::util::MAX_CHAT_MESSAGE_LENGTH,
+        ) {
+            Ok(message) => message,
+            Err(reason) => return Ok((StatusCode::UNPROCESSABLE_ENTITY, reason).into_response()),
+        };
+
+        let server = Self::running_server(&state, id, &user).await?;
+        server
+            .lock()
+            .await
+            .write_console(format!("say {message}\n"))
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(axum::Json(json!({"message": message})).into_response())
+    }
+
+    /// `POST /api/worlds/{id}/op`, makes a player an operator of the running server with `op`. without a username
+    /// it's the owner's linked Minecraft account. only the owner (or a privileged user) can do it, and only if their
+    /// group can run `op`
+    async fn op_player(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        axum::Json(json): axum::Json<OpJson>,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        use crate::minecraft::util::{is_player_name, parse_op_result};
+
+        let group = user.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        if world.owner_id != user.id && !group.is_privileged {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let owner = state
+            .database
+            .get_one::<User>(world.owner_id, None)
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let Some(username) = json.username.or(owner.minecraft_username.clone()) else {
+            return Ok((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "no username was given and the owner has no Minecraft account linked",
+            )
+                .into_response());
+        };
+        let username = username.trim().to_string();
+        if !is_player_name(&username) {
+            return Ok((StatusCode::BAD_REQUEST, "not a Minecraft username").into_response());
+        }
+        // the owner's own account too, anyone can link any name to their account
+        if !group.can_run_command("op") {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let server = Self::running_server(&state, id, &user).await?;
+        info!("{} is making {username} an operator of {id}", user.username);
+        let result = Self::command_output(&server, &format!("op {username}"), OP_TIMEOUT, |line| {
+            parse_op_result(line, &username)
+        })
+        .await?
+        .ok_or(StatusCode::GATEWAY_TIMEOUT)?;
+        Ok(axum::Json(json!({"username": username, "result": result})).into_response())
+    }
+
+    /// `GET /api/worlds/{id}/gamerules`, the values of the [`crate::minecraft::util::GAMERULES`] on the running server.
+    /// the ones the server doesn't have are left out
+    async fn get_gamerules(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<axum::Json<std::collections::BTreeMap<&'static str, crate::minecraft::util::GameruleValue>>, StatusCode>
+    {
+        use crate::minecraft::util::{GAMERULES, parse_gamerule};
+
+        let server = Self::running_server(&state, id, &user).await?;
+        let commands: Vec<String> = GAMERULES.iter().map(|(rule, _)| format!("gamerule {rule}")).collect();
+        let mut values = std::collections::BTreeMap::new();
+        // each command gets a line with the value, or an error pointing at the rule if the server doesn't have it
+        let mut answered = 0;
+        Self::command_output(&server, &commands.join("\n"), GAMERULE_TIMEOUT, |line| {
+            if let Some((rule, value)) = parse_gamerule(line) {
+                values.insert(rule, value);
+                answered += 1;
+            } else if line.contains("<--[HERE]") {
+                answered += 1;
+            }
+            (answered >= GAMERULES.len()).then_some(())
+        })
+        .await?;
+        if values.is_empty() {
+            return Err(StatusCode::GATEWAY_TIMEOUT);
+        }
+        Ok(axum::Json(values))
+    }
+
+    /// `POST /api/worlds/{id}/gamerule`, sets one of the [`crate::minecraft::util::GAMERULES`] on the running server,
+    /// returning the value it was set to
+    async fn set_gamerule(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        axum::Json(json): axum::Json<GameruleJson>,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        use crate::minecraft::util::{check_gamerule, is_unknown_command, parse_gamerule};
+
+        if let Err(reason) = check_gamerule(&json.rule, json.value) {
+            return Ok((StatusCode::BAD_REQUEST, reason).into_response());
+        }
+        let group = user.group(state.database.clone(), None).await;
+        if !group.can_run_command("gamerule") {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let server = Self::running_server(&state, id, &user).await?;
+        info!("{} is setting {} to {} on {id}", user.username, json.rule, json.value);
+        let command = format!("gamerule {} {}", json.rule, json.value);
+        let result = Self::command_output(&server, &command, GAMERULE_TIMEOUT, |line| {
+            if is_unknown_command(line) {
+                return Some(None);
+            }
+            parse_gamerule(line).filter(|(rule, _)| *rule == json.rule).map(Some)
+        })
+        .await?
+        .ok_or(StatusCode::GATEWAY_TIMEOUT)?;
+        let Some((rule, value)) = result else {
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, "the server doesn't have the gamerule").into_response());
+        };
+        Ok(axum::Json(json!({"rule": rule, "value": value})).into_response())
+    }
+
+    /// the pregeneration of the world, if one is running
+    async fn running_pregeneration(state: &AppState, id: Id) -> Option<Pregeneration> {
+        state
+            .pregenerations
+            .read()
+            .await
+            .get(&id)
+            .filter(|pregeneration| pregeneration.is_running())
+            .cloned()
+    }
+
+    /// runs a Chunky command, returning what it answered with. [`None`] if the server doesn't have Chunky
+    async fn chunky_command(
+        server: &crate::minecraft::server::ServerMutex,
+        command: &str,
+    ) -> Result<Option<ChunkyEvent>, StatusCode> {
+        use crate::minecraft::util::is_unknown_command;
+
+        Self::command_output(server, command, PREGENERATION_TIMEOUT, |line| {
+            if is_unknown_command(line) {
+                return Some(None);
+            }
+            parse_chunky(line).map(Some)
+        })
+        .await?
+        .ok_or(StatusCode::GATEWAY_TIMEOUT)
+    }
+
+    /// `POST /api/worlds/{id}/pregenerate`, starts generating the chunks in the radius around the center with Chunky.
+    /// the progress is followed in the console output and shown by `GET` and the world's status. servers without
+    /// Chunky get `422 Unprocessable Entity`
+    async fn pregenerate(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+        axum::Json(json): axum::Json<PregenerateJson>,
+    ) -> Result<Response, StatusCode> {
+        if json.radius == 0 || CONFIG.world.max_pregeneration_radius.is_some_and(|max| json.radius > max) {
+            return Ok((StatusCode::BAD_REQUEST, "the radius is out of range").into_response());
+        }
+        let group = user.group(state.database.clone(), None).await;
+        if !group.can_run_command("chunky") {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let server = Self::running_server(&state, id, &user).await?;
+        if Self::running_pregeneration(&state, id).await.is_some() {
+            return Ok((StatusCode::CONFLICT, "the world is already being pregenerated").into_response());
+        }
+
+        let unsupported = || {
+            (StatusCode::UNPROCESSABLE_ENTITY, "the server can't pregenerate, it needs the Chunky mod or plugin").into_response()
+        };
+        let center = format!("chunky center {} {}", json.center_x, json.center_z);
+        if Self::chunky_command(&server, &center).await?.is_none() {
+            return Ok(unsupported());
+        }
+        Self::chunky_command(&server, &format!("chunky radius {}", json.radius)).await?;
+
+        // subscribed before starting, so no progress is missed
+        let stdout = server.lock().await.stdout();
+        info!("{} is pregenerating {id} in a radius of {}", user.username, json.radius);
+        match Self::chunky_command(&server, "chunky start").await? {
+            None => return Ok(unsupported()),
+            Some(ChunkyEvent::Started) => {}
+            Some(ChunkyEvent::AlreadyRunning) => {
+                return Ok((StatusCode::CONFLICT, "the world is already being pregenerated").into_response());
+            }
+            Some(event) => {
+                warn!("Chunky didn't start pregenerating {id}, it answered with {event:?}");
+                return Err(StatusCode::BAD_GATEWAY);
+            }
+        }
+
+        let pregeneration = Pregeneration::new(json.radius, json.center_x, json.center_z);
+        state.pregenerations.write().await.insert(id, pregeneration.clone());
+        tokio::task::spawn(crate::minecraft::pregeneration::track(state.pregenerations.clone(), id, stdout));
+        Ok((StatusCode::ACCEPTED, axum::Json(pregeneration)).into_response())
+    }
+
+    /// `GET /api/worlds/{id}/pregenerate`, the last pregeneration of the world and how far it got
+    async fn get_pregeneration(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<axum::Json<Pregeneration>, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        let pregenerations = state.pregenerations.read().await;
+        pregenerations.get(&id).cloned().map(axum::Json).ok_or(StatusCode::NOT_FOUND)
+    }
+
+    /// `DELETE /api/worlds/{id}/pregenerate`, cancels the running pregeneration. the chunks generated so far are kept
+    async fn cancel_pregeneration(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<axum::Json<Pregeneration>, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        if !group.can_run_command("chunky") {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let server = Self::running_server(&state, id, &user).await?;
+        if Self::running_pregeneration(&state, id).await.is_none() {
+            return Err(StatusCode::CONFLICT);
+        }
+
+        info!("{} is cancelling the pregeneration of {id}", user.username);
+        Self::chunky_command(&server, "chunky cancel").await?;
+        let mut pregenerations = state.pregenerations.write().await;
+        let pregeneration = pregenerations.get_mut(&id).ok_or(StatusCode::CONFLICT)?;
+        // it could have finished in the meantime
+        if pregeneration.is_running() {
+            pregeneration.apply(ChunkyEvent::Cancelled);
+        }
+        Ok(axum::Json(pregeneration.clone()))
+    }
+
+    async fn save_off(
+        id: Path<Id>,
+        state: State<AppState>,
+        user: UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        Self::set_saving(id.0, state.0, user.0, false).await
+    }
+
+    async fn save_on(
+        id: Path<Id>,
+        state: State<AppState>,
+        user: UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        Self::set_saving(id.0, state.0, user.0, true).await
+    }
+
+    async fn set_saving(
+        id: Id,
+        state: AppState,
+        user: User,
+        enabled: bool,
+    ) -> Result<axum::Json<serde_json::Value>, axum::http::StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        if !group.can_run_command(if enabled { "save-on" } else { "save-off" }) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        let server = Self::running_server(&state, id, &user).await?;
+
+        server.lock().await.set_saving(enabled).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        Ok(axum::Json(json!({"saving": enabled})))
+    }
+
+    /// changes a [`QuickSetting`]. persistent settings are written to `server.properties`, and the setting is
+    /// applied to the server through the console if it's running
+    async fn quick_set<S: QuickSetting>(
+        id: Path<Id>,
+        state: State<AppState>,
+        user: UserAuth,
+        json: axum::extract::Json<QuickSetJson<S>>,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let id = id.0;
+        let state = state.0;
+        let user = user.0;
+        let value = json.0.value;
+
+        let group = user.group(state.database.clone(), None).await;
+
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        if !group.can_run_command(&value.command()) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+        if let Some(property) = S::PROPERTY
+            && exact_config_value(&group, property, value.to_string()).is_err()
+        {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let server = state
+            .servers
+            .get_or_create_server(&world)
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        let mut server = server.lock().await;
+
+        let running = matches!(server.status().await, Ok(MinecraftServerStatus::Running));
+
+        if let Some(property) = S::PROPERTY {
+            let mut config = server.config().await.map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            config.insert(property.to_string(), value.to_string());
+            server.set_config(config).await.map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        } else if !running {
+            // there is nowhere to apply a non-persistent setting to
+            return Err(StatusCode::CONFLICT);
+        }
+
+        if running {
+            server
+                .write_console(format!("{}\n", value.command()))
+                .await
+                .map_err(|err| {
+                    error!("{err}");
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+        }
+
+        Ok(axum::Json(json!({"value": value})))
+    }
+
+    /// `GET /api/worlds/{id}/seed`, read with the `seed` command while the server is running, or from `level.dat`.
+    /// it's kept on the world once it's known
+    async fn get_seed(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<impl IntoResponse, StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        if let Some(seed) = world.seed {
+            return Ok(axum::Json(json!({ "seed": seed })).into_response());
+        }
+
+        let server = state.servers.get_or_create_server(&world).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let running = matches!(server.lock().await.status().await, Ok(MinecraftServerStatus::Running));
+        let mut seed = None;
+        if running {
+            seed = Self::command_output(&server, "seed", SEED_TIMEOUT, crate::minecraft::util::parse_seed).await?;
+        }
+        // a running server also has it in level.dat, in case the command didn't answer
+        if seed.is_none() {
+            let level_data = server.lock().await.level_data().await.map_err(|err| {
+                file_error(err.wrap_err(format!("could not read level.dat of world {id}")))
+            })?;
+            seed = level_data.and_then(|level_data| level_data.seed);
+        }
+        let Some(seed) = seed else {
+            return Ok((
+                StatusCode::NOT_FOUND,
+                "the world has not been generated yet, start it at least once",
+            )
+                .into_response());
+        };
+
+        // read again, so a change made in the meantime isn't overwritten
+        let world: World = state.database.get_one(id, None).await.map_err(crate::api::handlers::handle_database_error)?;
+        state
+            .database
+            .update(&World { seed: Some(seed), ..world }, None)
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        Ok(axum::Json(json!({ "seed": seed })).into_response())
+    }
+
+    async fn get_world_info(
+        id: Path<Id>,
+        state: State<AppState>,
+        user: UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let id = id.0;
+        let state = state.0;
+        let user = user.0;
+
+        let group = user.group(state.database.clone(), None).await;
+
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let server = state
+            .servers
+            .get_or_create_server(&world)
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        let level_data = server.lock().await.level_data().await.map_err(|err| {
+            file_error(err.wrap_err(format!("could not read level.dat of world {id}")))
+        })?;
+
+        match level_data {
+            Some(level_data) => Ok(axum::Json(level_data).into_response()),
+            None => Ok((
+                StatusCode::NOT_FOUND,
+                "the world has not been generated yet, start it at least once",
+            )
+                .into_response()),
+        }
+    }
+
+    /// lists the datapacks in the world's `datapacks` directory
+    async fn list_datapacks(
+        id: Path<Id>,
+        state: State<AppState>,
+        user: UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let group = user.0.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id.0, Some((&user.0, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+
+        let server = state.servers.get_or_create_server(&world).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let server = server.lock().await;
+        // the level directory first, it fails for servers whose files aren't accessible
+        let level_directory = server.level_directory().await.map_err(file_error)?;
+        let config = server.config().await.map_err(file_error)?;
+        drop(server);
+
+        let datapacks = tokio::task::spawn_blocking(move || datapack::list(&level_directory, &config))
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?
+            .map_err(|err| {
+                error!("could not list the datapacks of world {}: {err}", world.id);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        Ok(axum::Json(datapacks))
+    }
+
+    async fn enable_datapack(
+        Path((id, name)): Path<(Id, String)>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        Self::set_datapack_enabled(id, &name, state, user, true).await
+    }
+
+    async fn disable_datapack(
+        Path((id, name)): Path<(Id, String)>,
+        State(state): State<AppState>,
+        UserAuth(user): UserAuth,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        Self::set_datapack_enabled(id, &name, state, user, false).await
+    }
+
+    /// enables or disables a datapack. it's stored in `server.properties`, so it survives the world being
+    /// regenerated, and applied with the `datapack` command if the server is running
+    async fn set_datapack_enabled(
+        id: Id,
+        name: &str,
+        state: AppState,
+        user: User,
+        enabled: bool,
+    ) -> Result<axum::Json<serde_json::Value>, axum::http::StatusCode> {
+        let group = user.group(state.database.clone(), None).await;
+        let world = state
+            .database
+            .get_one::<Self>(id, Some((&user, &group)))
+            .await
+            .map_err(crate::api::handlers::handle_database_error)?;
+        if !group.can_edit_config(datapack::ENABLED_PACKS_PROPERTY)
+            || !group.can_edit_config(datapack::DISABLED_PACKS_PROPERTY)
+            || !group.can_run_command("datapack")
+        {
+            return Err(StatusCode::FORBIDDEN);
+        }
+
+        let server = state.servers.get_or_create_server(&world).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let mut server = server.lock().await;
+        let level_directory = server.level_directory().await.map_err(file_error)?;
+        if datapack::resolve(&level_directory, name).is_none() {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        let id = datapack::pack_id(name);
+
+        let mut config = server.config().await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        datapack::set_enabled_property(&mut config, &id, enabled);
+        for key in [datapack::ENABLED_PACKS_PROPERTY, datapack::DISABLED_PACKS_PROPERTY] {
+            let value = config.remove(key).unwrap_or_default();
+            match exact_config_value(&group, key, value) {
+                Ok(value) => config.insert(key.to_string(), value),
+                Err(reason) => {
+                    debug!("not setting {key} of world {}: {reason}", world.id);
+                    return Err(StatusCode::FORBIDDEN);
+                }
+            };
+        }
+        server.set_config(config).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let running = matches!(server.status().await, Ok(MinecraftServerStatus::Running));
+        if running {
+            server
+                .write_console(datapack::command(&id, enabled))
+                .await
+                .map_err(|err| {
+                    error!("{err}");
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+        }
+
+        Ok(axum::Json(json!({
+            "id": id,
+            "enabled": enabled,
+            // a stopped server picks up the pack state from the world when it's started
+            "applied": running,
+        })))
+    }
+
+    async fn get_server_log(
+        id: Path<Id>,
+        state: State<AppState>,
+        Query(share): Query<ShareQuery>,
+        user: Result<UserAuth, StatusCode>,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        let id = id.0;
+        let state = state.0;
+
+        let world = Self::get_shared(&state, id, user, share.share, SharePermission::Console).await?;
+
+        let server = state
+            .servers
+            .get_or_create_server(&world)
+            .await
+            .map_err(|err| {
+                error!("{err}");
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        let mut server = server.lock().await;
+
+        Ok(axum::Json(json!({"log": server.latest_log().await.unwrap_or_default()})))
+
+    }
+
+    /// the recent console output, for clients that can't keep the console socket open
+    async fn get_console_history(
+        Path(id): Path<Id>,
+        State(state): State<AppState>,
+        Query(share): Query<ShareQuery>,
+        Query(query): Query<ConsoleHistoryQuery>,
+        user: Result<UserAuth, StatusCode>,
+    ) -> Result<impl IntoResponse, axum::http::StatusCode> {
+        Self::get_shared(&state, id, user, share.share, SharePermission::Console).await?;
+
+        let lines = query
+            .lines
+            .unwrap_or(DEFAULT_CONSOLE_HISTORY_LINES)
+            .min(CONSOLE_HISTORY_SIZE);
+
+        // a server that was never loaded hasn't printed anything
+        let Some(server) = state.servers.get_server(id).await else {
+            return Ok(axum::Json(Vec::new()));
+        };
+
+        let history = server.lock().await.console_history(lines).await.map_err(|err| {
+            error!("{err}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        Ok(axum::Json(history))
+    }
+}
+
+#[test]
+fn hostname_test() {
+    assert_eq!(into_valid_hostname("My Box World!"), "my-box-world");
+    assert!(is_valid_hostname(&into_valid_hostname("My Box World!")));
+    assert!(!is_valid_hostname("My_World"));
+    assert!(!is_valid_hostname(""));
+}
+
+#[test]
+fn world_address_test() {
+    assert_eq!(world_address("survival", "example.net", 25565), "survival.example.net");
+    assert_eq!(world_address("survival", "example.net", 25566), "survival.example.net:25566");
+}
+
+#[test]
+fn reserved_hostname_test() {
+    let reserved = vec![String::from("status"), String::from("WWW")];
+    assert!(is_reserved_hostname("status", &reserved, "example"));
+    assert!(is_reserved_hostname("Status", &reserved, "example"));
+    assert!(is_reserved_hostname("www", &reserved, "example"));
+    // the proxy's own hostname
+    assert!(is_reserved_hostname("EXAMPLE", &reserved, "example"));
+    assert!(!is_reserved_hostname("statuses", &reserved, "example"));
+    assert!(!is_reserved_hostname("survival", &[], "example.net"));
+}
+
+#[test]
+fn merge_patch_test() {
+    use crate::database::testing::test_world;
+
+    let world = World {
+        backup_schedule: Some(String::from("0 4 * * *")),
+        ..test_world(Id::default(), Id::default())
+    };
+    let patch = |json: serde_json::Value| {
+        serde_json::from_value::<JsonUpdate>(json).map(|json| world.update_with_json(&json))
+    };
+
+    // absent fields are left unchanged
+    assert_eq!(patch(serde_json::json!({})).unwrap(), world);
+    // null clears a nullable field
+    assert_eq!(
+        patch(serde_json::json!({"backup_schedule": null}))
+            .unwrap()
+            .backup_schedule,
+        None
+    );
+    // and a value sets it
+    assert_eq!(
+        patch(serde_json::json!({"name": "renamed"})).unwrap().name,
+        "renamed"
+    );
+    // a field that can't be null can't be cleared
+    assert!(patch(serde_json::json!({"name": null})).is_err());
+    // memory that doesn't fit the column is rejected instead of clamped
+    assert_eq!(
+        patch(serde_json::json!({"allocated_memory": i32::MAX}))
+            .unwrap()
+            .allocated_memory,
+        i32::MAX
+    );
+    assert!(patch(serde_json::json!({"allocated_memory": i64::from(i32::MAX) + 1})).is_err());
+}
+
+#[test]
+fn limit_config_value_test() {
+    use crate::database::testing::test_group;
+
+    let group = Group {
+        config_limits: HashMap::from([(
+            String::from("view-distance"),
+            ServerConfigLimit::Range(3, 16),
+        )]),
+        ..test_group()
+    };
+    let limit = |value: &str| limit_config_value(&group, "view-distance", value.to_string());
+
+    assert_eq!(limit("10"), ConfigValue::Accepted(String::from("10")));
+    assert_eq!(limit("3"), ConfigValue::Accepted(String::from("3")));
+    assert_eq!(limit("32"), ConfigValue::Clamped(String::from("16")));
+    assert_eq!(limit("-1"), ConfigValue::Clamped(String::from("3")));
+    assert_eq!(limit("far"), ConfigValue::Rejected("the value is not a number"));
+
+    assert_eq!(
+        config_key_limits(&group, "view-distance"),
+        ConfigKeyLimits {
+            editable: true,
+            min: Some(3),
+            max: Some(16),
+            ..ConfigKeyLimits::default()
+        }
+    );
+    let blacklisted = Group {
+        config_blacklist: vec![String::from("view-distance")],
+        ..group.clone()
+    };
+    assert_eq!(
+        limit_config_value(&blacklisted, "view-distance", String::from("10")),
+        ConfigValue::Rejected("the key can't be edited")
+    );
+    assert!(!config_key_limits(&blacklisted, "view-distance").editable);
+}
+
+#[test]
+fn apply_config_test() {
+    use crate::database::testing::test_group;
+
+    let group = Group {
+        config_blacklist: vec![String::from("online-mode")],
+        config_limits: HashMap::from([(
+            String::from("view-distance"),
+            ServerConfigLimit::Range(3, 16),
+        )]),
+        ..test_group()
+    };
+    let config = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>()
+    };
+
+    let current = visible_config(&group, config(&[("motd", "hi"), ("online-mode", "true")]));
+    assert_eq!(current, config(&[("motd", "hi")]));
+
+    let changes = apply_config(
+        &group,
+        current,
+        config(&[("pvp", "false"), ("view-distance", "32"), ("online-mode", "false")]),
+    );
+    assert_eq!(changes.config, config(&[("motd", "hi"), ("pvp", "false"), ("view-distance", "16")]));
+    assert_eq!(changes.accepted, vec![String::from("pvp")]);
+    assert_eq!(changes.clamped, config(&[("view-distance", "16")]));
+    assert_eq!(changes.rejected, HashMap::from([(String::from("online-mode"), "the key can't be edited")]));
+}
+
+#[test]
+fn restart_plan_test() {
+    let mut json: JsonUpdate = serde_json::from_value(json!({"allocated_memory": 2048, "defer_restart": true})).unwrap();
+    assert!(json.defer_restart);
+    assert!(World::update_details(&json).is_empty());
+
+    json.restart = Some(RestartPlan::Scheduled { delay: 10 });
+    assert_eq!(
+        serde_json::Value::Object(World::update_details(&json)),
+        json!({"restart": {"mode": "scheduled", "delay": 10}})
+    );
+    json.restart = Some(RestartPlan::Deferred);
+    assert_eq!(World::update_details(&json)["restart"], json!({"mode": "deferred"}));
+}
+
+#[test]
+fn memory_reduction_test() {
+    let memory = Memory::try_from(2048).unwrap();
+    assert!(check_memory_reduction(None, memory).is_ok());
+    assert!(check_memory_reduction(Some(2048), memory).is_ok());
+    assert!(matches!(
+        check_memory_reduction(Some(3000), memory),
+        Err(DatabaseError::Unprocessable(reason)) if reason.contains("3000 MiB")
+    ));
+    let json: JsonUpdate = serde_json::from_value(json!({"allocated_memory": 1024, "force_memory": true})).unwrap();
+    assert!(json.force_memory);
+}
+
+#[tokio::test]
+async fn missing_version_test() {
+    use crate::api::handlers::handle_database_error;
+    use crate::database::objects::Mod;
+    use crate::database::testing::{insert_test_version, test_group, test_state};
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = Group {
+        can_upload_mods: true,
+        ..test_group()
+    };
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    let user = database
+        .create_user_from(User { username: String::from("player"), group_id: group.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+
+    let mut json = JsonFrom {
+        name: String::from("Survival"),
+        hostname: String::from("survival"),
+        allocated_memory: None,
+        version_id: Id::new_random(),
+        backup_schedule: None,
+        setup_commands: None,
+        forward_player_ip: None,
+    };
+    let err = World::before_api_create(state.clone(), &mut json, &user).await.unwrap_err();
+    assert!(matches!(&err, DatabaseError::Unprocessable(reason) if reason == "version not found"));
+    assert_eq!(handle_database_error(err), StatusCode::UNPROCESSABLE_ENTITY);
+
+    json.version_id = version.id;
+    let world = World::from_json(&json, &user);
+    database.insert(&world, None).await.unwrap();
+    let mut update: JsonUpdate = serde_json::from_value(json!({"version_id": Id::new_random()})).unwrap();
+    assert!(matches!(
+        world.before_api_update(state.clone(), &mut update, &user).await,
+        Err(DatabaseError::Unprocessable(_))
+    ));
+
+    let mut json = crate::database::objects::modification::JsonFrom {
+        version_id: Id::new_random(),
+        name: String::from("Sodium"),
+        description: None,
+        modrinth_id: None,
+        public: None,
+        dependencies: None,
+    };
+    assert!(matches!(
+        Mod::before_api_create(state.clone(), &mut json, &user).await,
+        Err(DatabaseError::Unprocessable(_))
+    ));
+    json.version_id = version.id;
+    Mod::before_api_create(state, &mut json, &user).await.unwrap();
+}
+
+#[tokio::test]
+async fn failed_transfer_test() {
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    let mut users = Vec::new();
+    for username in ["owner", "new_owner"] {
+        let user = User { username: String::from(username), group_id: group.id, ..Default::default() };
+        users.push(database.create_user_from(user, "hunter2").await.unwrap());
+    }
+    let world = test_world(users[0].id, version.id);
+    database.insert(&world, None).await.unwrap();
+
+    // the directory the world would be moved to is already taken, so moving it fails
+    let old_directory = crate::util::dirs::worlds_dir().join(format!("{}/{}", users[0].id, world.id));
+    let new_directory = crate::util::dirs::worlds_dir().join(format!("{}/{}", users[1].id, world.id));
+    std::fs::create_dir_all(&old_directory).unwrap();
+    std::fs::create_dir_all(&new_directory).unwrap();
+
+    let result = World::transfer_ownership(
+        Path(world.id),
+        State(state.clone()),
+        AdminAuth(users[0].clone()),
+        axum::Json(TransferJson { new_owner_id: users[1].id }),
+    )
+    .await;
+    assert_eq!(result.err(), Some(StatusCode::INTERNAL_SERVER_ERROR));
+    assert_eq!(database.get_one::<World>(world.id, None).await.unwrap().owner_id, users[0].id);
+    assert!(old_directory.exists());
+
+    for user in &users {
+        std::fs::remove_dir_all(crate::util::dirs::worlds_dir().join(user.id.to_string())).unwrap();
+    }
+}
+
+#[tokio::test]
+async fn blacklisted_command_test() {
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+    use crate::minecraft::util::Weather;
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = Group {
+        console_command_blacklist: vec![String::from("weather"), String::from("save-off")],
+        ..test_group()
+    };
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    let user = database
+        .create_user_from(User { username: String::from("player"), group_id: group.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+    let world = test_world(user.id, version.id);
+    database.insert(&world, None).await.unwrap();
+
+    let result = World::quick_set(
+        Path(world.id),
+        State(state.clone()),
+        UserAuth(user.clone()),
+        axum::Json(QuickSetJson { value: Weather::Rain }),
+    )
+    .await;
+    assert_eq!(result.err(), Some(StatusCode::FORBIDDEN));
+    assert_eq!(
+        World::save_off(Path(world.id), State(state.clone()), UserAuth(user.clone())).await.err(),
+        Some(StatusCode::FORBIDDEN)
+    );
+    // allowed, but the server isn't running
+    assert_eq!(
+        World::save_on(Path(world.id), State(state), UserAuth(user)).await.err(),
+        Some(StatusCode::CONFLICT)
+    );
+}
+
+#[tokio::test]
+async fn op_own_account_test() {
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = Group {
+        console_command_blacklist: vec![String::from("op")],
+        ..test_group()
+    };
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    let user = User {
+        username: String::from("player"),
+        group_id: group.id,
+        minecraft_username: Some(String::from("Notch")),
+        ..Default::default()
+    };
+    let user = database.create_user_from(user, "hunter2").await.unwrap();
+    let world = test_world(user.id, version.id);
+    database.insert(&world, None).await.unwrap();
+
+    // the linked account could be anyone's
+    let result = World::op_player(
+        Path(world.id),
+        State(state),
+        UserAuth(user),
+        axum::Json(OpJson { username: None }),
+    )
+    .await;
+    assert_eq!(result.err(), Some(StatusCode::FORBIDDEN));
+}
+
+#[test]
+fn public_address_test() {
+    for ip in ["1.1.1.1", "2606:4700:4700::1111", "::ffff:8.8.8.8"] {
+        assert!(is_public_address(ip.parse().unwrap()), "{ip}");
+    }
+    for ip in [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+    ] {
+        assert!(!is_public_address(ip.parse().unwrap()), "{ip}");
+    }
+}
+
+#[tokio::test]
+async fn set_resource_pack_test() {
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+    use crate::minecraft::server::ConfigPattern;
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = Group {
+        config_limits: HashMap::from([(
+            String::from("resource-pack"),
+            ServerConfigLimit::Regex(ConfigPattern::new(r"^https://packs\.example\.com/").unwrap()),
+        )]),
+        ..test_group()
+    };
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    let user = database
+        .create_user_from(User { username: String::from("player"), group_id: group.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+    let world = test_world(user.id, version.id);
+    database.insert(&world, None).await.unwrap();
+
+    let set = |url: &str| {
+        World::set_resource_pack(
+            Path(world.id),
+            State(state.clone()),
+            UserAuth(user.clone()),
+            axum::Json(ResourcePackJson { url: url.to_string(), prompt: None, required: None }),
+        )
+    };
+    // the limit is checked before anything is downloaded
+    let response = set("https://example.org/pack.zip").await.unwrap().into_response();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    database
+        .update(&Group { config_limits: HashMap::new(), ..group }, None)
+        .await
+        .unwrap();
+    for url in ["http://127.0.0.1:1/pack.zip", "http://[::1]/pack.zip", "http://localhost/pack.zip"] {
+        let response = set(url).await.unwrap().into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY, "{url}");
+    }
+}
+
+#[tokio::test]
+async fn quick_set_limit_test() {
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+    use crate::minecraft::server::ConfigPattern;
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = Group {
+        config_limits: HashMap::from([(
+            String::from("difficulty"),
+            ServerConfigLimit::Regex(ConfigPattern::new("^(peaceful|easy)$").unwrap()),
+        )]),
+        ..test_group()
+    };
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    let user = database
+        .create_user_from(User { username: String::from("player"), group_id: group.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+    let world = test_world(user.id, version.id);
+    database.insert(&world, None).await.unwrap();
+
+    let result = World::quick_set(
+        Path(world.id),
+        State(state),
+        UserAuth(user),
+        axum::Json(QuickSetJson { value: Difficulty::Hard }),
+    )
+    .await;
+    assert_eq!(result.err(), Some(StatusCode::FORBIDDEN));
+}
+
+#[tokio::test]
+async fn status_wait_test() {
+    use crate::database::objects::world_share::{self, SharePermission};
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    let user = database
+        .create_user_from(User { username: String::from("player"), group_id: group.id, ..Default::default() }, "hunter2")
+        .await
+        .unwrap();
+    let world = test_world(user.id, version.id);
+    database.insert(&world, None).await.unwrap();
+    let json = world_share::JsonFrom { world_id: world.id, permission: Some(SharePermission::View), expires: None };
+    let share = WorldShare::from_json(&json, &user);
+    database.insert(&share, None).await.unwrap();
+
+    // waiting through a share doesn't load the world's server, it's stopped until it has one
+    let response = World::world_get_status(
+        Path(world.id),
+        State(state.clone()),
+        Query(ShareQuery { share: Some(share.token) }),
+        Query(StatusWaitQuery { wait: Some(1) }),
+        Err(StatusCode::UNAUTHORIZED),
+    )
+    .await
+    .unwrap()
+    .into_response();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let status: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(status["status"], "exited");
+    assert_eq!(status["changed"], false);
+    assert!(state.servers.get_server(world.id).await.is_none());
+}
 
+#[tokio::test]
+async fn schedule_restart_test() {
+    use crate::database::testing::test_state;
+
+    let state = test_state(Database::memory().await);
+    let id = Id::new_random();
+    let handle = |state: &AppState| state.scheduled_restarts.lock().unwrap().get(&id).cloned().unwrap();
+
+    World::schedule_restart(&state, id, 3600);
+    let first = handle(&state);
+    // a second one replaces the first instead of restarting the server twice
+    World::schedule_restart(&state, id, 3600);
+    let second = handle(&state);
+    assert_ne!(first.id(), second.id());
+    assert_eq!(state.scheduled_restarts.lock().unwrap().len(), 1);
+    tokio::task::yield_now().await;
+    assert!(first.is_finished());
+    assert!(!second.is_finished());
+
+    // it's taken out once it's done
+    World::schedule_restart(&state, id, 0);
+    let third = handle(&state);
+    while !third.is_finished() {
+        tokio::task::yield_now().await;
     }
+    assert!(second.is_finished());
+    assert!(state.scheduled_restarts.lock().unwrap().is_empty());
 }
@@ -0,0 +1,305 @@
+use crate::api::handlers::{ApiCreate, ApiGet, ApiList, ApiObject, ApiRemove};
+use crate::api::serve::AppState;
+use crate::database::objects::{DbObject, FromJson, User, World};
+use crate::database::types::{Access, Column, Id};
+use crate::database::{Cachable, Database, DatabaseError, ValueType};
+use async_trait::async_trait;
+use axum::Router;
+use axum::routing::{get, post};
+use chrono::{DateTime, Utc};
+use duplicate::duplicate_item;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use sqlx::{Arguments, FromRow, IntoArguments, Row};
+use std::any::Any;
+use std::fmt::{Debug, Display, Formatter};
+use std::str::FromStr;
+use uuid::Uuid;
+
+/// What a [`WorldShare`] token allows its holder to do. each level includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SharePermission {
+    /// view the world's status
+    View,
+    /// read the world's console and log
+    Console,
+    /// send commands to the world's console
+    Command,
+}
+
+impl SharePermission {
+    /// how the permissions are stored in the database
+    pub const VALUES: &'static [&'static str] = &["view", "console", "command"];
+}
+
+impl Display for SharePermission {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SharePermission::View => f.write_str("view"),
+            SharePermission::Console => f.write_str("console"),
+            SharePermission::Command => f.write_str("command"),
+        }
+    }
+}
+
+impl FromStr for SharePermission {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "view" => Ok(SharePermission::View),
+            "console" => Ok(SharePermission::Console),
+            "command" => Ok(SharePermission::Command),
+            _ => Err(color_eyre::eyre::eyre!("invalid share permission: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct WorldShare {
+    /// Unique [`Id`] of the share
+    pub id: Id,
+    /// The [`World`] the share gives access to
+    pub world_id: Id,
+    /// The user who created the share
+    pub creator_id: Id,
+    /// The token passed as `?share=<token>` to access the world
+    #[serde(with = "uuid::serde::simple")]
+    pub token: Uuid,
+    /// What the holder of the token can do
+    pub permission: SharePermission,
+    /// When does the share stop working. [`None`] means never
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl DbObject for WorldShare {
+    fn view_access() -> Access {
+        Access::Owner("creator_id").or(Access::PrivilegedUser)
+    }
+
+    fn update_access() -> Access {
+        Access::Owner("creator_id").or(Access::PrivilegedUser)
+    }
+
+    fn create_access() -> Access {
+        Access::User
+    }
+
+    fn table_name() -> &'static str {
+        "world_shares"
+    }
+
+    const COLUMNS: Lazy<Vec<Column>> = Lazy::new(|| {
+        vec![
+            Column::new("id", ValueType::Id).primary_key(),
+            Column::new("world_id", ValueType::Id)
+                .not_null()
+                .references("worlds(id)"),
+            Column::new("creator_id", ValueType::Id)
+                .not_null()
+                .references("users(id)"),
+            Column::new("token", ValueType::Token).not_null().unique(),
+            Column::new("permission", ValueType::Enum(SharePermission::VALUES)).not_null(),
+            Column::new("expires", ValueType::Datetime),
+        ]
+    });
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn owner_id(&self) -> Option<Id> {
+        Some(self.creator_id)
+    }
+}
+
+impl Cachable for WorldShare {
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self as Box<dyn Any>
+    }
+}
+
+#[duplicate_item(Row; [sqlx::sqlite::SqliteRow]; [sqlx::postgres::PgRow])]
+impl FromRow<'_, Row> for WorldShare {
+    fn from_row(row: &Row) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get(0)?,
+            world_id: row.try_get(1)?,
+            creator_id: row.try_get(2)?,
+            token: row.try_get(3)?,
+            permission: SharePermission::from_str(row.try_get(4)?).map_err(|err| {
+                sqlx::Error::ColumnDecode {
+                    index: "permission".to_string(),
+                    source: err.into(),
+                }
+            })?,
+            expires: row.try_get(5)?,
+        })
+    }
+}
+
+impl<'a> IntoArguments<'a, sqlx::Sqlite> for WorldShare {
+    fn into_arguments(self) -> sqlx::sqlite::SqliteArguments<'a> {
+        let mut arguments = sqlx::sqlite::SqliteArguments::default();
+        arguments.add(self.id).expect("Failed to add argument");
+        arguments
+            .add(self.world_id)
+            .expect("Failed to add argument");
+        arguments
+            .add(self.creator_id)
+            .expect("Failed to add argument");
+        arguments.add(self.token).expect("Failed to add argument");
+        arguments
+            .add(self.permission.to_string())
+            .expect("Failed to add argument");
+        arguments.add(self.expires).expect("Failed to add argument");
+        arguments
+    }
+}
+
+impl<'a> IntoArguments<'a, sqlx::Postgres> for WorldShare {
+    fn into_arguments(self) -> sqlx::postgres::PgArguments {
+        let mut arguments = sqlx::postgres::PgArguments::default();
+        arguments.add(self.id).expect("Failed to add argument");
+        arguments
+            .add(self.world_id)
+            .expect("Failed to add argument");
+        arguments
+            .add(self.creator_id)
+            .expect("Failed to add argument");
+        arguments.add(self.token).expect("Failed to add argument");
+        arguments
+            .add(self.permission.to_string())
+            .expect("Failed to add argument");
+        arguments.add(self.expires).expect("Failed to add argument");
+        arguments
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct JsonFrom {
+    pub world_id: Id,
+    pub permission: Option<SharePermission>,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl FromJson for WorldShare {
+    type JsonFrom = JsonFrom;
+
+    fn from_json(data: &Self::JsonFrom, user: &User) -> Self {
+        Self {
+            id: Id::default(),
+            world_id: data.world_id,
+            creator_id: user.id,
+            token: Uuid::new_v4(),
+            permission: data.permission.unwrap_or(SharePermission::View),
+            expires: data.expires,
+        }
+    }
+}
+
+impl WorldShare {
+    /// finds the share with the given token, if it exists and has not expired
+    pub async fn from_token(database: &Database, token: Uuid) -> Result<Self, DatabaseError> {
+        let share: Self = database.get_where("token", token, None).await?;
+        if share.expired() {
+            return Err(DatabaseError::Unauthorized);
+        }
+        Ok(share)
+    }
+
+    pub fn expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= Utc::now())
+    }
+
+    /// whether the share allows doing `permission` on the world with the id `world_id`
+    pub fn grants(&self, world_id: Id, permission: SharePermission) -> bool {
+        self.world_id == world_id && self.permission >= permission && !self.expired()
+    }
+}
+
+impl ApiObject for WorldShare {
+    fn routes() -> Router<AppState> {
+        Router::new()
+            .route("/", get(Self::api_list).post(Self::api_create))
+            .route("/delete", post(Self::api_remove_many))
+            .route("/{id}", get(Self::api_get).delete(Self::api_remove))
+    }
+}
+
+impl ApiList for WorldShare {}
+impl ApiGet for WorldShare {}
+#[async_trait]
+impl ApiCreate for WorldShare {
+    async fn before_api_create(
+        state: AppState,
+        json: &mut Self::JsonFrom,
+        user: &User,
+    ) -> Result<(), DatabaseError> {
+        // only those who can access the world can share it
+        let group = user.group(state.database.clone(), None).await;
+        state
+            .database
+            .get_one::<World>(json.world_id, Some((user, &group)))
+            .await?;
+        Ok(())
+    }
+}
+impl ApiRemove for WorldShare {}
+
+#[tokio::test]
+async fn share_test() {
+    use crate::api::filters::UserAuth;
+    use crate::api::handlers::RecursiveQuery;
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+    use axum::extract::{Path, State};
+    use axum::http::{HeaderMap, StatusCode};
+
+    let database = Database::memory().await;
+    let state = test_state(database.clone());
+
+    let group = crate::database::objects::Group { id: Id::new_random(), ..test_group() };
+    database.insert(&group, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    let mut users = vec![];
+    for username in ["owner", "other"] {
+        let user = User { username: String::from(username), group_id: group.id, ..Default::default() };
+        users.push(database.create_user_from(user, "hunter2").await.unwrap());
+    }
+    let (owner, other) = (users[0].clone(), users[1].clone());
+    let world = test_world(owner.id, version.id);
+    database.insert(&world, None).await.unwrap();
+    let json = |permission| JsonFrom { world_id: world.id, permission: Some(permission), expires: None };
+    let recursive = RecursiveQuery { recursive: None };
+
+    // only those who can access the world can share it
+    let result = WorldShare::create_from_api(recursive, state.clone(), other.clone(), json(SharePermission::Command)).await;
+    assert_eq!(result.err(), Some(StatusCode::NOT_FOUND));
+
+    let share = WorldShare::create_from_api(recursive, state.clone(), owner.clone(), json(SharePermission::Console))
+        .await
+        .unwrap();
+    let share: WorldShare = serde_json::from_value(share).unwrap();
+    assert_eq!(share.creator_id, owner.id);
+
+    let get = |permission, token| World::get_shared(&state, world.id, Err(StatusCode::UNAUTHORIZED), token, permission);
+    assert_eq!(get(SharePermission::View, Some(share.token)).await.unwrap().id, world.id);
+    assert_eq!(get(SharePermission::Console, Some(share.token)).await.unwrap().id, world.id);
+    // a valid token without the permission is forbidden, an unknown one isn't authenticated
+    assert_eq!(get(SharePermission::Command, Some(share.token)).await.err(), Some(StatusCode::FORBIDDEN));
+    assert_eq!(get(SharePermission::View, Some(Uuid::new_v4())).await.err(), Some(StatusCode::UNAUTHORIZED));
+    assert_eq!(get(SharePermission::View, None).await.err(), Some(StatusCode::UNAUTHORIZED));
+    let other_world = World { id: Id::new_random(), hostname: String::from("other"), ..world.clone() };
+    database.insert(&other_world, None).await.unwrap();
+    let result = World::get_shared(&state, other_world.id, Err(StatusCode::UNAUTHORIZED), Some(share.token), SharePermission::View);
+    assert_eq!(result.await.err(), Some(StatusCode::FORBIDDEN));
+
+    let expired = WorldShare { expires: Some(Utc::now() - chrono::Duration::minutes(1)), ..share.clone() };
+    assert!(!expired.grants(world.id, SharePermission::View));
+
+    // revoked shares stop working
+    let status = WorldShare::api_remove(Path(share.id), State(state.clone()), UserAuth(owner), HeaderMap::new()).await;
+    assert_eq!(status, Ok(StatusCode::NO_CONTENT));
+    assert_eq!(get(SharePermission::View, Some(share.token)).await.err(), Some(StatusCode::UNAUTHORIZED));
+}
@@ -0,0 +1,235 @@
+//! files in the data directory that no database row refers to anymore, like the directory of a world whose deletion
+//! partially failed
+
+use crate::database::objects::{Mod, User, Version, World};
+use crate::database::types::Id;
+use crate::database::{Database, DatabaseError};
+use crate::minecraft::server::MinecraftServerCollection;
+use crate::util;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// files changed more recently than this are never reported, as they can belong to an object that is being created
+const MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Orphan {
+    pub path: PathBuf,
+    /// what the file was for, like "world" or "mod jar"
+    pub kind: &'static str,
+    /// size in bytes
+    pub size: u64,
+}
+
+/// the ids of everything that can have files in the data directory
+#[derive(Debug, Default)]
+struct KnownIds {
+    /// owner of each world
+    worlds: HashMap<Id, Id>,
+    users: HashSet<Id>,
+    versions: HashSet<Id>,
+    mods: HashSet<Id>,
+    /// worlds with a server in the [`MinecraftServerCollection`], their files are in use even without a row
+    servers: HashSet<Id>,
+}
+
+/// finds the files in the data directory without a corresponding database row
+pub async fn find_orphans(
+    database: &Database,
+    servers: &MinecraftServerCollection,
+) -> Result<Vec<Orphan>, DatabaseError> {
+    let known = KnownIds {
+        worlds: database
+            .get_all::<World>(None)
+            .await?
+            .into_iter()
+            .map(|world| (world.id, world.owner_id))
+            .collect(),
+        users: ids(&database.get_all::<User>(None).await?, |user| user.id),
+        versions: ids(&database.get_all::<Version>(None).await?, |version| version.id),
+        mods: ids(&database.get_all::<Mod>(None).await?, |modification| modification.id),
+        servers: servers
+            .get_all_worlds()
+            .await
+            .into_iter()
+            .map(|world| world.id)
+            .collect(),
+    };
+
+    tokio::task::spawn_blocking(move || scan(&known))
+        .await
+        .map_err(|err| DatabaseError::InternalServerError(err.to_string()))
+}
+
+fn ids<T>(objects: &[T], id: impl Fn(&T) -> Id) -> HashSet<Id> {
+    objects.iter().map(id).collect()
+}
+
+fn scan(known: &KnownIds) -> Vec<Orphan> {
+    let mut orphans = Vec::new();
+    let mut add = |kind: &'static str, paths: Vec<PathBuf>| {
+        orphans.extend(paths.into_iter().map(|path| Orphan {
+            size: size(&path),
+            path,
+            kind,
+        }));
+    };
+
+    // worlds are stored as worlds/{owner_id}/{world_id}
+    if let Ok(entries) = std::fs::read_dir(util::dirs::worlds_dir()) {
+        for entry in entries.flatten() {
+            let Some(owner_id) = parse_id(&entry.path(), &[]) else {
+                continue;
+            };
+            add(
+                "world",
+                orphaned_entries(&entry.path(), &[], MIN_AGE, |id| {
+                    known.worlds.get(&id) == Some(&owner_id) || known.servers.contains(&id)
+                }),
+            );
+        }
+    }
+    add(
+        "backups",
+        orphaned_entries(&util::dirs::backups_dir(), &[], MIN_AGE, |id| {
+            known.worlds.contains_key(&id)
+        }),
+    );
+    add(
+        "version jar",
+        orphaned_entries(&util::dirs::versions_dir(), &["jar"], MIN_AGE, |id| {
+            known.versions.contains(&id)
+        }),
+    );
+    add(
+        "mod jar",
+        orphaned_entries(&util::dirs::mod_jars_dir(), &["jar"], MIN_AGE, |id| {
+            known.mods.contains(&id)
+        }),
+    );
+    for (kind, dir, known) in [
+        ("user avatar", util::dirs::user_avatars_dir(), &known.users),
+        ("mod icon", util::dirs::mod_icons_dir(), &known.mods),
+        ("world icon", util::dirs::world_icons_dir(), &known.worlds.keys().copied().collect()),
+    ] {
+        // the icons are in shards, see `util::dirs::icon_shard`, with their resized variants in a directory per size
+        for shard in subdirectories(&dir) {
+            add(
+                kind,
+                orphaned_entries(&shard, &["gif", "webp"], MIN_AGE, |id| known.contains(&id)),
+            );
+            for variants in subdirectories(&shard) {
+                add(
+                    kind,
+                    orphaned_entries(&variants, &["png", "webp"], MIN_AGE, |id| known.contains(&id)),
+                );
+            }
+        }
+    }
+
+    orphans
+}
+
+fn subdirectories(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_dir()))
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// the entries of `dir` named after an [`Id`] (with one of the `extensions`, or none if it's empty) that isn't known.
+/// entries with other names are not ours, so they are left alone
+fn orphaned_entries(
+    dir: &Path,
+    extensions: &[&str],
+    min_age: Duration,
+    is_known: impl Fn(Id) -> bool,
+) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| parse_id(path, extensions).is_some_and(|id| !is_known(id)))
+        .filter(|path| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| modified.elapsed().unwrap_or_default() >= min_age)
+        })
+        .collect()
+}
+
+fn parse_id(path: &Path, extensions: &[&str]) -> Option<Id> {
+    let name = path.file_name()?.to_str()?;
+    let name = if extensions.is_empty() {
+        name
+    } else {
+        let (name, extension) = name.rsplit_once('.')?;
+        if !extensions.contains(&extension) {
+            return None;
+        }
+        name
+    };
+    Id::from_str(name).ok()
+}
+
+fn size(path: &Path) -> u64 {
+    if path.is_dir() {
+        util::dir_size(path).unwrap_or_default()
+    } else {
+        std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or_default()
+    }
+}
+
+/// deletes the orphans, returning the ones that couldn't be deleted with the reason
+pub async fn remove_orphans(orphans: Vec<Orphan>) -> Vec<(Orphan, String)> {
+    tokio::task::spawn_blocking(move || {
+        orphans
+            .into_iter()
+            .filter_map(|orphan| {
+                let result = if orphan.path.is_dir() {
+                    std::fs::remove_dir_all(&orphan.path)
+                } else {
+                    std::fs::remove_file(&orphan.path)
+                };
+                result.err().map(|err| (orphan, err.to_string()))
+            })
+            .collect()
+    })
+    .await
+    .unwrap_or_default()
+}
+
+#[test]
+fn orphaned_entries_test() {
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let known = Id::default();
+    let orphan = Id::default();
+    for name in [
+        format!("{known}.jar"),
+        format!("{orphan}.jar"),
+        format!("{orphan}.jar.part"),
+        String::from("notes.txt"),
+    ] {
+        std::fs::write(dir.join(name), b"").unwrap();
+    }
+
+    let is_known = |id| id == known;
+    assert_eq!(
+        orphaned_entries(&dir, &["jar"], Duration::ZERO, is_known),
+        vec![dir.join(format!("{orphan}.jar"))]
+    );
+    // just created files could belong to something that is being created
+    assert!(orphaned_entries(&dir, &["jar"], MIN_AGE, is_known).is_empty());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,118 @@
+//! what the tests share: an in-memory database and the objects most of them need in it
+
+use crate::api::serve::AppState;
+use crate::database::Database;
+use crate::database::objects::version::VersionKind;
+use crate::database::objects::{Group, ModLoader, Version, World};
+use crate::database::types::Id;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// an empty in-memory database. it has a single connection, as every connection would get its own database
+pub(crate) async fn memory_pool() -> sqlx::SqlitePool {
+    open(sqlx::sqlite::SqliteConnectOptions::from_str("sqlite::memory:").unwrap()).await
+}
+
+async fn open(options: sqlx::sqlite::SqliteConnectOptions) -> sqlx::SqlitePool {
+    sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await
+        .expect("failed to open the database")
+}
+
+impl Database {
+    /// an in-memory database with the tables created
+    pub(crate) async fn memory() -> Self {
+        Self::init_memory(memory_pool().await).await
+    }
+
+    /// [`Database::memory`] without checking foreign keys, for objects that reference ones the test doesn't need
+    pub(crate) async fn memory_unchecked() -> Self {
+        let options = sqlx::sqlite::SqliteConnectOptions::from_str("sqlite::memory:").unwrap().foreign_keys(false);
+        Self::init_memory(open(options).await).await
+    }
+
+    async fn init_memory(pool: sqlx::SqlitePool) -> Self {
+        let database = Database::new(pool.into());
+        database.init().await.expect("failed to init the database");
+        database
+    }
+}
+
+/// a group without any limits or permissions
+pub(crate) fn test_group() -> Group {
+    Group {
+        id: Id::default(),
+        name: String::from("test"),
+        total_memory_limit: None,
+        per_world_memory_limit: None,
+        world_limit: None,
+        active_world_limit: None,
+        storage_limit: None,
+        config_blacklist: vec![],
+        config_whitelist: vec![],
+        config_limits: HashMap::new(),
+        console_command_blacklist: vec![],
+        console_command_whitelist: vec![],
+        nice: None,
+        cpu_limit: None,
+        can_upload_mods: false,
+        is_privileged: false,
+    }
+}
+
+/// a disabled world, so creating its server doesn't start it
+pub(crate) fn test_world(owner_id: Id, version_id: Id) -> World {
+    World {
+        id: Id::default(),
+        owner_id,
+        name: String::from("world"),
+        hostname: String::from("world"),
+        allocated_memory: 1024,
+        version_id,
+        enabled: false,
+        backup_schedule: None,
+        setup_commands: String::new(),
+        initialized: false,
+        seed: None,
+        forward_player_ip: None,
+    }
+}
+
+/// inserts Minecraft 1.21 with the Vanilla mod loader
+pub(crate) async fn insert_test_version(database: &Database) -> Version {
+    let mod_loader = ModLoader {
+        id: Id::default(),
+        name: String::from("Vanilla"),
+        can_load_mods: false,
+    };
+    database.insert(&mod_loader, None).await.unwrap();
+    let version = Version {
+        id: Id::default(),
+        minecraft_version: String::from("1.21"),
+        mod_loader_id: mod_loader.id,
+        min_java: None,
+        required_java: None,
+        kind: VersionKind::Release,
+        jar_sha1: None,
+    };
+    database.insert(&version, None).await.unwrap();
+    version
+}
+
+/// the state of the api, with nothing in its caches
+pub(crate) fn test_state(database: Database) -> AppState {
+    AppState {
+        servers: crate::minecraft::server::MinecraftServerCollection::new(database.clone()),
+        database,
+        console_tickets: moka::future::Cache::new(1),
+        idempotency_keys: moka::future::Cache::new(1),
+        proxy_hosts: crate::minecraft::proxy::ProxyHosts::default(),
+        proxy_status: crate::minecraft::proxy::ProxyStatus::default(),
+        tasks: crate::tasks::TaskHealth::default(),
+        streams: crate::api::streams::ActiveStreams::default(),
+        pregenerations: crate::minecraft::pregeneration::Pregenerations::default(),
+        scheduled_restarts: crate::database::objects::world::ScheduledRestarts::default(),
+    }
+}
@@ -22,6 +22,8 @@ pub struct Column {
     pub modifiers: Vec<Modifier>,
     pub nullable: bool,
     pub hidden: bool,
+    /// description of the column, stored as a `COMMENT ON COLUMN` on Postgres
+    pub comment: Option<&'static str>,
 }
 
 impl Column {
@@ -32,6 +34,7 @@ impl Column {
             modifiers: Vec::new(),
             nullable: true,
             hidden: false,
+            comment: None,
         }
     }
 
@@ -44,6 +47,14 @@ impl Column {
         for modifier in &self.modifiers {
             descriptor = modifier.apply_to(&descriptor).to_string();
         }
+        if let ValueType::Enum(values) = self.data_type {
+            let values = values
+                .iter()
+                .map(|value| format!("'{}'", value.replace('\'', "''")))
+                .collect::<Vec<_>>()
+                .join(", ");
+            descriptor = format!("{descriptor} CHECK ({} IN ({values}))", self.name);
+        }
         descriptor.to_string()
     }
 
@@ -75,11 +86,21 @@ impl Column {
         self.with_modifier(Modifier::Default(value))
     }
 
+    pub fn check(self, expression: &'static str) -> Self {
+        self.with_modifier(Modifier::Check(expression))
+    }
+
     pub fn hidden(self) -> Self {
         let mut new = self;
         new.hidden = true;
         new
     }
+
+    pub fn comment(self, comment: &'static str) -> Self {
+        let mut new = self;
+        new.comment = Some(comment);
+        new
+    }
 }
 
 impl<T: Row> ColumnIndex<T> for Column {
@@ -100,6 +121,8 @@ pub enum Modifier {
     Unique,
     References(&'static str),
     Default(&'static str),
+    /// a `CHECK` constraint. the expression has to be valid in both Sqlite and Postgres
+    Check(&'static str),
 }
 
 impl Modifier {
@@ -110,6 +133,7 @@ impl Modifier {
             Modifier::Unique => "UNIQUE".to_string(),
             Modifier::References(s) => format!("REFERENCES {s}"),
             Modifier::Default(s) => format!("DEFAULT {s}"),
+            Modifier::Check(s) => format!("CHECK ({s})"),
         }
     }
 
@@ -126,6 +150,8 @@ impl Modifier {
 /// [`Access::IfPublic(column_name: String)`]: pass if the object implements is_public() = true if using `can_access`, and if the the column name provided is true if using `access_filter`
 /// [`Access::PrivilegedUser`]: every user with `privileged = true` has access
 /// [`Access::None`]: access is always denied
+/// [`Access::TimeWindowed(access, window)`]: the inner access during the [`TimeWindow`], denied outside of it
+#[derive(Debug)]
 pub enum Access {
     All,
     User,
@@ -136,6 +162,7 @@ pub enum Access {
 
     And(Box<Access>, Box<Access>),
     Or(Box<Access>, Box<Access>),
+    TimeWindowed(Box<Access>, TimeWindow),
 }
 
 impl Access {
@@ -145,6 +172,26 @@ impl Access {
     pub fn and(self, other: Access) -> Self {
         Access::And(Box::new(self), Box::new(other))
     }
+    pub fn during(self, window: TimeWindow) -> Self {
+        Access::TimeWindowed(Box::new(self), window)
+    }
+
+    /// [`Access::can_access`], logging a denial at debug level with the user, the object and the rule, to find out why
+    /// someone can't see or change something. only the ids are logged, not the fields of the object
+    pub fn check<T: DbObject>(&self, action: &str, object: Option<&T>, user: &User, group: &Group) -> bool {
+        let allowed = self.can_access(object, user, group);
+        if !allowed {
+            let object = object.map(|object| format!(" {}", object.id())).unwrap_or_default();
+            let disabled = if user.enabled { "" } else { ", the user is disabled" };
+            log::debug!(
+                "denied {} ({}) {action} {}{object} by {self:?}{disabled}",
+                user.username,
+                user.id,
+                T::table_name()
+            );
+        }
+        allowed
+    }
 
     /// whether a user can passes the access check.
     ///
@@ -166,26 +213,26 @@ impl Access {
             Access::Or(left, right) => {
                 right.can_access(object, user, group) || left.can_access(object, user, group)
             }
+            Access::TimeWindowed(access, window) => {
+                window.contains(chrono::Local::now().naive_local()) && access.can_access(object, user, group)
+            }
             _ => {
                 //all the following restrict the user to be enabled
                 if user.enabled {
                     match self {
                         Access::User => true,
                         //Access::Owner() => object.expect("owner access used with object being None").params()[] == user.id.to_sql().unwrap(),
-                        Access::Owner(_) => {
-                            let object = object.expect("Access::User must provide an object");
+                        // without an object, like when creating one, there is nothing to own or be public yet
+                        Access::Owner(_) => object.is_some_and(|object| {
                             object
                                 .owner_id()
                                 .expect("object does not implement owner_id()")
                                 == user.id
-                        }
-                        Access::IfPublic(_) => {
-                            let object = object.expect("Access::IfPublic must provide an object");
-                            object.is_public()
-                        }
+                        }),
+                        Access::IfPublic(_) => object.is_some_and(|object| object.is_public()),
                         Access::PrivilegedUser => group.is_privileged,
                         Access::None => false,
-                        Access::All | Access::And(..) | Access::Or(..) => {
+                        Access::All | Access::And(..) | Access::Or(..) | Access::TimeWindowed(..) => {
                             unreachable!();
                         }
                     }
@@ -230,6 +277,13 @@ impl Access {
                     format!("({left} OR {right})")
                 }
             }
+            Access::TimeWindowed(access, window) => {
+                if window.contains(chrono::Local::now().naive_local()) {
+                    access.access_filter::<T>(user, group)
+                } else {
+                    "FALSE".to_string()
+                }
+            }
             _ => {
                 //all the following restrict the user to be enabled
                 if user.enabled {
@@ -245,7 +299,7 @@ impl Access {
                             if group.is_privileged { "TRUE" } else { "FALSE" }.to_string()
                         }
                         Access::None => "FALSE".to_string(),
-                        Access::All | Access::And(..) | Access::Or(..) => {
+                        Access::All | Access::And(..) | Access::Or(..) | Access::TimeWindowed(..) => {
                             unreachable!();
                         }
                     }
@@ -257,6 +311,102 @@ impl Access {
     }
 }
 
+/// days of the week and a range of hours, like `mon-fri 8-22` or `sat,sun 10-2`. `*` means every day. the hours are
+/// in the server's local timezone, the end is exclusive and `24` is midnight. a range ending before it starts goes
+/// past midnight, the hours after midnight count for the day they fall on, not the day the range started on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeWindow {
+    days: Vec<chrono::Weekday>,
+    start_hour: u32,
+    end_hour: u32,
+}
+
+impl TimeWindow {
+    /// whether the time (in the server's timezone) is in the window
+    pub fn contains(&self, time: chrono::NaiveDateTime) -> bool {
+        use chrono::{Datelike, Timelike};
+
+        let hour = time.hour();
+        let in_hours = if self.start_hour <= self.end_hour {
+            (self.start_hour..self.end_hour).contains(&hour)
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        };
+        in_hours && self.days.contains(&time.weekday())
+    }
+}
+
+impl Display for TimeWindow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let days: Vec<String> = self
+            .days
+            .iter()
+            .map(|day| day.to_string().to_lowercase())
+            .collect();
+        write!(f, "{} {}-{}", days.join(","), self.start_hour, self.end_hour)
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (days, hours) = s
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| format!("invalid time window \"{s}\", expected days and hours like \"mon-fri 8-22\""))?;
+
+        let parse_day = |day: &str| {
+            day.trim()
+                .parse::<chrono::Weekday>()
+                .map_err(|_| format!("invalid day \"{day}\" in the time window \"{s}\""))
+        };
+        let mut parsed_days = Vec::new();
+        if days == "*" {
+            parsed_days.extend((0..7).map(|day| chrono::Weekday::try_from(day).expect("day out of range")));
+        } else {
+            for part in days.split(',') {
+                match part.split_once('-') {
+                    Some((first, last)) => {
+                        let (mut day, last) = (parse_day(first)?, parse_day(last)?);
+                        parsed_days.push(day);
+                        while day != last {
+                            day = day.succ();
+                            parsed_days.push(day);
+                        }
+                    }
+                    None => parsed_days.push(parse_day(part)?),
+                }
+            }
+        }
+
+        let parse_hour = |hour: &str| match hour.trim().parse::<u32>() {
+            Ok(hour) if hour <= 24 => Ok(hour),
+            _ => Err(format!("invalid hour \"{hour}\" in the time window \"{s}\", expected 0 to 24")),
+        };
+        let (start_hour, end_hour) = hours
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| format!("invalid hours in the time window \"{s}\", expected a range like 8-22"))?;
+
+        Ok(Self {
+            days: parsed_days,
+            start_hour: parse_hour(start_hour)?,
+            end_hour: parse_hour(end_hour)?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeWindow {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(Error::custom)
+    }
+}
+
 /// Id holds a 48-bit identifier, which can be accessed in a form of an `i64` or as an URL-safe base 64 encoded 8 character `string`
 ///
 /// It should be used in the numeric form in in the low level in the backend (eg. database fields), and in the string form everywhere else (like `JSON` fields).
@@ -395,6 +545,210 @@ impl Default for Id {
     }
 }
 
+/// a list of [`Id`]s, stored as text with the ids separated by commas
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct IdList(pub Vec<Id>);
+
+impl std::ops::Deref for IdList {
+    type Target = Vec<Id>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for IdList {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let ids: Vec<String> = self.0.iter().map(Id::to_string).collect();
+        f.write_str(&ids.join(","))
+    }
+}
+
+impl FromStr for IdList {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        s.split(',').map(|id| Id::from_string(id.trim())).collect::<Result<_>>().map(Self)
+    }
+}
+
+impl TryFrom<String> for IdList {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        Self::from_str(&value)
+    }
+}
+
+/// an amount of memory in MiB. it's checked to fit the `INTEGER` columns memory is stored in when it's created, so
+/// converting it never has to clamp or wrap
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Memory(u32);
+
+impl Memory {
+    pub const MAX: Self = Self(i32::MAX.unsigned_abs());
+
+    pub fn from_mib(mib: u64) -> Result<Self> {
+        match u32::try_from(mib) {
+            Ok(mib) if mib <= Self::MAX.0 => Ok(Self(mib)),
+            _ => Err(eyre!(
+                "{mib} MiB is too much memory, the most is {} MiB",
+                Self::MAX.0
+            )),
+        }
+    }
+
+    pub fn mib(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<i32> for Memory {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(value: i32) -> Result<Self> {
+        u64::try_from(value)
+            .map_err(|_| eyre!("memory can't be negative"))
+            .and_then(Self::from_mib)
+    }
+}
+
+impl From<Memory> for i32 {
+    fn from(value: Memory) -> Self {
+        i32::try_from(value.0).expect("memory is always within the i32 range")
+    }
+}
+
+impl From<Memory> for i64 {
+    fn from(value: Memory) -> Self {
+        i64::from(value.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Memory {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let mib = u64::deserialize(deserializer)?;
+        Self::from_mib(mib).map_err(|err| Error::custom(err.to_string()))
+    }
+}
+
+#[test]
+fn enum_column() {
+    use pretty_assertions::assert_eq;
+
+    let column = Column::new("permission", ValueType::Enum(&["view", "command"])).not_null();
+    assert_eq!(
+        column.descriptor(&DatabaseType::Sqlite),
+        "TEXT NOT NULL CHECK (permission IN ('view', 'command'))"
+    );
+    assert!(column.data_type.accepts("view"));
+    assert!(!column.data_type.accepts("admin"));
+    assert!(ValueType::Text.accepts("admin"));
+
+    let column = Column::new("allocated_memory", ValueType::Integer)
+        .not_null()
+        .check("allocated_memory >= 0");
+    assert_eq!(
+        column.descriptor(&DatabaseType::Postgres),
+        "INTEGER NOT NULL CHECK (allocated_memory >= 0)"
+    );
+}
+
+#[test]
+fn time_window() {
+    use chrono::NaiveDate;
+    use pretty_assertions::assert_eq;
+
+    // 2026-10-16 is a friday
+    let at = |day: u32, hour: u32| NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_hms_opt(hour, 30, 0).unwrap();
+
+    let window: TimeWindow = "mon-fri 8-22".parse().unwrap();
+    assert!(window.contains(at(16, 8)));
+    assert!(window.contains(at(16, 21)));
+    assert!(!window.contains(at(16, 22)));
+    assert!(!window.contains(at(16, 7)));
+    assert!(!window.contains(at(17, 12)));
+    assert_eq!(window.to_string(), "mon,tue,wed,thu,fri 8-22");
+
+    // past midnight
+    let window: TimeWindow = "sat,sun 22-2".parse().unwrap();
+    assert!(window.contains(at(17, 23)));
+    assert!(window.contains(at(18, 1)));
+    assert!(!window.contains(at(17, 12)));
+    assert!(!window.contains(at(16, 23)));
+
+    let window: TimeWindow = "* 0-24".parse().unwrap();
+    assert!(window.contains(at(14, 0)));
+    assert!(window.contains(at(18, 23)));
+
+    assert!("mon-fri".parse::<TimeWindow>().is_err());
+    assert!("someday 8-22".parse::<TimeWindow>().is_err());
+    assert!("mon 8-25".parse::<TimeWindow>().is_err());
+
+    // the inner access only applies within the window
+    use crate::database::objects::FromJson;
+
+    let user = User::default();
+    let group = Group::from_json(
+        &serde_json::from_value(serde_json::json!({"name": "group"})).unwrap(),
+        &user,
+    );
+    let always = "* 0-24".parse::<TimeWindow>().unwrap();
+    let never = "* 0-0".parse::<TimeWindow>().unwrap();
+    assert!(Access::User.during(always.clone()).can_access::<User>(None, &user, &group));
+    assert!(!Access::User.during(never.clone()).can_access::<User>(None, &user, &group));
+    assert!(!Access::None.during(always.clone()).can_access::<User>(None, &user, &group));
+    assert_eq!(Access::User.during(always).access_filter::<User>(&user, &group), "1");
+    assert_eq!(Access::User.during(never).access_filter::<User>(&user, &group), "FALSE");
+}
+
+#[test]
+fn objectless_access() {
+    use crate::database::objects::{FromJson, Mod};
+
+    let user = User::default();
+    let group = Group::from_json(
+        &serde_json::from_value(serde_json::json!({"name": "group"})).unwrap(),
+        &user,
+    );
+    let owned = Mod {
+        id: Id::new_random(),
+        owner_id: user.id,
+        version_id: Id::new_random(),
+        name: String::from("mod"),
+        description: String::new(),
+        modrinth_id: None,
+        public: false,
+        dependencies: IdList::default(),
+    };
+
+    // checked like create access, without an object
+    assert!(!Access::Owner("owner_id").can_access::<Mod>(None, &user, &group));
+    assert!(!Access::IfPublic("public").can_access::<Mod>(None, &user, &group));
+    assert!(Access::User.or(Access::Owner("owner_id")).can_access::<Mod>(None, &user, &group));
+    assert!(!Access::User.and(Access::Owner("owner_id")).can_access::<Mod>(None, &user, &group));
+    assert!(!Access::Owner("owner_id").or(Access::IfPublic("public")).can_access::<Mod>(None, &user, &group));
+
+    assert!(Access::Owner("owner_id").can_access(Some(&owned), &user, &group));
+    assert!(!Access::IfPublic("public").can_access(Some(&owned), &user, &group));
+
+    // the logged check decides the same
+    assert!(Access::Owner("owner_id").check("viewing", Some(&owned), &user, &group));
+    assert!(!Access::IfPublic("public").check("viewing", Some(&owned), &user, &group));
+    assert_eq!(
+        format!("{:?}", Access::Owner("owner_id").or(Access::PrivilegedUser)),
+        r#"Or(Owner("owner_id"), PrivilegedUser)"#
+    );
+}
+
 #[test]
 fn id() {
     use pretty_assertions::assert_eq;
@@ -525,6 +879,7 @@ impl ValueType {
     pub const fn descriptor(&self, db_type: &DatabaseType) -> &'static str {
         match self {
             ValueType::Integer => "INTEGER",
+            ValueType::BigInteger => "BIGINT",
             ValueType::Float => "REAL",
             ValueType::Text => "TEXT",
             ValueType::Boolean => "BOOLEAN",
@@ -538,6 +893,30 @@ impl ValueType {
                 DatabaseType::Postgres => "TIMESTAMPTZ",
                 DatabaseType::Sqlite => "DATETIME",
             },
+            ValueType::Enum(_) => "TEXT",
+        }
+    }
+
+    /// whether `value` is valid for the type. only [`ValueType::Enum`] restricts text values
+    pub fn accepts(&self, value: &str) -> bool {
+        match self {
+            ValueType::Enum(values) => values.contains(&value),
+            _ => true,
         }
     }
 }
+
+#[test]
+fn memory() {
+    use pretty_assertions::assert_eq;
+
+    let parse = |value: serde_json::Value| serde_json::from_value::<Memory>(value);
+    assert_eq!(parse(serde_json::json!(0)).unwrap().mib(), 0);
+    assert_eq!(parse(serde_json::json!(i32::MAX)).unwrap(), Memory::MAX);
+    assert_eq!(i32::from(Memory::MAX), i32::MAX);
+    assert!(parse(serde_json::json!(i64::from(i32::MAX) + 1)).is_err());
+    assert!(parse(serde_json::json!(u64::MAX)).is_err());
+    assert!(parse(serde_json::json!(-1)).is_err());
+    assert!(Memory::try_from(-1).is_err());
+    assert_eq!(Memory::try_from(1024).unwrap().mib(), 1024);
+}
@@ -5,4 +5,5 @@ pub mod bin;
 pub mod config;
 pub mod database;
 pub mod minecraft;
+pub mod tasks;
 pub mod util;
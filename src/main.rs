@@ -1,11 +1,15 @@
 use color_eyre::Result;
-use log::{error, info};
+use log::{debug, error, info, warn};
 use mcmanager::api::serve::AppState;
+use mcmanager::api::streams::ActiveStreams;
 use mcmanager::config::{CONFIG, DatabaseType};
 use mcmanager::database::objects::{Group, ModLoader, User};
-use mcmanager::database::{Database, DatabasePool};
-use mcmanager::minecraft::proxy::{InfrarustServer, MinecraftProxy};
+use mcmanager::database::objects::world::ScheduledRestarts;
+use mcmanager::database::{Database, DatabasePool, OnConflict};
+use mcmanager::minecraft::pregeneration::Pregenerations;
+use mcmanager::minecraft::proxy::{InfrarustServer, MinecraftProxy, ProxyHosts, ProxyStatus};
 use mcmanager::minecraft::server::{MinecraftServerCollection, ServerConfigLimit};
+use mcmanager::tasks::TaskHealth;
 use mcmanager::util;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
@@ -46,7 +50,9 @@ async fn main() -> Result<()> {
 
     let pool: DatabasePool = match CONFIG.database.database_type {
         DatabaseType::Sqlite => {
-            let options = SqlitePoolOptions::new().max_connections(CONFIG.database.max_connections);
+            let options = SqlitePoolOptions::new()
+                .max_connections(CONFIG.database.max_connections)
+                .acquire_timeout(Duration::from_secs(CONFIG.database.acquire_timeout));
 
             options
                 .connect_with(
@@ -59,6 +65,7 @@ async fn main() -> Result<()> {
         }
         DatabaseType::Postgres => PgPoolOptions::new()
             .max_connections(CONFIG.database.max_connections)
+            .acquire_timeout(Duration::from_secs(CONFIG.database.acquire_timeout))
             .connect(CONFIG.database.pg_host.as_str())
             .await?
             .into(),
@@ -131,7 +138,6 @@ async fn main() -> Result<()> {
                         String::from("server-port"),
                     ],
                     config_whitelist: vec![
-                        String::new(),
                         String::from("allow-flight"),
                         String::from("allow-nether"),
                         String::from("broadcast-console-to-ops"),
@@ -160,7 +166,6 @@ async fn main() -> Result<()> {
                         String::from("motd"),
                         String::from("op-permission-level"),
                         String::from("player-idle-timeout"),
-                        String::from("player-idle-timeout"),
                         String::from("pvp"),
                         String::from("require-resource-pack"),
                         String::from("resource-pack"),
@@ -174,6 +179,14 @@ async fn main() -> Result<()> {
                         String::from("white-list"),
                     ],
                     config_limits,
+                    console_command_blacklist: vec![
+                        String::from("op"),
+                        String::from("deop"),
+                        String::from("stop"),
+                    ],
+                    console_command_whitelist: vec![],
+                    nice: None,
+                    cpu_limit: None,
                     can_upload_mods: false,
                     is_privileged: false,
                 }
@@ -191,19 +204,32 @@ async fn main() -> Result<()> {
                     config_blacklist: vec![],
                     config_whitelist: vec![],
                     config_limits: HashMap::new(),
+                    console_command_blacklist: vec![],
+                    console_command_whitelist: vec![],
+                    nice: None,
+                    cpu_limit: None,
                     can_upload_mods: true,
                     is_privileged: true,
                 }
             };
 
-            database
-                .insert(&default_group, None)
+            // the groups are matched by their names, so setting up again keeps the ones already there
+            let default_group = match database
+                .upsert(&default_group, OnConflict::Nothing)
                 .await
-                .expect("Failed to insert default user group");
-            database
-                .insert(&admin_group, None)
+                .expect("Failed to insert default user group")
+            {
+                Some(group) => group,
+                None => database.get_where::<Group, _>("name", default_group.name, None).await?,
+            };
+            let admin_group = match database
+                .upsert(&admin_group, OnConflict::Nothing)
                 .await
-                .expect("Failed to insert administrator group");
+                .expect("Failed to insert administrator group")
+            {
+                Some(group) => group,
+                None => database.get_where::<Group, _>("name", admin_group.name, None).await?,
+            };
 
             database
                 .create_user_from(
@@ -213,6 +239,8 @@ async fn main() -> Result<()> {
                         group_id: admin_group.id,
                         total_memory_usage: 0,
                         enabled: true,
+                        minecraft_username: None,
+                        minecraft_uuid: None,
                     },
                     password.trim(),
                 )
@@ -234,33 +262,33 @@ async fn main() -> Result<()> {
 
             // add some basic default values
             database
-                .insert(
+                .upsert(
                     &ModLoader {
                         id: Id::default(),
                         name: "Vanilla".to_string(),
                         can_load_mods: false,
                     },
-                    None,
+                    OnConflict::Nothing,
                 )
                 .await?;
             database
-                .insert(
+                .upsert(
                     &ModLoader {
                         id: Id::default(),
                         name: "Fabric".to_string(),
                         can_load_mods: true,
                     },
-                    None,
+                    OnConflict::Nothing,
                 )
                 .await?;
             database
-                .insert(
+                .upsert(
                     &ModLoader {
                         id: Id::default(),
                         name: "Forge".to_string(),
                         can_load_mods: true,
                     },
-                    None,
+                    OnConflict::Nothing,
                 )
                 .await?;
         }
@@ -269,43 +297,158 @@ async fn main() -> Result<()> {
         return Ok(());
     }
 
+    if CONFIG.database.warm_cache {
+        match database.warm_cache().await {
+            Ok(warmed) => info!("warmed the cache with {warmed} objects"),
+            Err(err) => error!("failed to warm the cache: {err}"),
+        }
+    }
+
+    match *mcmanager::minecraft::util::JAVA_VERSION {
+        Some(java_version) => info!("detected Java {java_version}"),
+        None => warn!("Java was not detected, worlds may fail to start"),
+    }
+    mcmanager::minecraft::util::validate_java_runtimes()?;
+    mcmanager::minecraft::hooks::validate_hooks()?;
+    if !CONFIG.world.java_runtimes.is_empty() && !CONFIG.world.java_launch_command.contains("%java%") {
+        warn!("world.java_launch_command doesn't contain %java%, so world.java_runtimes are not used");
+    }
+
     let console_tickets = moka::future::CacheBuilder::new(10000) //10000 ought to be enough
         .time_to_live(Duration::from_secs(30*60)) // 30 minute ttl ought to be enough
         .build();
 
+    let idempotency_keys = moka::future::CacheBuilder::new(10000)
+        .time_to_live(Duration::from_secs(24*60*60))
+        .build();
+
     let state = AppState {
+        servers: MinecraftServerCollection::new(database.clone()),
         database,
-        servers: MinecraftServerCollection::new(),
-        console_tickets
+        console_tickets,
+        idempotency_keys,
+        proxy_hosts: ProxyHosts::default(),
+        proxy_status: ProxyStatus::default(),
+        tasks: TaskHealth::default(),
+        streams: ActiveStreams::default(),
+        pregenerations: Pregenerations::default(),
+        scheduled_restarts: ScheduledRestarts::default(),
     };
 
-    tokio::task::spawn({
+    let tasks = state.tasks.clone();
+    tasks.spawn("cache invalidation", None, {
+        let database = state.database.clone();
+        move || {
+            let database = database.clone();
+            async move { database.listen_for_invalidations().await }
+        }
+    });
+
+    tasks.spawn("database health", None, {
+        let database = state.database.clone();
+        move || {
+            let database = database.clone();
+            async move { database.monitor_health().await }
+        }
+    });
+
+    tasks.spawn_loop("session cleanup", Duration::from_secs(CONFIG.sessions.cleanup_interval), {
+        let database = state.database.clone();
+        move || {
+            let database = database.clone();
+            async move {
+                match database
+                    .remove_expired_sessions(Duration::from_secs(CONFIG.sessions.lifetime))
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(removed) => info!("removed {removed} expired sessions"),
+                    Err(err) => error!("failed to remove the expired sessions: {err}"),
+                }
+            }
+        }
+    });
+
+    tasks.spawn_loop("server polling", Duration::from_millis(1000), {
         let servers = state.servers.clone();
-        async move {
-            let mut interval = tokio::time::interval(Duration::from_millis(1000));
-            loop {
-                interval.tick().await;
+        move || {
+            let servers = servers.clone();
+            async move {
                 servers.poll_servers().await;
+                if CONFIG.world.prune_stopped_after > 0 {
+                    let pruned = servers
+                        .prune_servers(Duration::from_secs(CONFIG.world.prune_stopped_after))
+                        .await;
+                    if !pruned.is_empty() {
+                        debug!("removed the stopped servers of {pruned:?}");
+                    }
+                }
             }
         }
     });
 
-    tokio::task::spawn({
+    tasks.spawn_loop("port reconciliation", Duration::from_secs(300), {
         let servers = state.servers.clone();
-        async move {
-            info!("starting minecraft proxy at {}", CONFIG.proxy.port);
-            let mut proxy =
-                InfrarustServer::new(servers).expect("failed to create an infrarust server");
-            proxy
-                .start()
-                .await
-                .expect("failed to start an infrarust server");
+        move || {
+            let servers = servers.clone();
+            async move {
+                let freed = servers.reconcile_ports().await;
+                if !freed.is_empty() {
+                    warn!("freed the leaked ports {freed:?}");
+                }
+            }
+        }
+    });
+
+    tasks.spawn_loop("cache stats", Duration::from_secs(60), {
+        let database = state.database.clone();
+        move || {
+            let database = database.clone();
+            async move { database.cache.log_stats() }
+        }
+    });
+
+    tasks.spawn("backup scheduler", None, {
+        let state = state.clone();
+        move || mcmanager::minecraft::backup::run_scheduler(state.clone())
+    });
+
+    if CONFIG.proxy.offline_status.enabled {
+        tasks.spawn("offline status", None, {
+            let database = state.database.clone();
+            move || mcmanager::minecraft::offline::run(database.clone())
+        });
+    }
+
+    tasks.spawn("proxy", Some(Duration::from_millis(1000)), {
+        let servers = state.servers.clone();
+        let database = state.database.clone();
+        let hosts = state.proxy_hosts.clone();
+        let status = state.proxy_status.clone();
+        let tasks = tasks.clone();
+        move || {
+            let servers = servers.clone();
+            let database = database.clone();
+            let hosts = hosts.clone();
+            let status = status.clone();
+            let tasks = tasks.clone();
+            async move {
+                info!("starting minecraft proxy at {}", CONFIG.proxy.port);
+                let mut proxy =
+                    InfrarustServer::new(servers, database, hosts).expect("failed to create an infrarust server");
+                proxy
+                    .start()
+                    .await
+                    .expect("failed to start an infrarust server");
 
-            let mut interval = tokio::time::interval(Duration::from_millis(1000));
-            loop {
-                interval.tick().await;
-                if let Err(err) = proxy.update().await {
-                    error!("failed to update the infrarust server: {err}");
+                let mut interval = tokio::time::interval(Duration::from_millis(1000));
+                loop {
+                    interval.tick().await;
+                    if let Err(err) = proxy.update().await {
+                        error!("failed to update the infrarust server: {err}");
+                    }
+                    *status.write().expect("the proxy status lock is poisoned") = Some(proxy.status().await);
+                    tasks.tick("proxy");
                 }
             }
         }
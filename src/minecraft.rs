@@ -1,3 +1,14 @@
+pub mod account;
+pub mod backup;
+pub mod capacity;
+pub mod datapack;
+pub mod hooks;
+pub mod jar;
+pub mod limits;
+pub mod modrinth;
+pub mod nbt;
+pub mod offline;
+pub mod pregeneration;
 pub mod proxy;
 pub mod server;
 pub mod util;
@@ -0,0 +1,72 @@
+use crate::config::CONFIG;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::sync::LazyLock;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// how long a profile lookup can take
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(LOOKUP_TIMEOUT)
+        .build()
+        .expect("failed to build the profile api client")
+});
+
+/// a Minecraft account, as the profile api returns it
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Profile {
+    /// the account's UUID. the api sends it without dashes
+    pub id: Uuid,
+    /// the username, capitalized the way the account has it
+    pub name: String,
+}
+
+/// the UUID offline-mode servers give a player, which is Java's `UUID.nameUUIDFromBytes("OfflinePlayer:" + name)`
+pub fn offline_uuid(name: &str) -> Uuid {
+    use md5::{Digest, Md5};
+
+    let hash = Md5::digest(format!("OfflinePlayer:{name}"));
+    uuid::Builder::from_md5_bytes(hash.into()).into_uuid()
+}
+
+pub fn parse_profile(body: &[u8]) -> Result<Profile, String> {
+    serde_json::from_slice(body).map_err(|err| format!("the profile api sent an invalid profile: {err}"))
+}
+
+/// looks up the account with the username using [`crate::config::AccountConfig::profile_api`]. [`None`] if there
+/// is no such account, the error is a reason that can be logged
+pub async fn lookup(name: &str) -> Result<Option<Profile>, String> {
+    let response = CLIENT
+        .get(format!("{}{name}", CONFIG.accounts.profile_api))
+        .send()
+        .await
+        .map_err(|err| format!("could not reach the profile api: {err}"))?;
+    match response.status() {
+        StatusCode::NOT_FOUND | StatusCode::NO_CONTENT => return Ok(None),
+        status if !status.is_success() => return Err(format!("the profile api responded with {status}")),
+        _ => {}
+    }
+    let body = response
+        .bytes()
+        .await
+        .map_err(|err| format!("could not read the profile: {err}"))?;
+    parse_profile(&body).map(Some)
+}
+
+#[test]
+fn account_test() {
+    assert_eq!(
+        offline_uuid("Notch").to_string(),
+        "b50ad385-829d-3141-a216-7e7d7539ba7f"
+    );
+    assert_eq!(offline_uuid("Notch").get_version_num(), 3);
+    assert_ne!(offline_uuid("Notch"), offline_uuid("notch"));
+
+    let profile = parse_profile(br#"{"id":"069a79f444e94726a5befca90e38aaf5","name":"Notch"}"#).unwrap();
+    assert_eq!(profile.id.to_string(), "069a79f4-44e9-4726-a5be-fca90e38aaf5");
+    assert_eq!(profile.name, "Notch");
+    assert!(parse_profile(br#"{"name":"Notch"}"#).is_err());
+}
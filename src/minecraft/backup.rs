@@ -0,0 +1,312 @@
+//! snapshots of world directories, stored as `.tar.gz` archives in [`util::dirs::backups_dir`]
+
+use crate::api::serve::AppState;
+use crate::config::CONFIG;
+use crate::database::objects::{Group, User, World};
+use crate::database::types::Id;
+use crate::minecraft::server::{MinecraftServerStatus, wait_for_save};
+use crate::util;
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{ContextCompat, bail};
+use color_eyre::Result;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use log::{debug, error, info, warn};
+use serde::Serialize;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+const EXTENSION: &str = ".tar.gz";
+/// how long to wait for the server to confirm `save-all flush` before backing up anyway
+const SAVE_TIMEOUT: Duration = Duration::from_secs(60);
+/// how often the scheduler checks for due backups
+const SCHEDULER_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Backup {
+    /// file name of the backup, unique within the world
+    pub name: String,
+    /// size of the archive in bytes
+    pub size: u64,
+    pub created: DateTime<Utc>,
+}
+
+impl Backup {
+    fn from_path(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(Self {
+            name: path
+                .file_name()
+                .context("backup has no file name")?
+                .to_string_lossy()
+                .into_owned(),
+            size: metadata.len(),
+            created: metadata.modified()?.into(),
+        })
+    }
+}
+
+/// where the backups of a world are stored
+pub fn world_backups_dir(world_id: Id) -> PathBuf {
+    util::dirs::backups_dir().join(world_id.to_string())
+}
+
+/// parses a cron expression. the seconds field is optional, so the usual 5 field expressions work
+pub fn parse_schedule(schedule: &str) -> Result<cron::Schedule> {
+    let schedule = schedule.trim();
+    let schedule = if schedule.split_whitespace().count() == 5 {
+        format!("0 {schedule}")
+    } else {
+        schedule.to_string()
+    };
+    Ok(cron::Schedule::from_str(&schedule)?)
+}
+
+/// whether `name` can be the name of a backup, so it can't be used to escape the backup directory
+pub fn is_valid_name(name: &str) -> bool {
+    name.ends_with(EXTENSION)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.starts_with('.')
+}
+
+/// archives `directory` as a new backup of the world. this is blocking
+pub fn create(world_id: Id, directory: &Path) -> Result<Backup> {
+    if !directory.exists() {
+        bail!("{} does not exist", directory.display());
+    }
+    let backups_dir = world_backups_dir(world_id);
+    fs::create_dir_all(&backups_dir)?;
+
+    let name = format!("{}{EXTENSION}", Utc::now().format("%Y-%m-%dT%H-%M-%S%.3fZ"));
+    let path = backups_dir.join(&name);
+    // write to a temporary file first, so an interrupted backup doesn't look like a finished one
+    let partial = backups_dir.join(format!("{name}.part"));
+
+    let result = (|| {
+        let mut archive = tar::Builder::new(GzEncoder::new(
+            File::create(&partial)?,
+            Compression::default(),
+        ));
+        archive.follow_symlinks(false);
+        archive.append_dir_all(".", directory)?;
+        archive.into_inner()?.finish()?;
+        fs::rename(&partial, &path)
+    })();
+    if let Err(err) = result {
+        let _ = fs::remove_file(&partial);
+        return Err(err.into());
+    }
+
+    Backup::from_path(&path)
+}
+
+/// lists the backups of a world, newest first
+pub fn list(world_id: Id) -> Result<Vec<Backup>> {
+    let backups_dir = world_backups_dir(world_id);
+    if !backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(backups_dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && is_valid_name(&entry.file_name().to_string_lossy()) {
+            backups.push(Backup::from_path(&entry.path())?);
+        }
+    }
+    backups.sort_by(|a, b| b.created.cmp(&a.created).then_with(|| b.name.cmp(&a.name)));
+    Ok(backups)
+}
+
+/// path to the backup of a world with the given name. [`None`] if the name is invalid or there is no such backup
+pub fn path(world_id: Id, name: &str) -> Option<PathBuf> {
+    if !is_valid_name(name) {
+        return None;
+    }
+    let path = world_backups_dir(world_id).join(name);
+    path.is_file().then_some(path)
+}
+
+/// removes a backup of a world. returns false if there is no such backup
+pub fn remove(world_id: Id, name: &str) -> Result<bool> {
+    match path(world_id, name) {
+        Some(path) => {
+            fs::remove_file(path)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// removes the oldest backups of a world so that at most `retention` of them are kept and they take up at most
+/// `max_size` bytes. the newest backup is always kept. returns the removed backups
+pub fn prune(world_id: Id, retention: usize, max_size: Option<u64>) -> Result<Vec<Backup>> {
+    let backups = list(world_id)?;
+    let mut removed = Vec::new();
+    let mut total_size = 0;
+
+    for (i, backup) in backups.into_iter().enumerate() {
+        total_size += backup.size;
+        let over_size = max_size.is_some_and(|max_size| total_size > max_size);
+        if i > 0 && (i >= retention || over_size) {
+            fs::remove_file(world_backups_dir(world_id).join(&backup.name))?;
+            total_size -= backup.size;
+            removed.push(backup);
+        }
+    }
+    Ok(removed)
+}
+
+/// how many bytes of backups can the world have, considering the config and the owner's storage limit
+async fn max_backups_size(state: &AppState, world: &World) -> Result<Option<u64>> {
+    let config_limit = CONFIG.backups.max_size.map(|max_size| max_size * 1024 * 1024);
+
+    let owner: User = state.database.get_one(world.owner_id, None).await?;
+    let group: Group = state.database.get_one(owner.group_id, None).await?;
+    let Some(storage_limit) = group.storage_limit else {
+        return Ok(config_limit);
+    };
+    let storage_limit = u64::try_from(storage_limit).unwrap_or_default() * 1024 * 1024;
+
+    // everything the owner stores, except for the backups of this world
+    let mut used = 0;
+    for owned in state
+        .database
+        .get_all_where::<World, _>("owner_id", owner.id, None)
+        .await?
+    {
+        used += util::dir_size(
+            util::dirs::worlds_dir().join(format!("{}/{}", owned.owner_id, owned.id)),
+        )?;
+        if owned.id != world.id {
+            used += util::dir_size(world_backups_dir(owned.id))?;
+        }
+    }
+
+    let storage_available = storage_limit.saturating_sub(used);
+    Ok(Some(config_limit.map_or(storage_available, |limit| {
+        limit.min(storage_available)
+    })))
+}
+
+/// backs up the world. if it is running, it is saved and saving is paused for the duration of the backup,
+/// otherwise it is backed up cold. old backups are pruned afterwards
+pub async fn backup_world(state: &AppState, world: &World) -> Result<Backup> {
+    let server = state.servers.get_or_create_server(world).await?;
+    let directory = server
+        .lock()
+        .await
+        .directory()
+        .context("backups are only supported for local servers")?;
+
+    let running = matches!(
+        server.lock().await.status().await,
+        Ok(MinecraftServerStatus::Running)
+    );
+    if running {
+        let stdout = server.lock().await.save().await?;
+        if !wait_for_save(stdout, SAVE_TIMEOUT).await {
+            warn!("world {} did not confirm the save, backing it up anyway", world.id);
+        }
+        server.lock().await.set_saving(false).await?;
+    }
+
+    let world_id = world.id;
+    let result = tokio::task::spawn_blocking(move || create(world_id, &directory)).await;
+
+    if running && let Err(err) = server.lock().await.set_saving(true).await {
+        error!("could not turn saving back on for world {}: {err}", world.id);
+    }
+    let backup = result??;
+    info!(
+        "created backup {} of world {} ({} bytes)",
+        backup.name, world.id, backup.size
+    );
+
+    let max_size = max_backups_size(state, world).await?;
+    for removed in prune(world.id, CONFIG.backups.retention, max_size)? {
+        info!("removed old backup {} of world {}", removed.name, world.id);
+    }
+
+    Ok(backup)
+}
+
+/// runs forever, backing up worlds whose `backup_schedule` is due
+pub async fn run_scheduler(state: AppState) {
+    let mut interval = tokio::time::interval(SCHEDULER_INTERVAL);
+    let mut last_check = Utc::now();
+    loop {
+        interval.tick().await;
+        let now = Utc::now();
+
+        let worlds = match state.database.get_all::<World>(None).await {
+            Ok(worlds) => worlds,
+            Err(err) => {
+                error!("could not get worlds for scheduled backups: {err}");
+                continue;
+            }
+        };
+
+        for world in worlds {
+            let Some(schedule) = &world.backup_schedule else {
+                continue;
+            };
+            let schedule = match parse_schedule(schedule) {
+                Ok(schedule) => schedule,
+                Err(err) => {
+                    warn!("world {} has an invalid backup schedule: {err}", world.id);
+                    continue;
+                }
+            };
+            if schedule.after(&last_check).next().is_some_and(|next| next <= now) {
+                debug!("running scheduled backup of world {}", world.id);
+                tokio::spawn({
+                    let state = state.clone();
+                    async move {
+                        if let Err(err) = backup_world(&state, &world).await {
+                            error!("scheduled backup of world {} failed: {err}", world.id);
+                        }
+                    }
+                });
+            }
+        }
+
+        last_check = now;
+    }
+}
+
+#[test]
+fn backup_test() {
+    let world_id = Id::new_random();
+    let directory = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(directory.join("world")).unwrap();
+    fs::write(directory.join("world/level.dat"), "level").unwrap();
+
+    let first = create(world_id, &directory).unwrap();
+    std::thread::sleep(Duration::from_millis(10));
+    let second = create(world_id, &directory).unwrap();
+    assert!(is_valid_name(&first.name));
+    assert_eq!(list(world_id).unwrap(), vec![second.clone(), first.clone()]);
+
+    assert_eq!(prune(world_id, 1, None).unwrap(), vec![first]);
+    // the newest backup is kept even if it is over the size limit
+    assert!(prune(world_id, 1, Some(0)).unwrap().is_empty());
+    assert_eq!(list(world_id).unwrap(), vec![second.clone()]);
+    assert_eq!(path(world_id, &second.name), Some(world_backups_dir(world_id).join(&second.name)));
+    assert!(remove(world_id, &second.name).unwrap());
+    assert!(!remove(world_id, &second.name).unwrap());
+
+    assert_eq!(path(world_id, "../../config.toml"), None);
+    assert!(!is_valid_name("../../config.tar.gz"));
+    assert!(!is_valid_name("backup.zip"));
+    assert!(parse_schedule("0 4 * * *").is_ok());
+    assert!(parse_schedule("every day").is_err());
+
+    fs::remove_dir_all(directory).unwrap();
+    fs::remove_dir_all(world_backups_dir(world_id)).unwrap();
+}
@@ -0,0 +1,160 @@
+use crate::api::serve::AppState;
+use crate::config::{CONFIG, InstanceLimits};
+use crate::database::DatabaseError;
+use crate::database::objects::World;
+use crate::minecraft::server::MinecraftServerStatus;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// how long the usage is reused for, so checking it doesn't query every world each time one is enabled
+const USAGE_CACHE_TIME: Duration = Duration::from_secs(5);
+
+static USAGE: Lazy<Mutex<Option<(Instant, InstanceUsage)>>> = Lazy::new(|| Mutex::new(None));
+
+/// what the whole instance uses of the [`InstanceLimits`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct InstanceUsage {
+    pub worlds: i64,
+    /// the worlds whose servers are running
+    pub running_worlds: i64,
+    /// the memory allocated to the enabled worlds, in MiB
+    pub allocated_memory: i64,
+}
+
+impl InstanceUsage {
+    /// whether one more world can be created
+    pub fn check_create(&self, limits: &InstanceLimits) -> Result<(), DatabaseError> {
+        if let Some(max) = limits.max_worlds
+            && self.worlds >= i64::from(max)
+        {
+            return Err(capacity_reached(&format!("there are already {max} worlds")));
+        }
+        Ok(())
+    }
+
+    /// whether a world can be started (if `starting`) and have `added_memory` more MiB allocated than it had
+    pub fn check_enable(&self, limits: &InstanceLimits, starting: bool, added_memory: i64) -> Result<(), DatabaseError> {
+        if starting
+            && let Some(max) = limits.max_running_worlds
+            && self.running_worlds >= i64::from(max)
+        {
+            return Err(capacity_reached(&format!("{max} worlds are already running")));
+        }
+        if added_memory > 0
+            && let Some(max) = limits.max_memory
+            && self.allocated_memory + added_memory > i64::from(max)
+        {
+            return Err(capacity_reached(&format!(
+                "{} of {max} MiB are already allocated",
+                self.allocated_memory
+            )));
+        }
+        Ok(())
+    }
+
+    /// counts a world that was just enabled with `memory` MiB, for checking several worlds enabled at once
+    pub fn add_enabled(&mut self, memory: i64) {
+        self.running_worlds += 1;
+        self.allocated_memory += memory;
+    }
+}
+
+fn capacity_reached(reason: &str) -> DatabaseError {
+    DatabaseError::Unprocessable(format!("instance capacity reached: {reason}"))
+}
+
+/// what the instance uses right now, counted in the database and the running servers
+pub async fn current_usage(state: &AppState) -> Result<InstanceUsage, DatabaseError> {
+    let worlds = state.database.count::<World>().await?;
+    let allocated_memory = state
+        .database
+        .sum_where::<World, _>("allocated_memory", "enabled", true)
+        .await?;
+    let mut running_worlds = 0;
+    for server in state.servers.get_all_servers().await {
+        if matches!(server.lock().await.status().await, Ok(MinecraftServerStatus::Running)) {
+            running_worlds += 1;
+        }
+    }
+    Ok(InstanceUsage {
+        worlds,
+        running_worlds,
+        allocated_memory,
+    })
+}
+
+/// [`current_usage`], reused for [`USAGE_CACHE_TIME`]. without any limits in the config nothing is counted
+pub async fn usage(state: &AppState) -> Result<InstanceUsage, DatabaseError> {
+    let mut cached = USAGE.lock().await;
+    if let Some((counted_at, usage)) = *cached
+        && counted_at.elapsed() < USAGE_CACHE_TIME
+    {
+        return Ok(usage);
+    }
+    let usage = current_usage(state).await?;
+    *cached = Some((Instant::now(), usage));
+    Ok(usage)
+}
+
+/// makes the next [`usage`] count again, after a world was created or changed
+pub async fn invalidate() {
+    *USAGE.lock().await = None;
+}
+
+/// [`InstanceUsage::check_create`] with the configured limits
+pub async fn check_create(state: &AppState) -> Result<(), DatabaseError> {
+    let limits = &CONFIG.instance;
+    if limits.max_worlds.is_none() {
+        return Ok(());
+    }
+    usage(state).await?.check_create(limits)
+}
+
+/// [`usage`] for checking worlds being enabled. [`None`] if there are no limits on enabling them in the config
+pub async fn enable_usage(state: &AppState) -> Result<Option<InstanceUsage>, DatabaseError> {
+    let limits = &CONFIG.instance;
+    if limits.max_running_worlds.is_none() && limits.max_memory.is_none() {
+        return Ok(None);
+    }
+    usage(state).await.map(Some)
+}
+
+/// [`InstanceUsage::check_enable`] with the configured limits
+pub async fn check_enable(state: &AppState, starting: bool, added_memory: i64) -> Result<(), DatabaseError> {
+    match enable_usage(state).await? {
+        Some(usage) => usage.check_enable(&CONFIG.instance, starting, added_memory),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn capacity_test() {
+    let usage = InstanceUsage {
+        worlds: 10,
+        running_worlds: 3,
+        allocated_memory: 6144,
+    };
+    let unlimited = InstanceLimits::default();
+    assert!(usage.check_create(&unlimited).is_ok());
+    assert!(usage.check_enable(&unlimited, true, 100_000).is_ok());
+
+    let limits = InstanceLimits {
+        max_worlds: Some(10),
+        max_running_worlds: Some(4),
+        max_memory: Some(8192),
+    };
+    assert!(matches!(usage.check_create(&limits), Err(DatabaseError::Unprocessable(reason)) if reason.starts_with("instance capacity reached")));
+    assert!(usage.check_enable(&limits, true, 2048).is_ok());
+    assert!(usage.check_enable(&limits, true, 2049).is_err());
+    // lowering the memory of a world is always allowed
+    assert!(usage.check_enable(&limits, false, -1024).is_ok());
+
+    let mut full = usage;
+    full.add_enabled(1024);
+    assert_eq!(full.allocated_memory, 7168);
+    assert!(full.check_enable(&limits, true, 0).is_err());
+    // a running world can still change its memory
+    assert!(full.check_enable(&limits, false, 1024).is_ok());
+}
@@ -0,0 +1,243 @@
+//! datapacks installed in a world's `datapacks` directory. Minecraft refers to them as `file/{name}`, where the name
+//! is the name of the directory or zip file
+
+use crate::minecraft::nbt;
+use crate::minecraft::nbt::Tag;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// `pack.mcmeta` files larger than this are not read
+const MAX_MCMETA_SIZE: u64 = 64 * 1024;
+
+pub const ENABLED_PACKS_PROPERTY: &str = "initial-enabled-packs";
+pub const DISABLED_PACKS_PROPERTY: &str = "initial-disabled-packs";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Datapack {
+    /// name of the directory or zip file in the `datapacks` directory
+    pub name: String,
+    /// how Minecraft refers to the pack, like "file/my-pack.zip"
+    pub id: String,
+    pub zip: bool,
+    pub description: Option<String>,
+    pub pack_format: Option<i64>,
+    /// whether the pack is enabled, according to `level.dat` or, if the world wasn't generated yet,
+    /// `server.properties`. [`None`] if it's in neither
+    pub enabled: Option<bool>,
+}
+
+/// the id Minecraft uses for the pack named `name`
+pub fn pack_id(name: &str) -> String {
+    format!("file/{name}")
+}
+
+/// lists the datapacks of the world save in `level_directory`
+pub fn list(level_directory: &Path, properties: &HashMap<String, String>) -> io::Result<Vec<Datapack>> {
+    let entries = match std::fs::read_dir(level_directory.join("datapacks")) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    let enabled = enabled_packs(level_directory, properties);
+
+    let mut packs: Vec<Datapack> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let path = entry.path();
+            // symlinks are skipped, they could point outside of the world
+            let file_type = entry.file_type().ok()?;
+            let zip = file_type.is_file() && name.ends_with(".zip");
+            let mcmeta = if zip {
+                read_zipped_mcmeta(&path)
+            } else if file_type.is_dir() {
+                read_mcmeta(&path.join("pack.mcmeta"))
+            } else {
+                return None;
+            };
+            let id = pack_id(&name);
+            let pack = mcmeta.as_ref().and_then(|mcmeta| mcmeta.get("pack"));
+            Some(Datapack {
+                enabled: enabled.get(&id).copied(),
+                description: pack.and_then(|pack| pack.get("description")).map(description),
+                pack_format: pack
+                    .and_then(|pack| pack.get("pack_format"))
+                    .and_then(serde_json::Value::as_i64),
+                name,
+                id,
+                zip,
+            })
+        })
+        .collect();
+    packs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(packs)
+}
+
+/// the path of the datapack called `name`. [`None`] if there is no such pack, or the name points outside of the
+/// `datapacks` directory
+pub fn resolve(level_directory: &Path, name: &str) -> Option<PathBuf> {
+    let mut components = Path::new(name).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return None;
+    }
+    let path = level_directory.join("datapacks").join(name);
+    let metadata = std::fs::symlink_metadata(&path).ok()?;
+    (metadata.is_dir() || (metadata.is_file() && name.ends_with(".zip"))).then_some(path)
+}
+
+/// moves the pack to `initial-enabled-packs` or `initial-disabled-packs`, so a regenerated world starts with it in
+/// the same state
+pub fn set_enabled_property(properties: &mut HashMap<String, String>, id: &str, enabled: bool) {
+    let (add, remove) = if enabled {
+        (ENABLED_PACKS_PROPERTY, DISABLED_PACKS_PROPERTY)
+    } else {
+        (DISABLED_PACKS_PROPERTY, ENABLED_PACKS_PROPERTY)
+    };
+
+    let list = |key: &str, with_id: bool| {
+        let default = if key == ENABLED_PACKS_PROPERTY { "vanilla" } else { "" };
+        let mut packs: Vec<&str> = properties
+            .get(key)
+            .map_or(default, String::as_str)
+            .split(',')
+            .map(str::trim)
+            .filter(|pack| !pack.is_empty() && *pack != id)
+            .collect();
+        if with_id {
+            packs.push(id);
+        }
+        packs.join(",")
+    };
+    let added = list(add, true);
+    let removed = list(remove, false);
+    properties.insert(add.to_string(), added);
+    properties.insert(remove.to_string(), removed);
+}
+
+/// the console command enabling or disabling the pack
+pub fn command(id: &str, enabled: bool) -> String {
+    let action = if enabled { "enable" } else { "disable" };
+    format!(
+        "datapack {action} \"{}\"\n",
+        id.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// which packs are enabled. `level.dat` has the state of a generated world, otherwise the properties are used
+fn enabled_packs(level_directory: &Path, properties: &HashMap<String, String>) -> HashMap<String, bool> {
+    let level_dat = std::fs::read(level_directory.join("level.dat"))
+        .ok()
+        .and_then(|data| nbt::read_file(&data).ok());
+    let data_packs = level_dat
+        .as_ref()
+        .and_then(|root| root.get("Data"))
+        .and_then(|data| data.get("DataPacks"));
+
+    let mut packs = HashMap::new();
+    if let Some(data_packs) = data_packs {
+        for (key, enabled) in [("Disabled", false), ("Enabled", true)] {
+            if let Some(Tag::List(list)) = data_packs.get(key) {
+                packs.extend(list.iter().filter_map(Tag::as_str).map(|id| (id.to_string(), enabled)));
+            }
+        }
+    } else {
+        for (key, enabled) in [(DISABLED_PACKS_PROPERTY, false), (ENABLED_PACKS_PROPERTY, true)] {
+            if let Some(list) = properties.get(key) {
+                packs.extend(
+                    list.split(',')
+                        .map(str::trim)
+                        .filter(|id| !id.is_empty())
+                        .map(|id| (id.to_string(), enabled)),
+                );
+            }
+        }
+    }
+    packs
+}
+
+fn read_mcmeta(path: &Path) -> Option<serde_json::Value> {
+    let mut contents = String::new();
+    File::open(path)
+        .ok()?
+        .take(MAX_MCMETA_SIZE)
+        .read_to_string(&mut contents)
+        .ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+fn read_zipped_mcmeta(path: &Path) -> Option<serde_json::Value> {
+    let mut archive = zip::ZipArchive::new(File::open(path).ok()?).ok()?;
+    let file = archive.by_name("pack.mcmeta").ok()?;
+    let mut contents = String::new();
+    file.take(MAX_MCMETA_SIZE).read_to_string(&mut contents).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// the description is a text component, which can be a plain string or a JSON object or array of them
+fn description(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => parts.iter().map(description).collect(),
+        serde_json::Value::Object(component) => {
+            let mut text = component
+                .get("text")
+                .and_then(serde_json::Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            if let Some(extra) = component.get("extra") {
+                text.push_str(&description(extra));
+            }
+            text
+        }
+        other => other.to_string(),
+    }
+}
+
+#[test]
+fn datapack_test() {
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    let datapacks = dir.join("datapacks");
+    std::fs::create_dir_all(datapacks.join("tweaks")).unwrap();
+    std::fs::write(
+        datapacks.join("tweaks/pack.mcmeta"),
+        r#"{"pack": {"pack_format": 48, "description": [{"text": "small "}, "tweaks"]}}"#,
+    )
+    .unwrap();
+    std::fs::write(datapacks.join("notes.txt"), b"").unwrap();
+
+    let mut properties = HashMap::new();
+    set_enabled_property(&mut properties, "file/tweaks", false);
+    assert_eq!(properties[ENABLED_PACKS_PROPERTY], "vanilla");
+    assert_eq!(properties[DISABLED_PACKS_PROPERTY], "file/tweaks");
+
+    assert_eq!(
+        list(&dir, &properties).unwrap(),
+        vec![Datapack {
+            name: String::from("tweaks"),
+            id: String::from("file/tweaks"),
+            zip: false,
+            description: Some(String::from("small tweaks")),
+            pack_format: Some(48),
+            enabled: Some(false),
+        }]
+    );
+
+    set_enabled_property(&mut properties, "file/tweaks", true);
+    assert_eq!(properties[ENABLED_PACKS_PROPERTY], "vanilla,file/tweaks");
+    assert_eq!(properties[DISABLED_PACKS_PROPERTY], "");
+
+    assert!(resolve(&dir, "tweaks").is_some());
+    assert!(resolve(&dir, "notes.txt").is_none());
+    assert!(resolve(&dir, "../datapacks").is_none());
+    assert!(resolve(&dir, "tweaks/pack.mcmeta").is_none());
+    assert_eq!(command("file/a \"b\"", true), "datapack enable \"file/a \\\"b\\\"\"\n");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,299 @@
+//! commands run on the host around the starts and stops of the servers, for integrating with other systems, like
+//! updating DNS records or registering the servers with monitoring. they are configured in
+//! [`crate::config::HookConfig`]
+//!
+//! `%id%`, `%hostname%` and `%port%` in the commands are replaced with the world's id, hostname and port, quoted for
+//! the shell. the commands get the same values in `MCMANAGER_WORLD_ID`, `MCMANAGER_HOSTNAME` and `MCMANAGER_PORT`,
+//! but none of the environment of MCManager other than `PATH`, so secrets like the database password don't leak to
+//! them
+
+use crate::config::{CONFIG, HookConfig};
+use crate::database::types::Id;
+use color_eyre::eyre::{bail, eyre};
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use subprocess::{Exec, ExitStatus, Redirection};
+
+/// the placeholders a hook can use
+const PLACEHOLDERS: [&str; 3] = ["id", "hostname", "port"];
+/// how much of the output of a hook is logged
+const MAX_LOGGED_OUTPUT: usize = 4096;
+
+/// when a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// before the server process is launched, once the server has a port
+    BeforeStart,
+    /// after the server process was launched
+    AfterStart,
+    /// after the server process exited
+    AfterStop,
+}
+
+impl HookEvent {
+    pub const ALL: [HookEvent; 3] = [HookEvent::BeforeStart, HookEvent::AfterStart, HookEvent::AfterStop];
+
+    /// the name of the hook in the config
+    pub fn name(self) -> &'static str {
+        match self {
+            HookEvent::BeforeStart => "before_start",
+            HookEvent::AfterStart => "after_start",
+            HookEvent::AfterStop => "after_stop",
+        }
+    }
+
+    /// the command template configured for the event, if there is one
+    pub fn template(self, config: &HookConfig) -> Option<&str> {
+        match self {
+            HookEvent::BeforeStart => config.before_start.as_deref(),
+            HookEvent::AfterStart => config.after_start.as_deref(),
+            HookEvent::AfterStop => config.after_stop.as_deref(),
+        }
+        .filter(|template| !template.trim().is_empty())
+    }
+}
+
+/// the server a hook runs for
+#[derive(Debug, Clone)]
+pub struct HookContext {
+    pub id: Id,
+    pub hostname: String,
+    pub port: Option<u16>,
+}
+
+impl HookContext {
+    fn value(&self, placeholder: &str) -> String {
+        match placeholder {
+            "id" => self.id.to_string(),
+            "hostname" => self.hostname.clone(),
+            "port" => self.port.map(|port| port.to_string()).unwrap_or_default(),
+            _ => unreachable!("unknown placeholder {placeholder}"),
+        }
+    }
+}
+
+/// the placeholders in the template, in order. fails on a `%` that doesn't start a known placeholder, `%%` is a
+/// literal `%`
+fn placeholders(template: &str) -> color_eyre::Result<Vec<(usize, usize, &str)>> {
+    let mut found = Vec::new();
+    let mut rest = 0;
+    while let Some(start) = template[rest..].find('%').map(|offset| rest + offset) {
+        let Some(end) = template[start + 1..].find('%').map(|offset| start + 1 + offset) else {
+            bail!("unmatched % at {start}");
+        };
+        let name = &template[start + 1..end];
+        if !name.is_empty() && !PLACEHOLDERS.contains(&name) {
+            bail!("unknown placeholder %{name}%, the placeholders are %id%, %hostname% and %port%");
+        }
+        found.push((start, end + 1, name));
+        rest = end + 1;
+    }
+    Ok(found)
+}
+
+/// checks that the template only uses known placeholders
+pub fn validate(template: &str) -> color_eyre::Result<()> {
+    if template.contains('\0') {
+        bail!("the command contains a null byte");
+    }
+    placeholders(template).map(|_| ())
+}
+
+/// makes sure every hook in `world.hooks` is valid, so a typo doesn't only show up when a server starts
+pub fn validate_hooks() -> color_eyre::Result<()> {
+    for event in HookEvent::ALL {
+        if let Some(template) = event.template(&CONFIG.world.hooks) {
+            validate(template).map_err(|err| eyre!("world.hooks.{}: {err}", event.name()))?;
+        }
+    }
+    Ok(())
+}
+
+/// the command with the placeholders replaced with the quoted values
+pub fn render(template: &str, context: &HookContext) -> color_eyre::Result<String> {
+    let mut command = String::with_capacity(template.len());
+    let mut rest = 0;
+    for (start, end, name) in placeholders(template)? {
+        command.push_str(&template[rest..start]);
+        if name.is_empty() {
+            command.push('%');
+        } else {
+            command.push_str(&format!("'{}'", context.value(name).replace('\'', "'\\''")));
+        }
+        rest = end;
+    }
+    command.push_str(&template[rest..]);
+    Ok(command)
+}
+
+/// runs the hook in `directory`, killing it after `timeout`. returns its output, with stderr merged into stdout.
+/// fails if it can't be started, times out, or exits with a non-zero code
+pub fn run(template: &str, context: &HookContext, directory: &Path, timeout: Duration) -> color_eyre::Result<String> {
+    let command = render(template, context)?;
+    let mut exec = Exec::shell(&command)
+        .cwd(directory)
+        .stdin(Redirection::None)
+        .stdout(Redirection::Pipe)
+        .stderr(Redirection::Merge)
+        .env_clear()
+        .env("MCMANAGER_WORLD_ID", context.id.to_string())
+        .env("MCMANAGER_HOSTNAME", &context.hostname)
+        .env("MCMANAGER_PORT", context.value("port"));
+    if let Some(path) = std::env::var_os("PATH") {
+        exec = exec.env("PATH", path);
+    }
+    let mut process = exec.popen().map_err(|err| eyre!("could not run `{command}`: {err}"))?;
+
+    let output = match process.communicate_start(None).limit_time(timeout).read() {
+        Ok((output, _)) => output.unwrap_or_default(),
+        Err(err) => {
+            let _ = process.kill();
+            let _ = process.wait();
+            let output = String::from_utf8_lossy(err.capture.0.as_deref().unwrap_or_default()).into_owned();
+            if err.kind() == std::io::ErrorKind::TimedOut {
+                bail!("`{command}` did not finish in {} seconds, output: {}", timeout.as_secs(), output.trim());
+            }
+            bail!("could not read the output of `{command}`: {}", err.error);
+        }
+    };
+    let output = String::from_utf8_lossy(&output).into_owned();
+    // the output is closed, but the process may not have exited yet
+    let status = match process.wait_timeout(timeout)? {
+        Some(status) => status,
+        None => {
+            let _ = process.kill();
+            bail!("`{command}` did not exit in {} seconds", timeout.as_secs());
+        }
+    };
+    match status {
+        ExitStatus::Exited(0) => Ok(output),
+        status => bail!("`{command}` failed with {status:?}, output: {}", output.trim()),
+    }
+}
+
+/// runs the hook configured for the event, if there is one, logging its output. a failing hook is logged as a
+/// warning, and its error is returned only if it's a [`HookEvent::BeforeStart`] hook and [`HookConfig::fatal`] is set
+pub async fn run_hook(event: HookEvent, context: HookContext, directory: &Path) -> color_eyre::Result<()> {
+    let config = &CONFIG.world.hooks;
+    let Some(template) = event.template(config) else {
+        return Ok(());
+    };
+    debug!("running the {} hook of {}", event.name(), context.id);
+
+    let result = {
+        let (template, context, directory) = (template.to_string(), context.clone(), directory.to_path_buf());
+        let timeout = Duration::from_secs(config.timeout);
+        tokio::task::spawn_blocking(move || run(&template, &context, &directory, timeout)).await?
+    };
+    match result {
+        Ok(output) => {
+            let output = output.trim();
+            if output.is_empty() {
+                info!("ran the {} hook of {}", event.name(), context.id);
+            } else {
+                info!(
+                    "ran the {} hook of {}: {}",
+                    event.name(),
+                    context.id,
+                    truncate(output, MAX_LOGGED_OUTPUT)
+                );
+            }
+            Ok(())
+        }
+        Err(err) => {
+            warn!("the {} hook of {} failed: {}", event.name(), context.id, truncate(&err.to_string(), MAX_LOGGED_OUTPUT));
+            if event == HookEvent::BeforeStart && config.fatal {
+                return Err(err);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// [`run_hook`] in a task of its own, for the hooks nothing waits for. the server's lock isn't held while they run,
+/// so a slow hook doesn't hold up the requests to the server or the polling of the others
+pub fn spawn_hook(event: HookEvent, context: HookContext, directory: PathBuf) {
+    if event.template(&CONFIG.world.hooks).is_none() {
+        return;
+    }
+    tokio::task::spawn(async move {
+        let id = context.id;
+        if let Err(err) = run_hook(event, context, &directory).await {
+            warn!("could not run the {} hook of {id}: {err}", event.name());
+        }
+    });
+}
+
+fn truncate(text: &str, length: usize) -> &str {
+    match text.char_indices().nth(length) {
+        Some((index, _)) => &text[..index],
+        None => text,
+    }
+}
+
+#[test]
+fn render_test() {
+    use pretty_assertions::assert_eq;
+
+    let context = HookContext {
+        id: "AAAAAAAA".parse().unwrap(),
+        hostname: String::from("it's"),
+        port: Some(25570),
+    };
+    assert_eq!(
+        render("register %hostname% %port% --id=%id% 100%%", &context).unwrap(),
+        "register 'it'\\''s' '25570' --id='AAAAAAAA' 100%"
+    );
+    assert_eq!(render("no placeholders", &context).unwrap(), "no placeholders");
+    assert_eq!(
+        render("%port%", &HookContext { port: None, ..context }).unwrap(),
+        "''"
+    );
+
+    assert!(validate("echo %id% %hostname% %port%").is_ok());
+    assert!(validate("echo %world%").is_err());
+    assert!(validate("echo 100%").is_err());
+}
+
+#[tokio::test]
+async fn run_test() {
+    use pretty_assertions::assert_eq;
+
+    let base = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&base).unwrap();
+    let context = HookContext {
+        id: "AAAAAAAA".parse().unwrap(),
+        hostname: String::from("survival; touch pwned"),
+        port: Some(25570),
+    };
+    let timeout = Duration::from_secs(5);
+
+    let output = run(
+        "echo %id% %hostname% %port% \"$MCMANAGER_PORT\" \"$(pwd)\" > hook.txt; echo done",
+        &context,
+        &base,
+        timeout,
+    )
+    .unwrap();
+    assert_eq!(output, "done\n");
+    assert_eq!(
+        std::fs::read_to_string(base.join("hook.txt")).unwrap(),
+        format!("AAAAAAAA survival; touch pwned 25570 25570 {}\n", base.canonicalize().unwrap().display())
+    );
+    assert!(!base.join("pwned").exists());
+
+    // the environment of MCManager isn't passed on
+    let output = run("echo \"[$HOME]\"", &context, &base, timeout).unwrap();
+    assert_eq!(output, "[]\n");
+
+    let err = run("echo broken; exit 3", &context, &base, timeout).unwrap_err();
+    assert!(err.to_string().contains("Exited(3)"));
+    assert!(err.to_string().contains("broken"));
+
+    let started = std::time::Instant::now();
+    assert!(run("sleep 10", &context, &base, Duration::from_millis(200)).is_err());
+    assert!(started.elapsed() < Duration::from_secs(5));
+
+    std::fs::remove_dir_all(&base).unwrap();
+}
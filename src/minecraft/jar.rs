@@ -0,0 +1,224 @@
+//! checks run on uploaded jars before they are stored
+
+use crate::config::CONFIG;
+use color_eyre::eyre::eyre;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use subprocess::{Exec, ExitStatus, Redirection};
+
+#[derive(Debug)]
+pub enum ScanError {
+    /// the jar is not acceptable, with a reason that can be shown to the user
+    Rejected(String),
+    /// the scan itself failed
+    Internal(color_eyre::eyre::Error),
+}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanError::Rejected(reason) => write!(f, "rejected: {reason}"),
+            ScanError::Internal(err) => write!(f, "scan failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+/// a check an uploaded jar has to pass. scanners are blocking
+pub trait JarScanner: Send + Sync {
+    fn scan(&self, path: &Path) -> Result<(), ScanError>;
+}
+
+impl<F: Fn(&Path) -> Result<(), ScanError> + Send + Sync> JarScanner for F {
+    fn scan(&self, path: &Path) -> Result<(), ScanError> {
+        self(path)
+    }
+}
+
+/// makes sure the file is a zip archive with a manifest, so executables and other files can't pose as jars
+pub struct StructureScanner;
+
+impl JarScanner for StructureScanner {
+    fn scan(&self, path: &Path) -> Result<(), ScanError> {
+        let mut file = File::open(path).map_err(|err| ScanError::Internal(err.into()))?;
+
+        let mut magic = [0u8; 4];
+        if file.read_exact(&mut magic).is_err() || magic != *b"PK\x03\x04" {
+            return Err(ScanError::Rejected(String::from("the file is not a jar")));
+        }
+
+        let mut archive = zip::ZipArchive::new(file)
+            .map_err(|err| ScanError::Rejected(format!("the jar is corrupted: {err}")))?;
+        if archive.by_name("META-INF/MANIFEST.MF").is_err() {
+            return Err(ScanError::Rejected(String::from(
+                "the jar has no META-INF/MANIFEST.MF",
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// files that mark a jar as a mod for a loader. the first one is the one the loader reads, the rest are accepted as well
+fn loader_markers(loader: &str) -> Option<&'static [&'static str]> {
+    match loader.to_ascii_lowercase().as_str() {
+        "fabric" => Some(&["fabric.mod.json"]),
+        // quilt can load fabric mods
+        "quilt" => Some(&["quilt.mod.json", "fabric.mod.json"]),
+        "forge" => Some(&["META-INF/mods.toml", "mcmod.info"]),
+        "neoforge" => Some(&["META-INF/neoforge.mods.toml", "META-INF/mods.toml"]),
+        _ => None,
+    }
+}
+
+/// makes sure the jar is a mod for the given loader, by looking for the loader's metadata file
+pub struct LoaderScanner {
+    /// name of the [`crate::database::objects::ModLoader`]
+    pub loader: String,
+}
+
+impl JarScanner for LoaderScanner {
+    fn scan(&self, path: &Path) -> Result<(), ScanError> {
+        // loaders that we don't know the markers of are not checked
+        let Some(markers) = loader_markers(&self.loader) else {
+            return Ok(());
+        };
+
+        let file = File::open(path).map_err(|err| ScanError::Internal(err.into()))?;
+        let archive = zip::ZipArchive::new(file)
+            .map_err(|err| ScanError::Rejected(format!("the jar is corrupted: {err}")))?;
+        let contains = |name: &str| archive.file_names().any(|file| file == name);
+
+        if markers.iter().any(|marker| contains(marker)) {
+            return Ok(());
+        }
+
+        let other_loader = ["Fabric", "Quilt", "Forge", "NeoForge"].into_iter().find(|loader| {
+            loader_markers(loader).is_some_and(|markers| contains(markers[0]))
+        });
+        Err(ScanError::Rejected(match other_loader {
+            Some(other_loader) => format!(
+                "this is a {other_loader} mod, but the version uses {}",
+                self.loader
+            ),
+            None => format!(
+                "this is not a {} mod, it has no {}",
+                self.loader, markers[0]
+            ),
+        }))
+    }
+}
+
+/// runs an external command, like a virus scanner, on the jar. `%file%` in the command is replaced with the path to
+/// the jar. a non-zero exit code rejects the jar
+pub struct CommandScanner {
+    pub command: String,
+}
+
+impl JarScanner for CommandScanner {
+    fn scan(&self, path: &Path) -> Result<(), ScanError> {
+        let quoted = format!("'{}'", path.display().to_string().replace('\'', "'\\''"));
+        let command = self.command.replace("%file%", &quoted);
+
+        let capture = Exec::shell(&command)
+            .stdout(Redirection::Pipe)
+            .stderr(Redirection::Merge)
+            .capture()
+            .map_err(|err| ScanError::Internal(eyre!("could not run `{command}`: {err}")))?;
+
+        match capture.exit_status {
+            ExitStatus::Exited(0) => Ok(()),
+            ExitStatus::Exited(_) => {
+                let output = capture.stdout_str();
+                Err(ScanError::Rejected(format!(
+                    "the scan did not pass: {}",
+                    output.trim()
+                )))
+            }
+            status => Err(ScanError::Internal(eyre!(
+                "`{command}` did not exit normally: {status:?}"
+            ))),
+        }
+    }
+}
+
+/// the scanners configured in the config. the structure check is always first, followed by the loader check if the
+/// jar is a mod for the loader named `loader`
+pub fn scanners(loader: Option<&str>) -> Vec<Box<dyn JarScanner>> {
+    let mut scanners: Vec<Box<dyn JarScanner>> = vec![Box::new(StructureScanner)];
+    if let Some(loader) = loader {
+        scanners.push(Box::new(LoaderScanner {
+            loader: loader.to_string(),
+        }));
+    }
+    if let Some(command) = &CONFIG.uploads.scan_command
+        && !command.trim().is_empty()
+    {
+        scanners.push(Box::new(CommandScanner {
+            command: command.clone(),
+        }));
+    }
+    scanners
+}
+
+/// runs all the scanners on the jar, stopping at the first one that fails
+pub fn scan(path: &Path, scanners: &[Box<dyn JarScanner>]) -> Result<(), ScanError> {
+    for scanner in scanners {
+        scanner.scan(path)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn scan_test() {
+    use std::io::Write;
+
+    let base = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&base).unwrap();
+
+    let jar = base.join("mod.jar");
+    let mut writer = zip::ZipWriter::new(File::create(&jar).unwrap());
+    writer
+        .start_file("META-INF/MANIFEST.MF", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    writer.write_all(b"Manifest-Version: 1.0\n").unwrap();
+    writer
+        .start_file("fabric.mod.json", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    writer.write_all(b"{}").unwrap();
+    writer.finish().unwrap();
+
+    let fake = base.join("fake.jar");
+    std::fs::write(&fake, b"MZ\x90\x00 definitely not a virus").unwrap();
+
+    let scanners: Vec<Box<dyn JarScanner>> = vec![Box::new(StructureScanner)];
+    assert!(scan(&jar, &scanners).is_ok());
+    assert!(matches!(scan(&fake, &scanners), Err(ScanError::Rejected(_))));
+
+    let loader = |loader: &str| LoaderScanner {
+        loader: loader.to_string(),
+    };
+    assert!(loader("Fabric").scan(&jar).is_ok());
+    assert!(loader("Quilt").scan(&jar).is_ok());
+    assert!(loader("Vanilla").scan(&jar).is_ok());
+    match loader("Forge").scan(&jar) {
+        Err(ScanError::Rejected(reason)) => {
+            assert_eq!(reason, "this is a Fabric mod, but the version uses Forge");
+        }
+        result => panic!("expected a rejection, got {result:?}"),
+    }
+
+    let rejecting = CommandScanner {
+        command: String::from("grep -q forbidden %file% && exit 1 || exit 0"),
+    };
+    std::fs::write(base.join("it's forbidden.jar"), b"forbidden").unwrap();
+    assert!(rejecting.scan(&jar).is_ok());
+    assert!(matches!(
+        rejecting.scan(&base.join("it's forbidden.jar")),
+        Err(ScanError::Rejected(_))
+    ));
+
+    std::fs::remove_dir_all(base).unwrap();
+}
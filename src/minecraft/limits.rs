@@ -0,0 +1,187 @@
+//! CPU and memory limits of the servers. the niceness is set with `nice`, the CPU and memory limits by placing the
+//! server in its own cgroup (v2, Linux only). limits that can't be applied are skipped with a warning
+
+use crate::config::CONFIG;
+use crate::database::objects::{Group, World};
+use crate::database::types::Id;
+use log::warn;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// length of the period `cpu.max` is measured in, in microseconds
+const CPU_PERIOD: u64 = 100_000;
+
+/// the limits a server runs with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ResourceLimits {
+    /// niceness of the server process
+    pub nice: Option<i32>,
+    /// CPU time the server can use, in percent of one core
+    pub cpu_limit: Option<u32>,
+    /// memory the server can use, in MiB
+    pub memory_limit: Option<u32>,
+}
+
+impl ResourceLimits {
+    /// the limits of `world`, owned by a member of `group`. the group's values override the config
+    pub fn for_world(world: &World, group: &Group) -> Self {
+        let memory_limit = CONFIG.world.memory_overhead.map(|overhead| {
+            u32::try_from(world.allocated_memory)
+                .unwrap_or_default()
+                .saturating_add(overhead)
+        });
+        Self {
+            nice: group.nice.or(CONFIG.world.nice),
+            cpu_limit: group
+                .cpu_limit
+                .and_then(|cpu_limit| u32::try_from(cpu_limit).ok())
+                .or(CONFIG.world.cpu_limit),
+            memory_limit,
+        }
+    }
+
+    /// wraps the launch command so the server runs with the limits. the shell moves itself into the world's cgroup
+    /// before executing the command, so the server can't spawn anything outside of it
+    pub fn wrap_command(&self, world_id: Id, command: &str) -> String {
+        let mut prefix = String::new();
+        if let Some(root) = &CONFIG.world.cgroup
+            && (self.cpu_limit.is_some() || self.memory_limit.is_some())
+        {
+            if cfg!(target_os = "linux") {
+                match self.create_cgroup(root, world_id) {
+                    Ok(cgroup) => {
+                        prefix = format!(
+                            "echo $$ > {} && ",
+                            quote(&cgroup.join("cgroup.procs"))
+                        );
+                    }
+                    Err(err) => warn!(
+                        "could not create the cgroup of {world_id} in {}, it will run without CPU and memory limits: {err}",
+                        root.display()
+                    ),
+                }
+            } else {
+                warn!("cgroups are only supported on Linux, {world_id} will run without CPU and memory limits");
+            }
+        }
+
+        match self.nice {
+            Some(nice) => format!("{prefix}exec nice -n {nice} {command}"),
+            None if !prefix.is_empty() => format!("{prefix}exec {command}"),
+            None => command.to_string(),
+        }
+    }
+
+    /// creates (or updates) the cgroup of the world, returning its directory
+    fn create_cgroup(&self, root: &Path, world_id: Id) -> std::io::Result<PathBuf> {
+        let cgroup = cgroup_directory(root, world_id);
+        std::fs::create_dir_all(&cgroup)?;
+        std::fs::write(cgroup.join("cpu.max"), self.cpu_max())?;
+        std::fs::write(cgroup.join("memory.max"), self.memory_max())?;
+        Ok(cgroup)
+    }
+
+    /// value of the `cpu.max` cgroup file, like "200000 100000"
+    fn cpu_max(&self) -> String {
+        match self.cpu_limit {
+            Some(cpu_limit) => format!("{} {CPU_PERIOD}", u64::from(cpu_limit) * CPU_PERIOD / 100),
+            None => format!("max {CPU_PERIOD}"),
+        }
+    }
+
+    /// value of the `memory.max` cgroup file, in bytes
+    fn memory_max(&self) -> String {
+        match self.memory_limit {
+            Some(memory_limit) => (u64::from(memory_limit) * 1024 * 1024).to_string(),
+            None => String::from("max"),
+        }
+    }
+}
+
+/// the directory of the world's cgroup in `root`
+fn cgroup_directory(root: &Path, world_id: Id) -> PathBuf {
+    root.join(format!("world-{world_id}"))
+}
+
+/// removes the cgroup of the world, once its server exited or the world was deleted. a cgroup with processes in it
+/// can't be removed, so it's left for the next time
+pub fn remove_cgroup(world_id: Id) {
+    if let Some(root) = &CONFIG.world.cgroup
+        && let Err(err) = remove_cgroup_in(root, world_id)
+    {
+        warn!("could not remove the cgroup of {world_id} in {}: {err}", root.display());
+    }
+}
+
+fn remove_cgroup_in(root: &Path, world_id: Id) -> std::io::Result<()> {
+    // the files in it aren't real, removing the directory removes the cgroup
+    match std::fs::remove_dir(cgroup_directory(root, world_id)) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// how much memory the process uses (its resident set), in MiB. [`None`] if it can't be read, like outside of Linux
+pub fn process_memory(pid: u32) -> Option<u64> {
+    parse_resident_memory(&std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?)
+}
+
+/// the `VmRSS` of a `/proc/{pid}/status` file, in MiB
+fn parse_resident_memory(status: &str) -> Option<u64> {
+    let line = status.lines().find_map(|line| line.strip_prefix("VmRSS:"))?;
+    let kib: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kib / 1024)
+}
+
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.display().to_string().replace('\'', "'\\''"))
+}
+
+#[test]
+fn resource_limits_test() {
+    let limits = ResourceLimits {
+        nice: Some(10),
+        cpu_limit: Some(150),
+        memory_limit: Some(1536),
+    };
+    assert_eq!(limits.cpu_max(), "150000 100000");
+    assert_eq!(limits.memory_max(), "1610612736");
+    assert_eq!(ResourceLimits::default().cpu_max(), "max 100000");
+    assert_eq!(ResourceLimits::default().memory_max(), "max");
+
+    // without a cgroup configured only the niceness is applied
+    if CONFIG.world.cgroup.is_none() {
+        assert_eq!(
+            limits.wrap_command(Id::default(), "java -jar server.jar"),
+            "exec nice -n 10 java -jar server.jar"
+        );
+        assert_eq!(
+            ResourceLimits::default().wrap_command(Id::default(), "java -jar server.jar"),
+            "java -jar server.jar"
+        );
+    }
+}
+
+#[test]
+fn remove_cgroup_test() {
+    let root = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    let world_id = Id::new_random();
+    std::fs::create_dir_all(cgroup_directory(&root, world_id)).unwrap();
+
+    remove_cgroup_in(&root, world_id).unwrap();
+    assert!(!cgroup_directory(&root, world_id).exists());
+    // already gone, or never created
+    remove_cgroup_in(&root, world_id).unwrap();
+    std::fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn resident_memory_test() {
+    let status = "Name:\tjava\nVmPeak:\t 5000000 kB\nVmRSS:\t 2097152 kB\nThreads:\t42\n";
+    assert_eq!(parse_resident_memory(status), Some(2048));
+    assert_eq!(parse_resident_memory("Name:\tjava\n"), None);
+    // this process exists on every system /proc does
+    if cfg!(target_os = "linux") {
+        assert!(process_memory(std::process::id()).is_some());
+    }
+}
@@ -0,0 +1,148 @@
+//! fetches the icons of the Modrinth projects mods are linked to, see [`crate::database::objects::Mod::modrinth_id`]
+
+use crate::config::CONFIG;
+use axum::body::Bytes;
+use image::ImageFormat;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// how long a request to Modrinth can take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// how many projects' icons are kept in memory, a mod is usually added for several Minecraft versions
+const ICON_CACHE_SIZE: usize = 64;
+
+static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        // Modrinth asks for a user agent that identifies the application
+        .user_agent(concat!("mcmanager/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("failed to build the Modrinth api client")
+});
+
+/// when the last request was sent, see [`crate::config::ModrinthConfig::request_interval`]
+static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::const_new(None);
+
+/// the icons that were downloaded, by project. [`None`] if the project has no icon
+static ICONS: LazyLock<Mutex<HashMap<String, Option<Icon>>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub type Icon = (Bytes, ImageFormat);
+
+#[derive(Debug, Deserialize)]
+struct Project {
+    icon_url: Option<url::Url>,
+}
+
+/// waits until a request can be sent without going over the rate limit
+async fn wait_turn() {
+    let mut last = LAST_REQUEST.lock().await;
+    let interval = Duration::from_millis(CONFIG.modrinth.request_interval);
+    if let Some(last) = *last {
+        tokio::time::sleep_until(last + interval).await;
+    }
+    *last = Some(Instant::now());
+}
+
+/// the format of the icon, if it's one that can be used as one
+pub fn icon_format(bytes: &[u8]) -> Result<ImageFormat, String> {
+    match image::guess_format(bytes) {
+        Ok(format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP | ImageFormat::Gif | ImageFormat::Bmp)) => {
+            Ok(format)
+        }
+        Ok(format) => Err(format!("the icon is in an unsupported format ({format:?})")),
+        Err(_) => Err(String::from("the icon is not an image")),
+    }
+}
+
+/// downloads the icon, giving up once it gets larger than `max_size`
+async fn download(url: url::Url, max_size: u64) -> Result<Bytes, String> {
+    wait_turn().await;
+    let mut response = CLIENT
+        .get(url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|err| format!("could not download the icon: {err}"))?;
+    if response.content_length().is_some_and(|length| length > max_size) {
+        return Err(String::from("the icon is too large"));
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|err| format!("could not download the icon: {err}"))?
+    {
+        if (bytes.len() + chunk.len()) as u64 > max_size {
+            return Err(String::from("the icon is too large"));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(bytes))
+}
+
+/// the icon of the Modrinth project with the id or slug. [`None`] if there is no such project or it has no icon, the
+/// error is a reason that can be logged
+pub async fn icon(project: &str) -> Result<Option<Icon>, String> {
+    if let Some(icon) = ICONS.lock().await.get(project) {
+        return Ok(icon.clone());
+    }
+
+    // a slug could otherwise point somewhere else in the api
+    if project.is_empty() || !project.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Ok(None);
+    }
+
+    wait_turn().await;
+    let response = CLIENT
+        .get(format!("{}project/{project}", CONFIG.modrinth.api))
+        .send()
+        .await
+        .map_err(|err| format!("could not reach Modrinth: {err}"))?;
+    let icon_url = match response.status() {
+        StatusCode::NOT_FOUND => None,
+        status if !status.is_success() => return Err(format!("Modrinth responded with {status}")),
+        _ => {
+            let body = response
+                .bytes()
+                .await
+                .map_err(|err| format!("could not read the project: {err}"))?;
+            serde_json::from_slice::<Project>(&body)
+                .map_err(|err| format!("Modrinth sent an invalid project: {err}"))?
+                .icon_url
+        }
+    };
+
+    let icon = match icon_url {
+        Some(url) => {
+            let bytes = download(url, CONFIG.modrinth.max_icon_size).await?;
+            let format = icon_format(&bytes)?;
+            Some((bytes, format))
+        }
+        None => None,
+    };
+
+    let mut icons = ICONS.lock().await;
+    if icons.len() >= ICON_CACHE_SIZE {
+        icons.clear();
+    }
+    icons.insert(project.to_string(), icon.clone());
+    Ok(icon)
+}
+
+#[test]
+fn icon_format_test() {
+    let mut png = Vec::new();
+    image::DynamicImage::new_rgb8(4, 4)
+        .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+        .unwrap();
+    assert_eq!(icon_format(&png), Ok(ImageFormat::Png));
+    assert_eq!(icon_format(b"GIF89a\x01\x00\x01\x00"), Ok(ImageFormat::Gif));
+    assert!(icon_format(b"<html></html>").is_err());
+    assert!(icon_format(b"II*\x00").is_err());
+}
@@ -0,0 +1,349 @@
+//! a minimal reader for Minecraft's NBT format, enough to read `level.dat`
+
+use flate2::read::GzDecoder;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+
+/// how deep can compounds and lists be nested, same as Minecraft's limit
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// gets a child of a compound tag
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(value) => Some(*value as i64),
+            Tag::Short(value) => Some(*value as i64),
+            Tag::Int(value) => Some(*value as i64),
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// reads a NBT file, which may or may not be gzip compressed. returns the root tag
+pub fn read_file(data: &[u8]) -> io::Result<Tag> {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        read(&mut GzDecoder::new(data))
+    } else {
+        read(&mut &data[..])
+    }
+}
+
+/// reads a named root tag
+pub fn read(reader: &mut impl Read) -> io::Result<Tag> {
+    let tag_type = read_u8(reader)?;
+    if tag_type == 0 {
+        return Err(invalid_data("the root tag is empty"));
+    }
+    let _name = read_string(reader)?;
+    read_payload(reader, tag_type, 0)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_payload(reader: &mut impl Read, tag_type: u8, depth: usize) -> io::Result<Tag> {
+    if depth > MAX_DEPTH {
+        return Err(invalid_data("NBT is nested too deep"));
+    }
+    Ok(match tag_type {
+        1 => Tag::Byte(read_u8(reader)? as i8),
+        2 => Tag::Short(i16::from_be_bytes(read_array(reader)?)),
+        3 => Tag::Int(i32::from_be_bytes(read_array(reader)?)),
+        4 => Tag::Long(i64::from_be_bytes(read_array(reader)?)),
+        5 => Tag::Float(f32::from_be_bytes(read_array(reader)?)),
+        6 => Tag::Double(f64::from_be_bytes(read_array(reader)?)),
+        7 => {
+            let length = read_length(reader)?;
+            Tag::ByteArray(read_bytes(reader, length)?.into_iter().map(|b| b as i8).collect())
+        }
+        8 => Tag::String(read_string(reader)?),
+        9 => {
+            let item_type = read_u8(reader)?;
+            let length = read_length(reader)?;
+            let mut items = Vec::new();
+            for _ in 0..length {
+                items.push(read_payload(reader, item_type, depth + 1)?);
+            }
+            Tag::List(items)
+        }
+        10 => {
+            let mut map = HashMap::new();
+            loop {
+                let child_type = read_u8(reader)?;
+                if child_type == 0 {
+                    break;
+                }
+                let name = read_string(reader)?;
+                map.insert(name, read_payload(reader, child_type, depth + 1)?);
+            }
+            Tag::Compound(map)
+        }
+        11 => {
+            let length = read_length(reader)?;
+            let mut items = Vec::new();
+            for _ in 0..length {
+                items.push(i32::from_be_bytes(read_array(reader)?));
+            }
+            Tag::IntArray(items)
+        }
+        12 => {
+            let length = read_length(reader)?;
+            let mut items = Vec::new();
+            for _ in 0..length {
+                items.push(i64::from_be_bytes(read_array(reader)?));
+            }
+            Tag::LongArray(items)
+        }
+        _ => return Err(invalid_data(&format!("unknown tag type {tag_type}"))),
+    })
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    Ok(read_array::<1>(reader)?[0])
+}
+
+fn read_length(reader: &mut impl Read) -> io::Result<usize> {
+    usize::try_from(i32::from_be_bytes(read_array(reader)?))
+        .map_err(|_| invalid_data("negative length"))
+}
+
+fn read_bytes(reader: &mut impl Read, length: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(length as u64).read_to_end(&mut buf)?;
+    if buf.len() != length {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = u16::from_be_bytes(read_array(reader)?) as usize;
+    // strings are in java's modified UTF-8, which only differs from UTF-8 for characters we don't care about
+    Ok(String::from_utf8_lossy(&read_bytes(reader, length)?).into_owned())
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SpawnPosition {
+    pub x: i64,
+    pub y: i64,
+    pub z: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LevelVersion {
+    pub name: Option<String>,
+    pub id: Option<i64>,
+}
+
+/// the interesting parts of a world's `level.dat`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LevelData {
+    pub level_name: Option<String>,
+    pub seed: Option<i64>,
+    pub spawn: Option<SpawnPosition>,
+    pub game_type: Option<String>,
+    pub difficulty: Option<String>,
+    pub hardcore: bool,
+    pub day_time: Option<i64>,
+    pub world_type: Option<String>,
+    pub version: Option<LevelVersion>,
+}
+
+impl LevelData {
+    /// parses the contents of a `level.dat` file. handles both the old layout and the one used since 1.16
+    pub fn from_level_dat(data: &[u8]) -> io::Result<Self> {
+        let root = read_file(data)?;
+        let data = root
+            .get("Data")
+            .ok_or_else(|| invalid_data("level.dat has no Data tag"))?;
+
+        let seed = data
+            .get("WorldGenSettings")
+            .and_then(|settings| settings.get("seed"))
+            .or_else(|| data.get("RandomSeed"))
+            .and_then(Tag::as_i64);
+
+        let spawn = match data.get("spawn").and_then(|spawn| spawn.get("pos")) {
+            Some(Tag::IntArray(pos)) if pos.len() == 3 => Some(SpawnPosition {
+                x: pos[0] as i64,
+                y: pos[1] as i64,
+                z: pos[2] as i64,
+            }),
+            _ => match (
+                data.get("SpawnX").and_then(Tag::as_i64),
+                data.get("SpawnY").and_then(Tag::as_i64),
+                data.get("SpawnZ").and_then(Tag::as_i64),
+            ) {
+                (Some(x), Some(y), Some(z)) => Some(SpawnPosition { x, y, z }),
+                _ => None,
+            },
+        };
+
+        let game_type = data.get("GameType").and_then(Tag::as_i64).map(|game_type| {
+            match game_type {
+                0 => "survival",
+                1 => "creative",
+                2 => "adventure",
+                3 => "spectator",
+                _ => "unknown",
+            }
+            .to_string()
+        });
+
+        let difficulty_settings = data.get("difficulty_settings");
+        let difficulty = match difficulty_settings.and_then(|settings| settings.get("difficulty")) {
+            Some(Tag::String(difficulty)) => Some(difficulty.clone()),
+            _ => data.get("Difficulty").and_then(Tag::as_i64).map(|difficulty| {
+                match difficulty {
+                    0 => "peaceful",
+                    1 => "easy",
+                    2 => "normal",
+                    3 => "hard",
+                    _ => "unknown",
+                }
+                .to_string()
+            }),
+        };
+
+        let hardcore = difficulty_settings
+            .and_then(|settings| settings.get("hardcore"))
+            .or_else(|| data.get("hardcore"))
+            .and_then(Tag::as_i64)
+            .is_some_and(|hardcore| hardcore != 0);
+
+        let generator = data
+            .get("WorldGenSettings")
+            .and_then(|settings| settings.get("dimensions"))
+            .and_then(|dimensions| dimensions.get("minecraft:overworld"))
+            .and_then(|overworld| overworld.get("generator"));
+        let world_type = generator
+            .and_then(|generator| generator.get("settings").and_then(Tag::as_str))
+            .or_else(|| generator.and_then(|generator| generator.get("type").and_then(Tag::as_str)))
+            .or_else(|| data.get("generatorName").and_then(Tag::as_str))
+            .map(String::from);
+
+        let version = data.get("Version").map(|version| LevelVersion {
+            name: version.get("Name").and_then(Tag::as_str).map(String::from),
+            id: version.get("Id").and_then(Tag::as_i64),
+        });
+
+        Ok(Self {
+            level_name: data.get("LevelName").and_then(Tag::as_str).map(String::from),
+            seed,
+            spawn,
+            game_type,
+            difficulty,
+            hardcore,
+            day_time: data.get("DayTime").and_then(Tag::as_i64),
+            world_type,
+            version,
+        })
+    }
+}
+
+#[test]
+fn level_dat_test() {
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn named(buf: &mut Vec<u8>, tag_type: u8, name: &str) {
+        buf.push(tag_type);
+        buf.extend((name.len() as u16).to_be_bytes());
+        buf.extend(name.as_bytes());
+    }
+
+    let mut nbt = Vec::new();
+    named(&mut nbt, 10, "");
+    named(&mut nbt, 10, "Data");
+    named(&mut nbt, 8, "LevelName");
+    nbt.extend(5u16.to_be_bytes());
+    nbt.extend(b"world");
+    named(&mut nbt, 4, "RandomSeed");
+    nbt.extend((-1234567890123i64).to_be_bytes());
+    for (name, value) in [("SpawnX", 16i32), ("SpawnY", 64), ("SpawnZ", -32), ("GameType", 1)] {
+        named(&mut nbt, 3, name);
+        nbt.extend(value.to_be_bytes());
+    }
+    named(&mut nbt, 1, "Difficulty");
+    nbt.push(3);
+    named(&mut nbt, 4, "DayTime");
+    nbt.extend(6000i64.to_be_bytes());
+    named(&mut nbt, 9, "ServerBrands");
+    nbt.push(8);
+    nbt.extend(1i32.to_be_bytes());
+    nbt.extend(7u16.to_be_bytes());
+    nbt.extend(b"vanilla");
+    named(&mut nbt, 10, "Version");
+    named(&mut nbt, 8, "Name");
+    nbt.extend(6u16.to_be_bytes());
+    nbt.extend(b"1.12.2");
+    named(&mut nbt, 3, "Id");
+    nbt.extend(1343i32.to_be_bytes());
+    nbt.push(0);
+    nbt.push(0);
+    nbt.push(0);
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&nbt).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let expected = LevelData {
+        level_name: Some("world".to_string()),
+        seed: Some(-1234567890123),
+        spawn: Some(SpawnPosition { x: 16, y: 64, z: -32 }),
+        game_type: Some("creative".to_string()),
+        difficulty: Some("hard".to_string()),
+        hardcore: false,
+        day_time: Some(6000),
+        world_type: None,
+        version: Some(LevelVersion {
+            name: Some("1.12.2".to_string()),
+            id: Some(1343),
+        }),
+    };
+
+    assert_eq!(LevelData::from_level_dat(&compressed).unwrap(), expected);
+    assert_eq!(LevelData::from_level_dat(&nbt).unwrap(), expected);
+    assert!(LevelData::from_level_dat(&nbt[..nbt.len() - 10]).is_err());
+}
@@ -0,0 +1,271 @@
+//! answers players connecting to a world that exists but isn't running. the proxy routes the hostnames of stopped
+//! worlds here (see [`crate::minecraft::proxy::ProxyHost::offline`]), and the server list shows
+//! [`crate::config::OfflineStatusConfig::motd`] for them, while joining shows the kick message
+
+use crate::config::{CONFIG, OfflineStatusConfig};
+use crate::database::Database;
+use crate::database::objects::World;
+use log::{debug, error, info};
+use std::io;
+use std::io::Read;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// the handshake is the only packet read before answering, so anything longer isn't a client
+const MAX_PACKET_LENGTH: usize = 1024;
+/// how long a connection can stay open
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+const NEXT_STATE_STATUS: i32 = 1;
+
+/// the first packet a client sends
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub protocol: i32,
+    /// the address the player typed in, like `survival.example.net`
+    pub address: String,
+    pub port: u16,
+    /// 1 for a server list ping, 2 for joining, 3 for a transfer from another server
+    pub next_state: i32,
+}
+
+impl Handshake {
+    /// the hostname of the world the player connects to, which is the address without the proxy's hostname
+    pub fn hostname(&self, proxy_hostname: &str) -> Option<String> {
+        // Forge appends a marker after a null byte, and a fully qualified address ends with a dot
+        let address = self.address.split('\0').next()?.trim_end_matches('.').to_lowercase();
+        let hostname = address.strip_suffix(&format!(".{}", proxy_hostname.to_lowercase()))?;
+        (!hostname.is_empty()).then(|| hostname.to_string())
+    }
+}
+
+/// the message with `{name}` and `{hostname}` replaced
+fn render(template: &str, name: &str, hostname: &str) -> String {
+    template.replace("{name}", name).replace("{hostname}", hostname)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_varint(reader: &mut impl Read) -> io::Result<i32> {
+    let mut value = 0u32;
+    for position in 0..5 {
+        let mut byte = [0];
+        reader.read_exact(&mut byte)?;
+        value |= u32::from(byte[0] & 0x7f) << (7 * position);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(invalid("the varint is too long"))
+}
+
+fn write_varint(buffer: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7f == 0 {
+            buffer.push(value as u8);
+            return;
+        }
+        buffer.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = usize::try_from(read_varint(reader)?).map_err(|_| invalid("negative string length"))?;
+    if length > MAX_PACKET_LENGTH {
+        return Err(invalid("the string is too long"));
+    }
+    let mut bytes = vec![0; length];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| invalid("the string isn't UTF-8"))
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+    write_varint(buffer, value.len() as i32);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+/// a packet, prefixed with its length
+fn packet(id: i32, data: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_varint(&mut body, id);
+    body.extend_from_slice(data);
+    let mut packet = Vec::new();
+    write_varint(&mut packet, body.len() as i32);
+    packet.extend(body);
+    packet
+}
+
+/// reads a packet, returning its id and data
+async fn read_packet(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<(i32, Vec<u8>)> {
+    let mut length = 0u32;
+    for position in 0..3 {
+        let byte = stream.read_u8().await?;
+        length |= u32::from(byte & 0x7f) << (7 * position);
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    if length == 0 || length as usize > MAX_PACKET_LENGTH {
+        return Err(invalid("invalid packet length"));
+    }
+    let mut body = vec![0; length as usize];
+    stream.read_exact(&mut body).await?;
+    let mut reader = body.as_slice();
+    let id = read_varint(&mut reader)?;
+    Ok((id, reader.to_vec()))
+}
+
+fn parse_handshake(mut data: &[u8]) -> io::Result<Handshake> {
+    let protocol = read_varint(&mut data)?;
+    let address = read_string(&mut data)?;
+    let mut port = [0; 2];
+    Read::read_exact(&mut data, &mut port)?;
+    let next_state = read_varint(&mut data)?;
+    Ok(Handshake {
+        protocol,
+        address,
+        port: u16::from_be_bytes(port),
+        next_state,
+    })
+}
+
+/// answers the client on the `stream`. `world_name` gives the name of the world the client connects to, the
+/// connection is closed without an answer if there is no such world
+pub async fn respond<S, F, Fut>(stream: &mut S, config: &OfflineStatusConfig, world_name: F) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Option<String>>,
+{
+    let (id, data) = read_packet(stream).await?;
+    if id != 0 {
+        return Err(invalid("expected a handshake"));
+    }
+    let handshake = parse_handshake(&data)?;
+    let Some(hostname) = handshake.hostname(&CONFIG.proxy.hostname) else {
+        return Err(invalid("the address isn't under the proxy's hostname"));
+    };
+    let Some(name) = world_name(hostname.clone()).await else {
+        debug!("not answering a connection to {hostname}, there is no such world");
+        return Ok(());
+    };
+
+    if handshake.next_state != NEXT_STATE_STATUS {
+        let mut data = Vec::new();
+        let message = serde_json::json!({ "text": render(&config.kick_message, &name, &hostname) });
+        write_string(&mut data, &message.to_string());
+        return stream.write_all(&packet(0, &data)).await;
+    }
+
+    loop {
+        match read_packet(stream).await? {
+            (0, _) => {
+                let status = serde_json::json!({
+                    "version": { "name": "offline", "protocol": handshake.protocol },
+                    "players": { "max": 0, "online": 0 },
+                    "description": { "text": render(&config.motd, &name, &hostname) },
+                });
+                let mut data = Vec::new();
+                write_string(&mut data, &status.to_string());
+                stream.write_all(&packet(0, &data)).await?;
+            }
+            // the ping, answered with the same payload
+            (1, payload) => return stream.write_all(&packet(1, &payload)).await,
+            (id, _) => return Err(invalid(&format!("unexpected packet {id}"))),
+        }
+    }
+}
+
+/// answers the connections the proxy sends for stopped worlds, on [`OfflineStatusConfig::port`]
+pub async fn run(database: Database) {
+    let config = &CONFIG.proxy.offline_status;
+    let listener = TcpListener::bind(("127.0.0.1", config.port))
+        .await
+        .expect("failed to bind the offline status responder");
+    info!("answering for stopped worlds at 127.0.0.1:{}", config.port);
+
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                error!("failed to accept a connection for a stopped world: {err}");
+                continue;
+            }
+        };
+        let database = database.clone();
+        tokio::task::spawn(async move {
+            let world_name = |hostname: String| async move {
+                database
+                    .get_where::<World, _>("hostname", hostname, None)
+                    .await
+                    .ok()
+                    .map(|world| world.name)
+            };
+            match tokio::time::timeout(CONNECTION_TIMEOUT, respond(&mut stream, config, world_name)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => debug!("failed to answer a connection for a stopped world: {err}"),
+                Err(_) => debug!("a connection for a stopped world timed out"),
+            }
+        });
+    }
+}
+
+#[tokio::test]
+async fn respond_test() {
+    let config = OfflineStatusConfig {
+        enabled: true,
+        port: 0,
+        motd: String::from("{name} ({hostname}) is asleep"),
+        kick_message: String::from("\"{name}\" is offline"),
+    };
+    let handshake = |address: &str, next_state: i32| {
+        let mut data = Vec::new();
+        write_varint(&mut data, 770);
+        write_string(&mut data, address);
+        data.extend_from_slice(&25565u16.to_be_bytes());
+        write_varint(&mut data, next_state);
+        packet(0, &data)
+    };
+    let world_name = |hostname: String| async move { (hostname == "survival").then(|| String::from("Survival")) };
+    let address = format!("Survival.{}.", CONFIG.proxy.hostname);
+
+    // a server list ping
+    let (mut client, mut server) = tokio::io::duplex(4096);
+    client.write_all(&handshake(&address, 1)).await.unwrap();
+    client.write_all(&packet(0, &[])).await.unwrap();
+    client.write_all(&packet(1, &42i64.to_be_bytes())).await.unwrap();
+    respond(&mut server, &config, world_name).await.unwrap();
+
+    let (id, data) = read_packet(&mut client).await.unwrap();
+    assert_eq!(id, 0);
+    let status: serde_json::Value = serde_json::from_str(&read_string(&mut data.as_slice()).unwrap()).unwrap();
+    assert_eq!(status["description"]["text"], "Survival (survival) is asleep");
+    assert_eq!(status["version"]["protocol"], 770);
+    assert_eq!(read_packet(&mut client).await.unwrap(), (1, 42i64.to_be_bytes().to_vec()));
+
+    // joining
+    let (mut client, mut server) = tokio::io::duplex(4096);
+    client.write_all(&handshake(&format!("{address}\0FML3\0"), 2)).await.unwrap();
+    respond(&mut server, &config, world_name).await.unwrap();
+    let (id, data) = read_packet(&mut client).await.unwrap();
+    assert_eq!(id, 0);
+    let message: serde_json::Value = serde_json::from_str(&read_string(&mut data.as_slice()).unwrap()).unwrap();
+    assert_eq!(message["text"], "\"Survival\" is offline");
+
+    // a world that doesn't exist gets no answer
+    let (mut client, mut server) = tokio::io::duplex(4096);
+    client.write_all(&handshake(&format!("creative.{}", CONFIG.proxy.hostname), 2)).await.unwrap();
+    respond(&mut server, &config, world_name).await.unwrap();
+    drop(server);
+    assert!(read_packet(&mut client).await.is_err());
+
+    // another domain
+    let (mut client, mut server) = tokio::io::duplex(4096);
+    client.write_all(&handshake("survival.example.org", 1)).await.unwrap();
+    assert!(respond(&mut server, &config, world_name).await.is_err());
+}
@@ -0,0 +1,236 @@
+//! pregenerates the chunks around the spawn with [Chunky](https://modrinth.com/plugin/chunky), so players exploring
+//! don't wait for them to generate. servers without it can't pregenerate. see
+//! [`crate::database::objects::World`]'s `/pregenerate`
+
+use crate::database::types::Id;
+use crate::minecraft::server::{McStdout, next_output};
+use crate::minecraft::util::strip_formatting;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{RwLock, broadcast};
+
+/// the last pregeneration of each world, shared with the api
+pub type Pregenerations = Arc<RwLock<HashMap<Id, Pregeneration>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PregenerationState {
+    Running,
+    Finished,
+    Cancelled,
+    /// the server stopped before it was finished
+    Interrupted,
+}
+
+/// a pregeneration and how far it got
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Pregeneration {
+    pub state: PregenerationState,
+    /// in blocks, from the center
+    pub radius: u32,
+    pub center_x: i32,
+    pub center_z: i32,
+    /// how many chunks were generated
+    pub chunks: u64,
+    /// how much of the area was generated, from 0 to 100
+    pub percent: f64,
+    /// how long Chunky expects the rest to take, like `0:03:12`
+    pub eta: Option<String>,
+}
+
+impl Pregeneration {
+    pub fn new(radius: u32, center_x: i32, center_z: i32) -> Self {
+        Self {
+            state: PregenerationState::Running,
+            radius,
+            center_x,
+            center_z,
+            chunks: 0,
+            percent: 0.0,
+            eta: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == PregenerationState::Running
+    }
+
+    /// updates the progress with what Chunky reported
+    pub fn apply(&mut self, event: ChunkyEvent) {
+        match event {
+            ChunkyEvent::Progress { chunks, percent, eta } => {
+                self.chunks = chunks;
+                self.percent = percent;
+                self.eta = eta;
+            }
+            ChunkyEvent::Finished { chunks } => {
+                self.chunks = chunks;
+                self.percent = 100.0;
+                self.eta = None;
+                self.state = PregenerationState::Finished;
+            }
+            ChunkyEvent::Cancelled => {
+                self.eta = None;
+                self.state = PregenerationState::Cancelled;
+            }
+            ChunkyEvent::Started | ChunkyEvent::AlreadyRunning | ChunkyEvent::Other => {}
+        }
+    }
+}
+
+/// what Chunky reported in the console
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChunkyEvent {
+    Started,
+    /// a task was started before and hasn't finished
+    AlreadyRunning,
+    Progress { chunks: u64, percent: f64, eta: Option<String> },
+    Finished { chunks: u64 },
+    /// cancelled or paused
+    Cancelled,
+    /// anything else, like the answer to `chunky radius`
+    Other,
+}
+
+/// the message of a console line Chunky logged, without its `[Chunky]` prefix. like with
+/// [`crate::minecraft::util::parse_seed`], a player can't fake it in chat
+fn chunky_message(line: &str) -> Option<String> {
+    let line = strip_formatting(line);
+    let (_, message) = line.split_once("]: ")?;
+    Some(message.strip_prefix("[Chunky] ")?.trim().to_string())
+}
+
+/// the number before `suffix` in `text`, like the 1024 of `Processed: 1024 chunks`
+fn number_before<T: std::str::FromStr>(text: &str, prefix: &str, suffix: &str) -> Option<T> {
+    let (_, rest) = text.split_once(prefix)?;
+    rest.split_once(suffix)?.0.trim().parse().ok()
+}
+
+/// parses a console line of Chunky, like
+/// `Task running for minecraft:overworld. Processed: 1024 chunks (5.12%), ETA: 0:03:12, Rate: 85.3 cps, Current: 10, -4`
+pub fn parse_chunky(line: &str) -> Option<ChunkyEvent> {
+    let message = chunky_message(line)?;
+    let event = if message.starts_with("Task started for") {
+        ChunkyEvent::Started
+    } else if message.starts_with("Task running for") {
+        ChunkyEvent::Progress {
+            chunks: number_before(&message, "Processed:", "chunks")?,
+            percent: number_before(&message, "(", "%)")?,
+            eta: message
+                .split_once("ETA:")
+                .and_then(|(_, eta)| eta.split(',').next())
+                .map(|eta| eta.trim().to_string()),
+        }
+    } else if message.starts_with("Task finished for") {
+        ChunkyEvent::Finished {
+            chunks: number_before(&message, "Processed:", "chunks")?,
+        }
+    } else if message.starts_with("Task cancelled for")
+        || message.starts_with("Task stopped for")
+        || message.starts_with("Task paused for")
+    {
+        ChunkyEvent::Cancelled
+    } else if message.contains("already running") || message.starts_with("Task already exists") {
+        ChunkyEvent::AlreadyRunning
+    } else {
+        ChunkyEvent::Other
+    };
+    Some(event)
+}
+
+/// follows the pregeneration of the world in its console output until it ends, or the server stops
+pub async fn track(pregenerations: Pregenerations, id: Id, mut stdout: broadcast::Receiver<McStdout>) {
+    while let Some(message) = next_output(&mut stdout).await {
+        let event = match message {
+            McStdout::Log { message, .. } => match parse_chunky(&message) {
+                Some(event) => event,
+                None => continue,
+            },
+            McStdout::Status(status) if status.status != "running" => break,
+            McStdout::Status(_) | McStdout::Lagged { .. } => continue,
+        };
+        let mut pregenerations = pregenerations.write().await;
+        let Some(pregeneration) = pregenerations.get_mut(&id) else {
+            return;
+        };
+        pregeneration.apply(event);
+        if !pregeneration.is_running() {
+            return;
+        }
+    }
+
+    if let Some(pregeneration) = pregenerations.write().await.get_mut(&id)
+        && pregeneration.is_running()
+    {
+        pregeneration.state = PregenerationState::Interrupted;
+        pregeneration.eta = None;
+    }
+}
+
+#[test]
+fn parse_chunky_test() {
+    assert_eq!(
+        parse_chunky("[12:00:00] [Server thread/INFO]: [Chunky] Task started for minecraft:overworld at 0, 0 with radius 1000."),
+        Some(ChunkyEvent::Started)
+    );
+    assert_eq!(
+        parse_chunky(
+            "[12:00:05 INFO]: [Chunky] Task running for minecraft:overworld. Processed: 1024 chunks (5.12%), ETA: 0:03:12, \
+             Rate: 85.3 cps, Current: 10, -4"
+        ),
+        Some(ChunkyEvent::Progress { chunks: 1024, percent: 5.12, eta: Some(String::from("0:03:12")) })
+    );
+    assert_eq!(
+        parse_chunky(
+            "[12:03:20 INFO]: [Chunky] Task finished for minecraft:overworld. Processed: 20164 chunks (100.00%), Total \
+             time: 0:03:15"
+        ),
+        Some(ChunkyEvent::Finished { chunks: 20164 })
+    );
+    assert_eq!(
+        parse_chunky("[12:01:00 INFO]: [Chunky] Task cancelled for minecraft:overworld."),
+        Some(ChunkyEvent::Cancelled)
+    );
+    assert_eq!(
+        parse_chunky("[12:01:00 INFO]: [Chunky] Radius changed to 1000 for minecraft:overworld."),
+        Some(ChunkyEvent::Other)
+    );
+    // players can't fake it in chat
+    assert_eq!(parse_chunky("[12:00:00 INFO]: <Steve> [Chunky] Task finished for minecraft:overworld."), None);
+    assert_eq!(parse_chunky("[12:00:00 INFO]: Done (3.2s)! For help, type \"help\""), None);
+
+    let mut pregeneration = Pregeneration::new(1000, 0, 0);
+    pregeneration.apply(ChunkyEvent::Progress { chunks: 10, percent: 1.5, eta: Some(String::from("0:01:00")) });
+    assert!(pregeneration.is_running());
+    assert_eq!((pregeneration.chunks, pregeneration.percent), (10, 1.5));
+    pregeneration.apply(ChunkyEvent::Finished { chunks: 100 });
+    assert_eq!(pregeneration.state, PregenerationState::Finished);
+    assert_eq!(pregeneration.eta, None);
+}
+
+#[tokio::test]
+async fn track_test() {
+    use crate::minecraft::server::{ConsoleOutput, MinecraftServerStatus};
+
+    let id = Id::new_random();
+    let pregenerations = Pregenerations::default();
+    let output = ConsoleOutput::default();
+
+    let log = |message: &str| McStdout::Log { seq: 0, message: message.to_string() };
+    pregenerations.write().await.insert(id, Pregeneration::new(500, 0, 0));
+    let task = tokio::task::spawn(track(pregenerations.clone(), id, output.subscribe()));
+    output.publish(log("[12:00:05 INFO]: [Chunky] Task running for minecraft:overworld. Processed: 50 chunks (10.00%), ETA: 0:00:45, Rate: 10.0 cps, Current: 1, 2"));
+    output.publish(log("[12:00:50 INFO]: [Chunky] Task finished for minecraft:overworld. Processed: 500 chunks (100.00%), Total time: 0:00:50"));
+    task.await.unwrap();
+    let finished = pregenerations.read().await[&id].clone();
+    assert_eq!((finished.state, finished.chunks), (PregenerationState::Finished, 500));
+
+    // the server stopping ends it
+    pregenerations.write().await.insert(id, Pregeneration::new(500, 0, 0));
+    let task = tokio::task::spawn(track(pregenerations.clone(), id, output.subscribe()));
+    output.publish_status(MinecraftServerStatus::Running.into());
+    output.publish_status(MinecraftServerStatus::Exited(0).into());
+    task.await.unwrap();
+    assert_eq!(pregenerations.read().await[&id].state, PregenerationState::Interrupted);
+}
@@ -1,16 +1,22 @@
 use crate::config::CONFIG;
+use crate::database::Database;
+use crate::database::objects::World;
 use crate::minecraft::server::{MinecraftServerCollection, MinecraftServerStatus};
 use crate::util;
 use color_eyre::eyre::bail;
-use log::{error, warn};
-use std::collections::HashMap;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::fs::File;
 use std::io::{Write};
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 use async_trait::async_trait;
 use subprocess::{Exec, ExitStatus, Popen};
+use yaml_rust2::yaml::Hash;
+use yaml_rust2::{Yaml, YamlEmitter, YamlLoader};
 
 #[async_trait]
 pub trait MinecraftProxy {
@@ -20,42 +26,183 @@ pub trait MinecraftProxy {
     async fn update(&mut self) -> color_eyre::Result<()>;
 }
 
+/// the hosts the proxy routes, by hostname. shared with the api, see [`proxy_config`]
+pub type ProxyHosts = Arc<RwLock<HashMap<String, ProxyHost>>>;
+
+/// the status of the proxy as of its last update, shared with the api. [`None`] until it's started
+pub type ProxyStatus = Arc<RwLock<Option<MinecraftServerStatus>>>;
+
+/// how long the worlds routed to the offline responder are cached. the proxy updates every second, and reading every
+/// world that often isn't worth it for a world that was just created or renamed
+const OFFLINE_WORLDS_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
 pub struct InfrarustServer {
     status: MinecraftServerStatus,
     servers: MinecraftServerCollection,
     path: PathBuf,
     process: Option<Popen>,
-    hosts: HashMap<String, String>,
+    hosts: ProxyHosts,
+    database: Database,
+    /// the worlds as of when they were last read, see [`OFFLINE_WORLDS_REFRESH_INTERVAL`]
+    offline_worlds: Option<(Instant, Vec<World>)>,
+}
+
+/// the options of a server in Infrarust, rendered into its file in the `proxies` directory
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProxyHost {
+    /// the domains routed to the server
+    pub domains: Vec<String>,
+    /// where the server listens
+    pub addresses: Vec<String>,
+    pub proxy_mode: String,
+    /// whether to tell the server the address of the player with the PROXY protocol
+    pub send_proxy_protocol: bool,
+    pub proxy_protocol_version: i64,
+}
+
+impl ProxyHost {
+    /// the options of the `world`'s server, listening on `address`. the world's proxy settings override the ones of the
+    /// instance
+    pub fn new(world: &World, address: &str) -> Self {
+        Self {
+            domains: vec![format!("{}.{}", world.hostname, CONFIG.proxy.hostname)],
+            addresses: vec![address.to_string()],
+            proxy_mode: String::from("passthrough"),
+            send_proxy_protocol: world.forward_player_ip.unwrap_or(CONFIG.proxy.forward_player_ip),
+            proxy_protocol_version: 2,
+        }
+    }
+
+    /// the options of a `world` that isn't running, routed to [`crate::minecraft::offline`]
+    pub fn offline(world: &World) -> Self {
+        Self {
+            // the responder doesn't understand the PROXY protocol
+            send_proxy_protocol: false,
+            ..Self::new(world, &format!("127.0.0.1:{}", CONFIG.proxy.offline_status.port))
+        }
+    }
+
+    fn to_yaml(&self) -> Yaml {
+        let strings = |values: &[String]| Yaml::Array(values.iter().cloned().map(Yaml::String).collect());
+        let mut hash = Hash::new();
+        for (key, value) in [
+            ("domains", strings(&self.domains)),
+            ("addresses", strings(&self.addresses)),
+            ("proxyMode", Yaml::String(self.proxy_mode.clone())),
+            ("sendProxyProtocol", Yaml::Boolean(self.send_proxy_protocol)),
+            ("proxy_protocol_version", Yaml::Integer(self.proxy_protocol_version)),
+        ] {
+            hash.insert(Yaml::String(key.to_string()), value);
+        }
+        Yaml::Hash(hash)
+    }
+
+    fn from_yaml(yaml: &Yaml) -> Option<Self> {
+        let strings = |value: &Yaml| {
+            value
+                .as_vec()?
+                .iter()
+                .map(|value| value.as_str().map(String::from))
+                .collect::<Option<Vec<_>>>()
+        };
+        Some(Self {
+            domains: strings(&yaml["domains"])?,
+            addresses: strings(&yaml["addresses"])?,
+            proxy_mode: yaml["proxyMode"].as_str()?.to_string(),
+            send_proxy_protocol: yaml["sendProxyProtocol"].as_bool()?,
+            proxy_protocol_version: yaml["proxy_protocol_version"].as_i64()?,
+        })
+    }
+
+    /// reads a file written by [`ProxyHost::render`]
+    pub fn parse(rendered: &str) -> Option<Self> {
+        match YamlLoader::load_from_str(rendered).ok()?.as_slice() {
+            [yaml] => Self::from_yaml(yaml),
+            _ => None,
+        }
+    }
+
+    /// renders the options as YAML. the output is parsed back, so a broken file is never written
+    pub fn render(&self) -> color_eyre::Result<String> {
+        let yaml = self.to_yaml();
+        let mut rendered = String::new();
+        YamlEmitter::new(&mut rendered).dump(&yaml)?;
+        rendered.push('\n');
+
+        match YamlLoader::load_from_str(&rendered)?.as_slice() {
+            [parsed] if *parsed == yaml => Ok(rendered),
+            _ => bail!("the rendered proxy config does not match the options"),
+        }
+    }
 }
 
 impl InfrarustServer {
-    pub fn new(servers: MinecraftServerCollection) -> color_eyre::Result<Self> {
+    /// `hosts` is kept up to date with the hosts the proxy routes
+    pub fn new(servers: MinecraftServerCollection, database: Database, hosts: ProxyHosts) -> color_eyre::Result<Self> {
         Ok(Self {
             status: MinecraftServerStatus::Exited(0),
             servers,
             path: util::dirs::infrarust_dir(),
             process: None,
-            hosts: HashMap::default(),
+            hosts,
+            database,
+            offline_worlds: None,
         })
     }
 
-    fn add_server(&mut self, hostname: &str, address: &str) -> color_eyre::Result<()> {
-        self.hosts.insert(hostname.to_string(), address.to_string());
+    fn hosts(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, ProxyHost>> {
+        self.hosts.write().expect("the proxy hosts lock is poisoned")
+    }
+
+    fn add_server(&mut self, hostname: &str, host: ProxyHost) -> color_eyre::Result<()> {
+        let rendered = host.render()?;
         let mut file = File::create(self.path.join(format!("proxies/{hostname}.yml")))?;
-        file.write_all(
-            include_str!("../resources/configs/default_infrarust_server_config.yml")
-                .replace(
-                    "$hostname",
-                    &format!("{}.{}", hostname, CONFIG.proxy.hostname),
-                )
-                .replace("$address", address)
-                .as_bytes(),
-        )?;
+        file.write_all(rendered.as_bytes())?;
+        self.hosts().insert(hostname.to_string(), host);
         Ok(())
     }
 
+    /// the hosts of the servers that are running, and with [`crate::config::OfflineStatusConfig::enabled`] the ones
+    /// of the other worlds. a world that starts gets its server's host on the next update, a new or renamed one that
+    /// isn't running gets the offline host within [`OFFLINE_WORLDS_REFRESH_INTERVAL`]
+    async fn expected_hosts(&mut self) -> HashMap<String, ProxyHost> {
+        let mut hosts = HashMap::new();
+        for server in self.servers.get_all_servers().await {
+            let server = server.lock().await;
+            if let Some(port) = server.port() {
+                let world = server.world();
+                let host = ProxyHost::new(&world, &format!("{}:{}", server.host(), port));
+                hosts.insert(world.hostname, host);
+            }
+        }
+        if CONFIG.proxy.offline_status.enabled {
+            for world in self.offline_worlds().await {
+                hosts
+                    .entry(world.hostname.clone())
+                    .or_insert_with(|| ProxyHost::offline(world));
+            }
+        }
+        hosts
+    }
+
+    /// every world, read again once the cached ones are older than [`OFFLINE_WORLDS_REFRESH_INTERVAL`]. if reading
+    /// them fails the cached ones are kept
+    async fn offline_worlds(&mut self) -> &[World] {
+        let stale = self
+            .offline_worlds
+            .as_ref()
+            .is_none_or(|(read_at, _)| read_at.elapsed() >= OFFLINE_WORLDS_REFRESH_INTERVAL);
+        if stale {
+            match self.database.get_all::<World>(None).await {
+                Ok(worlds) => self.offline_worlds = Some((Instant::now(), worlds)),
+                Err(err) => warn!("failed to get the stopped worlds for the proxy: {err}"),
+            }
+        }
+        self.offline_worlds.as_ref().map_or(&[], |(_, worlds)| worlds)
+    }
+
     fn remove_server(&mut self, hostname: &str) -> color_eyre::Result<()> {
-        self.hosts.remove(hostname);
+        self.hosts().remove(hostname);
         let path = self.path.join(format!("proxies/{hostname}.yml"));
         if path.exists() {
             std::fs::remove_file(path)?;
@@ -86,6 +233,9 @@ impl MinecraftProxy for InfrarustServer {
             config_file.write_all(include_bytes!("../resources/configs/default_infrarust_config.yml"))?;
         }
         fs::create_dir_all(self.path.join("proxies"))?;
+        // files left behind by a previous run would otherwise be served until they happen to be overwritten
+        let expected = self.expected_hosts().await;
+        *self.hosts() = reconcile_proxy_files(&self.path.join("proxies"), &expected)?;
 
         let command = Exec::shell(executable_path)
             .cwd(self.path.clone())
@@ -137,6 +287,7 @@ impl MinecraftProxy for InfrarustServer {
             warn!("no process to stop");
             let code = match self.status {
                 MinecraftServerStatus::Exited(code) => code,
+                MinecraftServerStatus::Signaled(signal) => return Ok(ExitStatus::Signaled(signal)),
                 MinecraftServerStatus::Running => {
                     self.status = MinecraftServerStatus::Exited(1);
                     1
@@ -164,21 +315,15 @@ impl MinecraftProxy for InfrarustServer {
             self.start().await?;
         }
 
-        let mut new_hosts = HashMap::new();
-        for server in self.servers.get_all_servers() {
-            let server = server.lock().await;
-            if let Some(port) = server.port() {
-                if let Some(hostname) = server.hostname() {
-                    let address = format!("{}:{}", server.host(), port);
-                    if self.hosts.get(&hostname) != Some(&address) {
-                        self.add_server(&hostname, &address)?;
-                    }
-                    new_hosts.insert(hostname, address);
-                }
+        let new_hosts = self.expected_hosts().await;
+        for (hostname, host) in &new_hosts {
+            if self.hosts().get(hostname) != Some(host) {
+                self.add_server(hostname, host.clone())?;
             }
         }
 
-        for (hostname, _) in self.hosts.clone() {
+        let old_hosts: Vec<String> = self.hosts().keys().cloned().collect();
+        for hostname in old_hosts {
             if !new_hosts.contains_key(hostname.as_str()) {
                 self.remove_server(&hostname)?;
             }
@@ -186,4 +331,221 @@ impl MinecraftProxy for InfrarustServer {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// removes the files in `dir` that aren't exactly the `expected` hosts, returning the ones that are. they don't need to
+/// be written again
+fn reconcile_proxy_files(
+    dir: &Path,
+    expected: &HashMap<String, ProxyHost>,
+) -> color_eyre::Result<HashMap<String, ProxyHost>> {
+    let mut hosts = HashMap::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let Some(hostname) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".yml"))
+        else {
+            continue;
+        };
+        let host = fs::read_to_string(&path)
+            .ok()
+            .and_then(|rendered| ProxyHost::parse(&rendered));
+        match (host, expected.get(hostname)) {
+            (Some(host), Some(expected)) if host == *expected => {
+                hosts.insert(hostname.to_string(), host);
+            }
+            _ => {
+                info!("removing stale proxy config {}", path.display());
+                fs::remove_file(&path)?;
+            }
+        }
+    }
+    Ok(hosts)
+}
+
+/// the config Infrarust runs with, for finding out why players can't reach a world
+#[derive(Debug, Serialize)]
+pub struct ProxyConfig {
+    /// the contents of `config.yaml`. [`None`] if it hasn't been written yet
+    pub config: Option<String>,
+    /// the hosts the proxy routes right now, by hostname
+    pub hosts: BTreeMap<String, ProxyConfigHost>,
+    /// files in the `proxies` directory that don't belong to any of the hosts, by file name
+    pub unknown_files: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProxyConfigHost {
+    #[serde(flatten)]
+    pub host: ProxyHost,
+    /// the contents of its file in the `proxies` directory. [`None`] if it's missing
+    pub file: Option<String>,
+    /// whether the file is what the host renders to. Infrarust only knows about the file
+    pub in_sync: bool,
+}
+
+/// reads the config files in the Infrarust directory `path`, next to the `hosts` the proxy has in memory. the
+/// `secrets` are replaced wherever they show up in the files
+pub fn proxy_config(path: &Path, hosts: &HashMap<String, ProxyHost>, secrets: &[&str]) -> ProxyConfig {
+    let redact = |mut contents: String| {
+        for secret in secrets.iter().filter(|secret| !secret.is_empty()) {
+            contents = contents.replace(secret, "<redacted>");
+        }
+        contents
+    };
+    let proxies = path.join("proxies");
+
+    let hosts = hosts
+        .iter()
+        .map(|(hostname, host)| {
+            let file = fs::read_to_string(proxies.join(format!("{hostname}.yml"))).ok();
+            let in_sync = file.as_deref().and_then(ProxyHost::parse).as_ref() == Some(host);
+            let host = ProxyConfigHost {
+                host: host.clone(),
+                file: file.map(redact),
+                in_sync,
+            };
+            (hostname.clone(), host)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let mut unknown_files = BTreeMap::new();
+    for entry in fs::read_dir(&proxies).into_iter().flatten().flatten() {
+        let Some(name) = entry.file_name().to_str().map(String::from) else {
+            continue;
+        };
+        if name.strip_suffix(".yml").is_some_and(|hostname| hosts.contains_key(hostname)) {
+            continue;
+        }
+        if let Ok(contents) = fs::read_to_string(entry.path()) {
+            unknown_files.insert(name, redact(contents));
+        }
+    }
+
+    ProxyConfig {
+        config: fs::read_to_string(path.join("config.yaml")).ok().map(redact),
+        hosts,
+        unknown_files,
+    }
+}
+
+#[test]
+fn proxy_host_test() {
+    let host = ProxyHost {
+        domains: vec![String::from("survival.example.net")],
+        addresses: vec![String::from("127.0.0.1:24000")],
+        proxy_mode: String::from("passthrough"),
+        send_proxy_protocol: false,
+        proxy_protocol_version: 2,
+    };
+    assert_eq!(
+        host.render().unwrap(),
+        "---\ndomains:\n  - survival.example.net\naddresses:\n  - \"127.0.0.1:24000\"\nproxyMode: passthrough\nsendProxyProtocol: false\nproxy_protocol_version: 2\n"
+    );
+
+    // values that would break a substituted template are quoted
+    let host = ProxyHost {
+        domains: vec![String::from("a\"\n- b: c")],
+        ..host
+    };
+    let rendered = host.render().unwrap();
+    let parsed = YamlLoader::load_from_str(&rendered).unwrap();
+    assert_eq!(parsed[0]["domains"][0].as_str(), Some("a\"\n- b: c"));
+}
+
+#[test]
+fn world_proxy_settings_test() {
+    use crate::database::testing::test_world;
+    use crate::database::types::Id;
+
+    let world = World { hostname: String::from("survival"), ..test_world(Id::new_random(), Id::new_random()) };
+    let host = ProxyHost::new(&world, "127.0.0.1:24000");
+    assert_eq!(host.domains, vec![format!("survival.{}", CONFIG.proxy.hostname)]);
+    assert_eq!(host.send_proxy_protocol, CONFIG.proxy.forward_player_ip);
+
+    // the world's setting overrides the instance's
+    for forward in [true, false] {
+        let world = World { forward_player_ip: Some(forward), ..world.clone() };
+        assert_eq!(ProxyHost::new(&world, "127.0.0.1:24000").send_proxy_protocol, forward);
+    }
+    // the offline responder doesn't understand the PROXY protocol either way
+    let world = World { forward_player_ip: Some(true), ..world };
+    assert!(!ProxyHost::offline(&world).send_proxy_protocol);
+}
+
+#[test]
+fn reconcile_proxy_files_test() {
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+
+    let host = |address: &str| ProxyHost {
+        domains: vec![String::from("survival.example.net")],
+        addresses: vec![String::from(address)],
+        proxy_mode: String::from("passthrough"),
+        send_proxy_protocol: false,
+        proxy_protocol_version: 2,
+    };
+    fs::write(dir.join("survival.yml"), host("127.0.0.1:24000").render().unwrap()).unwrap();
+    // left behind by a world that was deleted while mcmanager wasn't running
+    fs::write(dir.join("deleted.yml"), host("127.0.0.1:24001").render().unwrap()).unwrap();
+    // the server got a different port
+    fs::write(dir.join("creative.yml"), host("127.0.0.1:24002").render().unwrap()).unwrap();
+    fs::write(dir.join("notes.txt"), "").unwrap();
+
+    let expected = HashMap::from([
+        (String::from("survival"), host("127.0.0.1:24000")),
+        (String::from("creative"), host("127.0.0.1:24003")),
+    ]);
+    let hosts = reconcile_proxy_files(&dir, &expected).unwrap();
+
+    assert_eq!(hosts, HashMap::from([(String::from("survival"), host("127.0.0.1:24000"))]));
+    assert!(dir.join("survival.yml").exists());
+    assert!(!dir.join("deleted.yml").exists());
+    assert!(!dir.join("creative.yml").exists());
+    assert!(dir.join("notes.txt").exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn proxy_config_test() {
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(dir.join("proxies")).unwrap();
+
+    let host = |address: &str| ProxyHost {
+        domains: vec![String::from("survival.example.net")],
+        addresses: vec![String::from(address)],
+        proxy_mode: String::from("passthrough"),
+        send_proxy_protocol: false,
+        proxy_protocol_version: 2,
+    };
+    fs::write(dir.join("config.yaml"), "bind: \"0.0.0.0:25565\"\nsecret: hunter2\n").unwrap();
+    fs::write(dir.join("proxies/survival.yml"), host("127.0.0.1:24000").render().unwrap()).unwrap();
+    // written before the server got a new port
+    fs::write(dir.join("proxies/creative.yml"), host("127.0.0.1:24001").render().unwrap()).unwrap();
+    fs::write(dir.join("proxies/manual.yml"), "secret: hunter2").unwrap();
+
+    let hosts = HashMap::from([
+        (String::from("survival"), host("127.0.0.1:24000")),
+        (String::from("creative"), host("127.0.0.1:24002")),
+        (String::from("lobby"), host("127.0.0.1:24003")),
+    ]);
+    let config = proxy_config(&dir, &hosts, &["hunter2", ""]);
+
+    assert_eq!(config.config.as_deref(), Some("bind: \"0.0.0.0:25565\"\nsecret: <redacted>\n"));
+    assert!(config.hosts["survival"].in_sync);
+    assert!(!config.hosts["creative"].in_sync);
+    assert_eq!(config.hosts["creative"].host, host("127.0.0.1:24002"));
+    assert!(config.hosts["lobby"].file.is_none() && !config.hosts["lobby"].in_sync);
+    assert_eq!(
+        config.unknown_files,
+        BTreeMap::from([(String::from("manual.yml"), String::from("secret: <redacted>"))])
+    );
+
+    let json = serde_json::to_value(&config).unwrap();
+    assert_eq!(json["hosts"]["survival"]["addresses"][0], "127.0.0.1:24000");
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -1,91 +1,134 @@
 use crate::config::{CONFIG, ServerType};
-use crate::database::objects::World;
+use crate::database::objects::{Version, World};
+use crate::database::{Database, DatabaseError};
 use crate::database::types::Id;
 use crate::minecraft;
 use color_eyre::Result;
 use async_trait::async_trait;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::Debug;
+use std::path::PathBuf;
 use std::result;
-use std::sync::{Arc, RwLock};
+use std::sync::Arc;
 use image::DynamicImage;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, RwLock};
 use crate::database::objects::world::MinecraftServerStatusJson;
+use crate::minecraft::nbt::LevelData;
 
 pub type ServerMutex = Arc<Mutex<Box<dyn MinecraftServer>>>;
 
 #[derive(Debug, Clone)]
 pub struct MinecraftServerCollection {
     servers: Arc<RwLock<HashMap<Id, ServerMutex>>>,
+    /// held while a server is being created, so concurrent requests for the same world don't both create (and start)
+    /// one. the entries are removed once the server is in `servers`
+    creating: Arc<RwLock<HashMap<Id, Arc<Mutex<()>>>>>,
+    /// read while a server is created, from reserving its port until it's in `servers`, and written while
+    /// [`MinecraftServerCollection::reconcile_ports`] takes its snapshot of the reserved ports. otherwise the port of a
+    /// server that isn't in `servers` yet would be freed as leaked
+    port_snapshot: Arc<RwLock<()>>,
+    /// since when the servers of disabled worlds have been seen stopped by [`MinecraftServerCollection::poll_servers`],
+    /// see [`MinecraftServerCollection::prune_servers`]
+    stopped_since: Arc<RwLock<HashMap<Id, tokio::time::Instant>>>,
+    database: Database,
 }
 
-impl Default for MinecraftServerCollection {
-    fn default() -> Self {
+impl MinecraftServerCollection {
+    pub fn new(database: Database) -> Self {
         Self {
             servers: Arc::new(RwLock::new(HashMap::new())),
+            creating: Arc::default(),
+            port_snapshot: Arc::default(),
+            stopped_since: Arc::default(),
+            database,
         }
     }
-}
-
-impl MinecraftServerCollection {
-    pub fn new() -> Self {
-        Self::default()
-    }
 
-    pub fn get_server(&self, id: Id) -> Option<ServerMutex> {
-        self.servers.read().expect("poisoned mutex").get(&id).cloned()
+    pub async fn get_server(&self, id: Id) -> Option<ServerMutex> {
+        self.servers.read().await.get(&id).cloned()
     }
 
     pub async fn get_or_create_server(
         &self,
         world: &World,
     ) -> Result<minecraft::server::ServerMutex> {
-        let mut server = self.get_server(world.id);
-        match server {
-            Some(server) => Ok(server),
-            None => {
-                self.add_server(match CONFIG.minecraft_server_type {
-                    ServerType::Internal => {
-                        Box::new(internal::InternalServer::new(world.clone()).await.map_err(|err| {
-                            crate::database::DatabaseError::InternalServerError(err.to_string())
-                        })?)
-                    }
-                    ServerType::Remote => Box::new(external::MinimanagerServer::new(
-                        CONFIG
-                            .remote
-                            .host
-                            .host()
-                            .expect("invalid remote server hostname")
-                            .to_string(),
-                        world.clone(),
-                    )),
-                });
-                server = self.get_server(world.id);
-                assert!(server.is_some());
-                Ok(server.unwrap())
-            }
+        if let Some(server) = self.get_server(world.id).await {
+            return Ok(server);
         }
-    }
 
-    pub fn add_server(&self, server: Box<dyn MinecraftServer>) {
-        self.servers
+        let lock = self
+            .creating
             .write()
-            .expect("poisoned mutex")
-            .insert(server.id(), Arc::new(Mutex::new(server)));
+            .await
+            .entry(world.id)
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+        // another request could have created it while we were waiting
+        if let Some(server) = self.get_server(world.id).await {
+            return Ok(server);
+        }
+
+        let server = {
+            let _creating = self.port_snapshot.read().await;
+            let result = self.create_server(world).await;
+            let server = result.map(|server| -> ServerMutex { Arc::new(Mutex::new(server)) });
+            if let Ok(server) = &server {
+                self.servers.write().await.insert(world.id, server.clone());
+            }
+            server
+        };
+        // only once the server is in `servers`, so a request coming in between finds one of them
+        self.creating.write().await.remove(&world.id);
+        server
+    }
+
+    async fn create_server(&self, world: &World) -> Result<Box<dyn MinecraftServer>> {
+        Ok(match CONFIG.minecraft_server_type {
+            ServerType::Internal => {
+                Box::new(internal::InternalServer::new(world.clone(), self.database.clone()).await?)
+            }
+            ServerType::Remote => Box::new(external::MinimanagerServer::new(
+                CONFIG
+                    .remote
+                    .host
+                    .host()
+                    .expect("invalid remote server hostname")
+                    .to_string(),
+                world.clone(),
+            )),
+        })
+    }
+
+    pub async fn add_server(&self, server: Box<dyn MinecraftServer>) {
+        self.servers.write().await.insert(server.id(), Arc::new(Mutex::new(server)));
+    }
+
+    pub async fn remove_server(&self, id: &Id) {
+        self.servers.write().await.remove(id);
+    }
+
+    /// the ids of the worlds that have a server
+    pub async fn ids(&self) -> Vec<Id> {
+        self.servers.read().await.keys().copied().collect()
     }
 
-    pub fn remove_server(&self, id: &Id) {
-        self.servers.write().expect("poisoned mutex").remove(id);
+    /// all the servers. the map is only locked while they are cloned out of it, so the servers can be locked
+    /// without blocking other requests from getting theirs
+    pub async fn get_all_servers(&self) -> Vec<ServerMutex> {
+        self.servers.read().await.values().cloned().collect()
     }
 
-    pub fn get_all_servers(&self) -> Vec<ServerMutex> {
-        self.servers.read().expect("poisoned mutex").values().cloned().collect()
+    /// [`MinecraftServerCollection::get_all_servers`] with the ids of their worlds
+    pub async fn get_all_servers_with_ids(&self) -> Vec<(Id, ServerMutex)> {
+        self.servers.read().await.iter().map(|(id, server)| (*id, server.clone())).collect()
     }
 
     pub async fn get_all_worlds(&self) -> Vec<World> {
         futures::future::join_all(
             self.get_all_servers()
+                .await
                 .into_iter()
                 .map(|server: ServerMutex| async move { server.lock().await.world() }),
         )
@@ -93,12 +136,272 @@ impl MinecraftServerCollection {
     }
 
     pub async fn poll_servers(&self) {
-        futures::future::join_all(
-            self.get_all_servers()
-                .into_iter()
-                .map(|server: ServerMutex| async move { server.lock().await.poll().await }),
-        )
+        let polled = futures::future::join_all(self.get_all_servers().await.into_iter().map(
+            |server: ServerMutex| async move {
+                let mut server = server.lock().await;
+                server.poll().await;
+                let stopped = !server.world().enabled
+                    && matches!(
+                        server.status().await,
+                        Ok(MinecraftServerStatus::Exited(_) | MinecraftServerStatus::Signaled(_))
+                    );
+                (server.id(), stopped)
+            },
+        ))
         .await;
+
+        let mut stopped_since = self.stopped_since.write().await;
+        stopped_since.retain(|id, _| polled.iter().any(|(polled, stopped)| polled == id && *stopped));
+        for (id, stopped) in polled {
+            if stopped {
+                stopped_since.entry(id).or_insert_with(tokio::time::Instant::now);
+            }
+        }
+    }
+
+    /// removes the servers of disabled worlds that have been stopped for longer than `grace`, they're created again
+    /// when they're needed. a server something else holds on to, like a request about to start it, is kept. returns
+    /// the ids of the removed ones
+    pub async fn prune_servers(&self, grace: std::time::Duration) -> Vec<Id> {
+        let mut stopped_since = self.stopped_since.write().await;
+        let mut servers = self.servers.write().await;
+        let mut pruned = Vec::new();
+        for (id, since) in stopped_since.iter() {
+            let Some(server) = servers.get(id) else {
+                continue;
+            };
+            // nothing can get another reference while the map is locked
+            if since.elapsed() < grace || Arc::strong_count(server) > 1 {
+                continue;
+            }
+            // checked again, as it could have been enabled since it was polled
+            let Ok(guard) = server.try_lock() else {
+                continue;
+            };
+            if guard.world().enabled || guard.port().is_some() {
+                continue;
+            }
+            drop(guard);
+            servers.remove(id);
+            pruned.push(*id);
+        }
+        stopped_since.retain(|id, _| !pruned.contains(id));
+        pruned
+    }
+
+    /// the reserved local ports and the servers running on them. the reservations are read before the servers are
+    /// locked, so a server that's being started is waited for instead of showing up without its port
+    pub async fn port_allocations(&self) -> PortAllocations {
+        let taken = internal::taken_local_ports().clone();
+        let mut running = HashMap::new();
+        for server in self.get_all_servers().await {
+            let server = server.lock().await;
+            if let Some(port) = server.port()
+                && taken.contains(&port)
+                && matches!(server.status().await, Ok(MinecraftServerStatus::Running))
+            {
+                running.insert(port, server.id());
+            }
+        }
+        PortAllocations::new(&taken, &running)
+    }
+
+    /// rebuilds the port reservations from the servers that are actually running, freeing the ones no live process
+    /// is behind (like ones left behind by servers that exited without being stopped). the servers are polled first,
+    /// so ones that just exited count as stopped. returns the freed ports
+    pub async fn reconcile_ports(&self) -> Vec<u16> {
+        let snapshot = self.reserved_ports().await;
+        let mut live = HashSet::new();
+        for server in self.get_all_servers().await {
+            let mut server = server.lock().await;
+            server.poll().await;
+            if let Some(port) = server.port()
+                && matches!(server.status().await, Ok(MinecraftServerStatus::Running))
+            {
+                live.insert(port);
+            }
+        }
+        reconcile_ports(&mut internal::taken_local_ports(), &snapshot, &live)
+    }
+
+    /// the reserved ports, once no server is being created. the ones of servers that are created later are reserved
+    /// after it's taken, so [`reconcile_ports`] keeps them
+    async fn reserved_ports(&self) -> HashSet<u16> {
+        let _snapshot = self.port_snapshot.write().await;
+        internal::taken_local_ports().clone()
+    }
+}
+
+/// frees the ports of `snapshot` that aren't `live`, and reserves the `live` ones. ports reserved after the snapshot
+/// was taken belong to servers that started in the meantime, so they're kept
+fn reconcile_ports(taken: &mut HashSet<u16>, snapshot: &HashSet<u16>, live: &HashSet<u16>) -> Vec<u16> {
+    let mut freed: Vec<u16> = snapshot.difference(live).copied().collect();
+    freed.sort_unstable();
+    for port in &freed {
+        taken.remove(port);
+    }
+    taken.extend(live);
+    freed
+}
+
+/// body of `GET /api/admin/ports`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PortAllocations {
+    pub range: std::ops::Range<u16>,
+    /// the reserved ports and the worlds running on them. [`None`] means the reservation was leaked
+    pub taken: BTreeMap<u16, Option<Id>>,
+    /// how many ports in the range are left for servers to start on
+    pub free: usize,
+}
+
+impl PortAllocations {
+    fn new(taken: &HashSet<u16>, running: &HashMap<u16, Id>) -> Self {
+        let range = CONFIG.world.port_range.clone();
+        let free = range
+            .clone()
+            .filter(|port| !taken.contains(port) && !CONFIG.proxy.uses_port(*port))
+            .count();
+        Self {
+            range,
+            taken: taken.iter().map(|port| (*port, running.get(port).copied())).collect(),
+            free,
+        }
+    }
+}
+
+/// why a server could not be started, for errors the user can fix
+#[derive(Debug)]
+pub enum ServerStartError {
+    /// the server jar of the world's [`crate::database::objects::Version`] is not in the versions directory
+    JarMissing { version_id: Id },
+    /// the installed Java is older than the version's `min_java`
+    JavaTooOld {
+        minecraft_version: String,
+        min_java: i32,
+        java_version: u32,
+    },
+    /// the server jar doesn't have the SHA-1 recorded on the version, like when it was only partly downloaded
+    JarCorrupt {
+        version_id: Id,
+        expected: String,
+        actual: String,
+    },
+    /// the `before_start` hook failed and [`crate::config::HookConfig::fatal`] is set. the reason is only logged, it
+    /// can contain details of the host
+    HookFailed,
+}
+
+impl std::fmt::Display for ServerStartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerStartError::JarMissing { version_id } => {
+                write!(f, "server jar for version {version_id} not downloaded")
+            }
+            ServerStartError::JavaTooOld {
+                minecraft_version,
+                min_java,
+                java_version,
+            } => write!(
+                f,
+                "Minecraft {minecraft_version} requires Java {min_java}, but Java {java_version} is installed"
+            ),
+            ServerStartError::JarCorrupt {
+                version_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "server jar for version {version_id} is corrupted (its SHA-1 is {actual} instead of {expected}), download it again"
+            ),
+            ServerStartError::HookFailed => write!(f, "the server could not be prepared to start, try again later"),
+        }
+    }
+}
+
+impl std::error::Error for ServerStartError {}
+
+impl From<ServerStartError> for crate::database::DatabaseError {
+    fn from(value: ServerStartError) -> Self {
+        crate::database::DatabaseError::Unprocessable(value.to_string())
+    }
+}
+
+/// the modification time and size a jar had when it was hashed, and its SHA-1
+type JarHash = (std::time::SystemTime, u64, String);
+
+/// the SHA-1 of the jars that were checked, by path. a jar that hasn't changed isn't hashed again
+static JAR_HASHES: std::sync::LazyLock<std::sync::Mutex<HashMap<PathBuf, JarHash>>> =
+    std::sync::LazyLock::new(Default::default);
+
+/// the SHA-1 of the jar in lowercase hex, see [`JAR_HASHES`]
+pub fn jar_sha1(path: &std::path::Path) -> std::io::Result<String> {
+    use sha1::{Digest, Sha1};
+
+    let metadata = std::fs::metadata(path)?;
+    let key = (metadata.modified()?, metadata.len());
+    if let Some((modified, len, sha1)) = JAR_HASHES.lock().expect("the jar hash lock is poisoned").get(path)
+        && (*modified, *len) == key
+    {
+        return Ok(sha1.clone());
+    }
+
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    let sha1 = format!("{:x}", hasher.finalize());
+    JAR_HASHES
+        .lock()
+        .expect("the jar hash lock is poisoned")
+        .insert(path.to_path_buf(), (key.0, key.1, sha1.clone()));
+    Ok(sha1)
+}
+
+/// checks the server jar of the version at `path` against its [`Version::jar_sha1`]
+pub fn verify_jar(path: &std::path::Path, version: &Version) -> Result<()> {
+    let Some(expected) = &version.jar_sha1 else {
+        return Ok(());
+    };
+    let actual = jar_sha1(path)?;
+    if actual != *expected {
+        return Err(ServerStartError::JarCorrupt {
+            version_id: version.id,
+            expected: expected.clone(),
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// turns an error from starting or updating a server into a [`crate::database::DatabaseError`], keeping the reason of
+/// a [`ServerStartError`] so it can be shown to the user
+pub fn server_error(err: color_eyre::Report) -> crate::database::DatabaseError {
+    match err.downcast::<ServerStartError>() {
+        Ok(err) => err.into(),
+        Err(err) => crate::database::DatabaseError::InternalServerError(err.to_string()),
+    }
+}
+
+/// the files of the server can't be read or changed from here, like the ones of minimanager servers
+#[derive(Debug)]
+pub struct FilesNotAccessible;
+
+impl std::fmt::Display for FilesNotAccessible {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the files of minimanager servers are not accessible")
+    }
+}
+
+impl std::error::Error for FilesNotAccessible {}
+
+/// the status an error from reading or changing the files of a server is answered with. a server whose files aren't
+/// accessible gets 501 Not Implemented, other errors are logged
+pub fn file_error(err: color_eyre::Report) -> axum::http::StatusCode {
+    match err.downcast::<FilesNotAccessible>() {
+        Ok(_) => axum::http::StatusCode::NOT_IMPLEMENTED,
+        Err(err) => {
+            log::error!("{err:#}");
+            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        }
     }
 }
 
@@ -114,23 +417,83 @@ pub struct Server {
 pub enum MinecraftServerStatus {
     Running,
     Exited(u32),
+    /// the process was killed by the signal, like `SIGKILL` by the OOM killer or `SIGSEGV` when the JVM crashes
+    Signaled(u8),
+}
+
+impl From<subprocess::ExitStatus> for MinecraftServerStatus {
+    fn from(value: subprocess::ExitStatus) -> MinecraftServerStatus {
+        match value {
+            subprocess::ExitStatus::Exited(code) => MinecraftServerStatus::Exited(code),
+            subprocess::ExitStatus::Signaled(signal) => MinecraftServerStatus::Signaled(signal),
+            subprocess::ExitStatus::Other(_) | subprocess::ExitStatus::Undetermined => MinecraftServerStatus::Exited(1),
+        }
+    }
+}
+
+/// the name of a unix signal, like `SIGKILL`, for the common ones
+pub fn signal_name(signal: u8) -> Option<&'static str> {
+    Some(match signal {
+        1 => "SIGHUP",
+        2 => "SIGINT",
+        3 => "SIGQUIT",
+        4 => "SIGILL",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        13 => "SIGPIPE",
+        15 => "SIGTERM",
+        _ => return None,
+    })
 }
 
 impl From<MinecraftServerStatus> for MinecraftServerStatusJson {
     fn from(value: MinecraftServerStatus) -> MinecraftServerStatusJson {
-        match value { 
-            MinecraftServerStatus::Running => MinecraftServerStatusJson {
-                status: "running".to_string(),
-                code: 0,
-            },
-            MinecraftServerStatus::Exited(code) => MinecraftServerStatusJson {
-                status: "exited".to_string(),
-                code,
-            },
+        let (status, code, signal) = match value {
+            MinecraftServerStatus::Running => ("running", 0, None),
+            MinecraftServerStatus::Exited(code) => ("exited", code, None),
+            // the code a shell would report for the signal
+            MinecraftServerStatus::Signaled(signal) => ("signaled", 128 + u32::from(signal), Some(signal)),
+        };
+        MinecraftServerStatusJson {
+            status: status.to_string(),
+            code,
+            signal,
+            signal_name: signal.and_then(signal_name).map(str::to_string),
+            port: None,
+            hostname: None,
+            started_at: None,
+            uptime: None,
         }
     }
 }
 
+impl MinecraftServerStatusJson {
+    /// adds where the server can be reached, if it's running
+    pub fn with_address(mut self, port: Option<u16>, hostname: Option<String>) -> Self {
+        if self.status == "running" {
+            self.port = port;
+            self.hostname = hostname;
+        }
+        self
+    }
+
+    /// adds since when the server has been running, if it is
+    pub fn with_uptime(
+        mut self,
+        started_at: Option<chrono::DateTime<chrono::Utc>>,
+        uptime: Option<std::time::Duration>,
+    ) -> Self {
+        if self.status == "running" {
+            self.started_at = started_at;
+            self.uptime = uptime.map(|uptime| uptime.as_secs());
+        }
+        self
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum MCStdin {
@@ -141,6 +504,249 @@ pub enum MCStdin {
 pub enum McStdout {
     Log{seq: usize, message: String},
     Status(MinecraftServerStatusJson),
+    /// stands in for the messages a subscriber missed by falling more than [`CONSOLE_BUFFER_SIZE`] behind, see
+    /// [`next_output`]. it's never published
+    Lagged{skipped: u64},
+}
+
+/// how many messages a subscriber to the console output can fall behind before the oldest are dropped for it
+pub const CONSOLE_BUFFER_SIZE: usize = 128;
+
+/// how many messages of console output a server keeps for [`MinecraftServer::console_history`]
+pub const CONSOLE_HISTORY_SIZE: usize = 1000;
+
+/// the recent console output of a server, the oldest messages are dropped once it holds [`CONSOLE_HISTORY_SIZE`]
+#[derive(Clone, Debug, Default)]
+pub struct ConsoleHistory {
+    messages: Arc<std::sync::Mutex<std::collections::VecDeque<McStdout>>>,
+}
+
+impl ConsoleHistory {
+    pub fn push(&self, message: McStdout) {
+        let mut messages = self.messages.lock().expect("console history lock poisoned");
+        if messages.len() >= CONSOLE_HISTORY_SIZE {
+            messages.pop_front();
+        }
+        messages.push_back(message);
+    }
+
+    /// the last `count` messages, oldest first
+    pub fn last(&self, count: usize) -> Vec<McStdout> {
+        let messages = self.messages.lock().expect("console history lock poisoned");
+        messages
+            .iter()
+            .skip(messages.len().saturating_sub(count))
+            .cloned()
+            .collect()
+    }
+}
+
+/// how long after a status is published the next one is held back for. the statuses published in the meantime are
+/// coalesced into the last of them
+pub const STATUS_COALESCE_INTERVAL: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// the statuses [`ConsoleOutput`] has published and is holding back
+#[derive(Debug, Default)]
+struct StatusState {
+    /// the last published status and exit code, so a transition is only published once
+    last: Option<(String, u32)>,
+    /// a tokio instant, so tests can pause the clock
+    published_at: Option<tokio::time::Instant>,
+    /// the status waiting for [`STATUS_COALESCE_INTERVAL`] to pass
+    pending: Option<MinecraftServerStatusJson>,
+}
+
+/// where a server publishes its console output: messages are broadcast to the subscribers as they come and kept in a
+/// [`ConsoleHistory`]. both [`MinecraftServer`] implementations publish through it, the internal one from the server
+/// process and the remote one from minimanager's console stream and status responses, so the console and the status
+/// events work the same no matter where the server runs
+#[derive(Clone, Debug)]
+pub struct ConsoleOutput {
+    sender: tokio::sync::broadcast::Sender<McStdout>,
+    history: ConsoleHistory,
+    status: Arc<std::sync::Mutex<StatusState>>,
+}
+
+impl Default for ConsoleOutput {
+    fn default() -> Self {
+        Self {
+            sender: tokio::sync::broadcast::channel(CONSOLE_BUFFER_SIZE).0,
+            history: ConsoleHistory::default(),
+            status: Arc::default(),
+        }
+    }
+}
+
+impl ConsoleOutput {
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<McStdout> {
+        self.sender.subscribe()
+    }
+
+    /// how many are subscribed to the output
+    pub fn receiver_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// the last `count` messages, oldest first
+    pub fn history(&self, count: usize) -> Vec<McStdout> {
+        self.history.last(count)
+    }
+
+    /// sends the message to the subscribers and records it in the history. a status is left out if it's the same as
+    /// the last one, so where the status was learned from doesn't matter, and one coming sooner than
+    /// [`STATUS_COALESCE_INTERVAL`] after the last is held back until it passes, only the latest being published then
+    pub fn publish(&self, message: McStdout) {
+        let McStdout::Status(status) = message else {
+            self.send(message);
+            return;
+        };
+
+        let mut state = self.status.lock().expect("console status lock poisoned");
+        if state.pending.is_some() {
+            state.pending = Some(status);
+            return;
+        }
+        if state.last == Some((status.status.clone(), status.code)) {
+            return;
+        }
+        let wait = state
+            .published_at
+            .map(|published_at| STATUS_COALESCE_INTERVAL.saturating_sub(published_at.elapsed()))
+            .unwrap_or_default();
+        // without a runtime there is nothing to publish it later with
+        if let Ok(runtime) = tokio::runtime::Handle::try_current()
+            && !wait.is_zero()
+        {
+            state.pending = Some(status);
+            let output = self.clone();
+            runtime.spawn(async move {
+                tokio::time::sleep(wait).await;
+                output.flush_status();
+            });
+            return;
+        }
+        self.send_status(&mut state, status);
+    }
+
+    pub fn publish_status(&self, status: MinecraftServerStatusJson) {
+        self.publish(McStdout::Status(status));
+    }
+
+    /// publishes the held back status, unless it ended up the same as the last one
+    fn flush_status(&self) {
+        let mut state = self.status.lock().expect("console status lock poisoned");
+        if let Some(status) = state.pending.take()
+            && state.last != Some((status.status.clone(), status.code))
+        {
+            self.send_status(&mut state, status);
+        }
+    }
+
+    fn send_status(&self, state: &mut StatusState, status: MinecraftServerStatusJson) {
+        state.last = Some((status.status.clone(), status.code));
+        state.published_at = Some(tokio::time::Instant::now());
+        self.send(McStdout::Status(status));
+    }
+
+    fn send(&self, message: McStdout) {
+        self.history.push(message.clone());
+        let _ = self.sender.send(message);
+    }
+}
+
+/// the next message of the console output, for subscribers forwarding it to a client. one that fell behind gets a
+/// [`McStdout::Lagged`] in place of the messages it missed, so the client can tell the output was cut. [`None`] once
+/// the server is dropped
+pub async fn next_output(stdout: &mut tokio::sync::broadcast::Receiver<McStdout>) -> Option<McStdout> {
+    match stdout.recv().await {
+        Ok(message) => Some(message),
+        Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => Some(McStdout::Lagged { skipped }),
+        Err(tokio::sync::broadcast::error::RecvError::Closed) => None,
+    }
+}
+
+/// waits for the server to confirm a save started by [`MinecraftServer::save`]. returns false on timeout
+pub async fn wait_for_save(
+    mut stdout: tokio::sync::broadcast::Receiver<McStdout>,
+    timeout: std::time::Duration,
+) -> bool {
+    let confirmation = async {
+        loop {
+            match stdout.recv().await {
+                Ok(McStdout::Log { message, .. })
+                    if message.contains("Saved the game") || message.contains("Saved the world") =>
+                {
+                    return true;
+                }
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return false,
+            }
+        }
+    };
+    tokio::time::timeout(timeout, confirmation).await.unwrap_or(false)
+}
+
+/// waits for a console line `parse` returns something for, returning it. [`None`] on timeout
+pub async fn wait_for_output<T>(
+    mut stdout: tokio::sync::broadcast::Receiver<McStdout>,
+    timeout: std::time::Duration,
+    mut parse: impl FnMut(&str) -> Option<T>,
+) -> Option<T> {
+    let output = async {
+        loop {
+            match stdout.recv().await {
+                Ok(McStdout::Log { message, .. }) => {
+                    if let Some(parsed) = parse(&message) {
+                        return Some(parsed);
+                    }
+                }
+                Ok(McStdout::Status(_) | McStdout::Lagged { .. })
+                | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    };
+    tokio::time::timeout(timeout, output).await.ok().flatten()
+}
+
+/// how long a server has to finish starting for its setup commands to be run
+const SETUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+/// how many times [`run_setup_commands`] reads the world again when it changed before it could be marked initialized
+const SETUP_CLAIM_ATTEMPTS: u32 = 3;
+
+/// runs the [`World::setup_commands`] once the server is done starting, if they haven't been run yet, and marks the
+/// world as initialized. returns whether they were run
+pub async fn run_setup_commands(
+    id: Id,
+    database: Database,
+    stdout: tokio::sync::broadcast::Receiver<McStdout>,
+    stdin: tokio::sync::mpsc::Sender<MCStdin>,
+) -> Result<bool> {
+    if wait_for_output(stdout, SETUP_TIMEOUT, |line| minecraft::util::is_startup_done(line).then_some(())).await.is_none() {
+        return Ok(false);
+    }
+
+    // the world is read again, the server's copy doesn't know if an earlier start already ran them. it's marked as
+    // initialized before they're run, and only if it didn't change since it was read, so they are run at most once and
+    // an update in between isn't overwritten
+    let mut attempt = 0;
+    let world = loop {
+        let world: World = database.get_one(id, None).await?;
+        if world.initialized {
+            return Ok(false);
+        }
+        let initialized = World { initialized: true, ..world.clone() };
+        match database.update_unchanged(&initialized, None, |stored| *stored == world).await {
+            Ok(()) => break world,
+            Err(DatabaseError::PreconditionFailed) if attempt < SETUP_CLAIM_ATTEMPTS => attempt += 1,
+            Err(err) => return Err(err.into()),
+        }
+    };
+    for command in world.setup_commands.lines() {
+        stdin.send(MCStdin::Command(format!("{command}\n"))).await?;
+    }
+    log::info!("ran the setup commands of {id}");
+    Ok(true)
 }
 
 #[async_trait]
@@ -153,18 +759,73 @@ pub trait MinecraftServer: Send + Debug {
     fn host(&self) -> String;
     /// Where the minecraft server resides
     fn hostname(&self) -> Option<String>;
+    /// the local directory with the server's files. [`None`] if the files aren't stored locally
+    fn directory(&self) -> Option<PathBuf>;
+    /// the CPU and memory limits the server is running with. [`None`] if it's not running, or they aren't known
+    fn resource_limits(&self) -> Option<minecraft::limits::ResourceLimits> {
+        None
+    }
+    /// when the server was started. [`None`] if it's not running, or it isn't known
+    fn started_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        None
+    }
+    /// how long the server has been running. [`None`] if it's not running, or it isn't known
+    fn uptime(&self) -> Option<std::time::Duration> {
+        None
+    }
+    /// how much memory the running server uses, in MiB. [`None`] if it's not running, or it isn't known
+    async fn memory_usage(&mut self) -> Option<u64> {
+        None
+    }
     async fn update_world(&mut self, world: World) -> Result<()>;
+    /// like [`MinecraftServer::update_world`], but a running server isn't restarted, the changes that need it (like
+    /// the allocated memory) apply the next time it starts
+    async fn update_world_without_restart(&mut self, world: World) -> Result<()> {
+        self.update_world(world).await
+    }
+    /// creates the server's files without starting it, so its config can be edited before the first start. doesn't
+    /// reserve a port, that only happens when the server starts
+    async fn prepare(&mut self) -> Result<()>;
+    /// stops the server and starts it again on the same port, so the proxy keeps pointing at it. the proxy reads the
+    /// port through the server's lock, so it never sees the server without one
+    async fn restart(&mut self) -> Result<()>;
+    /// kills the server process right away, without sending `stop` or waiting for it to exit on its own. for servers
+    /// that don't shut down cleanly, anything not saved is lost. returns the status the server ended up with
+    async fn kill(&mut self) -> Result<MinecraftServerStatus>;
     async fn config(&self) -> Result<HashMap<String, String>>;
     async fn set_config(&mut self, config: HashMap<String, String>) -> Result<()>;
     async fn set_icon(&mut self, image: DynamicImage) -> Result<()>;
     async fn latest_log(&mut self) -> Result<String>;
     async fn write_console(&mut self, data: String) -> Result<()>;
     async fn status(&self) -> Result<MinecraftServerStatus>;
+    /// reads the world's `level.dat`. returns [`None`] if the world hasn't been generated yet
+    async fn level_data(&self) -> Result<Option<LevelData>>;
+    /// the directory of the world save, `level-name` inside the server's directory
+    async fn level_directory(&self) -> Result<PathBuf>;
     /// fully removes the server and its files
     async fn remove(&mut self) -> Result<()>;
     /// updates the status of the server. this should return false if the server is updated through somewhere else
     async fn poll(&mut self) -> bool;
     fn stdout(&self) -> tokio::sync::broadcast::Receiver<McStdout>;
+    /// the last `lines` messages of console output (at most [`CONSOLE_HISTORY_SIZE`]), oldest first
+    async fn console_history(&self, lines: usize) -> Result<Vec<McStdout>>;
+
+    /// starts flushing the world to disk with `save-all flush`. the returned receiver is subscribed before the
+    /// command was sent, pass it to [`wait_for_save`] once the server is unlocked
+    async fn save(&mut self) -> Result<tokio::sync::broadcast::Receiver<McStdout>> {
+        let stdout = self.stdout();
+        self.write_console(String::from("save-all flush\n")).await?;
+        Ok(stdout)
+    }
+
+    /// turns automatic saving on or off with `save-on`/`save-off`, so that backups can get a consistent snapshot
+    async fn set_saving(&mut self, enabled: bool) -> Result<()> {
+        if enabled {
+            self.write_console(String::from("save-on\n")).await
+        } else {
+            self.write_console(String::from("save-off\n")).await
+        }
+    }
     /*
     async fn ws_handler(
         &self,
@@ -176,9 +837,14 @@ pub trait MinecraftServer: Send + Debug {
 pub mod internal {
     
 use crate::config::CONFIG;
-    use crate::database::objects::World;
+    use crate::database::Database;
+    use crate::database::objects::{Group, User, Version, World};
     use crate::database::types::Id;
-    use crate::minecraft::server::{MCStdin, McStdout, MinecraftServer, MinecraftServerStatus};
+    use crate::minecraft::hooks::{self, HookContext, HookEvent};
+    use crate::minecraft::limits::ResourceLimits;
+    use crate::minecraft::server::{
+        ConsoleOutput, MCStdin, McStdout, MinecraftServer, MinecraftServerStatus, ServerStartError,
+    };
     use crate::util;
     use async_trait::async_trait;
     use log::{debug, error, info, warn};
@@ -188,25 +854,65 @@ use crate::config::CONFIG;
     use std::io::{BufRead, BufReader, BufWriter, Read, Write};
     use std::path::PathBuf;
     use std::sync::{Arc, LazyLock};
-    use std::time::Duration;
-    use color_eyre::eyre::{bail, ContextCompat};
+    use std::time::{Duration, Instant};
+    use chrono::{DateTime, Utc};
+    use color_eyre::eyre::{bail, ContextCompat, WrapErr};
     use color_eyre::Result;
     use image::{DynamicImage, ImageFormat};
     use image::imageops::FilterType;
     use subprocess::{Exec, ExitStatus, Popen};
     use tokio::sync::{RwLock, Mutex, broadcast, mpsc};
     use crate::database::objects::world::MinecraftServerStatusJson;
+    use crate::minecraft::nbt::LevelData;
 
     pub(crate) static TAKEN_LOCAL_PORTS: LazyLock<std::sync::Mutex<HashSet<u16>>> =
         LazyLock::new(|| std::sync::Mutex::new(HashSet::new()));
 
-    fn get_free_local_port() -> Option<u16> {
-        let servers = TAKEN_LOCAL_PORTS.lock().expect("couldn't get servers");
+    /// the set of ports in use. like the server map, the set is fine to use after a panic, so poisoning is ignored
+    pub(crate) fn taken_local_ports() -> std::sync::MutexGuard<'static, HashSet<u16>> {
+        TAKEN_LOCAL_PORTS
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn get_free_local_port(taken: &HashSet<u16>) -> Option<u16> {
         crate::config::CONFIG
             .world
             .port_range
             .clone()
-            .find(|&port| !servers.contains(&port) && port != CONFIG.proxy.port)
+            .find(|&port| !taken.contains(&port) && !CONFIG.proxy.uses_port(port))
+    }
+
+    /// reserves a port for a server. `reserved` is a port the server already holds (like when it's restarted), it's
+    /// kept so the proxy doesn't have to be pointed elsewhere
+    fn reserve_port(reserved: Option<u16>) -> Option<u16> {
+        let mut taken = taken_local_ports();
+        let port = reserved.or_else(|| get_free_local_port(&taken))?;
+        taken.insert(port);
+        Some(port)
+    }
+
+    /// how long to wait for the process to exit after terminating and then killing it, see [`wait_for_exit`]
+    const TERMINATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// waits for the process to exit in stages. it gets `timeout` to exit on its own, then it's terminated, then
+    /// killed, with `grace` to exit after each. fails if it's still running after that, so nothing that needs it to be
+    /// gone (like deleting its files) happens while it's running
+    async fn wait_for_exit(process: &Mutex<Popen>, timeout: Duration, grace: Duration) -> Result<ExitStatus> {
+        let mut process = process.lock().await;
+        if let Some(status) = process.wait_timeout(timeout)? {
+            return Ok(status);
+        }
+        warn!("the server did not stop in {} seconds, terminating it", timeout.as_secs());
+        process.terminate()?;
+        if let Some(status) = process.wait_timeout(grace)? {
+            return Ok(status);
+        }
+        warn!("the server did not exit after being terminated, killing it");
+        process.kill()?;
+        process
+            .wait_timeout(grace)?
+            .context("the server process did not exit after being killed")
     }
 
     #[derive(Debug)]
@@ -218,8 +924,12 @@ use crate::config::CONFIG;
         hostname: String,
         io: Arc<RwLock<InternalSeverIO>>,
         stdin_tx: Option<mpsc::Sender<MCStdin>>,
-        stdout_tx: broadcast::Sender<McStdout>,
-
+        output: ConsoleOutput,
+        database: Database,
+        /// the limits the running server was started with
+        limits: Option<ResourceLimits>,
+        /// when the running server was started, as an [`Instant`] for measuring the uptime and as the time it's reported as
+        started: Option<(Instant, DateTime<Utc>)>,
     }
     #[derive(Default, Debug)]
     pub struct InternalSeverIO {
@@ -229,21 +939,21 @@ use crate::config::CONFIG;
     }
 
     impl InternalServer {
-        pub async fn new(world: World) -> Result<Self> {
+        pub async fn new(world: World, database: Database) -> Result<Self> {
             let enabled = world.enabled;
 
-            let (stdout_tx, _) = broadcast::channel(128);
-
             let mut new = Self {
                 status: MinecraftServerStatus::Exited(0),
                 hostname: world.hostname.clone(),
-                directory: util::dirs::worlds_dir()
-                    .join(format!("{}/{}", world.owner_id, world.id)),
+                directory: Self::world_directory(&world),
                 port: None,
                 world,
                 io: Arc::default(),
                 stdin_tx: None,
-                stdout_tx,
+                output: ConsoleOutput::default(),
+                database,
+                limits: None,
+                started: None,
             };
             if enabled {
                 new.start().await?;
@@ -251,12 +961,96 @@ use crate::config::CONFIG;
             Ok(new)
         }
 
-        fn initialise_files(&self) -> Result<()> {
+        /// where the files of the world are stored
+        fn world_directory(world: &World) -> PathBuf {
+            util::dirs::worlds_dir().join(format!("{}/{}", world.owner_id, world.id))
+        }
+
+        /// starts the [`HookEvent::AfterStop`] hook, frees the port and removes the server's cgroup, once the process
+        /// has exited. however it exited, stopped, killed or on its own, the hook gets the port the server had
+        fn release_port(&mut self) {
+            hooks::spawn_hook(HookEvent::AfterStop, self.hook_context(), self.directory.clone());
+            taken_local_ports().remove(&self.port.unwrap_or(0));
+            self.port = None;
+            crate::minecraft::limits::remove_cgroup(self.world.id);
+        }
+
+        /// what the [`hooks`] of the server get
+        fn hook_context(&self) -> HookContext {
+            HookContext {
+                id: self.world.id,
+                hostname: self.hostname.clone(),
+                port: self.port,
+            }
+        }
+
+        /// switches the server to the new version of its world. changing the memory or version restarts it, unless
+        /// `restart` is false, then they apply on its next start
+        async fn apply_world(&mut self, world: World, restart: bool) -> Result<()> {
+            let old = self.world();
+            let restart = restart
+                && (old.allocated_memory != world.allocated_memory || old.version_id != world.version_id);
+
+            if old.owner_id != world.owner_id {
+                self.stop().await?;
+                let directory = Self::world_directory(&world);
+                debug!(
+                    "moving {} to {}",
+                    self.directory.display(),
+                    directory.display()
+                );
+                util::move_dir(&self.directory, &directory)?;
+                self.directory = directory;
+            }
+
+            if (old.name != world.name || old.owner_id != world.owner_id)
+                && let Err(err) = self.rerender_motd(&old, &world).await
+            {
+                warn!("failed to update the MOTD of {}: {err}", world.id);
+            }
+
+            let enabled = world.enabled;
+
+            self.hostname = world.hostname.clone();
+            self.world = world;
+
+            if enabled && restart {
+                self.restart().await?;
+            } else if enabled {
+                self.start().await?;
+            } else {
+                self.stop().await?;
+            }
+            Ok(())
+        }
+
+        /// renders [`crate::config::WorldConfig::motd_template`] again for the `world`, whose name or owner changed from
+        /// the `old` one. it applies on the server's next start
+        async fn rerender_motd(&self, old: &World, world: &World) -> Result<()> {
+            let Some(template) = &CONFIG.world.motd_template else {
+                return Ok(());
+            };
+            // without the file the MOTD is rendered when it's created
+            let Ok(properties) = self.read_file("server.properties") else {
+                return Ok(());
+            };
+            let mut properties = crate::minecraft::util::parse_minecraft_properties(&properties);
+            let old_owner: User = self.database.get_one(old.owner_id, None).await?;
+            let owner: User = self.database.get_one(world.owner_id, None).await?;
+            let group = owner.group(self.database.clone(), None).await;
+
+            let previous = crate::minecraft::util::render_motd(template, &old.name, &old_owner.username, &CONFIG.info.name);
+            let motd = crate::minecraft::util::render_motd(template, &world.name, &owner.username, &CONFIG.info.name);
+            crate::minecraft::util::apply_motd(&mut properties, motd, Some(&previous), group.can_edit_config("motd"));
+            let properties = crate::minecraft::util::create_minecraft_properties(properties);
+            self.write_file("server.properties", &properties)
+        }
+
+        /// creates the server directory, `server.properties` and `eula.txt`. the ports are only written when there is
+        /// one, [`MinecraftServer::prepare`] creates the files before the server has a port
+        fn initialise_files(&self, port: Option<u16>, owner: &User, group: &Group) -> Result<()> {
             debug!("creating dir for server {}", self.world.id);
             fs::create_dir_all(self.directory.clone())?;
-            let port = self
-                .port()
-                .context("Port not set, cannot initialise files")?;
 
             //todo: maybe remove files that shouldn't be there
 
@@ -264,9 +1058,22 @@ use crate::config::CONFIG;
                 .read_file("server.properties")
                 .unwrap_or_default();
             let mut properties = crate::minecraft::util::parse_minecraft_properties(&properties);
-            properties.insert(String::from("query.port"), format!("{port}"));
-            properties.insert(String::from("server-port"), format!("{port}",));
-            properties.insert(String::from("rcon.port"), format!("{port}",));
+            if let Some(port) = port {
+                properties.insert(String::from("query.port"), format!("{port}"));
+                properties.insert(String::from("server-port"), format!("{port}",));
+                properties.insert(String::from("rcon.port"), format!("{port}",));
+            }
+            if let Some(template) = &CONFIG.world.motd_template {
+                // the user's own MOTD is kept if they're allowed to set one
+                let motd = crate::minecraft::util::render_motd(
+                    template,
+                    &self.world.name,
+                    &owner.username,
+                    &CONFIG.info.name,
+                );
+                crate::minecraft::util::apply_motd(&mut properties, motd, None, group.can_edit_config("motd"));
+            }
+            crate::minecraft::util::force_properties(&mut properties);
 
             let properties = crate::minecraft::util::create_minecraft_properties(properties);
             debug!("writing server.properties");
@@ -279,12 +1086,55 @@ use crate::config::CONFIG;
         }
 
         async fn start(&mut self) -> Result<()> {
+            self.start_on_port(None).await
+        }
+
+        /// starts the server on the `reserved` port if there is one, otherwise on a free port
+        async fn start_on_port(&mut self, reserved: Option<u16>) -> Result<()> {
             let jar_path =
                 util::dirs::versions_dir().join(format!("{}.jar", self.world.version_id));
             if !jar_path.exists() {
-                bail!("{} doesn't exist", jar_path.display());
+                warn!("cannot start {}: {} doesn't exist", self.world.id, jar_path.display());
+                self.status = MinecraftServerStatus::Exited(1);
+                return Err(ServerStartError::JarMissing {
+                    version_id: self.world.version_id,
+                }
+                .into());
             }
 
+            let version: Version = self.database.get_one(self.world.version_id, None).await?;
+            let verified = {
+                let (jar_path, version) = (jar_path.clone(), version.clone());
+                tokio::task::spawn_blocking(move || super::verify_jar(&jar_path, &version)).await?
+            };
+            if let Err(err) = verified {
+                warn!("cannot start {}: {err}", self.world.id);
+                self.status = MinecraftServerStatus::Exited(1);
+                return Err(err);
+            }
+            let required_java = version
+                .required_java
+                .map(u32::try_from)
+                .transpose()?;
+            let java = crate::minecraft::util::java_binary(required_java)?;
+            let java_version = required_java.or(*crate::minecraft::util::JAVA_VERSION);
+            if let Some(min_java) = version.min_java
+                && let Some(java_version) = java_version
+                && i64::from(java_version) < i64::from(min_java)
+            {
+                self.status = MinecraftServerStatus::Exited(1);
+                return Err(ServerStartError::JavaTooOld {
+                    minecraft_version: version.minecraft_version,
+                    min_java,
+                    java_version,
+                }
+                .into());
+            }
+
+            let owner: User = self.database.get_one(self.world.owner_id, None).await?;
+            let group = owner.group(self.database.clone(), None).await;
+            let limits = ResourceLimits::for_world(&self.world, &group);
+
             if !self.directory.exists() {
                 std::fs::create_dir_all(&self.directory)?;
             }
@@ -294,24 +1144,28 @@ use crate::config::CONFIG;
                 return Ok(());
             }
 
-            let port = get_free_local_port().context("No free ports left")?;
+            let port = reserve_port(reserved).context("No free ports left")?;
             info!("assigning port {} for {}", port, self.world.id);
             self.port = Some(port);
-            TAKEN_LOCAL_PORTS
-                .lock()
-                .expect("failed to lock local ports")
-                .insert(port);
 
             self
-                .initialise_files()
+                .initialise_files(Some(port), &owner, &group)
                 .inspect_err(|_| {
-                    TAKEN_LOCAL_PORTS
-                        .lock()
-                        .expect("failed to lock local ports")
-                        .remove(&self.port.unwrap());
+                    taken_local_ports().remove(&self.port.unwrap());
                 })?;
+            if let Err(err) = hooks::run_hook(HookEvent::BeforeStart, self.hook_context(), &self.directory).await {
+                debug!("not starting {}: {err}", self.world.id);
+                taken_local_ports().remove(&port);
+                self.port = None;
+                self.status = MinecraftServerStatus::Exited(1);
+                return Err(ServerStartError::HookFailed.into());
+            }
             debug!("starting server {}", self.id());
             let command = CONFIG.world.java_launch_command.clone();
+            let command = command.replace(
+                "%java%",
+                &format!("'{}'", java.display().to_string().replace('\'', "'\\''")),
+            );
             let command = command.replace("%jar%", jar_path.display().to_string().as_str());
             let command = command.replace(
                 "%min_mem%",
@@ -321,6 +1175,7 @@ use crate::config::CONFIG;
                 "%max_mem%",
                 &format!("-Xmx{}m", self.world.allocated_memory),
             );
+            let command = limits.wrap_command(self.world.id, &command);
             //println!("{command}");
             let mut command = Exec::shell(command)
                 .cwd(self.directory.clone())
@@ -334,17 +1189,30 @@ use crate::config::CONFIG;
                 .expect(":<");
 
             let (stdin_tx, mut stdin_rx) = mpsc::channel(64);
+            if !self.world.setup_commands.is_empty() {
+                tokio::task::spawn({
+                    let id = self.world.id;
+                    let database = self.database.clone();
+                    let stdout = self.output.subscribe();
+                    let stdin = stdin_tx.clone();
+                    async move {
+                        if let Err(err) = super::run_setup_commands(id, database, stdout, stdin).await {
+                            error!("could not run the setup commands of {id}: {err}");
+                        }
+                    }
+                });
+            }
             self.stdin_tx = Some(stdin_tx);
 
             let out_task = tokio::task::spawn({
-                let stdout_tx = self.stdout_tx.clone();
+                let output = self.output.clone();
                 let stdout = command.stdout.take().unwrap();
                 async move {
                     //output
                     let reader = BufReader::new(stdout);
                     for (seq, line) in reader.lines().enumerate() {
                         let message = line.expect("invalid output line");
-                        let _ = stdout_tx.send(McStdout::Log{seq, message});
+                        output.publish(McStdout::Log{seq, message});
                     }
                 }
             });
@@ -372,52 +1240,55 @@ use crate::config::CONFIG;
 
 
             self.status = MinecraftServerStatus::Running;
-            _ = self.stdout_tx.send(McStdout::Status(MinecraftServerStatusJson::from(self.status)));
+            self.limits = Some(limits);
+            self.started = Some((Instant::now(), Utc::now()));
+            self.output.publish_status(
+                MinecraftServerStatusJson::from(self.status)
+                    .with_address(self.port, Some(self.hostname.clone()))
+                    .with_uptime(self.started_at(), self.uptime()),
+            );
+            hooks::spawn_hook(HookEvent::AfterStart, self.hook_context(), self.directory.clone());
 
             Ok(())
         }
 
         async fn stop(&mut self) -> Result<()> {
+            if self.stop_process().await? {
+                self.release_port();
+            }
+            Ok(())
+        }
+
+        /// stops the server process without releasing its port. returns whether it was running
+        async fn stop_process(&mut self) -> Result<bool> {
             let stop_result = self.write_console(String::from("stop\n")).await;
             let process = if let Some(process) = self.io.read().await.process.clone() {
                 process
             } else {
                 debug!("not stopping process as it is not running");
-                return Ok(());
+                return Ok(false);
             };
 
             if stop_result.is_err() {
                 let _ = process.lock().await.kill();
             }
 
-            let result = process.lock().await.wait_timeout(Duration::from_secs(
-                crate::config::CONFIG.world.stop_timeout,
-            ))?;
-
-            TAKEN_LOCAL_PORTS
-                .lock()
-                .expect("failed to lock local ports")
-                .remove(&self.port.unwrap_or(0));
-            self.port = None;
-
-            match result {
-                Some(status) => {
-                    info!("stopped server {} with status {:?}", self.id(), status);
-                    if let ExitStatus::Exited(code) = status {
-                        self.status = MinecraftServerStatus::Exited(code);
-                    } else {
-                        self.status = MinecraftServerStatus::Exited(1);
-                    }
-                }
-                None => {
-                    process.lock().await.terminate()?;
-                    warn!("stopped server {} after timeout period elapsed", self.id());
-                    self.status = MinecraftServerStatus::Exited(1);
-                }
-            }
-            let _ = self.stdout_tx.send(McStdout::Status(MinecraftServerStatusJson::from(self.status)));
-
-            Ok(())
+            // the server keeps its port and status if it couldn't be stopped, it's still running
+            let status = wait_for_exit(
+                &process,
+                Duration::from_secs(crate::config::CONFIG.world.stop_timeout),
+                TERMINATE_TIMEOUT,
+            )
+            .await
+            .with_context(|| format!("could not stop server {}", self.id()))?;
+            self.io.write().await.process = None;
+
+            info!("stopped server {} with status {:?}", self.id(), status);
+            self.status = MinecraftServerStatus::from(status);
+            self.started = None;
+            self.output.publish_status(MinecraftServerStatusJson::from(self.status));
+
+            Ok(true)
         }
 
         /*
@@ -493,24 +1364,79 @@ use crate::config::CONFIG;
             Some(self.hostname.clone())
         }
 
+        fn directory(&self) -> Option<PathBuf> {
+            Some(self.directory.clone())
+        }
+
+        fn resource_limits(&self) -> Option<ResourceLimits> {
+            self.limits.filter(|_| matches!(self.status, MinecraftServerStatus::Running))
+        }
+
+        fn started_at(&self) -> Option<DateTime<Utc>> {
+            self.started.map(|(_, at)| at)
+        }
+
+        fn uptime(&self) -> Option<Duration> {
+            self.started.map(|(instant, _)| instant.elapsed())
+        }
+
+        async fn memory_usage(&mut self) -> Option<u64> {
+            let process = self.io.read().await.process.clone()?;
+            let pid = process.lock().await.pid()?;
+            crate::minecraft::limits::process_memory(pid)
+        }
+
         async fn update_world(&mut self, world: World) -> Result<()> {
-            let old = self.world();
-            if old.allocated_memory != world.allocated_memory || old.version_id != world.version_id
-            {
-                self.stop().await?;
-            }
+            self.apply_world(world, true).await
+        }
 
-            let enabled = world.enabled;
+        async fn update_world_without_restart(&mut self, world: World) -> Result<()> {
+            self.apply_world(world, false).await
+        }
 
-            self.hostname = world.hostname.clone();
-            self.world = world;
+        async fn prepare(&mut self) -> Result<()> {
+            let owner: User = self.database.get_one(self.world.owner_id, None).await?;
+            let group = owner.group(self.database.clone(), None).await;
+            self.initialise_files(None, &owner, &group)
+        }
 
-            if enabled {
-                self.start().await?;
-            } else {
-                self.stop().await?;
-            }
-            Ok(())
+        async fn restart(&mut self) -> Result<()> {
+            // the port is only still ours if the process was running, otherwise it was freed when it exited
+            let reserved = if self.stop_process().await? { self.port } else { None };
+            self.start_on_port(reserved).await.inspect_err(|_| {
+                if let Some(port) = reserved
+                    && self.port == Some(port)
+                {
+                    taken_local_ports().remove(&port);
+                    self.port = None;
+                }
+            })
+        }
+
+        async fn kill(&mut self) -> Result<MinecraftServerStatus> {
+            let Some(process) = self.io.read().await.process.clone() else {
+                debug!("not killing process as it is not running");
+                return Ok(self.status);
+            };
+
+            warn!("killing server {}", self.id());
+            let status = {
+                let mut process = process.lock().await;
+                process.kill()?;
+                process
+                    .wait_timeout(TERMINATE_TIMEOUT)?
+                    .context("the server process did not exit after being killed")?
+            };
+            self.io.write().await.process = None;
+
+            self.release_port();
+
+            info!("killed server {} with status {:?}", self.id(), status);
+            self.status = MinecraftServerStatus::from(status);
+            self.started = None;
+            self.output.publish_status(MinecraftServerStatusJson::from(self.status));
+
+            Ok(self.status)
         }
 
         async fn config(&self) -> Result<HashMap<String, String>> {
@@ -522,7 +1448,8 @@ use crate::config::CONFIG;
             ))
         }
 
-        async fn set_config(&mut self, config: HashMap<String, String>) -> Result<()> {
+        async fn set_config(&mut self, mut config: HashMap<String, String>) -> Result<()> {
+            crate::minecraft::util::force_properties(&mut config);
             let properties = crate::minecraft::util::create_minecraft_properties(config);
             self.write_file("server.properties", &properties)?;
             Ok(())
@@ -549,11 +1476,8 @@ use crate::config::CONFIG;
                 stdin_tx.send(MCStdin::Command(data)).await?;
                 Ok(())
             } else {
-                match self.status {
-                    MinecraftServerStatus::Exited(_) => {}
-                    _ => {
-                        self.status = MinecraftServerStatus::Exited(1);
-                    }
+                if matches!(self.status, MinecraftServerStatus::Running) {
+                    self.status = MinecraftServerStatus::Exited(1);
                 }
                 bail!("Cannot write to console: server is not running");
             }
@@ -585,8 +1509,35 @@ use crate::config::CONFIG;
             Ok(self.status)
         }
 
+        async fn level_directory(&self) -> Result<PathBuf> {
+            let properties = self.config().await?;
+            let level_name = properties
+                .get("level-name")
+                .filter(|name| !name.is_empty())
+                .map(String::as_str)
+                .unwrap_or("world");
+            let level_name = PathBuf::from(level_name);
+            if !level_name
+                .components()
+                .all(|component| matches!(component, std::path::Component::Normal(_)))
+            {
+                bail!("invalid level-name: {}", level_name.display());
+            }
+            Ok(self.directory.join(level_name))
+        }
+
+        async fn level_data(&self) -> Result<Option<LevelData>> {
+            let path = self.level_directory().await?.join("level.dat");
+            if !path.exists() {
+                return Ok(None);
+            }
+            Ok(Some(LevelData::from_level_dat(&fs::read(path)?)?))
+        }
+
         async fn remove(&mut self) -> Result<()> {
             self.stop().await?;
+            // left behind by a server that ran before mcmanager was restarted
+            crate::minecraft::limits::remove_cgroup(self.world.id);
             debug!("removing directory {}", self.directory.display());
             if self.directory.exists() {
                 std::fs::remove_dir_all(self.directory.clone())?;
@@ -601,32 +1552,176 @@ use crate::config::CONFIG;
             if let Some(exit_status) = exit_status {
                 self.io.write().await.process = None;
 
-                match exit_status {
-                    ExitStatus::Exited(code) => {
-                        self.status = MinecraftServerStatus::Exited(code);
-                    }
-                    _ => {
-                        self.status = MinecraftServerStatus::Exited(1);
-                    }
-                }
-                _ = self.stdout_tx.send(McStdout::Status(MinecraftServerStatusJson::from(self.status)));
+                self.status = MinecraftServerStatus::from(exit_status);
+                self.started = None;
+                self.output.publish_status(MinecraftServerStatusJson::from(self.status));
                 info!(
-                    "freed the port {} of {} because the server running on it has exited",
+                    "freeing the port {} of {} because the server running on it has exited",
                     self.port.unwrap_or(0),
                     self.world.id
                 );
-                TAKEN_LOCAL_PORTS
-                    .lock()
-                    .expect("failed to lock local ports")
-                    .remove(&self.port.unwrap_or(0));
+                self.release_port();
             }
             true
         }
 
         fn stdout(&self) -> broadcast::Receiver<McStdout> {
-            self.stdout_tx.subscribe()
+            self.output.subscribe()
+        }
+
+        async fn console_history(&self, lines: usize) -> Result<Vec<McStdout>> {
+            Ok(self.output.history(lines))
+        }
+
+    }
+
+    #[tokio::test]
+    async fn wait_for_exit_test() {
+        // ignores SIGTERM, so it has to be killed
+        let process = Exec::cmd("sh")
+            .args(&["-c", "trap '' TERM; while true; do sleep 1; done"])
+            .popen()
+            .unwrap();
+        let process = Mutex::new(process);
+        let status = wait_for_exit(&process, Duration::from_millis(100), Duration::from_millis(500))
+            .await
+            .unwrap();
+        assert_eq!(status, ExitStatus::Signaled(9));
+
+        let process = Mutex::new(Exec::cmd("true").popen().unwrap());
+        let status = wait_for_exit(&process, Duration::from_secs(5), Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert_eq!(status, ExitStatus::Exited(0));
+    }
+
+    #[tokio::test]
+    async fn exited_port_test() {
+        use crate::database::testing::test_world;
+
+        let mut server = InternalServer::new(test_world(Id::default(), Id::default()), Database::memory_unchecked().await)
+            .await
+            .unwrap();
+
+        // exits on its own, and is found by polling
+        let port = reserve_port(None).unwrap();
+        server.port = Some(port);
+        let process = Arc::new(Mutex::new(Exec::cmd("true").popen().unwrap()));
+        server.io.write().await.process = Some(process.clone());
+        process.lock().await.wait().unwrap();
+        server.poll().await;
+        assert_eq!(server.port, None);
+        assert!(!taken_local_ports().contains(&port));
+
+        let port = reserve_port(None).unwrap();
+        server.port = Some(port);
+        let process = Exec::cmd("sleep").arg("10").popen().unwrap();
+        server.io.write().await.process = Some(Arc::new(Mutex::new(process)));
+        assert!(matches!(server.kill().await.unwrap(), MinecraftServerStatus::Signaled(9)));
+        assert_eq!(server.port, None);
+        assert!(!taken_local_ports().contains(&port));
+    }
+
+    #[test]
+    fn port_allocations_test() {
+        use crate::minecraft::server::PortAllocations;
+
+        let start = CONFIG.world.port_range.start;
+        let world = Id::new_random();
+        let taken = HashSet::from([start, start + 1]);
+        let allocations = PortAllocations::new(&taken, &HashMap::from([(start, world)]));
+        assert_eq!(allocations.taken[&start], Some(world));
+        assert_eq!(allocations.taken[&(start + 1)], None);
+        assert!(allocations.free <= CONFIG.world.port_range.len() - 2);
+    }
+
+    #[test]
+    fn reconcile_ports_test() {
+        let start = CONFIG.world.port_range.start;
+        // start + 1 was leaked by a server that exited, start + 2 was reserved by a server starting meanwhile
+        let snapshot = HashSet::from([start, start + 1]);
+        let mut taken = HashSet::from([start, start + 1, start + 2]);
+        let freed = super::reconcile_ports(&mut taken, &snapshot, &HashSet::from([start]));
+        assert_eq!(freed, vec![start + 1]);
+        assert_eq!(taken, HashSet::from([start, start + 2]));
+
+        // a running server whose port was freed gets it back
+        let snapshot = taken.clone();
+        let freed = super::reconcile_ports(&mut taken, &snapshot, &HashSet::from([start, start + 3]));
+        assert_eq!(freed, vec![start + 2]);
+        assert_eq!(taken, HashSet::from([start, start + 3]));
+    }
+
+    #[test]
+    fn reserve_port_test() {
+        let Some(port) = reserve_port(None) else {
+            return;
+        };
+        // a restarted server keeps its port, nothing else can take it in the meantime
+        assert_eq!(reserve_port(Some(port)), Some(port));
+        assert!(taken_local_ports().contains(&port));
+        if let Some(other) = reserve_port(None) {
+            assert_ne!(other, port);
+            taken_local_ports().remove(&other);
         }
+        taken_local_ports().remove(&port);
+    }
 
+    // waiting for the process blocks a thread
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn remove_world_test() {
+        use crate::api::filters::UserAuth;
+        use crate::api::handlers::ApiRemove;
+        use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+        use axum::extract::{Path, State};
+        use axum::http::{HeaderMap, StatusCode};
+
+        let database = Database::memory().await;
+        let group = test_group();
+        database.insert(&group, None).await.unwrap();
+        let owner = User { username: String::from("owner"), group_id: group.id, ..Default::default() };
+        let owner = database.create_user_from(owner, "hunter2").await.unwrap();
+        let version = insert_test_version(&database).await;
+        let world = test_world(owner.id, version.id);
+        database.insert(&world, None).await.unwrap();
+        let state = test_state(database.clone());
+
+        // a server that keeps running after it's told to stop, until the test lets it exit
+        let mut server = InternalServer::new(world.clone(), database.clone()).await.unwrap();
+        fs::create_dir_all(&server.directory).unwrap();
+        let process = Exec::cmd("sh")
+            .args(&["-c", "while [ ! -e exit ]; do sleep 0.05; done"])
+            .cwd(&server.directory)
+            .popen()
+            .unwrap();
+        let process = Arc::new(Mutex::new(process));
+        server.io.write().await.process = Some(process.clone());
+        let (stdin_tx, mut stdin_rx) = mpsc::channel(1);
+        server.stdin_tx = Some(stdin_tx);
+        server.status = MinecraftServerStatus::Running;
+        let directory = server.directory.clone();
+        state.servers.add_server(Box::new(server)).await;
+
+        let remove = tokio::spawn(World::api_remove(
+            Path(world.id),
+            State(state.clone()),
+            UserAuth(owner.clone()),
+            HeaderMap::new(),
+        ));
+        assert!(matches!(stdin_rx.recv().await, Some(MCStdin::Command(command)) if command == "stop\n"));
+        // while it's stopping the world and its files are still there
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!remove.is_finished());
+        assert!(database.get_one::<World>(world.id, None).await.is_ok());
+        assert!(directory.exists());
+
+        fs::write(directory.join("exit"), "").unwrap();
+        assert_eq!(remove.await.unwrap(), Ok(StatusCode::NO_CONTENT));
+        assert!(process.lock().await.poll().is_some());
+        assert!(!directory.exists());
+        assert!(database.get_one::<World>(world.id, None).await.is_err());
+        assert!(state.servers.get_server(world.id).await.is_none());
+        let _ = fs::remove_dir(directory.parent().unwrap());
     }
 
     /*
@@ -644,19 +1739,43 @@ pub mod external {
     use crate::config::CONFIG;
     use crate::database::objects::World;
     use crate::database::types::Id;
-    use crate::minecraft::server::{McStdout, MinecraftServer, MinecraftServerStatus, Server};
+    use crate::minecraft::nbt::LevelData;
+    use crate::minecraft::server::{
+        ConsoleOutput, FilesNotAccessible, McStdout, MinecraftServer, MinecraftServerStatus, MinecraftServerStatusJson, Server,
+    };
+    use std::path::PathBuf;
     use color_eyre::{Result};
     use async_trait::async_trait;
-    use log::debug;
+    use log::{debug, warn};
     
     use std::collections::HashMap;
+    use std::sync::LazyLock;
+    use std::time::Duration;
     
-    
-    use color_eyre::eyre::bail;
+    use color_eyre::eyre::{bail, eyre};
     use image::DynamicImage;
     
     use tokio::sync::broadcast::Receiver;
-    
+    use tokio::task::JoinHandle;
+
+    /// how long to wait before reconnecting to the remote console stream
+    const RELAY_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+    /// delay before the first retry of a failed minimanager request
+    const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+    /// longest delay between retries
+    const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(10);
+
+    /// client shared by all requests to minimanager, so connections are kept open and reused. it has no overall
+    /// timeout, as the console stream stays open, requests set their own
+    static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+        reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(5))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(16)
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()
+            .expect("failed to build the minimanager client")
+    });
 
     #[derive(Debug)]
     pub struct MinimanagerServer {
@@ -664,37 +1783,120 @@ pub mod external {
         port: Option<u16>,
         hostname: String,
         world: World,
+        output: ConsoleOutput,
+        stdout_relay: JoinHandle<()>,
     }
 
     impl MinimanagerServer {
         pub fn new(host: String, world: World) -> Self {
+            let output = ConsoleOutput::default();
             Self {
                 hostname: world.hostname.clone(),
                 host,
                 port: None,
+                stdout_relay: tokio::task::spawn(relay_stdout(world.id, output.clone())),
+                output,
                 world,
             }
         }
 
+        /// publishes the status minimanager reported, if it changed. minimanager's console stream reports the status
+        /// too, but it's only read while someone is subscribed
+        fn publish_status(&self, server: &Server) {
+            self.output.publish_status(
+                MinecraftServerStatusJson::from(server.status).with_address(server.port, self.hostname()),
+            );
+        }
+
         pub async fn server(&self) -> Result<Server> {
             debug!("Requesting minimanager to update server");
-            let client = reqwest::Client::new();
             Ok(serde_json::from_str(
-                &client
-                    .post(format!("{}api/worlds", CONFIG.remote.host))
-                    .header(
-                        "Authorization",
-                        format!("Bearer {}", crate::config::secrets::SECRETS.api_secret),
-                    )
-                    .body(serde_json::to_string(&self.world).unwrap())
-                    .send()
-                    .await?
-                    .text()
-                    .await?,
+                &post_with_retry(
+                    &CLIENT,
+                    &format!("{}api/worlds", CONFIG.remote.host),
+                    &crate::config::secrets::SECRETS.api_secret,
+                    serde_json::to_string(&self.world).unwrap(),
+                    &RetryPolicy::from_config(),
+                )
+                .await?,
             )?)
         }
     }
 
+    /// how requests to minimanager are retried
+    #[derive(Debug, Clone, Copy)]
+    struct RetryPolicy {
+        retries: u32,
+        timeout: Duration,
+        /// delay before the first retry, doubled with every next one
+        backoff: Duration,
+    }
+
+    impl RetryPolicy {
+        fn from_config() -> Self {
+            Self {
+                retries: CONFIG.remote.retries,
+                timeout: Duration::from_secs(CONFIG.remote.timeout),
+                backoff: RETRY_BACKOFF,
+            }
+        }
+
+        fn delay(&self, attempt: u32) -> Duration {
+            self.backoff
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(MAX_RETRY_BACKOFF)
+        }
+    }
+
+    /// sends an authenticated POST to minimanager, returning the response body. timeouts, connection errors and 5xx
+    /// responses are retried with exponential backoff, other failures are returned right away
+    async fn post_with_retry(
+        client: &reqwest::Client,
+        url: &str,
+        secret: &str,
+        body: String,
+        policy: &RetryPolicy,
+    ) -> Result<String> {
+        let mut attempt = 0;
+        loop {
+            let result = client
+                .post(url)
+                .header("Authorization", format!("Bearer {secret}"))
+                .timeout(policy.timeout)
+                .body(body.clone())
+                .send()
+                .await;
+
+            let err = match result {
+                Ok(response) if response.status().is_server_error() => {
+                    eyre!("minimanager responded with {}", response.status())
+                }
+                Ok(response) if !response.status().is_success() => {
+                    let status = response.status();
+                    let text = response.text().await.unwrap_or_default();
+                    bail!("minimanager rejected the request with {status}: {text}");
+                }
+                Ok(response) => match response.text().await {
+                    Ok(text) => return Ok(text),
+                    Err(err) => err.into(),
+                },
+                Err(err) if err.is_timeout() || err.is_connect() || err.is_request() => err.into(),
+                Err(err) => return Err(err.into()),
+            };
+
+            if attempt >= policy.retries {
+                bail!(
+                    "minimanager request to {url} failed after {} attempts: {err}",
+                    attempt + 1
+                );
+            }
+            let delay = policy.delay(attempt);
+            warn!("minimanager request to {url} failed, retrying in {delay:?}: {err}");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
     #[async_trait]
     impl MinecraftServer for MinimanagerServer {
         fn id(&self) -> Id {
@@ -717,15 +1919,19 @@ pub mod external {
             Some(self.hostname.clone())
         }
 
+        fn directory(&self) -> Option<PathBuf> {
+            None
+        }
+
         async fn update_world(&mut self, world: World) -> Result<()> {
             self.hostname = world.hostname.clone();
             self.world = world;
-            //let client = reqwest::Client::new();
             let server = self.server().await;
 
             match server {
                 Ok(server) => {
                     self.port = server.port;
+                    self.publish_status(&server);
                     Ok(())
                 }
                 Err(err) => {
@@ -734,6 +1940,19 @@ pub mod external {
             }
         }
 
+        async fn prepare(&mut self) -> Result<()> {
+            // minimanager creates the files when it starts the server
+            Ok(())
+        }
+
+        async fn restart(&mut self) -> Result<()> {
+            bail!("restarting minimanager servers is not supported")
+        }
+
+        async fn kill(&mut self) -> Result<MinecraftServerStatus> {
+            bail!("killing minimanager servers is not supported")
+        }
+
         async fn config(&self) -> Result<HashMap<String, String>> {
             todo!()
         }
@@ -756,27 +1975,29 @@ pub mod external {
 
         async fn status(&self) -> Result<MinecraftServerStatus, color_eyre::eyre::Error> {
             let server = self.server().await?;
+            self.publish_status(&server);
             Ok(server.status)
         }
 
+        async fn level_directory(&self) -> Result<PathBuf> {
+            Err(FilesNotAccessible.into())
+        }
+
+        async fn level_data(&self) -> Result<Option<LevelData>> {
+            Err(FilesNotAccessible.into())
+        }
+
         async fn remove(&mut self) -> Result<()> {
             debug!("Requesting minimanager to remvoe server");
-            let client = reqwest::Client::new();
             Ok(serde_json::from_str(
-                &client
-                    .post(format!(
-                        "{}api/worlds/remove",
-                        CONFIG.remote.host
-                    ))
-                    .header(
-                        "Authorization",
-                        format!("Bearer {}", crate::config::secrets::SECRETS.api_secret),
-                    )
-                    .body(serde_json::to_string(&self.world).unwrap())
-                    .send()
-                    .await?
-                    .text()
-                    .await?,
+                &post_with_retry(
+                    &CLIENT,
+                    &format!("{}api/worlds/remove", CONFIG.remote.host),
+                    &crate::config::secrets::SECRETS.api_secret,
+                    serde_json::to_string(&self.world).unwrap(),
+                    &RetryPolicy::from_config(),
+                )
+                .await?,
             )?)
         }
 
@@ -785,18 +2006,195 @@ pub mod external {
         }
 
         fn stdout(&self) -> Receiver<McStdout> {
-            todo!()
+            self.output.subscribe()
+        }
+
+        /// only has the output relayed while someone was subscribed to the console
+        async fn console_history(&self, lines: usize) -> Result<Vec<McStdout>> {
+            Ok(self.output.history(lines))
+        }
+    }
+
+    impl Drop for MinimanagerServer {
+        fn drop(&mut self) {
+            self.stdout_relay.abort();
+        }
+    }
+
+    /// republishes the remote server's console into `output`, reconnecting whenever the connection drops.
+    /// the connection is only kept open while someone is subscribed to the console
+    async fn relay_stdout(id: Id, output: ConsoleOutput) {
+        loop {
+            if output.receiver_count() > 0
+                && let Err(err) = read_remote_stdout(&CLIENT, id, &output).await
+            {
+                warn!("console relay for {id} dropped: {err}");
+            }
+            tokio::time::sleep(RELAY_RECONNECT_DELAY).await;
         }
     }
+
+    /// reads minimanager's console stream, which is a newline-delimited list of JSON encoded [`McStdout`] messages
+    async fn read_remote_stdout(
+        client: &reqwest::Client,
+        id: Id,
+        output: &ConsoleOutput,
+    ) -> Result<()> {
+        debug!("connecting to the remote console of {id}");
+        let mut response = client
+            .get(format!("{}api/worlds/{id}/console", CONFIG.remote.host))
+            .header(
+                "Authorization",
+                format!("Bearer {}", crate::config::secrets::SECRETS.api_secret),
+            )
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let mut buffer = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+                let line = buffer.drain(..=end).collect::<Vec<_>>();
+                let line = line.trim_ascii();
+                if line.is_empty() {
+                    continue;
+                }
+                match serde_json::from_slice::<McStdout>(line) {
+                    Ok(message) => output.publish(message),
+                    Err(err) => warn!("invalid console message from the remote console of {id}: {err}"),
+                }
+            }
+            if output.receiver_count() == 0 {
+                debug!("nobody is listening to the remote console of {id}, disconnecting");
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn post_with_retry_test() {
+        use axum::http::StatusCode;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        // fails twice with a 503, then succeeds. /bad always fails with a 400
+        let hits = Arc::new(AtomicU32::new(0));
+        let router = axum::Router::new()
+            .route(
+                "/flaky",
+                axum::routing::post({
+                    let hits = hits.clone();
+                    move || async move {
+                        if hits.fetch_add(1, Ordering::SeqCst) < 2 {
+                            (StatusCode::SERVICE_UNAVAILABLE, "")
+                        } else {
+                            (StatusCode::OK, "ok")
+                        }
+                    }
+                }),
+            )
+            .route(
+                "/bad",
+                axum::routing::post({
+                    let hits = hits.clone();
+                    move || async move {
+                        hits.fetch_add(1, Ordering::SeqCst);
+                        (StatusCode::BAD_REQUEST, "no")
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let client = reqwest::Client::new();
+        let policy = RetryPolicy {
+            retries: 3,
+            timeout: Duration::from_secs(5),
+            backoff: Duration::from_millis(1),
+        };
+        let url = |path: &str| format!("http://{address}{path}");
+
+        let body = post_with_retry(&client, &url("/flaky"), "secret", String::new(), &policy).await;
+        assert_eq!(body.unwrap(), "ok");
+        assert_eq!(hits.swap(0, Ordering::SeqCst), 3);
+
+        // client errors are not retried
+        assert!(post_with_retry(&client, &url("/bad"), "secret", String::new(), &policy).await.is_err());
+        assert_eq!(hits.swap(0, Ordering::SeqCst), 1);
+
+        // retries run out
+        let policy = RetryPolicy { retries: 1, ..policy };
+        assert!(post_with_retry(&client, &url("/flaky"), "secret", String::new(), &policy).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        assert_eq!(RetryPolicy::from_config().delay(0), RETRY_BACKOFF);
+        assert_eq!(RetryPolicy::from_config().delay(2), RETRY_BACKOFF * 4);
+        assert_eq!(RetryPolicy::from_config().delay(30), MAX_RETRY_BACKOFF);
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum ServerConfigLimit {
     MoreThan(i64),
     LessThan(i64),
+    /// inclusive range, written as `min..max`
+    Range(i64, i64),
+    /// the whole value has to match the pattern, written as `re:pattern`
+    Regex(ConfigPattern),
     Whitelist(Vec<String>),
 }
 
+/// longest pattern a [`ServerConfigLimit::Regex`] can have
+const MAX_PATTERN_LENGTH: usize = 256;
+/// how large a compiled [`ServerConfigLimit::Regex`] can get, in bytes. the regex engine runs in linear time, so this
+/// is what keeps a pattern from being expensive
+const MAX_PATTERN_SIZE: usize = 64 * 1024;
+
+/// a compiled [`ServerConfigLimit::Regex`] pattern
+#[derive(Debug, Clone)]
+pub struct ConfigPattern {
+    pattern: String,
+    regex: regex::Regex,
+}
+
+impl ConfigPattern {
+    pub fn new(pattern: &str) -> result::Result<Self, String> {
+        if pattern.len() > MAX_PATTERN_LENGTH {
+            return Err(format!(
+                "the pattern is longer than {MAX_PATTERN_LENGTH} characters"
+            ));
+        }
+        let regex = regex::RegexBuilder::new(&format!("^(?:{pattern})$"))
+            .size_limit(MAX_PATTERN_SIZE)
+            .dfa_size_limit(MAX_PATTERN_SIZE)
+            .build()
+            .map_err(|err| format!("invalid pattern: {err}"))?;
+        Ok(Self {
+            pattern: pattern.to_string(),
+            regex,
+        })
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl PartialEq for ConfigPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl Eq for ConfigPattern {}
+
 impl Serialize for ServerConfigLimit {
     fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
     where
@@ -805,6 +2203,10 @@ impl Serialize for ServerConfigLimit {
         match self {
             ServerConfigLimit::MoreThan(val) => serializer.serialize_str(&format!(">{val}")),
             ServerConfigLimit::LessThan(val) => serializer.serialize_str(&format!("<{val}")),
+            ServerConfigLimit::Range(min, max) => serializer.serialize_str(&format!("{min}..{max}")),
+            ServerConfigLimit::Regex(pattern) => {
+                serializer.serialize_str(&format!("re:{}", pattern.pattern))
+            }
             ServerConfigLimit::Whitelist(vals) => serializer.serialize_str(&vals.join("|")),
         }
     }
@@ -817,7 +2219,11 @@ impl<'de> Deserialize<'de> for ServerConfigLimit {
     {
         let s = String::deserialize(deserializer)?;
 
-        if let Some(val) = s.strip_prefix(">") {
+        if let Some(pattern) = s.strip_prefix("re:") {
+            return ConfigPattern::new(pattern)
+                .map(ServerConfigLimit::Regex)
+                .map_err(serde::de::Error::custom);
+        } else if let Some(val) = s.strip_prefix(">") {
             if let Ok(val) = val.parse() {
                 return Ok(ServerConfigLimit::MoreThan(val));
             };
@@ -825,9 +2231,444 @@ impl<'de> Deserialize<'de> for ServerConfigLimit {
             if let Ok(val) = val.parse() {
                 return Ok(ServerConfigLimit::LessThan(val));
             };
+        } else if let Some((min, max)) = s.split_once("..")
+            && let (Ok(min), Ok(max)) = (min.parse::<i64>(), max.parse::<i64>())
+        {
+            if min > max {
+                return Err(serde::de::Error::custom(format!(
+                    "the range {s} is empty, its minimum is larger than the maximum"
+                )));
+            }
+            return Ok(ServerConfigLimit::Range(min, max));
         };
         Ok(ServerConfigLimit::Whitelist(
             s.split('|').map(String::from).collect(),
         ))
     }
 }
+
+#[test]
+fn config_limit_test() {
+    let parse = |limit: &str| serde_json::from_value::<ServerConfigLimit>(serde_json::json!(limit));
+
+    assert_eq!(parse("3..16").unwrap(), ServerConfigLimit::Range(3, 16));
+    assert_eq!(parse("-5..-1").unwrap(), ServerConfigLimit::Range(-5, -1));
+    assert!(parse("16..3").is_err());
+    // anything that isn't a range of numbers is a whitelisted value
+    assert_eq!(
+        parse("a..b").unwrap(),
+        ServerConfigLimit::Whitelist(vec![String::from("a..b")])
+    );
+    assert_eq!(parse(">2").unwrap(), ServerConfigLimit::MoreThan(2));
+
+    let ServerConfigLimit::Regex(pattern) = parse("re:-?[0-9]+").unwrap() else {
+        panic!("expected a pattern");
+    };
+    assert!(pattern.is_match("-1234"));
+    // the whole value has to match
+    assert!(!pattern.is_match("12 34"));
+    assert!(parse("re:(").is_err());
+    assert!(parse(&format!("re:{}", "a".repeat(MAX_PATTERN_LENGTH + 1))).is_err());
+    assert!(parse("re:a{1000}{1000}").is_err());
+    assert_eq!(
+        serde_json::to_value(ServerConfigLimit::Regex(pattern)).unwrap(),
+        serde_json::json!("re:-?[0-9]+")
+    );
+    assert_eq!(
+        serde_json::to_value(ServerConfigLimit::Range(3, 16)).unwrap(),
+        serde_json::json!("3..16")
+    );
+}
+
+#[tokio::test]
+async fn jar_missing_test() {
+    use crate::api::filters::UserAuth;
+    use crate::api::handlers::{ApiUpdate, RecursiveQuery};
+    use crate::database::objects::User;
+    use crate::database::testing::{insert_test_version, test_group, test_state, test_world};
+    use axum::extract::{Path, Query, State};
+    use axum::Json;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    let database = Database::memory().await;
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    let owner = User { username: String::from("owner"), group_id: group.id, ..Default::default() };
+    let owner = database.create_user_from(owner, "hunter2").await.unwrap();
+    // no jar is ever downloaded for the version
+    let version = insert_test_version(&database).await;
+    let world = test_world(owner.id, version.id);
+    database.insert(&world, None).await.unwrap();
+    let state = test_state(database);
+
+    let update = serde_json::from_value(serde_json::json!({"enabled": true})).unwrap();
+    let response = World::api_update(
+        Path(world.id),
+        Query(RecursiveQuery { recursive: None }),
+        State(state.clone()),
+        UserAuth(owner),
+        Json(update),
+    )
+    .await
+    .into_response();
+    // the user is told why, instead of getting a bare 500
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(body, format!("server jar for version {} not downloaded", version.id));
+
+    assert!(state.servers.get_server(world.id).await.is_none());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_create_test() {
+    use crate::database::objects::User;
+    use crate::database::testing::{insert_test_version, test_group, test_world};
+
+    let database = Database::memory().await;
+    let servers = MinecraftServerCollection::new(database.clone());
+    let group = test_group();
+    database.insert(&group, None).await.unwrap();
+    let owner = User { username: String::from("owner"), group_id: group.id, ..Default::default() };
+    database.insert(&owner, None).await.unwrap();
+    let version = insert_test_version(&database).await;
+    // not a real server, it only has to get far enough to be given a port
+    let jar = crate::util::dirs::versions_dir().join(format!("{}.jar", version.id));
+    std::fs::create_dir_all(jar.parent().unwrap()).unwrap();
+    std::fs::write(&jar, b"not a jar").unwrap();
+
+    // an enabled world, so creating the server starts it
+    let world = World { enabled: true, ..test_world(owner.id, version.id) };
+    let ports_before = internal::taken_local_ports().clone();
+
+    let created = futures::future::join_all((0..8).map(|_| {
+        let servers = servers.clone();
+        let world = world.clone();
+        tokio::spawn(async move { servers.get_or_create_server(&world).await.unwrap() })
+    }))
+    .await;
+    let created: Vec<ServerMutex> = created.into_iter().map(Result::unwrap).collect();
+
+    assert_eq!(servers.get_all_servers().await.len(), 1);
+    assert!(created.iter().all(|server| Arc::ptr_eq(server, &created[0])));
+    assert!(servers.creating.read().await.is_empty());
+    // it was started once, on a single port
+    let port = created[0].lock().await.port().expect("the server wasn't started");
+    let reserved: HashSet<u16> = internal::taken_local_ports().difference(&ports_before).copied().collect();
+    assert_eq!(reserved, HashSet::from([port]));
+
+    created[0].lock().await.remove().await.unwrap();
+    assert!(!internal::taken_local_ports().contains(&port));
+    std::fs::remove_file(jar).unwrap();
+    std::fs::remove_dir(crate::util::dirs::worlds_dir().join(owner.id.to_string())).unwrap();
+}
+
+#[tokio::test]
+async fn files_not_accessible_test() {
+    use crate::database::testing::test_world;
+    use axum::http::StatusCode;
+
+    let server = external::MinimanagerServer::new(String::from("localhost"), test_world(Id::default(), Id::default()));
+    let err = server.level_data().await.unwrap_err();
+    assert_eq!(file_error(err.wrap_err("could not read level.dat")), StatusCode::NOT_IMPLEMENTED);
+    assert_eq!(file_error(server.level_directory().await.unwrap_err()), StatusCode::NOT_IMPLEMENTED);
+    assert_eq!(file_error(color_eyre::eyre::eyre!("disk full")), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn panicking_holder_test() {
+    use crate::database::testing::test_world;
+
+    let servers = MinecraftServerCollection::new(Database::memory().await);
+
+    // a task panicking while holding the map must not break it for everyone else
+    let map = servers.servers.clone();
+    tokio::spawn(async move {
+        let _guard = map.write().await;
+        panic!("panicking while holding the server map");
+    })
+    .await
+    .expect_err("the task didn't panic");
+
+    let world = test_world(Id::default(), Id::default());
+    let server = servers.get_or_create_server(&world).await.unwrap();
+    assert!(Arc::ptr_eq(&server, &servers.get_server(world.id).await.unwrap()));
+    servers.remove_server(&world.id).await;
+    assert!(servers.get_all_servers().await.is_empty());
+}
+
+#[test]
+fn verify_jar_test() {
+    use crate::database::objects::version::VersionKind;
+
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("server.jar");
+    std::fs::write(&path, "abc").unwrap();
+
+    let mut version = Version {
+        id: Id::default(),
+        minecraft_version: String::from("1.21"),
+        mod_loader_id: Id::default(),
+        min_java: None,
+        required_java: None,
+        kind: VersionKind::Release,
+        jar_sha1: None,
+    };
+    verify_jar(&path, &version).unwrap();
+    version.jar_sha1 = Some(String::from("a9993e364706816aba3e25717850c26c9cd0d89d"));
+    verify_jar(&path, &version).unwrap();
+
+    // cut short, like an interrupted download
+    std::fs::write(&path, "ab").unwrap();
+    let err = verify_jar(&path, &version).expect_err("a corrupted jar was accepted");
+    match err.downcast::<ServerStartError>().unwrap() {
+        ServerStartError::JarCorrupt { actual, .. } => {
+            assert_eq!(actual, "da23614e02469a0d7c7bd1bdab5c9c474b1904dc");
+        }
+        err => panic!("expected the jar to be corrupted, got {err:?}"),
+    }
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn prune_servers_test() {
+    use crate::database::testing::test_world;
+
+    let servers = MinecraftServerCollection::new(Database::memory().await);
+
+    let world = test_world(Id::default(), Id::default());
+    let server = servers.get_or_create_server(&world).await.unwrap();
+    servers.poll_servers().await;
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+
+    // not stopped for long enough
+    assert!(servers.prune_servers(std::time::Duration::from_secs(3600)).await.is_empty());
+    // still held by someone
+    assert!(servers.prune_servers(std::time::Duration::ZERO).await.is_empty());
+    drop(server);
+
+    assert_eq!(servers.prune_servers(std::time::Duration::ZERO).await, vec![world.id]);
+    assert!(servers.get_all_servers().await.is_empty());
+    assert!(servers.stopped_since.read().await.is_empty());
+    // and created again when it's needed
+    servers.get_or_create_server(&world).await.unwrap();
+    assert_eq!(servers.ids().await, vec![world.id]);
+}
+
+#[test]
+fn console_history_test() {
+    let history = ConsoleHistory::default();
+    assert!(history.last(10).is_empty());
+
+    for seq in 0..CONSOLE_HISTORY_SIZE + 5 {
+        history.push(McStdout::Log { seq, message: format!("line {seq}") });
+    }
+
+    let seqs = |messages: Vec<McStdout>| -> Vec<usize> {
+        messages
+            .into_iter()
+            .map(|message| match message {
+                McStdout::Log { seq, .. } => seq,
+                message => panic!("unexpected {message:?}"),
+            })
+            .collect()
+    };
+    assert_eq!(seqs(history.last(3)), vec![CONSOLE_HISTORY_SIZE + 2, CONSOLE_HISTORY_SIZE + 3, CONSOLE_HISTORY_SIZE + 4]);
+    // the oldest lines were dropped
+    let all = seqs(history.last(usize::MAX));
+    assert_eq!(all.len(), CONSOLE_HISTORY_SIZE);
+    assert_eq!(all[0], 5);
+}
+
+/// moves the paused clock forward and lets the held back statuses be published. the tasks publishing them get to
+/// start sleeping first, or they would sleep from the advanced time
+#[cfg(test)]
+async fn advance_clock(duration: std::time::Duration) {
+    tokio::task::yield_now().await;
+    tokio::time::advance(duration).await;
+    tokio::task::yield_now().await;
+}
+
+#[tokio::test(start_paused = true)]
+async fn console_output_test() {
+    let output = ConsoleOutput::default();
+    let mut stdout = output.subscribe();
+
+    output.publish(McStdout::Log { seq: 0, message: String::from("starting") });
+    output.publish_status(MinecraftServerStatus::Running.into());
+    // learned again from somewhere else, with the address
+    output.publish_status(MinecraftServerStatusJson::from(MinecraftServerStatus::Running).with_address(Some(25565), None));
+    output.publish_status(MinecraftServerStatus::Exited(1).into());
+    advance_clock(STATUS_COALESCE_INTERVAL * 2).await;
+
+    let statuses = |messages: Vec<McStdout>| -> Vec<String> {
+        messages
+            .into_iter()
+            .map(|message| match message {
+                McStdout::Log { message, .. } => message,
+                McStdout::Status(status) => format!("{} {}", status.status, status.code),
+                McStdout::Lagged { skipped } => format!("lagged {skipped}"),
+            })
+            .collect()
+    };
+    let expected = vec!["starting", "running 0", "exited 1"];
+    assert_eq!(statuses(output.history(10)), expected);
+    let mut received = Vec::new();
+    while let Ok(message) = stdout.try_recv() {
+        received.push(message);
+    }
+    assert_eq!(statuses(received), expected);
+}
+
+#[tokio::test(start_paused = true)]
+async fn status_coalesce_test() {
+    let output = ConsoleOutput::default();
+    let mut stdout = output.subscribe();
+    let code = |message: McStdout| match message {
+        McStdout::Status(status) => (status.status, status.code),
+        message => panic!("expected a status, got {message:?}"),
+    };
+
+    output.publish_status(MinecraftServerStatus::Running.into());
+    // a crash and a restart right after it
+    output.publish_status(MinecraftServerStatus::Exited(1).into());
+    output.publish_status(MinecraftServerStatus::Running.into());
+    output.publish_status(MinecraftServerStatus::Exited(1).into());
+    output.publish_status(MinecraftServerStatus::Exited(0).into());
+    assert_eq!(code(stdout.try_recv().unwrap()), (String::from("running"), 0));
+    assert!(stdout.try_recv().is_err());
+
+    advance_clock(STATUS_COALESCE_INTERVAL * 2).await;
+    assert_eq!(code(stdout.try_recv().unwrap()), (String::from("exited"), 0));
+    assert!(stdout.try_recv().is_err());
+
+    // it was published once the interval passed, so the next one goes out right away after another interval
+    advance_clock(STATUS_COALESCE_INTERVAL).await;
+    output.publish_status(MinecraftServerStatus::Running.into());
+    assert_eq!(code(stdout.try_recv().unwrap()), (String::from("running"), 0));
+    // changing back to the last published status within the interval publishes nothing
+    output.publish_status(MinecraftServerStatus::Exited(0).into());
+    output.publish_status(MinecraftServerStatus::Running.into());
+    advance_clock(STATUS_COALESCE_INTERVAL * 2).await;
+    output.publish_status(MinecraftServerStatus::Running.into());
+    assert!(stdout.try_recv().is_err());
+}
+
+#[test]
+fn status_uptime_test() {
+    let started_at = chrono::Utc::now();
+    let uptime = Some(std::time::Duration::from_millis(90_500));
+
+    let status = MinecraftServerStatusJson::from(MinecraftServerStatus::Running).with_uptime(Some(started_at), uptime);
+    assert_eq!(status.started_at, Some(started_at));
+    assert_eq!(status.uptime, Some(90));
+
+    // a stopped server has no uptime, even if it's passed
+    let status =
+        MinecraftServerStatusJson::from(MinecraftServerStatus::Exited(0)).with_uptime(Some(started_at), uptime);
+    assert_eq!(status.started_at, None);
+    assert_eq!(status.uptime, None);
+}
+
+#[test]
+fn signaled_status_test() {
+    assert!(matches!(
+        MinecraftServerStatus::from(subprocess::ExitStatus::Signaled(9)),
+        MinecraftServerStatus::Signaled(9)
+    ));
+    assert!(matches!(
+        MinecraftServerStatus::from(subprocess::ExitStatus::Undetermined),
+        MinecraftServerStatus::Exited(1)
+    ));
+
+    let status = MinecraftServerStatusJson::from(MinecraftServerStatus::Signaled(9));
+    assert_eq!(status.status, "signaled");
+    assert_eq!(status.code, 137);
+    assert_eq!(status.signal_name.as_deref(), Some("SIGKILL"));
+    let status = MinecraftServerStatusJson::from(MinecraftServerStatus::Exited(0));
+    assert_eq!((status.signal, status.signal_name), (None, None));
+    assert_eq!(signal_name(64), None);
+}
+
+#[tokio::test]
+async fn setup_commands_test() {
+    use crate::database::testing::test_world;
+
+    // the world's owner and version don't exist
+    let database = Database::memory_unchecked().await;
+
+    let world = World {
+        id: Id::new_random(),
+        setup_commands: String::from("gamerule keepInventory true\nworldborder set 1000"),
+        ..test_world(Id::new_random(), Id::new_random())
+    };
+    database.insert(&world, None).await.unwrap();
+
+    let output = ConsoleOutput::default();
+    let (stdin_tx, mut stdin_rx) = tokio::sync::mpsc::channel(16);
+    let start = |output: &ConsoleOutput| {
+        let stdout = output.subscribe();
+        output.publish(McStdout::Log { seq: 0, message: String::from("[12:00:00 INFO]: Preparing level \"world\"") });
+        output.publish(McStdout::Log { seq: 1, message: String::from("[12:00:01 INFO]: Done (1.0s)! For help, type \"help\"") });
+        stdout
+    };
+
+    assert!(run_setup_commands(world.id, database.clone(), start(&output), stdin_tx.clone()).await.unwrap());
+    let mut commands = Vec::new();
+    while let Ok(MCStdin::Command(command)) = stdin_rx.try_recv() {
+        commands.push(command);
+    }
+    assert_eq!(commands, ["gamerule keepInventory true\n", "worldborder set 1000\n"]);
+    assert!(database.get_one::<World>(world.id, None).await.unwrap().initialized);
+
+    // the next start doesn't run them again
+    assert!(!run_setup_commands(world.id, database, start(&output), stdin_tx).await.unwrap());
+    assert!(stdin_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn slow_subscriber_test() {
+    let output = ConsoleOutput::default();
+    let mut slow = output.subscribe();
+    let mut fast = output.subscribe();
+
+    for seq in 0..CONSOLE_BUFFER_SIZE + 10 {
+        output.publish(McStdout::Log { seq, message: format!("line {seq}") });
+        if seq < 5 {
+            assert!(matches!(next_output(&mut fast).await, Some(McStdout::Log { seq: received, .. }) if received == seq));
+        }
+    }
+
+    // the oldest lines were dropped for the subscriber that didn't keep up, and it's told how many
+    assert!(matches!(next_output(&mut slow).await, Some(McStdout::Lagged { skipped: 10 })));
+    assert!(matches!(next_output(&mut slow).await, Some(McStdout::Log { seq: 10, .. })));
+    // the other one only missed what it didn't read either
+    assert!(matches!(next_output(&mut fast).await, Some(McStdout::Lagged { skipped: 5 })));
+
+    // a subscriber going away stops taking the output
+    drop(slow);
+    assert_eq!(output.receiver_count(), 1);
+    drop(output);
+    while let Some(message) = next_output(&mut fast).await {
+        assert!(matches!(message, McStdout::Log { .. }));
+    }
+}
+
+#[tokio::test]
+async fn port_snapshot_test() {
+    let servers = MinecraftServerCollection::new(Database::memory().await);
+
+    // a server being created holds off the snapshot, its port may not be in `servers` yet
+    let creating = servers.port_snapshot.clone().read_owned().await;
+    let snapshot = tokio::spawn({
+        let servers = servers.clone();
+        async move { servers.reserved_ports().await }
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(!snapshot.is_finished());
+
+    drop(creating);
+    snapshot.await.unwrap();
+}
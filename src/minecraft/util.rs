@@ -1,23 +1,1008 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use color_eyre::eyre::{bail, eyre};
+use log::{info, warn};
+use crate::config::{CONFIG, WorldConfig};
 
-pub fn parse_minecraft_properties(file: &str) -> HashMap<String, String> {
-    let mut properties = HashMap::new();
-    for line in file.lines() {
-        if line.starts_with('#') {
+/// a `key=value` pair of a `.properties` file, see [`parse_properties`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PropertiesEntry {
+    /// the number of the line the entry starts on, starting at 1
+    line: usize,
+    key: String,
+    value: String,
+    /// whether the key was followed by `=` or `:`, rather than just whitespace or nothing
+    separated: bool,
+}
+
+/// splits a `.properties` file into its entries, following the format of Java's `Properties::load`: comments start
+/// with `#` or `!`, a line ending with an odd number of `\` continues on the next one, the key ends at the first
+/// unescaped `=`, `:` or whitespace, and both can contain escapes like `\t`, `\:` or `\u00e9`
+fn parse_properties(file: &str) -> Vec<PropertiesEntry> {
+    let is_whitespace = |char: char| matches!(char, ' ' | '\t' | '\x0c');
+    let mut entries = Vec::new();
+    let mut lines = file.lines().enumerate();
+    while let Some((number, line)) = lines.next() {
+        let line = line.trim_start_matches(is_whitespace);
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
             continue;
         }
-        let mut split = line.splitn(2, '=');
-        let key = split.next().unwrap_or("");
-        let value = split.next().unwrap_or("");
-        properties.insert(key.to_string(), value.to_string());
+
+        let mut logical = String::from(line);
+        while logical.chars().rev().take_while(|char| *char == '\\').count() % 2 == 1 {
+            logical.pop();
+            let Some((_, next)) = lines.next() else {
+                break;
+            };
+            logical.push_str(next.trim_start_matches(is_whitespace));
+        }
+
+        let mut chars = logical.chars().peekable();
+        let mut key = String::new();
+        let mut escaped = false;
+        while let Some(&char) = chars.peek() {
+            if !escaped && (char == '=' || char == ':' || is_whitespace(char)) {
+                break;
+            }
+            escaped = !escaped && char == '\\';
+            key.push(char);
+            chars.next();
+        }
+        while chars.next_if(|char| is_whitespace(*char)).is_some() {}
+        let separated = chars.next_if(|char| *char == '=' || *char == ':').is_some();
+        while chars.next_if(|char| is_whitespace(*char)).is_some() {}
+
+        entries.push(PropertiesEntry {
+            line: number + 1,
+            key: unescape_properties(&key),
+            value: unescape_properties(&chars.collect::<String>()),
+            separated,
+        });
+    }
+    entries
+}
+
+/// resolves the escapes of a `.properties` key or value
+fn unescape_properties(escaped: &str) -> String {
+    let mut units = Vec::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(char) = chars.next() {
+        let char = if char == '\\' {
+            match chars.next() {
+                Some('t') => '\t',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('f') => '\x0c',
+                Some('u') => {
+                    let hex: String = chars.clone().take(4).collect();
+                    match u16::from_str_radix(&hex, 16) {
+                        Ok(unit) if hex.len() == 4 => {
+                            chars.nth(3);
+                            units.push(unit);
+                            continue;
+                        }
+                        // Java fails on these, keeping them is less destructive
+                        _ => 'u',
+                    }
+                }
+                Some(char) => char,
+                None => break,
+            }
+        } else {
+            char
+        };
+        units.extend_from_slice(char.encode_utf16(&mut [0; 2]));
+    }
+    // `\u` escapes can be surrogate pairs
+    String::from_utf16_lossy(&units)
+}
+
+/// escapes a `.properties` key or value. everything outside of printable ASCII is written as `\uXXXX`, like Java's
+/// `Properties::store`, so the file reads the same no matter what charset the server reads it in
+fn escape_properties(value: &str, is_key: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (index, char) in value.chars().enumerate() {
+        match char {
+            // spaces only end the key, or get trimmed when they start the value
+            ' ' if is_key || index == 0 => escaped.push_str("\\ "),
+            '\\' | '=' | ':' | '#' | '!' => {
+                escaped.push('\\');
+                escaped.push(char);
+            }
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\x0c' => escaped.push_str("\\f"),
+            ' '..='~' => escaped.push(char),
+            _ => {
+                for unit in char.encode_utf16(&mut [0; 2]) {
+                    escaped.push_str(&format!("\\u{unit:04x}"));
+                }
+            }
+        }
     }
-    properties
+    escaped
 }
 
+pub fn parse_minecraft_properties(file: &str) -> HashMap<String, String> {
+    parse_properties(file)
+        .into_iter()
+        .map(|entry| (entry.key, entry.value))
+        .collect()
+}
+
+/// like [`parse_minecraft_properties`], but fails on entries without a key or without `=` or `:` between the key and
+/// the value (which Java would accept, but is most likely a mistake), with the number of the first such line
+pub fn parse_minecraft_properties_strict(file: &str) -> Result<HashMap<String, String>, String> {
+    let entries = parse_properties(file);
+    if let Some(entry) = entries.iter().find(|entry| entry.key.is_empty() || !entry.separated) {
+        let line = file.lines().nth(entry.line - 1).unwrap_or_default().trim_start();
+        return Err(format!("line {}: expected key=value, got \"{line}\"", entry.line));
+    }
+    Ok(entries.into_iter().map(|entry| (entry.key, entry.value)).collect())
+}
+
+#[test]
+fn parse_minecraft_properties_strict_test() {
+    let properties =
+        parse_minecraft_properties_strict("#Minecraft server properties\n\nmotd=A=B\npvp=true\n").unwrap();
+    assert_eq!(properties.len(), 2);
+    assert_eq!(properties["motd"], "A=B");
+    assert_eq!(
+        parse_minecraft_properties_strict("pvp=true\ngamemode creative\n"),
+        Err(String::from("line 2: expected key=value, got \"gamemode creative\""))
+    );
+    assert!(parse_minecraft_properties_strict("=value").is_err());
+}
+
+/// writes the properties in the format read by [`parse_minecraft_properties`], sorted by their keys
 pub fn create_minecraft_properties(properties: HashMap<String, String>) -> String {
+    let mut properties: Vec<_> = properties.into_iter().collect();
+    properties.sort_unstable();
     let mut file = String::new();
     for (key, value) in properties {
-        file.push_str(&format!("{key}={value}\n"));
+        file.push_str(&format!(
+            "{}={}\n",
+            escape_properties(&key, true),
+            escape_properties(&value, false)
+        ));
     }
     file
 }
+
+#[test]
+fn minecraft_properties_test() {
+    let properties = parse_minecraft_properties(concat!(
+        "# comment\n",
+        "  ! also a comment\n",
+        "motd = \\u00a76Welcome: to my server\n",
+        "level-seed:12=3\n",
+        "key\\ with\\ spaces value\n",
+        "resource-pack=https\\://example.net/pack.zip\n",
+        "long=a \\\n    b\\\\\n",
+        "empty=\n",
+        "flag\n",
+        "smile=\\ud83d\\ude00\n",
+    ));
+    assert_eq!(properties["motd"], "§6Welcome: to my server");
+    assert_eq!(properties["level-seed"], "12=3");
+    assert_eq!(properties["key with spaces"], "value");
+    assert_eq!(properties["resource-pack"], "https://example.net/pack.zip");
+    assert_eq!(properties["long"], "a b\\");
+    assert_eq!(properties["empty"], "");
+    assert_eq!(properties["flag"], "");
+    assert_eq!(properties["smile"], "😀");
+    assert_eq!(properties.len(), 8);
+
+    let properties = HashMap::from([
+        (String::from("motd"), String::from("  §6Hi: #1 = über 😀\\")),
+        (String::from("odd key=:"), String::from("line\nbreak")),
+        (String::from("pvp"), String::from("true")),
+    ]);
+    let created = create_minecraft_properties(properties.clone());
+    assert!(created.is_ascii());
+    assert!(created.ends_with("pvp=true\n"));
+    assert_eq!(parse_minecraft_properties(&created), properties);
+}
+
+/// compares two release versions like "1.20.1". [`None`] if either of them isn't a release version, like a snapshot
+pub fn compare_minecraft_versions(a: &str, b: &str) -> Option<Ordering> {
+    let parse = |version: &str| {
+        version
+            .trim()
+            .split('.')
+            .map(|part| part.parse::<u32>().ok())
+            .collect::<Option<Vec<_>>>()
+    };
+    let (a, b) = (parse(a)?, parse(b)?);
+
+    // "1.20" is the same as "1.20.0"
+    let length = a.len().max(b.len());
+    let pad = |version: Vec<u32>| {
+        version
+            .into_iter()
+            .chain(std::iter::repeat(0))
+            .take(length)
+            .collect::<Vec<_>>()
+    };
+    Some(pad(a).cmp(&pad(b)))
+}
+
+#[test]
+fn compare_minecraft_versions_test() {
+    assert_eq!(compare_minecraft_versions("1.20.1", "1.20"), Some(Ordering::Greater));
+    assert_eq!(compare_minecraft_versions("1.9", "1.10"), Some(Ordering::Less));
+    assert_eq!(compare_minecraft_versions("1.20", "1.20.0"), Some(Ordering::Equal));
+    assert_eq!(compare_minecraft_versions("24w14a", "1.20.4"), None);
+}
+
+/// the name of a console command, which is its first word without the leading `/` and namespace, so
+/// `/minecraft:op Steve` is `op`
+pub fn command_name(command: &str) -> &str {
+    let name = command.split_whitespace().next().unwrap_or_default();
+    let name = name.strip_prefix('/').unwrap_or(name);
+    name.rsplit_once(':').map_or(name, |(_, name)| name)
+}
+
+#[test]
+fn command_name_test() {
+    assert_eq!(command_name("op Steve"), "op");
+    assert_eq!(command_name("  /minecraft:op Steve"), "op");
+    assert_eq!(command_name("say hi: there"), "say");
+    assert_eq!(command_name(""), "");
+}
+
+/// the longest chat message Minecraft accepts
+pub const MAX_CHAT_MESSAGE_LENGTH: usize = 256;
+
+/// the longest command Minecraft accepts from a player
+pub const MAX_COMMAND_LENGTH: usize = 256;
+
+/// whether the name can be a Minecraft username: 3 to 16 letters, digits or underscores
+pub fn is_player_name(name: &str) -> bool {
+    (3..=16).contains(&name.len()) && name.chars().all(|char| char.is_ascii_alphanumeric() || char == '_')
+}
+
+/// what `op` did, read from the console output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpResult {
+    Opped,
+    AlreadyOp,
+    /// the server couldn't find the player, like a name without an account on an online mode server
+    UnknownPlayer,
+}
+
+/// the [`OpResult`] of `op <name>` in a console line, if it's about it
+pub fn parse_op_result(line: &str, name: &str) -> Option<OpResult> {
+    let line = strip_formatting(line);
+    let message = line.rsplit_once("]: ").map_or(line.as_str(), |(_, message)| message).trim();
+    if message.eq_ignore_ascii_case(&format!("Made {name} a server operator")) {
+        Some(OpResult::Opped)
+    } else if message == "Nothing changed. The player already is an operator" {
+        Some(OpResult::AlreadyOp)
+    } else if message == "That player does not exist" {
+        Some(OpResult::UnknownPlayer)
+    } else {
+        None
+    }
+}
+
+/// makes user supplied text safe to use as an argument of a console command. control characters are removed, as a
+/// newline would end the command and start another one. fails if nothing is left, or it's longer than `max_length`
+/// characters
+pub fn console_argument(text: &str, max_length: usize) -> Result<String, String> {
+    let text: String = text.chars().filter(|char| !char.is_control()).collect();
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(String::from("the text can't be empty"));
+    }
+    if text.chars().count() > max_length {
+        return Err(format!("the text can't be longer than {max_length} characters"));
+    }
+    Ok(text.to_string())
+}
+
+#[test]
+fn console_argument_test() {
+    assert_eq!(console_argument("  hello there ", 256), Ok(String::from("hello there")));
+    // a newline would run the rest as another command
+    assert_eq!(console_argument("hi\nop Steve\r", 256), Ok(String::from("hiop Steve")));
+    assert!(console_argument("\n\t ", 256).is_err());
+    assert!(console_argument("ab", 1).is_err());
+    assert_eq!(console_argument("žž", 2), Ok(String::from("žž")));
+}
+
+/// removes the ANSI escape sequences and `§` formatting codes servers color their console output with
+pub fn strip_formatting(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(char) = chars.next() {
+        match char {
+            '§' => {
+                chars.next();
+            }
+            // CSI sequences end with a letter
+            '\u{1b}' => {
+                for char in chars.by_ref() {
+                    if char.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            }
+            char => stripped.push(char),
+        }
+    }
+    stripped
+}
+
+/// the type of the values a gamerule takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameruleType {
+    Boolean,
+    Integer,
+}
+
+/// the gamerules of Java Edition 1.21, with the types of their values. older versions don't have all of them
+pub const GAMERULES: &[(&str, GameruleType)] = &[
+    ("announceAdvancements", GameruleType::Boolean),
+    ("blockExplosionDropDecay", GameruleType::Boolean),
+    ("commandBlockOutput", GameruleType::Boolean),
+    ("commandModificationBlockLimit", GameruleType::Integer),
+    ("disableElytraMovementCheck", GameruleType::Boolean),
+    ("disablePlayerMovementCheck", GameruleType::Boolean),
+    ("disableRaids", GameruleType::Boolean),
+    ("doDaylightCycle", GameruleType::Boolean),
+    ("doEntityDrops", GameruleType::Boolean),
+    ("doFireTick", GameruleType::Boolean),
+    ("doImmediateRespawn", GameruleType::Boolean),
+    ("doInsomnia", GameruleType::Boolean),
+    ("doLimitedCrafting", GameruleType::Boolean),
+    ("doMobLoot", GameruleType::Boolean),
+    ("doMobSpawning", GameruleType::Boolean),
+    ("doPatrolSpawning", GameruleType::Boolean),
+    ("doTileDrops", GameruleType::Boolean),
+    ("doTraderSpawning", GameruleType::Boolean),
+    ("doVinesSpread", GameruleType::Boolean),
+    ("doWardenSpawning", GameruleType::Boolean),
+    ("doWeatherCycle", GameruleType::Boolean),
+    ("drowningDamage", GameruleType::Boolean),
+    ("enderPearlsVanishOnDeath", GameruleType::Boolean),
+    ("fallDamage", GameruleType::Boolean),
+    ("fireDamage", GameruleType::Boolean),
+    ("forgiveDeadPlayers", GameruleType::Boolean),
+    ("freezeDamage", GameruleType::Boolean),
+    ("globalSoundEvents", GameruleType::Boolean),
+    ("keepInventory", GameruleType::Boolean),
+    ("lavaSourceConversion", GameruleType::Boolean),
+    ("logAdminCommands", GameruleType::Boolean),
+    ("maxCommandChainLength", GameruleType::Integer),
+    ("maxCommandForkCount", GameruleType::Integer),
+    ("maxEntityCramming", GameruleType::Integer),
+    ("mobExplosionDropDecay", GameruleType::Boolean),
+    ("mobGriefing", GameruleType::Boolean),
+    ("naturalRegeneration", GameruleType::Boolean),
+    ("playersNetherPortalCreativeDelay", GameruleType::Integer),
+    ("playersNetherPortalDefaultDelay", GameruleType::Integer),
+    ("playersSleepingPercentage", GameruleType::Integer),
+    ("projectilesCanBreakBlocks", GameruleType::Boolean),
+    ("randomTickSpeed", GameruleType::Integer),
+    ("reducedDebugInfo", GameruleType::Boolean),
+    ("sendCommandFeedback", GameruleType::Boolean),
+    ("showDeathMessages", GameruleType::Boolean),
+    ("snowAccumulationHeight", GameruleType::Integer),
+    ("spawnChunkRadius", GameruleType::Integer),
+    ("spawnRadius", GameruleType::Integer),
+    ("spectatorsGenerateChunks", GameruleType::Boolean),
+    ("tntExplosionDropDecay", GameruleType::Boolean),
+    ("universalAnger", GameruleType::Boolean),
+    ("waterSourceConversion", GameruleType::Boolean),
+];
+
+/// the value of a gamerule, sent as a JSON boolean or number
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum GameruleValue {
+    Boolean(bool),
+    Integer(i32),
+}
+
+impl Display for GameruleValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameruleValue::Boolean(value) => write!(f, "{value}"),
+            GameruleValue::Integer(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+impl GameruleValue {
+    /// parses a value the way the game prints it, for a rule of the type
+    fn parse(value: &str, value_type: GameruleType) -> Option<Self> {
+        match value_type {
+            GameruleType::Boolean => value.parse().ok().map(GameruleValue::Boolean),
+            GameruleType::Integer => value.parse().ok().map(GameruleValue::Integer),
+        }
+    }
+}
+
+/// the type of the gamerule, [`None`] if it's not one of the [`GAMERULES`]. the names are case sensitive, like in the
+/// game
+pub fn gamerule_type(rule: &str) -> Option<GameruleType> {
+    GAMERULES.iter().find(|(name, _)| *name == rule).map(|(_, value_type)| *value_type)
+}
+
+/// checks that the rule is one of the [`GAMERULES`] and the value is of its type
+pub fn check_gamerule(rule: &str, value: GameruleValue) -> Result<(), String> {
+    match (gamerule_type(rule), value) {
+        (None, _) => Err(format!("\"{rule}\" is not a gamerule")),
+        (Some(GameruleType::Boolean), GameruleValue::Boolean(_))
+        | (Some(GameruleType::Integer), GameruleValue::Integer(_)) => Ok(()),
+        (Some(GameruleType::Boolean), _) => Err(format!("{rule} takes true or false")),
+        (Some(GameruleType::Integer), _) => Err(format!("{rule} takes a number")),
+    }
+}
+
+/// the rule and value out of the output of `gamerule <rule>` or `gamerule <rule> <value>`, like
+/// `Gamerule keepInventory is currently set to: false`. only the [`GAMERULES`] are recognized
+pub fn parse_gamerule(line: &str) -> Option<(&'static str, GameruleValue)> {
+    let line = strip_formatting(line);
+    let (_, message) = line.split_once("]: ")?;
+    let (rule, value) = message
+        .strip_prefix("Gamerule ")?
+        .split_once(" is currently set to: ")
+        .or_else(|| message.strip_prefix("Gamerule ")?.split_once(" is now set to: "))?;
+    let (rule, value_type) = GAMERULES.iter().find(|(name, _)| *name == rule)?;
+    Some((rule, GameruleValue::parse(value.trim(), *value_type)?))
+}
+
+#[test]
+fn gamerule_test() {
+    assert_eq!(gamerule_type("keepInventory"), Some(GameruleType::Boolean));
+    assert_eq!(gamerule_type("keepinventory"), None);
+    assert!(check_gamerule("keepInventory", GameruleValue::Boolean(true)).is_ok());
+    assert!(check_gamerule("randomTickSpeed", GameruleValue::Integer(3)).is_ok());
+    assert!(check_gamerule("keepInventory", GameruleValue::Integer(1)).is_err());
+    assert!(check_gamerule("randomTickSpeed", GameruleValue::Boolean(false)).is_err());
+    assert!(check_gamerule("keepInventory true\nop Steve", GameruleValue::Boolean(true)).is_err());
+
+    let value = |json| serde_json::from_value::<GameruleValue>(json);
+    assert_eq!(value(serde_json::json!(true)).unwrap(), GameruleValue::Boolean(true));
+    assert_eq!(value(serde_json::json!(-1)).unwrap(), GameruleValue::Integer(-1));
+    assert!(value(serde_json::json!("true")).is_err());
+    assert!(value(serde_json::json!(1.5)).is_err());
+
+    assert_eq!(
+        parse_gamerule("[12:00:00 INFO]: Gamerule keepInventory is currently set to: false"),
+        Some(("keepInventory", GameruleValue::Boolean(false)))
+    );
+    assert_eq!(
+        parse_gamerule("[12:00:00] [Server thread/INFO]: Gamerule randomTickSpeed is now set to: 10"),
+        Some(("randomTickSpeed", GameruleValue::Integer(10)))
+    );
+    assert_eq!(parse_gamerule("[12:00:00 INFO]: <Steve> Gamerule keepInventory is now set to: true"), None);
+    assert_eq!(parse_gamerule("[12:00:00 INFO]: Gamerule unknownRule is now set to: true"), None);
+}
+
+/// parses the output of the `seed` command, like `Seed: [-4172144997902289642]`. like with [`is_startup_done`], a
+/// player can't fake it in chat
+pub fn parse_seed(line: &str) -> Option<i64> {
+    let line = strip_formatting(line);
+    let (_, message) = line.split_once("]: ")?;
+    let seed = message.strip_prefix("Seed: [")?;
+    seed.split_once(']')?.0.trim().parse().ok()
+}
+
+#[test]
+fn seed_test() {
+    assert_eq!(
+        parse_seed("[12:00:00] [Server thread/INFO]: Seed: [-4172144997902289642]"),
+        Some(-4172144997902289642)
+    );
+    assert_eq!(parse_seed("[12:00:00 INFO]: Seed: [§a42§r]"), Some(42));
+    assert_eq!(parse_seed("[12:00:00 INFO]: <Steve> Seed: [42]"), None);
+    assert_eq!(parse_seed("[12:00:00 INFO]: Seed: [unknown]"), None);
+}
+
+/// whether the console line is the server rejecting a command it doesn't have. like with [`parse_seed`], a player
+/// can't fake it in chat
+pub fn is_unknown_command(line: &str) -> bool {
+    let line = strip_formatting(line);
+    line.split_once("]: ").is_some_and(|(_, message)| {
+        message.starts_with("Unknown or incomplete command") || message.starts_with("Unknown command")
+    })
+}
+
+/// parses the TPS of the last 1, 5 and 15 minutes out of the output of Paper's `tps` command, like
+/// `TPS from last 1m, 5m, 15m: 19.98, *20.0, 20.0`. Paper marks values capped at 20 with a `*`
+pub fn parse_tps(line: &str) -> Option<[f64; 3]> {
+    let line = strip_formatting(line);
+    let (_, message) = line.split_once("]: ")?;
+    let values = message.strip_prefix("TPS from last 1m, 5m, 15m:")?;
+    let values = values
+        .split(',')
+        .map(|value| value.trim().trim_start_matches('*').parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    values.try_into().ok()
+}
+
+/// parses the average milliseconds per tick of the last minute out of the output of Paper's `mspt` command. the values
+/// are on the line after the `Server tick times (avg/min/max) from last 5s, 10s, 1m:` header, like
+/// `◴ 1.2/0.4/8.0, 1.1/0.4/8.0, 1.3/0.3/14.3`
+pub fn parse_mspt(line: &str) -> Option<f64> {
+    let line = strip_formatting(line);
+    let (_, message) = line.split_once("]: ")?;
+    // every window has to be numbers, so a chat message can't pass for the values
+    let windows = message
+        .trim_start_matches('◴')
+        .split(',')
+        .map(|window| {
+            window
+                .split('/')
+                .map(|value| value.trim().parse::<f64>().ok())
+                .collect::<Option<Vec<_>>>()
+        })
+        .collect::<Option<Vec<_>>>()?;
+    match windows.as_slice() {
+        [_, _, minute] if minute.len() == 3 => Some(minute[0]),
+        _ => None,
+    }
+}
+
+/// whether the line is the header Paper's `mspt` command prints before the values, see [`parse_mspt`]
+pub fn is_mspt_header(line: &str) -> bool {
+    let line = strip_formatting(line);
+    line.split_once("]: ").is_some_and(|(_, message)| message.starts_with("Server tick times"))
+}
+
+#[test]
+fn performance_test() {
+    assert_eq!(
+        parse_tps("[12:00:00 INFO]: §6TPS from last 1m, 5m, 15m: §a19.98, §a*20.0, §a20.0"),
+        Some([19.98, 20.0, 20.0])
+    );
+    assert_eq!(
+        parse_tps("[12:00:00 INFO]: \u{1b}[0;33mTPS from last 1m, 5m, 15m: \u{1b}[0;32m12.5, 15.0, 17.25\u{1b}[m"),
+        Some([12.5, 15.0, 17.25])
+    );
+    assert_eq!(parse_tps("[12:00:00 INFO]: <Steve> TPS from last 1m, 5m, 15m: lag"), None);
+    assert_eq!(parse_tps("[12:00:00 INFO]: <Steve> TPS from last 1m, 5m, 15m: 20.0, 20.0, 20.0"), None);
+    assert_eq!(parse_tps("[12:00:00 INFO]: Done (3.2s)!"), None);
+
+    assert_eq!(parse_mspt("[12:00:00 INFO]: ◴ §a1.2§7/§a0.4§7/§a8.0§7, §a1.1§7/§a0.4§7/§a8.0§7, §a1.3§7/§a0.3§7/§a14.3"), Some(1.3));
+    assert_eq!(parse_mspt("[12:00:00 INFO]: Server tick times (avg/min/max) from last 5s, 10s, 1m:"), None);
+    assert_eq!(parse_mspt("[12:00:00 INFO]: <Steve> 1/1/1, 1/1/1, 1/1/1"), None);
+    assert!(is_mspt_header("[12:00:00 INFO]: §6Server tick times §e(§7avg§e/§7min§e/§7max§e) from last 5s, 10s, 1m:"));
+    assert!(!is_mspt_header("[12:00:00 INFO]: <Steve> Server tick times"));
+
+    assert!(is_unknown_command("[12:00:00 INFO]: Unknown or incomplete command, see below for error"));
+    assert!(is_unknown_command("[12:00:00] [Server thread/INFO]: Unknown command. Type \"/help\" for help."));
+    assert!(!is_unknown_command("[12:00:00 INFO]: <Steve> Unknown command"));
+    assert!(!is_unknown_command("[12:00:00 INFO]: Done (3.2s)!"));
+}
+
+/// whether the line is the one servers print once they're done starting, like `Done (3.2s)! For help, type "help"`.
+/// players can't fake it in chat, their messages start with their name
+pub fn is_startup_done(line: &str) -> bool {
+    let line = strip_formatting(line);
+    line.split_once("]: ")
+        .is_some_and(|(_, message)| message.starts_with("Done (") && message.contains(")!"))
+}
+
+#[test]
+fn startup_done_test() {
+    assert!(is_startup_done("[12:00:00] [Server thread/INFO]: Done (3.456s)! For help, type \"help\""));
+    assert!(is_startup_done("[12:00:00 INFO]: \u{1b}[0mDone (12.1s)! For help, type \"help\""));
+    assert!(!is_startup_done("[12:00:00 INFO]: <Steve> Done (1s)!"));
+    assert!(!is_startup_done("[12:00:00 INFO]: Preparing level \"world\""));
+}
+
+/// the generated parts of the world saved at `level` (like `world`): the save itself, with `level.dat`, and the
+/// separate dimension saves Bukkit based servers make next to it
+pub fn level_paths(level: &Path) -> Vec<PathBuf> {
+    let name = level.file_name().unwrap_or_default().to_string_lossy();
+    vec![
+        level.to_path_buf(),
+        level.with_file_name(format!("{name}_nether")),
+        level.with_file_name(format!("{name}_the_end")),
+    ]
+}
+
+/// removes the [`level_paths`] of the level, so the server generates a new world on its next start. everything else in
+/// the server's `directory`, like `server.properties` and the mods, is kept. paths outside of `directory` are refused,
+/// and symlinks are removed without following them. returns the removed paths, relative to `directory`
+pub fn remove_level(directory: &Path, level: &Path) -> color_eyre::Result<Vec<PathBuf>> {
+    let directory = directory.canonicalize()?;
+    let mut removed = Vec::new();
+    for path in level_paths(level) {
+        let Ok(metadata) = path.symlink_metadata() else {
+            continue;
+        };
+        // only the parent is resolved, the path itself may be a symlink pointing anywhere
+        let parent = path.parent().ok_or_else(|| eyre!("{} has no parent", path.display()))?.canonicalize()?;
+        let name = path.file_name().ok_or_else(|| eyre!("{} has no name", path.display()))?;
+        let resolved = parent.join(name);
+        let Ok(relative) = resolved.strip_prefix(&directory) else {
+            bail!("{} is outside of {}", path.display(), directory.display());
+        };
+        if relative.as_os_str().is_empty() {
+            bail!("refusing to remove {}", directory.display());
+        }
+
+        if metadata.is_dir() {
+            std::fs::remove_dir_all(&resolved)?;
+        } else {
+            std::fs::remove_file(&resolved)?;
+        }
+        removed.push(relative.to_path_buf());
+    }
+    Ok(removed)
+}
+
+#[test]
+fn remove_level_test() {
+    let directory = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    for path in ["world/region", "world_nether/DIM-1", "mods"] {
+        std::fs::create_dir_all(directory.join(path)).unwrap();
+    }
+    std::fs::write(directory.join("world/level.dat"), "").unwrap();
+    std::fs::write(directory.join("server.properties"), "level-name=world").unwrap();
+
+    let mut removed = remove_level(&directory, &directory.join("world")).unwrap();
+    removed.sort();
+    assert_eq!(removed, [PathBuf::from("world"), PathBuf::from("world_nether")]);
+    assert!(!directory.join("world").exists() && !directory.join("world_nether").exists());
+    assert!(directory.join("mods").exists() && directory.join("server.properties").exists());
+
+    // nothing outside of the server's directory
+    let outside = directory.join("mods");
+    std::fs::create_dir_all(outside.join("world")).unwrap();
+    assert!(remove_level(&outside.join("world"), &directory.join("mods")).is_err());
+    assert!(outside.exists());
+    assert!(remove_level(&directory, &directory).is_err());
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
+
+/// the `server.properties` values the instance forces on every world, see [`WorldConfig::online_mode`]
+pub fn forced_properties(config: &WorldConfig) -> HashMap<&'static str, String> {
+    let mut forced = HashMap::new();
+    if let Some(online_mode) = config.online_mode {
+        forced.insert("online-mode", online_mode.to_string());
+    }
+    forced
+}
+
+/// the value the instance forces the `server.properties` key to, if it does
+pub fn forced_property(key: &str) -> Option<String> {
+    forced_properties(&CONFIG.world).remove(key)
+}
+
+/// overrides the properties with the [`forced_properties`] of the instance
+pub fn force_properties(properties: &mut HashMap<String, String>) {
+    for (key, value) in forced_properties(&CONFIG.world) {
+        properties.insert(key.to_string(), value);
+    }
+}
+
+#[test]
+fn forced_properties_test() {
+    let config = WorldConfig { online_mode: None, ..CONFIG.world.clone() };
+    assert!(forced_properties(&config).is_empty());
+    let config = WorldConfig { online_mode: Some(true), ..config };
+    assert_eq!(forced_properties(&config), HashMap::from([("online-mode", String::from("true"))]));
+}
+
+/// the MOTD Minecraft writes to `server.properties` when there isn't one
+pub const DEFAULT_MOTD: &str = "A Minecraft Server";
+
+/// fills in the `{world_name}`, `{owner}` and `{instance_name}` placeholders of
+/// [`crate::config::WorldConfig::motd_template`]. the values aren't searched for placeholders themselves, and control
+/// characters are removed, as a newline would end the property
+pub fn render_motd(template: &str, world_name: &str, owner: &str, instance_name: &str) -> String {
+    let placeholders = [
+        ("{world_name}", world_name),
+        ("{owner}", owner),
+        ("{instance_name}", instance_name),
+    ];
+    let mut motd = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        motd.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match placeholders.iter().find(|(placeholder, _)| rest.starts_with(placeholder)) {
+            Some((placeholder, value)) => {
+                motd.push_str(value);
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                motd.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    motd.push_str(rest);
+    motd.chars().filter(|char| !char.is_control()).collect()
+}
+
+#[test]
+fn render_motd_test() {
+    assert_eq!(
+        render_motd("{world_name} by {owner} - powered by {instance_name}", "Survival", "Steve", "MCManager"),
+        "Survival by Steve - powered by MCManager"
+    );
+    // placeholders in the values are left alone
+    assert_eq!(render_motd("{world_name}!", "{owner}", "Steve", "MCManager"), "{owner}!");
+    assert_eq!(render_motd("{unknown} {", "a", "b", "c"), "{unknown} {");
+    assert_eq!(render_motd("{world_name}", "two\nlines", "b", "c"), "twolines");
+}
+
+/// sets the rendered `motd` in the `properties`. if the owner `can_edit` it, their own MOTD is kept, unless it's the
+/// `previous` rendered one, from before the world's name or owner changed
+pub fn apply_motd(properties: &mut HashMap<String, String>, motd: String, previous: Option<&str>, can_edit: bool) {
+    let customised = properties
+        .get("motd")
+        .is_some_and(|current| !current.is_empty() && current != DEFAULT_MOTD && Some(current.as_str()) != previous);
+    if !customised || !can_edit {
+        properties.insert(String::from("motd"), motd);
+    }
+}
+
+#[test]
+fn apply_motd_test() {
+    let properties = |motd: &str| HashMap::from([(String::from("motd"), String::from(motd))]);
+
+    let mut custom = properties("my server");
+    apply_motd(&mut custom, String::from("Creative by Steve"), Some("Survival by Steve"), true);
+    assert_eq!(custom["motd"], "my server");
+    apply_motd(&mut custom, String::from("Creative by Steve"), None, false);
+    assert_eq!(custom["motd"], "Creative by Steve");
+
+    // the MOTD rendered for the old name is replaced, as is the default one
+    let mut rendered = properties("Survival by Steve");
+    apply_motd(&mut rendered, String::from("Creative by Steve"), Some("Survival by Steve"), true);
+    assert_eq!(rendered["motd"], "Creative by Steve");
+    let mut default = properties(DEFAULT_MOTD);
+    apply_motd(&mut default, String::from("Creative by Steve"), None, true);
+    assert_eq!(default["motd"], "Creative by Steve");
+}
+
+/// the major version of the `java` installed on the host. [`None`] if it couldn't be detected
+pub static JAVA_VERSION: LazyLock<Option<u32>> = LazyLock::new(|| match detect_java_version(Path::new("java")) {
+    Ok(version) => Some(version),
+    Err(err) => {
+        warn!("could not detect the installed Java version: {err}");
+        None
+    }
+});
+
+/// runs `<java> -version` and returns the major version of that Java
+pub fn detect_java_version(java: &Path) -> color_eyre::Result<u32> {
+    let output = std::process::Command::new(java).arg("-version").output()?;
+    // java prints the version to stderr
+    let output = String::from_utf8_lossy(&output.stderr);
+    parse_java_version(&output).ok_or_else(|| {
+        eyre!(
+            "unexpected `{} -version` output: {}",
+            java.display(),
+            output.trim()
+        )
+    })
+}
+
+/// the Java binary to run a version that requires `required_java` with. versions that don't require a specific
+/// Java are run with the default `java`
+pub fn java_binary(required_java: Option<u32>) -> color_eyre::Result<PathBuf> {
+    let Some(required_java) = required_java else {
+        return Ok(PathBuf::from("java"));
+    };
+    CONFIG
+        .world
+        .java_runtimes
+        .get(&required_java.to_string())
+        .cloned()
+        .ok_or_else(|| {
+            eyre!("Java {required_java} is required, but world.java_runtimes has no runtime for it")
+        })
+}
+
+/// makes sure every runtime in `world.java_runtimes` exists and is the Java version it is configured as
+pub fn validate_java_runtimes() -> color_eyre::Result<()> {
+    for (version, path) in &CONFIG.world.java_runtimes {
+        let Ok(version) = version.parse::<u32>() else {
+            bail!("world.java_runtimes: \"{version}\" is not a major Java version");
+        };
+        if !path.is_file() {
+            bail!(
+                "world.java_runtimes: the Java {version} runtime {} does not exist",
+                path.display()
+            );
+        }
+        match detect_java_version(path) {
+            Ok(detected) if detected != version => warn!(
+                "world.java_runtimes: {} is configured as Java {version}, but it is Java {detected}",
+                path.display()
+            ),
+            Ok(_) => info!("using {} for Java {version}", path.display()),
+            Err(err) => warn!("could not detect the version of {}: {err}", path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// parses the major version out of the output of `java -version`, like `openjdk version "21.0.2" 2024-01-16`.
+/// versions before Java 9 are formatted like "1.8.0_392"
+pub fn parse_java_version(output: &str) -> Option<u32> {
+    let version = output.split('"').nth(1)?;
+    let mut parts = version.split(['.', '_', '-', '+']);
+    let major = parts.next()?.parse::<u32>().ok()?;
+    if major == 1 {
+        parts.next()?.parse().ok()
+    } else {
+        Some(major)
+    }
+}
+
+#[test]
+fn parse_java_version_test() {
+    assert_eq!(
+        parse_java_version("openjdk version \"21.0.2\" 2024-01-16\nOpenJDK Runtime Environment"),
+        Some(21)
+    );
+    assert_eq!(parse_java_version("java version \"1.8.0_392\""), Some(8));
+    assert_eq!(parse_java_version("openjdk version \"17-ea\" 2021-09-14"), Some(17));
+    assert_eq!(parse_java_version("bash: java: command not found"), None);
+}
+
+/// a setting that can be changed through a console command, see [`crate::database::objects::World`]'s quick-set endpoints
+pub trait QuickSetting: DeserializeOwned + Serialize + Display + Send + 'static {
+    /// the `server.properties` key the setting is persisted in, if any
+    const PROPERTY: Option<&'static str>;
+
+    /// the console command that applies the setting to a running server
+    fn command(&self) -> String;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Peaceful,
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Display for Difficulty {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Difficulty::Peaceful => f.write_str("peaceful"),
+            Difficulty::Easy => f.write_str("easy"),
+            Difficulty::Normal => f.write_str("normal"),
+            Difficulty::Hard => f.write_str("hard"),
+        }
+    }
+}
+
+impl QuickSetting for Difficulty {
+    const PROPERTY: Option<&'static str> = Some("difficulty");
+
+    fn command(&self) -> String {
+        format!("difficulty {self}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+impl Display for GameMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameMode::Survival => f.write_str("survival"),
+            GameMode::Creative => f.write_str("creative"),
+            GameMode::Adventure => f.write_str("adventure"),
+            GameMode::Spectator => f.write_str("spectator"),
+        }
+    }
+}
+
+impl QuickSetting for GameMode {
+    const PROPERTY: Option<&'static str> = Some("gamemode");
+
+    /// sets the default game mode, which applies to players joining for the first time
+    fn command(&self) -> String {
+        format!("defaultgamemode {self}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TimePreset {
+    Day,
+    Noon,
+    Night,
+    Midnight,
+}
+
+/// either one of the named times of day, or the time in ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Time {
+    Preset(TimePreset),
+    Ticks(u32),
+}
+
+impl Display for Time {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Time::Preset(TimePreset::Day) => f.write_str("day"),
+            Time::Preset(TimePreset::Noon) => f.write_str("noon"),
+            Time::Preset(TimePreset::Night) => f.write_str("night"),
+            Time::Preset(TimePreset::Midnight) => f.write_str("midnight"),
+            Time::Ticks(ticks) => write!(f, "{ticks}"),
+        }
+    }
+}
+
+impl QuickSetting for Time {
+    const PROPERTY: Option<&'static str> = None;
+
+    fn command(&self) -> String {
+        format!("time set {self}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weather {
+    Clear,
+    Rain,
+    Thunder,
+}
+
+impl Display for Weather {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Weather::Clear => f.write_str("clear"),
+            Weather::Rain => f.write_str("rain"),
+            Weather::Thunder => f.write_str("thunder"),
+        }
+    }
+}
+
+impl QuickSetting for Weather {
+    const PROPERTY: Option<&'static str> = None;
+
+    fn command(&self) -> String {
+        format!("weather {self}")
+    }
+}
+
+#[test]
+fn op_test() {
+    assert!(is_player_name("Notch") && is_player_name("a_b") && is_player_name("sixteen_chars_16"));
+    assert!(!is_player_name("ab") && !is_player_name("seventeen_chars17") && !is_player_name("op Steve"));
+
+    let line = "[12:00:00] [Server thread/INFO]: Made Steve a server operator";
+    assert_eq!(parse_op_result(line, "steve"), Some(OpResult::Opped));
+    assert_eq!(parse_op_result(line, "Alex"), None);
+    assert_eq!(
+        parse_op_result("[12:00:00] [Server thread/INFO]: Nothing changed. The player already is an operator", "Steve"),
+        Some(OpResult::AlreadyOp)
+    );
+    assert_eq!(
+        parse_op_result("[12:00:00] [Server thread/INFO]: That player does not exist", "Steve"),
+        Some(OpResult::UnknownPlayer)
+    );
+    assert_eq!(parse_op_result("[12:00:00] [Server thread/INFO]: Steve joined the game", "Steve"), None);
+}
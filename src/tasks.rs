@@ -0,0 +1,160 @@
+//! supervision of the tasks running in the background for as long as mcmanager does. a task that panics is logged and
+//! started again, and the ticks of the looping ones are recorded, so `GET /api/health` can show the ones that stalled
+
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+/// how long to wait before restarting a task that panicked the first time, doubled with every panic after it
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// the longest wait before restarting a task. a task that ran for longer than this before panicking starts over from
+/// [`INITIAL_BACKOFF`]
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// how many intervals a looping task can miss before it's reported as stalled
+const STALLED_AFTER_INTERVALS: u32 = 3;
+
+#[derive(Debug, Clone, Default)]
+struct TaskState {
+    interval: Option<Duration>,
+    started: Option<Instant>,
+    last_tick: Option<(Instant, DateTime<Utc>)>,
+    restarts: u32,
+}
+
+/// the state of a background task, as reported by `GET /api/health`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskReport {
+    /// when the task last finished a tick. [`None`] if it hasn't yet, or it doesn't tick
+    pub last_tick: Option<DateTime<Utc>>,
+    /// how many times it was restarted after panicking
+    pub restarts: u32,
+    /// whether the task missed several ticks in a row
+    pub stalled: bool,
+}
+
+/// the supervised background tasks, by name
+#[derive(Debug, Clone, Default)]
+pub struct TaskHealth(Arc<RwLock<HashMap<&'static str, TaskState>>>);
+
+impl TaskHealth {
+    fn update(&self, name: &'static str, update: impl FnOnce(&mut TaskState)) {
+        let mut tasks = self.0.write().expect("the task health lock is poisoned");
+        update(tasks.entry(name).or_default());
+    }
+
+    /// records that the task finished a tick
+    pub fn tick(&self, name: &'static str) {
+        self.update(name, |task| task.last_tick = Some((Instant::now(), Utc::now())));
+    }
+
+    pub fn report(&self) -> BTreeMap<&'static str, TaskReport> {
+        let tasks = self.0.read().expect("the task health lock is poisoned");
+        tasks
+            .iter()
+            .map(|(name, task)| {
+                let stalled = task.interval.is_some_and(|interval| {
+                    // the first tick is only expected an interval after the task started
+                    let since = task.last_tick.map(|(instant, _)| instant).or(task.started);
+                    since.is_some_and(|since| since.elapsed() > interval * STALLED_AFTER_INTERVALS)
+                });
+                let report = TaskReport {
+                    last_tick: task.last_tick.map(|(_, time)| time),
+                    restarts: task.restarts,
+                    stalled,
+                };
+                (*name, report)
+            })
+            .collect()
+    }
+
+    /// runs the future made by `task` in the background, making a new one and running it again whenever it panics.
+    /// `interval` is how often it calls [`TaskHealth::tick`], if it does
+    pub fn spawn<F, Fut>(&self, name: &'static str, interval: Option<Duration>, task: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let health = self.clone();
+        health.update(name, |task| {
+            task.interval = interval;
+            task.started = Some(Instant::now());
+        });
+        tokio::task::spawn(async move {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                let started = Instant::now();
+                match tokio::task::spawn(task()).await {
+                    Ok(()) => return,
+                    Err(err) if err.is_panic() => {
+                        if started.elapsed() > MAX_BACKOFF {
+                            backoff = INITIAL_BACKOFF;
+                        }
+                        error!("the background task \"{name}\" panicked, restarting it in {backoff:?}");
+                        health.update(name, |task| task.restarts += 1);
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                    // cancelled, which only happens when the runtime shuts down
+                    Err(_) => return,
+                }
+            }
+        })
+    }
+
+    /// like [`TaskHealth::spawn`], for a task that runs `tick` every `interval`
+    pub fn spawn_loop<F, Fut>(&self, name: &'static str, interval: Duration, tick: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let health = self.clone();
+        let tick = Arc::new(tick);
+        self.spawn(name, Some(interval), move || {
+            let health = health.clone();
+            let tick = tick.clone();
+            async move {
+                let mut interval = tokio::time::interval(interval);
+                loop {
+                    interval.tick().await;
+                    tick().await;
+                    health.tick(name);
+                }
+            }
+        })
+    }
+}
+
+#[tokio::test]
+async fn restart_test() {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    let health = TaskHealth::default();
+    let ticks = Arc::new(AtomicU32::new(0));
+    let handle = health.spawn_loop("flaky", Duration::from_millis(10), {
+        let ticks = ticks.clone();
+        move || {
+            let tick = ticks.fetch_add(1, Ordering::SeqCst) + 1;
+            async move {
+                assert!(tick != 2, "the second tick panics");
+            }
+        }
+    });
+
+    // waits for it to be restarted after the first backoff
+    tokio::time::sleep(INITIAL_BACKOFF + Duration::from_millis(500)).await;
+    // it kept ticking after the panic
+    assert!(ticks.load(Ordering::SeqCst) > 3);
+    let report = &health.report()["flaky"];
+    assert_eq!(report.restarts, 1);
+    assert!(report.last_tick.is_some() && !report.stalled);
+    assert!(!handle.is_finished());
+
+    health.spawn("stuck", Some(Duration::from_millis(10)), std::future::pending);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(health.report()["stuck"].stalled);
+}
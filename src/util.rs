@@ -4,6 +4,7 @@ use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
 
 pub mod dirs {
+    use crate::database::types::Id;
     use std::env;
     use std::fs::create_dir_all;
     use std::path::PathBuf;
@@ -16,6 +17,10 @@ pub mod dirs {
             .to_owned()
     }
     pub fn data_dir() -> PathBuf {
+        // the tests get their own, so they don't touch the data of an installation next to the executable
+        if cfg!(test) {
+            return env::temp_dir().join("mcmanager-test-data");
+        }
         base_dir().join("data")
     }
     pub fn icons_dir() -> PathBuf {
@@ -30,6 +35,27 @@ pub mod dirs {
     pub fn world_icons_dir() -> PathBuf {
         icons_dir().join("worlds")
     }
+    /// the directory an icon is in, out of the ones in its table's directory, so none of them gets too big to list
+    pub fn icon_shard(id: &str) -> &str {
+        id.get(..2).unwrap_or(id)
+    }
+    /// where the icon of the object in the table is stored, like `icons/users/ab/abcd1234.webp`
+    pub fn icon_path(table: &str, id: Id, extension: &str) -> PathBuf {
+        let id = id.to_string();
+        icons_dir()
+            .join(table)
+            .join(icon_shard(&id))
+            .join(format!("{id}.{extension}"))
+    }
+    /// where the icon resized to `size` and converted to the `extension` is cached, like `icons/users/ab/64/abcd1234.png`
+    pub fn icon_variant_path(table: &str, id: Id, size: u32, extension: &str) -> PathBuf {
+        let id = id.to_string();
+        icons_dir()
+            .join(table)
+            .join(icon_shard(&id))
+            .join(size.to_string())
+            .join(format!("{id}.{extension}"))
+    }
     pub fn infrarust_dir() -> PathBuf {
         base_dir().join("infrarust")
     }
@@ -39,6 +65,12 @@ pub mod dirs {
     pub fn worlds_dir() -> PathBuf {
         data_dir().join("worlds")
     }
+    pub fn backups_dir() -> PathBuf {
+        data_dir().join("backups")
+    }
+    pub fn mod_jars_dir() -> PathBuf {
+        data_dir().join("mods")
+    }
 
     pub fn init_dirs() -> color_eyre::Result<()> {
         create_dir_all(data_dir())?;
@@ -46,11 +78,19 @@ pub mod dirs {
         create_dir_all(user_avatars_dir())?;
         create_dir_all(mod_icons_dir())?;
         create_dir_all(world_icons_dir())?;
+        for dir in [user_avatars_dir(), mod_icons_dir(), world_icons_dir()] {
+            let moved = super::shard_icons(&dir)?;
+            if moved > 0 {
+                log::info!("moved {moved} icons in {} into their shards", dir.display());
+            }
+        }
 
         create_dir_all(infrarust_dir())?;
 
         create_dir_all(versions_dir())?;
         create_dir_all(worlds_dir())?;
+        create_dir_all(backups_dir())?;
+        create_dir_all(mod_jars_dir())?;
 
         Ok(())
     }
@@ -141,6 +181,96 @@ pub fn copy_dir_all_no_overwrite(src: impl AsRef<Path>, dst: impl AsRef<Path>) -
     Ok(())
 }
 
+/// moves a directory, creating the destination's parent directories if needed. does nothing if `src` doesn't exist
+pub fn move_dir(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
+    if !src.as_ref().exists() {
+        return Ok(());
+    }
+    if dst.as_ref().exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dst.as_ref().display()),
+        ));
+    }
+    if let Some(parent) = dst.as_ref().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(src, dst)
+}
+
+/// moves the icons stored directly in `dir`, from before they were split by [`dirs::icon_shard`], into their shards.
+/// an icon already in its shard was uploaded since, so the old one is removed instead. returns how many were moved
+pub fn shard_icons(dir: &Path) -> io::Result<usize> {
+    let mut moved = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let file_name = entry.file_name();
+        let Some(name) = file_name.to_str() else {
+            continue;
+        };
+        let Some((id, "gif" | "webp")) = name.rsplit_once('.') else {
+            continue;
+        };
+        if crate::database::types::Id::from_string(id).is_err() {
+            continue;
+        }
+        let shard = dir.join(dirs::icon_shard(id));
+        fs::create_dir_all(&shard)?;
+        if shard.join(name).exists() {
+            fs::remove_file(entry.path())?;
+        } else {
+            fs::rename(entry.path(), shard.join(name))?;
+            moved += 1;
+        }
+    }
+    Ok(moved)
+}
+
+/// total size of the files in a directory, in bytes. a nonexistent directory has a size of 0
+pub fn dir_size(path: impl AsRef<Path>) -> io::Result<u64> {
+    if !path.as_ref().exists() {
+        return Ok(0);
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+#[test]
+fn move_dir_test() {
+    let base = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    let src = base.join("old_owner/world");
+    let dst = base.join("new_owner/world");
+
+    fs::create_dir_all(src.join("world")).unwrap();
+    fs::write(src.join("server.properties"), "motd=hi").unwrap();
+    fs::write(src.join("world/level.dat"), "level").unwrap();
+
+    move_dir(&src, &dst).unwrap();
+
+    assert!(!src.exists());
+    assert_eq!(fs::read_to_string(dst.join("server.properties")).unwrap(), "motd=hi");
+    assert_eq!(fs::read_to_string(dst.join("world/level.dat")).unwrap(), "level");
+
+    // moving a nonexistent directory is a no-op, moving onto an existing one fails
+    move_dir(&src, &dst).unwrap();
+    fs::create_dir_all(&src).unwrap();
+    assert!(move_dir(&src, &dst).is_err());
+
+    fs::remove_dir_all(base).unwrap();
+}
+
 #[allow(clippy::all, clippy::pedantic, clippy::nursery)] //not my code, not my problem
 pub mod base64 {
     const CHARSET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
@@ -212,3 +342,36 @@ pub mod base64 {
         ((combined & (0b1111110000000000u16 >> offset)) >> (10 - offset)) as u8
     }
 }
+
+#[test]
+fn shard_icons_test() {
+    use crate::database::types::Id;
+
+    let (a, b) = (Id::new_random(), Id::new_random());
+    let path = dirs::icon_path("users", a, "webp");
+    let relative = path.strip_prefix(dirs::icons_dir()).unwrap();
+    let shard = &a.to_string()[..2];
+    assert_eq!(relative, Path::new("users").join(shard).join(format!("{a}.webp")));
+
+    let dir = std::env::temp_dir().join(format!("mcmanager-test-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(format!("{a}.webp")), "old a").unwrap();
+    fs::write(dir.join(format!("{b}.gif")), "old b").unwrap();
+    fs::write(dir.join("notes.txt"), "not an icon").unwrap();
+    // b was uploaded again after the icons were sharded
+    let b_shard = dir.join(dirs::icon_shard(&b.to_string()));
+    fs::create_dir_all(&b_shard).unwrap();
+    fs::write(b_shard.join(format!("{b}.gif")), "new b").unwrap();
+
+    assert_eq!(shard_icons(&dir).unwrap(), 1);
+    assert_eq!(fs::read_to_string(dir.join(shard).join(format!("{a}.webp"))).unwrap(), "old a");
+    assert_eq!(fs::read_to_string(b_shard.join(format!("{b}.gif"))).unwrap(), "new b");
+    assert!(!dir.join(format!("{a}.webp")).exists() && !dir.join(format!("{b}.gif")).exists());
+    assert!(dir.join("notes.txt").exists());
+
+    // running it again changes nothing
+    assert_eq!(shard_icons(&dir).unwrap(), 0);
+    assert!(dir.join(shard).join(format!("{a}.webp")).exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}